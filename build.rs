@@ -6,8 +6,15 @@ use std::{
     process::{Command, Output},
 };
 
+/// Shaders compiled a second time with a preprocessor define, as source
+/// file, output file and define
+const SHADER_VARIANTS: &[(&str, &str, &str)] = &[
+    ("simple_shader.frag", "simple_shader_log_depth.frag.spv", "LOG_DEPTH"),
+];
+
 fn main() {
     compile_shaders();
+    compile_shader_variants();
 }
 
 fn compile_shaders() {
@@ -38,6 +45,25 @@ fn compile_shaders() {
         })
 }
 
+fn compile_shader_variants() {
+    let shader_dir_path = get_shader_source_dir_path();
+
+    for (source, output_name, define) in SHADER_VARIANTS {
+        println!("Compiling {} with {} into {}", source, define, output_name);
+
+        let result = Command::new("glslangValidator")
+            .current_dir(&shader_dir_path)
+            .arg("-V")
+            .arg(format!("-D{}", define))
+            .arg(shader_dir_path.join(source))
+            .arg("-o")
+            .arg(output_name)
+            .output();
+
+        handle_program_result(result);
+    }
+}
+
 fn get_shader_source_dir_path() -> PathBuf {
     let path = get_root_path().join("shaders");
     println!("Shader source directory: {:?}", path.as_os_str());
@@ -0,0 +1,13 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (push_constant) uniform Push {
+    vec4 boundsMin;
+    vec4 boundsMax;
+    vec4 color;
+} push;
+
+void main() {
+    outColor = push.color;
+}
@@ -0,0 +1,45 @@
+#version 450
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
+layout (set = 0, binding = 0) uniform GlobalUbo {
+    mat4 projection;
+    mat4 view;
+    vec4 ambientLightColor;
+    PointLight pointLights[10];
+    int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    float logDepthCoefficient; // 0 uses the regular perspective depth
+    vec4 fogColor; // w is density
+} ubo;
+
+layout (push_constant) uniform Push {
+    vec4 boundsMin; // world space, w unused
+    vec4 boundsMax;
+    vec4 color;
+} push;
+
+// Corner pairs of the 12 edges, bit 0 of a corner picks max x, bit 1 y and bit 2 z
+const int EDGES[24] = int[](
+    0, 1, 2, 3, 4, 5, 6, 7, // along x
+    0, 2, 1, 3, 4, 6, 5, 7, // along y
+    0, 4, 1, 5, 2, 6, 3, 7  // along z
+);
+
+void main() {
+    int corner = EDGES[gl_VertexIndex];
+    vec3 position = mix(push.boundsMin.xyz, push.boundsMax.xyz, vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1));
+
+    gl_Position = ubo.projection * ubo.view * vec4(position, 1.0);
+
+    if (ubo.logDepthCoefficient > 0.0) {
+        gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * ubo.logDepthCoefficient * gl_Position.w;
+    }
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout (location = 0) in vec2 fragUv;
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D glyphAtlas;
+
+layout (push_constant) uniform Push {
+    vec2 extent;
+    vec4 color;
+} push;
+
+void main() {
+    // The atlas is premultiplied white, tint it with the premultiplied color
+    outColor = texture(glyphAtlas, fragUv) * vec4(push.color.rgb * push.color.a, push.color.a);
+}
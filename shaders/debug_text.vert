@@ -0,0 +1,17 @@
+#version 450
+
+layout (location = 0) in vec2 position;
+layout (location = 1) in vec2 uv;
+
+layout (location = 0) out vec2 fragUv;
+
+layout (push_constant) uniform Push {
+    vec2 extent;
+    vec4 color;
+} push;
+
+void main() {
+    // Pixels from the top left corner, Vulkan's NDC y already points down
+    gl_Position = vec4(position / push.extent * 2.0 - 1.0, 0.0, 1.0);
+    fragUv = uv;
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout (location = 0) in vec2 fragUv;
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 1, binding = 0) uniform sampler2D decalTexture;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix;
+    vec4 color;
+} push;
+
+void main() {
+    // Premultiplied, the pipeline blends with ONE, ONE_MINUS_SRC_ALPHA
+    outColor = texture(decalTexture, fragUv) * push.color;
+}
@@ -0,0 +1,56 @@
+#version 450
+
+const vec2 OFFSETS[6] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(-1.0, 1.0),
+    vec2(-1.0, 1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, 1.0)
+);
+
+// Fraction of the view distance the quad is pulled towards the camera
+const float VIEW_OFFSET = 1e-4;
+
+layout (location = 0) out vec2 fragUv;
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
+layout (set = 0, binding = 0) uniform GlobalUbo {
+    mat4 projection;
+    mat4 view;
+    vec4 ambientLightColor;
+    PointLight pointLights[10];
+    int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    float logDepthCoefficient; // 0 uses the regular perspective depth
+    vec4 fogColor; // w is density
+} ubo;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix; // x and y span half the quad, z is the surface normal
+    vec4 color;
+} push;
+
+void main() {
+    vec2 offset = OFFSETS[gl_VertexIndex];
+    fragUv = offset * 0.5 + 0.5;
+
+    // The camera sits at the origin of view space, so scaling moves the quad
+    // towards it without changing where it lands on screen
+    vec4 positionView = ubo.view * push.modelMatrix * vec4(offset, 0.0, 1.0);
+    positionView.xyz *= 1.0 - VIEW_OFFSET;
+
+    gl_Position = ubo.projection * positionView;
+
+    if (ubo.logDepthCoefficient > 0.0) {
+        gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * ubo.logDepthCoefficient * gl_Position.w;
+    }
+}
@@ -0,0 +1,36 @@
+#version 450
+
+layout (location = 0) in vec2 fragUv;
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D depthImage;
+
+layout (push_constant) uniform Push {
+    float near;
+    float far;
+    uint linear; // 0 shows the raw depth
+} push;
+
+const vec3 NEAR_COLOR = vec3(1.0, 0.85, 0.2);
+const vec3 FAR_COLOR = vec3(0.05, 0.0, 0.3);
+
+void main() {
+    float depth = texture(depthImage, fragUv).r;
+
+    // Cleared, nothing was drawn there
+    if (depth >= 1.0) {
+        outColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    if (push.linear == 0) {
+        outColor = vec4(vec3(depth), 1.0);
+        return;
+    }
+
+    // Inverts the [0, 1] depth of a right handed perspective projection
+    float viewDepth = push.near * push.far / (push.far - depth * (push.far - push.near));
+    float t = clamp((viewDepth - push.near) / (push.far - push.near), 0.0, 1.0);
+
+    outColor = vec4(mix(NEAR_COLOR, FAR_COLOR, sqrt(t)), 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+// 0 to 1 over the screen, for shaders that don't use gl_FragCoord
+layout (location = 0) out vec2 fragUv;
+
+void main() {
+    // A single triangle covering the whole screen
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    fragUv = uv;
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
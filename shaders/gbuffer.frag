@@ -0,0 +1,23 @@
+#version 450
+
+layout (location = 0) in vec3 fragColor;
+layout (location = 1) in vec3 fragPosWorld;
+layout (location = 2) in vec3 fragNormalWorld;
+
+// w is 1 where geometry was written, the targets are cleared to 0
+layout (location = 0) out vec4 outPosition;
+layout (location = 1) out vec4 outNormal;
+layout (location = 2) out vec4 outAlbedo;
+
+void main() {
+  vec3 normalWorld = normalize(fragNormalWorld);
+
+  // Double sided faces are seen from behind
+  if (!gl_FrontFacing) {
+    normalWorld = -normalWorld;
+  }
+
+  outPosition = vec4(fragPosWorld, 1.0);
+  outNormal = vec4(normalWorld, 1.0);
+  outAlbedo = vec4(fragColor, 1.0);
+}
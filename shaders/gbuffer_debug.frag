@@ -0,0 +1,28 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D gbufferPosition;
+layout (set = 0, binding = 1) uniform sampler2D gbufferNormal;
+layout (set = 0, binding = 2) uniform sampler2D gbufferAlbedo;
+
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  int third = int(gl_FragCoord.x * 3.0 / float(textureSize(gbufferAlbedo, 0).x));
+
+  vec3 color;
+
+  if (third == 0) {
+    // Repeats every world unit
+    color = fract(texelFetch(gbufferPosition, pixel, 0).xyz);
+  } else if (third == 1) {
+    color = texelFetch(gbufferNormal, pixel, 0).xyz * 0.5 + 0.5;
+  } else {
+    color = texelFetch(gbufferAlbedo, pixel, 0).rgb;
+  }
+
+  // Nothing was written where w is 0
+  float coverage = texelFetch(gbufferAlbedo, pixel, 0).a;
+
+  outColor = vec4(color * coverage, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 450
+
+// Three lines from the origin along the world axes
+const vec3 POSITIONS[6] = vec3[](
+    vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0)
+);
+
+const vec3 COLORS[3] = vec3[](
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0)
+);
+
+layout (location = 0) out vec3 fragColor;
+
+layout(push_constant) uniform Push {
+    mat4 rotation;
+} push;
+
+void main() {
+    vec3 position = (push.rotation * vec4(POSITIONS[gl_VertexIndex], 0.0)).xyz;
+
+    // Orthographic, the gizmo viewport is square
+    gl_Position = vec4(position.xy * 0.8, 0.5, 1.0);
+    fragColor = COLORS[gl_VertexIndex / 2];
+}
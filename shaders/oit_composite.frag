@@ -0,0 +1,22 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D accumulation;
+layout (set = 0, binding = 1) uniform sampler2D revealage;
+
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  float revealed = texelFetch(revealage, pixel, 0).r;
+
+  // No transparent fragment covers the pixel
+  if (revealed >= 1.0) {
+    discard;
+  }
+
+  vec4 accumulated = texelFetch(accumulation, pixel, 0);
+  vec3 average = accumulated.rgb / max(accumulated.a, 1e-5);
+
+  // Premultiplied by the coverage of all layers together
+  outColor = vec4(average * (1.0 - revealed), 1.0 - revealed);
+}
@@ -0,0 +1,8 @@
+#version 450
+
+layout (location = 0) out vec4 outCount;
+
+void main() {
+  // Blended additively, the target ends up holding the number of fragments per pixel
+  outCount = vec4(1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D overdrawCounts;
+
+const float MAX_COUNT = 8.0;
+
+void main() {
+    float count = texelFetch(overdrawCounts, ivec2(gl_FragCoord.xy), 0).r;
+
+    if (count < 0.5) {
+        outColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // Blue for a single fragment through green and yellow to red at MAX_COUNT or more
+    float t = clamp((count - 1.0) / (MAX_COUNT - 1.0), 0.0, 1.0);
+
+    vec3 color = t < 0.5
+        ? mix(vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0), t * 2.0)
+        : mix(vec3(1.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), t * 2.0 - 1.0);
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 450
+
+// 0 is cleared background, objects write their id plus one
+layout (location = 0) out uint outId;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix;
+    uint objectId;
+} push;
+
+void main() {
+    outId = push.objectId;
+}
@@ -0,0 +1,31 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
+layout (set = 0, binding = 0) uniform GlobalUbo {
+    mat4 projection;
+    mat4 view;
+    vec4 ambientLightColor;
+    PointLight pointLights[10];
+    int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    vec4 fogColor; // w is density
+} ubo;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix;
+    uint objectId;
+} push;
+
+void main() {
+    gl_Position = ubo.projection * ubo.view * push.modelMatrix * vec4(position, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 color;
+layout (location = 2) in vec3 normal;
+layout (location = 3) in vec2 uv;
+
+// World space, the evaluation shader projects the tessellated positions
+layout (location = 0) out vec3 vertPosWorld;
+layout (location = 1) out vec3 vertNormalWorld;
+layout (location = 2) out vec3 vertColor;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix;
+    mat4 normalMatrix;
+} push;
+
+void main() {
+    vertPosWorld = (push.modelMatrix * vec4(position, 1.0)).xyz;
+    vertNormalWorld = normalize(mat3(push.normalMatrix) * normal);
+    vertColor = color;
+
+    // Replaced by the evaluation shader
+    gl_Position = vec4(vertPosWorld, 1.0);
+}
@@ -1,6 +1,7 @@
 #version 450
 
 layout (location = 0) in vec2 fragOffset;
+layout (location = 1) flat in vec3 fragColor;
 layout (location = 0) out vec4 outColor;
 
 struct PointLight {
@@ -14,20 +15,37 @@ layout (set = 0, binding = 0) uniform GlobalUbo {
     vec4 ambientLightColor;
     PointLight pointLights[10];
     int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    float logDepthCoefficient; // 0 uses the regular perspective depth
+    vec4 fogColor; // w is density
+    float exposure;
 } ubo;
 
-layout(push_constant) uniform Push {
-    vec4 position;
-    vec4 color;
-    float radius;
+layout (push_constant) uniform Push {
+    uint segments; // 0 draws the quad
 } push;
 
 void main() {
+    // The fan is the shape itself, its outline stays hard
+    if (push.segments > 0) {
+        outColor = vec4(fragColor * ubo.exposure, 1.0);
+        return;
+    }
+
     float dis = sqrt(dot(fragOffset, fragOffset));
-    
-    if (dis >= 1.0) {
+
+    // Fade over one pixel at the edge, fwidth keeps it in screen space whatever the radius
+    float edgeWidth = fwidth(dis);
+    float coverage = 1.0 - smoothstep(1.0 - edgeWidth, 1.0, dis);
+
+    if (coverage <= 0.0) {
         discard;
     }
 
-    outColor = vec4(push.color.xyz, 1.0);
+    // Premultiplied, the pipeline blends with ONE, ONE_MINUS_SRC_ALPHA
+    outColor = vec4(fragColor * ubo.exposure * coverage, coverage);
 }
@@ -10,9 +10,10 @@ const vec2 OFFSETS[6] = vec2[](
 );
 
 layout (location = 0) out vec2 fragOffset;
+layout (location = 1) flat out vec3 fragColor;
 
 struct PointLight {
-    vec4 position;
+    vec4 position; // w is the billboard radius
     vec4 color;
 };
 
@@ -22,23 +23,53 @@ layout (set = 0, binding = 0) uniform GlobalUbo {
     vec4 ambientLightColor;
     PointLight pointLights[10];
     int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    float logDepthCoefficient; // 0 uses the regular perspective depth
+    vec4 fogColor; // w is density
 } ubo;
 
-layout(push_constant) uniform Push {
-    vec4 position;
-    vec4 color;
-    float radius;
+layout (push_constant) uniform Push {
+    uint segments; // 0 draws the quad
 } push;
 
+const float TAU = 6.28318530718;
+
+// Three vertices per segment, the light center and two points on the circle
+vec2 fanOffset(uint index) {
+    uint segment = index / 3;
+    uint corner = index % 3;
+
+    if (corner == 0) {
+        return vec2(0.0);
+    }
+
+    // Counter clockwise like the quad, so it isn't culled
+    float angle = TAU * float(segment + corner - 1) / float(push.segments);
+    return vec2(cos(angle), sin(angle));
+}
+
 void main() {
-    fragOffset = OFFSETS[gl_VertexIndex];
+    // One instance per light, numLights instances are drawn
+    PointLight light = ubo.pointLights[gl_InstanceIndex];
+
+    fragOffset = push.segments == 0 ? OFFSETS[gl_VertexIndex] : fanOffset(gl_VertexIndex);
+    fragColor = light.color.xyz;
     
-    vec3 cameraRightWorld = {ubo.view[0][0], ubo.view[1][0], ubo.view[2][0]};
-    vec3 cameraUpWorld    = {ubo.view[0][1], ubo.view[1][1], ubo.view[2][1]};
+    // Offset in view space, so the quad faces the camera and the projection
+    // applies the same aspect ratio as to the rest of the scene. Scaling
+    // clip space offsets by the radius alone would stretch it with the window.
+    float radius = light.position.w;
+    vec4 positionView = ubo.view * vec4(light.position.xyz, 1.0);
+    positionView.xy += radius * fragOffset;
 
-    vec3 positionWorld = push.position.xyz
-        + push.radius * fragOffset.x * cameraRightWorld
-        + push.radius * fragOffset.y * cameraUpWorld;
+    gl_Position = ubo.projection * positionView;
 
-    gl_Position = ubo.projection * ubo.view * vec4(positionWorld, 1.0);
+    // Per vertex is enough for the small billboards
+    if (ubo.logDepthCoefficient > 0.0) {
+        gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * ubo.logDepthCoefficient * gl_Position.w;
+    }
 }
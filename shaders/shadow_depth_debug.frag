@@ -0,0 +1,17 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D shadowDepth;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / vec2(textureSize(shadowDepth, 0));
+    if (uv.x > 1.0 || uv.y > 1.0) {
+        outColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    // Perspective depth crowds near 1, stretch it so the casters stand out
+    float depth = texture(shadowDepth, uv).r;
+    outColor = vec4(vec3(pow(depth, 32.0)), 1.0);
+}
@@ -5,11 +5,14 @@
 layout (location = 0) in vec3 fragColor;
 layout (location = 1) in vec3 fragPosWorld;
 layout (location = 2) in vec3 fragNormalWorld;
+layout (location = 3) in float fragLogDepth;
 
 layout (location = 0) out vec4 outColor;
+// Only has an attachment in the weighted blended transparency pass
+layout (location = 1) out float outRevealage;
 
 struct PointLight {
-  vec4 position; // ignore w
+  vec4 position; // ignore w, the billboard radius
   vec4 color; // w is intensity
 };
 
@@ -19,17 +22,46 @@ layout(set = 0, binding = 0) uniform GlobalUbo {
   vec4 ambientLightColor; // w is intensity
   PointLight pointLights[10];
   int numLights;
+  float time;
+  vec2 resolution;
+  float ambientPulse;
+  float irradianceIntensity;
+  float ambientOcclusion;
+  float logDepthCoefficient; // 0 uses the regular perspective depth
+  vec4 fogColor; // w is density
+  float exposure;
+  float reflections; // scales the reflectivity, 0 without a reflection map
 } ubo;
 
+layout(set = 0, binding = 1) uniform samplerCube irradianceMap;
+layout(set = 0, binding = 2) uniform sampler2D occlusionMap;
+layout(set = 0, binding = 3) uniform sampler2D reflectionMap;
+
 layout(push_constant) uniform Push {
   mat4 modelMatrix;
+  // The fourth column's x is 1 for unlit materials, y for back-face tinting,
+  // z is the reflectivity and w the opacity. The fourth row's x is 1 for
+  // weighted blended transparency.
   mat4 normalMatrix;
 } push;
 
-void main() {
-  vec3 diffuseLight = ubo.ambientLightColor.xyz * ubo.ambientLightColor.w;
+vec3 litColor() {
   vec3 surfaceNormal = normalize(fragNormalWorld);
 
+  vec3 ambientLight;
+  if (ubo.irradianceIntensity > 0.0) {
+    ambientLight = texture(irradianceMap, surfaceNormal).rgb * ubo.irradianceIntensity;
+  } else {
+    ambientLight = ubo.ambientLightColor.xyz * ubo.ambientLightColor.w;
+  }
+
+  if (ubo.ambientOcclusion > 0.0) {
+    float occlusion = texture(occlusionMap, gl_FragCoord.xy / ubo.resolution).r;
+    ambientLight *= mix(1.0, occlusion, ubo.ambientOcclusion);
+  }
+
+  vec3 diffuseLight = ambientLight * (1.0 + ubo.ambientPulse * sin(ubo.time * 2.0));
+
   for (int i = 0; i < ubo.numLights; i++) {
     PointLight light = ubo.pointLights[i];
     vec3 directionToLight = light.position.xyz - fragPosWorld;
@@ -40,5 +72,52 @@ void main() {
     diffuseLight += intensity * cosAngIncidence;
   }
   
-  outColor = vec4(diffuseLight * fragColor, 1.0);
+  vec3 color = diffuseLight * fragColor;
+
+  if (ubo.fogColor.w > 0.0) {
+    // Squared exponential falloff over the view space distance
+    float viewDistance = length((ubo.view * vec4(fragPosWorld, 1.0)).xyz);
+    float fogAmount = viewDistance * ubo.fogColor.w;
+    float visibility = exp(-fogAmount * fogAmount);
+
+    color = mix(ubo.fogColor.xyz, color, visibility);
+  }
+
+  return color;
+}
+
+void main() {
+  vec3 color = push.normalMatrix[3].x > 0.5 ? fragColor : litColor();
+
+  // The mirrored scene lines up with the surface in screen space
+  float reflectivity = push.normalMatrix[3].z * ubo.reflections;
+  if (reflectivity > 0.0) {
+    vec3 reflection = texture(reflectionMap, gl_FragCoord.xy / ubo.resolution).rgb;
+    color = mix(color, reflection, reflectivity);
+  }
+
+  color *= ubo.exposure;
+
+  if (push.normalMatrix[3].y > 0.5 && !gl_FrontFacing) {
+    color = mix(color, vec3(1.0, 0.0, 0.0), 0.8);
+  }
+
+  // Premultiplied, opaque objects have an opacity of 1
+  float opacity = push.normalMatrix[3].w;
+  outColor = vec4(color * opacity, opacity);
+  outRevealage = opacity;
+
+  if (push.normalMatrix[0].w > 0.5) {
+    // Near and opaque fragments dominate the weighted average, the depth
+    // weight of McGuire and Bavoil's equation 9
+    float viewDepth = fragLogDepth - 1.0;
+    outColor *= clamp(0.03 / (1e-5 + pow(viewDepth / 200.0, 4.0)), 1e-2, 3e3);
+  }
+
+#ifdef LOG_DEPTH
+  // The per vertex logarithmic depth is wrong inside large triangles, which
+  // are nonlinear in depth. Writing depth costs early depth testing, so only
+  // the simple_shader_log_depth.frag.spv variant does it.
+  gl_FragDepth = log2(fragLogDepth) * ubo.logDepthCoefficient;
+#endif
 }
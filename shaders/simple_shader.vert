@@ -8,6 +8,7 @@ layout (location = 3) in vec2 uv;
 layout (location = 0) out vec3 fragColor;
 layout (location = 1) out vec3 fragPosWorld;
 layout (location = 2) out vec3 fragNormalWorld;
+layout (location = 3) out float fragLogDepth;
 
 struct PointLight {
     vec4 position;
@@ -20,6 +21,13 @@ layout (set = 0, binding = 0) uniform GlobalUbo {
     vec4 ambientLightColor;
     PointLight pointLights[10];
     int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    float logDepthCoefficient; // 0 uses the regular perspective depth
+    vec4 fogColor; // w is density
 } ubo;
 
 layout (push_constant) uniform Push {
@@ -30,6 +38,13 @@ layout (push_constant) uniform Push {
 void main() {
     vec4 positionWorld = push.modelMatrix * vec4(position, 1.0);
     gl_Position = ubo.projection * ubo.view * positionWorld;
+
+    // Logarithmic depth spreads the precision evenly over the view distance
+    if (ubo.logDepthCoefficient > 0.0) {
+        gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * ubo.logDepthCoefficient * gl_Position.w;
+    }
+
+    fragLogDepth = 1.0 + gl_Position.w;
     fragNormalWorld = normalize(mat3(push.normalMatrix) * normal);
     fragPosWorld = positionWorld.xyz;
     fragColor = color;
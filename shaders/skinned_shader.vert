@@ -0,0 +1,64 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 color;
+layout (location = 2) in vec3 normal;
+layout (location = 3) in vec2 uv;
+layout (location = 4) in uvec4 jointIndices;
+layout (location = 5) in vec4 jointWeights;
+
+layout (location = 0) out vec3 fragColor;
+layout (location = 1) out vec3 fragPosWorld;
+layout (location = 2) out vec3 fragNormalWorld;
+layout (location = 3) out float fragLogDepth;
+
+struct PointLight {
+    vec4 position;
+    vec4 color;
+};
+
+layout (set = 0, binding = 0) uniform GlobalUbo {
+    mat4 projection;
+    mat4 view;
+    vec4 ambientLightColor;
+    PointLight pointLights[10];
+    int numLights;
+    float time;
+    vec2 resolution;
+    float ambientPulse;
+    float irradianceIntensity;
+    float ambientOcclusion;
+    float logDepthCoefficient; // 0 uses the regular perspective depth
+    vec4 fogColor; // w is density
+} ubo;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix;
+    mat4 normalMatrix;
+} push;
+
+// Skinning matrices of the drawn object, selected with a dynamic offset
+layout (set = 1, binding = 0) readonly buffer Joints {
+    mat4 jointMatrices[];
+} joints;
+
+void main() {
+    mat4 skinMatrix =
+        jointWeights.x * joints.jointMatrices[jointIndices.x] +
+        jointWeights.y * joints.jointMatrices[jointIndices.y] +
+        jointWeights.z * joints.jointMatrices[jointIndices.z] +
+        jointWeights.w * joints.jointMatrices[jointIndices.w];
+
+    vec4 positionWorld = push.modelMatrix * skinMatrix * vec4(position, 1.0);
+    gl_Position = ubo.projection * ubo.view * positionWorld;
+
+    // Logarithmic depth spreads the precision evenly over the view distance
+    if (ubo.logDepthCoefficient > 0.0) {
+        gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * ubo.logDepthCoefficient * gl_Position.w;
+    }
+
+    fragLogDepth = 1.0 + gl_Position.w;
+    fragNormalWorld = normalize(mat3(push.normalMatrix) * mat3(skinMatrix) * normal);
+    fragPosWorld = positionWorld.xyz;
+    fragColor = color;
+}
@@ -0,0 +1,68 @@
+#version 450
+
+const int KERNEL_SIZE = 16;
+
+layout (location = 0) out float outOcclusion;
+
+// Cleared to a zero depth where there is no geometry
+layout (set = 0, binding = 0) uniform sampler2D normalDepth;
+layout (set = 0, binding = 1) uniform sampler2D noise;
+
+layout (set = 0, binding = 2) uniform Kernel {
+  vec4 samples[KERNEL_SIZE];
+} kernel;
+
+layout (set = 1, binding = 0) uniform Params {
+  mat4 projection;
+  float radius;
+  float bias;
+} params;
+
+vec3 viewPosition(vec2 uv, float depth) {
+  // Inverse of the projection for a known view depth, the clip w is the
+  // depth for a perspective and 1 for an orthographic projection
+  vec2 ndc = uv * 2.0 - 1.0;
+  float w = params.projection[3][3] - params.projection[2][3] * depth;
+  return vec3(ndc.x * w / params.projection[0][0], ndc.y * w / params.projection[1][1], -depth);
+}
+
+void main() {
+  vec2 size = vec2(textureSize(normalDepth, 0));
+  vec2 uv = gl_FragCoord.xy / size;
+
+  vec4 center = texture(normalDepth, uv);
+
+  if (center.w <= 0.0) {
+    outOcclusion = 1.0;
+    return;
+  }
+
+  vec3 position = viewPosition(uv, center.w);
+  vec3 normal = normalize(center.xyz);
+
+  // Tiled random rotation around the normal, the blur pass hides the pattern
+  vec3 randomVec = vec3(texelFetch(noise, ivec2(gl_FragCoord.xy) % 4, 0).xy * 2.0 - 1.0, 0.0);
+  vec3 tangent = normalize(randomVec - normal * dot(randomVec, normal));
+  vec3 bitangent = cross(normal, tangent);
+  mat3 tbn = mat3(tangent, bitangent, normal);
+
+  float occlusion = 0.0;
+  for (int i = 0; i < KERNEL_SIZE; i++) {
+    vec3 samplePosition = position + tbn * kernel.samples[i].xyz * params.radius;
+
+    vec4 offset = params.projection * vec4(samplePosition, 1.0);
+    vec2 sampleUv = (offset.xy / offset.w) * 0.5 + 0.5;
+
+    float sampleDepth = texture(normalDepth, sampleUv).w;
+
+    if (sampleDepth <= 0.0) {
+      continue;
+    }
+
+    // Ignore geometry far in front of the sampled point, it doesn't occlude it
+    float rangeCheck = smoothstep(0.0, 1.0, params.radius / abs(center.w - sampleDepth));
+    occlusion += (sampleDepth < -samplePosition.z - params.bias ? 1.0 : 0.0) * rangeCheck;
+  }
+
+  outOcclusion = 1.0 - occlusion / float(KERNEL_SIZE);
+}
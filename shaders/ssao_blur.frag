@@ -0,0 +1,20 @@
+#version 450
+
+layout (location = 0) out float outOcclusion;
+
+layout (set = 0, binding = 0) uniform sampler2D occlusion;
+
+void main() {
+  // 4x4 box blur, matches the size of the noise tile
+  ivec2 size = textureSize(occlusion, 0);
+  ivec2 coord = ivec2(gl_FragCoord.xy);
+
+  float result = 0.0;
+  for (int x = -2; x < 2; x++) {
+    for (int y = -2; y < 2; y++) {
+      result += texelFetch(occlusion, clamp(coord + ivec2(x, y), ivec2(0), size - 1), 0).r;
+    }
+  }
+
+  outOcclusion = result / 16.0;
+}
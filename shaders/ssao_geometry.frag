@@ -0,0 +1,41 @@
+#version 450
+
+layout (location = 0) in vec3 fragColor;
+layout (location = 1) in vec3 fragPosWorld;
+layout (location = 2) in vec3 fragNormalWorld;
+
+// View space normal, w is the distance along the view direction
+layout (location = 0) out vec4 outNormalDepth;
+
+struct PointLight {
+  vec4 position;
+  vec4 color;
+};
+
+layout(set = 0, binding = 0) uniform GlobalUbo {
+  mat4 projection;
+  mat4 view;
+  vec4 ambientLightColor;
+  PointLight pointLights[10];
+  int numLights;
+  float time;
+  vec2 resolution;
+  float ambientPulse;
+  float irradianceIntensity;
+  float ambientOcclusion;
+  vec4 fogColor;
+} ubo;
+
+void main() {
+  vec3 normalWorld = normalize(fragNormalWorld);
+
+  // Double sided faces are seen from behind
+  if (!gl_FrontFacing) {
+    normalWorld = -normalWorld;
+  }
+
+  vec3 normalView = normalize(mat3(ubo.view) * normalWorld);
+  float depth = -(ubo.view * vec4(fragPosWorld, 1.0)).z;
+
+  outNormalDepth = vec4(normalView, depth);
+}
@@ -0,0 +1,53 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (set = 0, binding = 0) uniform sampler2D currentColor;
+layout (set = 0, binding = 1) uniform sampler2D currentDepth;
+layout (set = 0, binding = 2) uniform sampler2D history;
+
+layout (set = 1, binding = 0) uniform PostProcessUbo {
+    // Weight of the history, the rest comes from the current frame
+    float taaFeedback;
+    float vignette;
+} post;
+
+layout (push_constant) uniform Push {
+    // Current normalized device coordinates to the previous frame's clip space
+    mat4 reprojection;
+    uint historyValid;
+} push;
+
+void main() {
+    ivec2 size = textureSize(currentColor, 0);
+    ivec2 coord = ivec2(gl_FragCoord.xy);
+    vec2 uv = gl_FragCoord.xy / vec2(size);
+
+    vec3 current = texelFetch(currentColor, coord, 0).rgb;
+
+    // The history may only contain colors the current neighborhood could
+    // produce, anything else is a disoccluded or moved surface
+    vec3 minColor = current;
+    vec3 maxColor = current;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec3 neighbor = texelFetch(currentColor, clamp(coord + ivec2(x, y), ivec2(0), size - 1), 0).rgb;
+            minColor = min(minColor, neighbor);
+            maxColor = max(maxColor, neighbor);
+        }
+    }
+
+    float depth = texelFetch(currentDepth, coord, 0).r;
+    vec4 previous = push.reprojection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    vec2 previousUv = previous.xy / previous.w * 0.5 + 0.5;
+
+    bool offscreen = any(lessThan(previousUv, vec2(0.0))) || any(greaterThan(previousUv, vec2(1.0)));
+    if (push.historyValid == 0 || offscreen) {
+        outColor = vec4(current, 1.0);
+        return;
+    }
+
+    vec3 previousColor = clamp(texture(history, previousUv).rgb, minColor, maxColor);
+
+    outColor = vec4(mix(current, previousColor, post.taaFeedback), 1.0);
+}
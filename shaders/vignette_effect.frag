@@ -0,0 +1,50 @@
+#version 450
+
+// Example for `Settings::fullscreen_effect`, darkens the corners and fades
+// distant surfaces into a slowly pulsing tint
+
+layout (location = 0) in vec2 fragUv;
+
+layout (location = 0) out vec4 outColor;
+
+struct PointLight {
+  vec4 position; // ignore w, the billboard radius
+  vec4 color; // w is intensity
+};
+
+layout(set = 0, binding = 0) uniform GlobalUbo {
+  mat4 projection;
+  mat4 view;
+  vec4 ambientLightColor; // w is intensity
+  PointLight pointLights[10];
+  int numLights;
+  float time;
+  vec2 resolution;
+  float ambientPulse;
+  float irradianceIntensity;
+  float ambientOcclusion;
+  float logDepthCoefficient; // 0 uses the regular perspective depth
+  vec4 fogColor; // w is density
+  float exposure;
+} ubo;
+
+layout (set = 1, binding = 0) uniform sampler2D sceneColor;
+layout (set = 1, binding = 1) uniform sampler2D sceneDepth;
+
+layout (set = 2, binding = 0) uniform PostProcessUbo {
+  float taaFeedback;
+  float vignette;
+} post;
+
+void main() {
+    vec3 color = texture(sceneColor, fragUv).rgb;
+    float depth = texture(sceneDepth, fragUv).r;
+
+    vec3 tint = vec3(0.2, 0.3, 0.5) * (0.75 + 0.25 * sin(ubo.time));
+    color = mix(color, tint, smoothstep(0.98, 1.0, depth) * 0.5);
+
+    vec2 centered = fragUv * 2.0 - 1.0;
+    float vignette = 1.0 - post.vignette * dot(centered, centered);
+
+    outColor = vec4(color * vignette, 1.0);
+}
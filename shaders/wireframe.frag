@@ -0,0 +1,12 @@
+#version 450
+
+layout (location = 0) out vec4 outColor;
+
+layout (push_constant) uniform Push {
+    mat4 modelMatrix;
+    vec4 edgeColor;
+} push;
+
+void main() {
+    outColor = push.edgeColor;
+}
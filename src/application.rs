@@ -1,48 +1,129 @@
-use std::{collections::HashMap, rc::Rc, f32::consts::PI};
+use std::{collections::HashMap, rc::Rc, f32::consts::PI, time::{Duration, Instant}};
 
 use winit::event_loop::EventLoop;
 
-use crate::{window::{Dimensions, Window, WindowSettings, WindowMode}, vulkan::{Renderer, Device, Model, GentooRenderError, MAX_FRAMES_IN_FLIGHT, descriptor_set::{DescriptorSetLayout, DescriptorPool, DescriptorSetWriter}, systems::{PointLightSystem, SimpleRenderSystem}, pipeline::PipelineCache, egui::EGuiIntegration, Buffer}, keyboard_movement_controller::KeyboardMovementController, camera::CameraBuilder, FrameInfo, input::Input, GlobalUbo, PointLight, MAX_LIGHTS, GameObject, TransformComponent};
+use crate::{window::{Window, WindowMode}, settings::{Settings, DebugView, ScreenCorner, AntiAliasingPreset, TransparencyMode, ProjectionMode}, vulkan::{Renderer, Device, ModelCache, GentooRenderError, Texture, MAX_FRAMES_IN_FLIGHT, MAX_RENDER_SCALE, descriptor_set::{DescriptorSetLayout, DescriptorPool, DescriptorSetWriter, FrameUniforms}, systems::{PointLightSystem, SimpleRenderSystem, OverdrawSystem, GizmoSystem, SsaoSystem, SkinnedRenderSystem, DebugTextSystem, GBufferSystem, PickingSystem, WireframeSystem, TaaSystem, ShadowCasterSystem, BoundsDebugSystem, DecalSystem, Decal, FullscreenEffectSystem, ReflectionSystem, OitSystem, DepthViewSystem, BOUNDS_COLOR, MIN_BILLBOARD_SEGMENTS, MAX_BILLBOARD_SEGMENTS}, pipeline::PipelineCache, egui::EGuiIntegration, Buffer, SkinnedModel, MeshOptions, DepthRange, FULL_DEPTH_RANGE}, keyboard_movement_controller::KeyboardMovementController, camera::CameraBuilder, FrameInfo, input::Input, frame_stats::FrameStats, GlobalUbo, PostProcessUbo, PointLight, MAX_LIGHTS, GameObject, GameObjectId, TransformComponent, AnimationComponent, DepthTest, RENDER_LAYERS};
+
+/// How long the window size has to stay unchanged before the swapchain is rebuilt.
+/// Dragging a window edge produces a burst of resize events, only the last one matters.
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Depth range of objects drawn in front of the rest of the scene.
+const IN_FRONT_DEPTH_RANGE: DepthRange = DepthRange { min: 0.0, max: 0.1 };
+
+/// Frames drawn after the last change with `redraw_on_demand`, lets temporal
+/// effects like TAA settle before the app goes idle
+const SETTLE_FRAMES: u32 = 8;
+
+/// Range and Ctrl +/- step of `Settings::ui_scale`
+const UI_SCALE_RANGE: std::ops::RangeInclusive<f32> = 0.5..=3.0;
+const UI_SCALE_STEP: f32 = 0.1;
+
+const NEAR_PLANE: f32 = 0.1;
+const FAR_PLANE: f32 = 100.0;
+const SHADOW_MAP_SIZE: u32 = 1024;
+/// Resolution of the shadow map shown in the depth window
+const DEPTH_VIEW_SIZE: u32 = 256;
+
+/// Room in the global pool for systems' own per frame uniforms, see `FrameUniforms`
+const SYSTEM_UNIFORM_SETS: u32 = 4;
 
 pub struct Application {
     pub window: Window,
+    settings: Settings,
     pipeline_cache: Rc<PipelineCache>,
-    egui_integration: EGuiIntegration,
+    egui_integration: Option<EGuiIntegration>,
     simple_render_system: SimpleRenderSystem,
+    skinned_render_system: SkinnedRenderSystem,
     point_light_system: PointLightSystem,
+    overdraw_system: OverdrawSystem,
+    gbuffer_system: GBufferSystem,
+    shadow_caster_system: ShadowCasterSystem,
+    picking_system: PickingSystem,
+    wireframe_system: WireframeSystem,
+    bounds_debug_system: BoundsDebugSystem,
+    decal_system: DecalSystem,
+    gizmo_system: GizmoSystem,
+    ssao_system: SsaoSystem,
+    taa_system: TaaSystem,
+    fullscreen_effect_system: Option<FullscreenEffectSystem>,
+    reflection_system: ReflectionSystem,
+    oit_system: OitSystem,
+    /// Shadow map shown in the depth window
+    depth_view_system: DepthViewSystem,
+    /// `depth_view_system`'s image, registered with egui
+    depth_view_texture: Option<egui::TextureId>,
+    /// Statistics overlay for builds without egui
+    debug_text_system: Option<DebugTextSystem>,
     renderer: Renderer,
-    game_objects: HashMap<u8, GameObject>,
+    game_objects: HashMap<GameObjectId, GameObject>,
+    /// Models loaded by file path, shared by the objects drawing them
+    model_cache: ModelCache,
     viewer_object: GameObject,
     camera_controller: KeyboardMovementController,
     global_pool: Rc<DescriptorPool>,
     global_set_layout: Rc<DescriptorSetLayout>,
     global_descriptor_sets: Vec<ash::vk::DescriptorSet>,
+    /// One per frame in flight, see `MAX_FRAMES_IN_FLIGHT`
     ubo_buffers: Vec<Buffer<GlobalUbo>>,
+    post_process_uniforms: FrameUniforms<PostProcessUbo>,
+    pending_resize: Option<Instant>,
+    /// Nothing is rendered while the window can't be seen
+    occluded: bool,
+    reload_shaders_requested: bool,
+    /// Frames left to draw before idling, see `needs_redraw`
+    redraw_frames: u32,
+    /// The last `run` couldn't get a swapchain image and presented nothing
+    frame_dropped: bool,
+    /// Set by a click in the scene, picked at the cursor in the next frame
+    pick_requested: bool,
+    /// Object under the cursor at the last click
+    picked_object: Option<GameObjectId>,
+    /// Toggled with `Settings::ui_toggle_key`, the scene keeps rendering
+    /// while the UI is hidden
+    ui_visible: bool,
+    /// Whether the toggle key is down, key repeats don't toggle again
+    ui_toggle_held: bool,
+    modifiers: winit::event::ModifiersState,
+    shader_error: Option<String>,
+    elapsed_time: f32,
+    /// Only keeps the texture the global sets point at alive
+    _irradiance_map: Rc<Texture>,
+    /// Bound in place of the SSAO result and the reflection while they are disabled
+    occlusion_placeholder: Rc<Texture>,
+    /// Whether the global sets currently point at the SSAO result
+    occlusion_bound: bool,
+    /// Whether the global sets currently point at the planar reflection
+    reflection_bound: bool,
 }
 
 impl Application {
-    pub fn new() -> anyhow::Result<(Self, EventLoop<()>), ApplicationError> {
+    pub fn new(settings: Settings) -> anyhow::Result<(Self, EventLoop<()>), ApplicationError> {
         let event_loop = EventLoop::new();
 
-        let window = Window::new(
-            &event_loop,
-            WindowSettings {
-                title: "Gentoo",
-                dimensions: Dimensions {
-                    width: 800, 
-                    height: 600,
-                },
-                resizable: true,
-            }
-        );
+        let window = Window::new(&event_loop, settings.window);
+
+        let device = Device::new(&window.raw_window, settings.gpu_preference, settings.validation_features, settings.hdr_output)?;
+        device.set_max_texture_dimension(settings.max_texture_dimension);
 
-        let device = Device::new(&window.raw_window)?;
+        // Without egui the scene pass is the last pass before presenting
+        let final_layout = if settings.enable_egui {
+            ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            ash::vk::ImageLayout::PRESENT_SRC_KHR
+        };
 
-        let renderer = Renderer::new(device.clone(), &window)?;
+        let mut renderer = Renderer::new(device.clone(), &window, final_layout, settings.swapchain_image_usage)?;
+        renderer.set_render_scale(settings.render_scale)?;
+        renderer.set_fence_timeout(settings.fence_timeout);
+        renderer.set_depth_stencil_clear(settings.clear_depth, settings.clear_stencil);
+        renderer.set_resolve_scene(Self::resolves_scene(&settings))?;
 
+        // The global sets and the systems' `FrameUniforms`
         let global_pool = DescriptorPool::new(device.clone())
-            .set_max_sets(MAX_FRAMES_IN_FLIGHT as u32)
-            .add_pool_size(ash::vk::DescriptorType::UNIFORM_BUFFER, MAX_FRAMES_IN_FLIGHT as u32)
+            .set_max_sets((1 + SYSTEM_UNIFORM_SETS) * MAX_FRAMES_IN_FLIGHT as u32)
+            .add_pool_size(ash::vk::DescriptorType::UNIFORM_BUFFER, (1 + SYSTEM_UNIFORM_SETS) * MAX_FRAMES_IN_FLIGHT as u32)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 3 * MAX_FRAMES_IN_FLIGHT as u32)
             .build()?;
 
         let mut ubo_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
@@ -54,32 +135,53 @@ impl Application {
                 ash::vk::MemoryPropertyFlags::HOST_VISIBLE,
             )?;
 
-            buffer.map(0)?;
+            buffer.map_persistent()?;
 
             ubo_buffers.push(buffer);
         }
 
         let global_set_layout = DescriptorSetLayout::new(renderer.device.clone())
             .add_binding(0, ash::vk::DescriptorType::UNIFORM_BUFFER, ash::vk::ShaderStageFlags::ALL_GRAPHICS, 1)
+            .add_binding(1, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(2, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(3, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
             .build()?;
 
+        let irradiance_map = Self::load_irradiance_map(device.clone(), &settings)?;
+        let occlusion_placeholder = Texture::new_linear(device.clone(), &[255; 4], 1, 1)?;
+
         let mut global_descriptor_sets = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
         for i in 0..MAX_FRAMES_IN_FLIGHT {
             let buffer_info = ubo_buffers[i].descriptor_info();
             let set = DescriptorSetWriter::new(global_set_layout.clone(), global_pool.clone())
                 .write_to_buffer(0, &[buffer_info])
+                .write_image(1, &[irradiance_map.descriptor_info()])
+                .write_image(2, &[occlusion_placeholder.descriptor_info()])
+                .write_image(3, &[occlusion_placeholder.descriptor_info()])
                 .build().unwrap();
 
             global_descriptor_sets.push(set);
         }
 
-        let pipeline_cache = PipelineCache::new(device.clone())?;
+        let pipeline_cache = if settings.persistent_pipeline_cache {
+            PipelineCache::new(device.clone())?
+        } else {
+            PipelineCache::new_transient(device.clone())?
+        };
 
         let simple_render_system = SimpleRenderSystem::new(
             device.clone(),
             &renderer.get_swapchain_render_pass(),
             &[global_set_layout.layout],
             &pipeline_cache,
+            settings.recording_threads,
+        )?;
+
+        let skinned_render_system = SkinnedRenderSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
         )?;
 
         let point_light_system = PointLightSystem::new(
@@ -89,69 +191,349 @@ impl Application {
             &pipeline_cache,
         )?;
 
-        let game_objects = Self::load_game_objects(device.clone())?;
+        let overdraw_system = OverdrawSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+        )?;
+
+        let gbuffer_system = GBufferSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+        )?;
+
+        let shadow_caster_system = ShadowCasterSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &pipeline_cache,
+            SHADOW_MAP_SIZE,
+        )?;
+
+        let picking_system = PickingSystem::new(
+            device.clone(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+        )?;
+
+        let wireframe_system = WireframeSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+        )?;
+
+        let bounds_debug_system = BoundsDebugSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+        )?;
+
+        let mut decal_system = DecalSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+            Self::load_decal_texture(device.clone(), &settings)?,
+            settings.decals.depth_bias,
+            settings.decals.mip_lod_bias,
+        )?;
+
+        // Placed by hand on the floor, which faces -y
+        decal_system.add(Decal::from_hit(glam::vec3(1.5, 0.5, -4.0), -glam::Vec3::Y, 0.75));
+        decal_system.add(Decal {
+            rotation: std::f32::consts::FRAC_PI_4,
+            color: glam::vec4(0.4, 0.1, 0.1, 0.8),
+            ..Decal::from_hit(glam::vec3(-1.5, 0.5, -6.0), -glam::Vec3::Y, 1.0)
+        });
+
+        let gizmo_system = GizmoSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &pipeline_cache,
+        )?;
+
+        let ssao_system = SsaoSystem::new(
+            device.clone(),
+            &[global_set_layout.layout],
+            &global_pool,
+            &pipeline_cache,
+        )?;
+
+        let post_process_uniforms = FrameUniforms::new(device.clone(), &global_pool, ash::vk::ShaderStageFlags::FRAGMENT)?;
+
+        let taa_system = TaaSystem::new(device.clone(), post_process_uniforms.set_layout.layout, &pipeline_cache)?;
+
+        let fullscreen_effect_system = match settings.fullscreen_effect {
+            Some(fragment_shader) => Some(FullscreenEffectSystem::new(
+                device.clone(),
+                &[global_set_layout.layout],
+                post_process_uniforms.set_layout.layout,
+                fragment_shader,
+            )?),
+            None => None,
+        };
+
+        let oit_system = OitSystem::new(
+            device.clone(),
+            &renderer.get_swapchain_render_pass(),
+            &[global_set_layout.layout],
+            &pipeline_cache,
+        )?;
+
+        let depth_view_system = DepthViewSystem::new(
+            device.clone(),
+            &pipeline_cache,
+            shadow_caster_system.shadow_map_view(),
+            ash::vk::Extent2D { width: DEPTH_VIEW_SIZE, height: DEPTH_VIEW_SIZE },
+        )?;
+
+        let reflection_system = ReflectionSystem::new(
+            device.clone(),
+            &global_set_layout,
+            &irradiance_map,
+            &occlusion_placeholder,
+            settings.reflections.resolution_scale,
+        )?;
+
+        let debug_text_system = if settings.debug_text.enabled && !settings.enable_egui {
+            Some(DebugTextSystem::new(
+                device.clone(),
+                &renderer.get_swapchain_render_pass(),
+                &pipeline_cache,
+            )?)
+        } else {
+            None
+        };
+
+        let mut model_cache = ModelCache::new(device.clone());
+        let game_objects = Self::load_game_objects(device.clone(), &mut model_cache, &settings)?;
 
         let mut viewer_object = GameObject::new(
             None,
             None,
             None,
-        );
+        )?;
 
-        viewer_object.transform.translation.z = -2.5;
+        viewer_object.transform.set_translation(settings.camera.translation);
+        viewer_object.transform.set_rotation(settings.camera.rotation);
 
         let camera_controller = KeyboardMovementController::new(Some(2.0), Some(2.0));
 
-        let egui_integration = EGuiIntegration::new(
-            &window,
-            device.clone(),
-            &renderer.swapchain,
-            renderer.swapchain.swapchain_image_format,
-            &pipeline_cache,
-        )?;
+        let mut egui_integration = if settings.enable_egui {
+            Some(EGuiIntegration::new(
+                &window,
+                device.clone(),
+                &renderer.swapchain,
+                renderer.swapchain.swapchain_image_format,
+                &pipeline_cache,
+            )?)
+        } else {
+            None
+        };
+
+        let depth_view_texture = match &mut egui_integration {
+            Some(egui_integration) => Some(egui_integration.register_user_texture(depth_view_system.color_view())?),
+            None => None,
+        };
 
         let application = Self {
+            settings,
             pipeline_cache,
             egui_integration,
             point_light_system,
             simple_render_system,
+            skinned_render_system,
+            overdraw_system,
+            gbuffer_system,
+            shadow_caster_system,
+            picking_system,
+            wireframe_system,
+            bounds_debug_system,
+            decal_system,
+            gizmo_system,
+            ssao_system,
+            taa_system,
+            fullscreen_effect_system,
+            reflection_system,
+            oit_system,
+            depth_view_system,
+            depth_view_texture,
+            debug_text_system,
             renderer,
             window,
             game_objects,
+            model_cache,
             viewer_object,
             camera_controller,
             global_pool,
             global_set_layout,
             global_descriptor_sets,
             ubo_buffers,
+            post_process_uniforms,
+            pending_resize: None,
+            occluded: false,
+            reload_shaders_requested: false,
+            redraw_frames: SETTLE_FRAMES,
+            frame_dropped: false,
+            pick_requested: false,
+            picked_object: None,
+            ui_visible: true,
+            ui_toggle_held: false,
+            modifiers: winit::event::ModifiersState::empty(),
+            shader_error: None,
+            elapsed_time: 0.0,
+            _irradiance_map: irradiance_map,
+            occlusion_placeholder,
+            occlusion_bound: false,
+            reflection_bound: false,
         };
 
         Ok((application, event_loop))
     }
 
     pub fn update(&mut self, event: &winit::event::WindowEvent) {
-        self.egui_integration.on_event(event);
+        self.redraw_frames = SETTLE_FRAMES;
+
+        if let winit::event::WindowEvent::ModifiersChanged(modifiers) = event {
+            self.modifiers = *modifiers;
+        }
+
+        if let winit::event::WindowEvent::KeyboardInput { input, .. } = event {
+            let pressed = input.state == winit::event::ElementState::Pressed;
+
+            if input.virtual_keycode.is_some() && input.virtual_keycode == self.settings.ui_toggle_key {
+                if pressed && !self.ui_toggle_held {
+                    self.ui_visible = !self.ui_visible;
+                }
+
+                self.ui_toggle_held = pressed;
+            }
+
+            if pressed && self.modifiers.ctrl() {
+                use winit::event::VirtualKeyCode;
+
+                let step = match input.virtual_keycode {
+                    Some(VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd) => UI_SCALE_STEP,
+                    Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) => -UI_SCALE_STEP,
+                    _ => 0.0,
+                };
+
+                self.settings.ui_scale = (self.settings.ui_scale + step).clamp(*UI_SCALE_RANGE.start(), *UI_SCALE_RANGE.end());
+            }
+        }
+
+        // A hidden UI doesn't take any input, but has to follow scale factor
+        // changes to be laid out right once shown again
+        let consumed = match &mut self.egui_integration {
+            Some(egui_integration) if self.ui_visible || matches!(event, winit::event::WindowEvent::ScaleFactorChanged { .. }) => egui_integration.on_event(event),
+            _ => false,
+        };
+
+        if let winit::event::WindowEvent::MouseInput {
+            state: winit::event::ElementState::Pressed,
+            button: winit::event::MouseButton::Left,
+            ..
+        } = event {
+            // Clicks on the UI don't select anything
+            self.pick_requested |= !consumed;
+        }
     }
 
     pub fn run(
         &mut self,
         input: &Input,
         frame_time: f32,
-        fps: usize,
+        frame_stats: &FrameStats,
     ) -> anyhow::Result<(), ApplicationError> {
+        if let Some(requested) = self.pending_resize {
+            if requested.elapsed() >= RESIZE_DEBOUNCE {
+                self.pending_resize = None;
+                self.resize()?;
+            }
+        }
+
+        if self.settings.render_scale != self.renderer.render_scale() {
+            self.renderer.set_render_scale(self.settings.render_scale)?;
+            self.settings.render_scale = self.renderer.render_scale();
+        }
+
+        let fixed_resolution = self.settings.fixed_resolution.enabled.then_some(ash::vk::Extent2D {
+            width: self.settings.fixed_resolution.dimensions.width,
+            height: self.settings.fixed_resolution.dimensions.height,
+        });
+
+        if fixed_resolution != self.renderer.fixed_resolution() {
+            self.renderer.set_fixed_resolution(fixed_resolution)?;
+        }
+
+        self.renderer.set_letterbox_color(self.settings.fixed_resolution.letterbox_color.extend(1.0).to_array());
+
+        if let Some(egui_integration) = &mut self.egui_integration {
+            if self.settings.ui_scale != egui_integration.ui_scale() {
+                egui_integration.set_ui_scale(self.settings.ui_scale);
+            }
+        }
+
+        if self.settings.decals.mip_lod_bias != self.decal_system.mip_lod_bias() {
+            let max_lod_bias = self.renderer.device.properties.limits.max_sampler_lod_bias;
+            self.settings.decals.mip_lod_bias = self.settings.decals.mip_lod_bias.clamp(-max_lod_bias, max_lod_bias);
+            self.decal_system.set_mip_lod_bias(self.settings.decals.mip_lod_bias)?;
+        }
+
+        if Self::resolves_scene(&self.settings) != self.renderer.resolve_scene() {
+            self.renderer.set_resolve_scene(Self::resolves_scene(&self.settings))?;
+        }
+
+        if self.is_log_depth_active() != self.simple_render_system.log_depth() {
+            self.set_log_depth(self.is_log_depth_active())?;
+        }
+
+        if self.reload_shaders_requested {
+            self.reload_shaders_requested = false;
+            self.reload_shaders()?;
+        }
+
+        self.update_occlusion_map()?;
+        self.update_reflection_map()?;
+
+        self.elapsed_time += frame_time;
+
+        for animation in self.game_objects.values_mut().filter_map(|obj| obj.animation.as_mut()) {
+            animation.advance(frame_time);
+        }
+
         let aspect = self.renderer.get_aspect_ratio();
 
-        self.camera_controller.move_in_plane_xz(
-            input,
-            frame_time,
-            &mut self.viewer_object,
-        );
-
-        let camera = CameraBuilder::new()
-            .set_view_xyz(
-                self.viewer_object.transform.translation,
-                self.viewer_object.transform.rotation,
-            )
-            .set_perspective_projection(50_f32.to_radians(), aspect, 0.1, 100.0)
+        if self.settings.camera.six_dof {
+            self.camera_controller.move_free(input, frame_time, &mut self.viewer_object);
+        } else {
+            self.camera_controller.move_in_plane_xz(input, frame_time, &mut self.viewer_object);
+        }
+
+        let jitter = if self.is_taa_active() {
+            self.taa_system.jitter(self.renderer.get_scene_extent())
+        } else {
+            glam::Vec2::ZERO
+        };
+
+        let mut camera_builder = CameraBuilder::new();
+
+        match self.camera_controller.orientation() {
+            Some(orientation) => camera_builder.set_view_quat(self.viewer_object.transform.translation(), orientation),
+            None => camera_builder.set_view_xyz(self.viewer_object.transform.translation(), self.viewer_object.transform.rotation()),
+        };
+
+        match self.settings.camera.projection {
+            ProjectionMode::Perspective => camera_builder.set_perspective_projection(50_f32.to_radians(), aspect, NEAR_PLANE, FAR_PLANE),
+            ProjectionMode::Orthographic => camera_builder.set_orthographic_view(self.settings.camera.ortho_view_size, aspect, NEAR_PLANE, FAR_PLANE),
+        };
+
+        let camera = camera_builder
+            .set_jitter(jitter)
             .build();
 
         let extent = Renderer::get_window_extent(&self.window);
@@ -160,6 +542,13 @@ impl Application {
             return Ok(());
         }
 
+        self.frame_dropped = false;
+
+        let debug_text = self.debug_text_lines(frame_stats);
+
+        // Anything requesting another frame while drawing this one sets it again
+        self.redraw_frames = self.redraw_frames.saturating_sub(1);
+
         Ok(match self.renderer.begin_frame(&self.window)? {
             Some(command_buffer) => {
                 let frame_index = self.renderer.get_frame_index();
@@ -167,128 +556,1038 @@ impl Application {
                     frame_index,
                     frame_time,
                     command_buffer,
+                    extent: self.renderer.get_scene_extent(),
                     camera,
                     game_objects: &self.game_objects,
                     global_descriptor_set: self.global_descriptor_sets[frame_index],
+                    post_process_descriptor_set: self.post_process_uniforms.descriptor_set(frame_index),
                 };
 
                 // update
                 let mut ubo = GlobalUbo {
                     projection: frame_info.camera.projection_matrix,
                     view: frame_info.camera.view_matrix,
-                    ambient_light_color: glam::vec4(1.0, 1.0, 1.0, 0.02),
+                    ambient_light_color: self.settings.environment.ambient_light_color,
                     point_lights: [PointLight { position: Default::default(), color: Default::default() }; MAX_LIGHTS],
                     num_lights: 0,
+                    time: self.elapsed_time,
+                    resolution: {
+                        let extent = self.renderer.get_scene_extent();
+                        glam::vec2(extent.width as f32, extent.height as f32)
+                    },
+                    ambient_pulse: if self.settings.pulse_ambient { 0.5 } else { 0.0 },
+                    irradiance_intensity: match self.settings.environment.irradiance_map {
+                        Some(_) => self.settings.environment.irradiance_intensity,
+                        None => 0.0,
+                    },
+                    ambient_occlusion: if self.settings.ssao.enabled { self.settings.ssao.intensity } else { 0.0 },
+                    log_depth_coefficient: if self.is_log_depth_active() { 1.0 / (FAR_PLANE + 1.0).log2() } else { 0.0 },
+                    fog_color: {
+                        let environment = &self.settings.environment;
+                        let density = if environment.fog_enabled { environment.fog_density } else { 0.0 };
+                        environment.fog_color.extend(density)
+                    },
+                    exposure: self.settings.exposure,
+                    reflections: if self.reflection_bound { 1.0 } else { 0.0 },
                 };
 
                 self.point_light_system.update(&frame_info, &mut ubo);
 
-                self.ubo_buffers[frame_index].write_to_buffer(&[ubo]);
+                self.renderer.validate_frame_resources(frame_index)?;
+
+                self.ubo_buffers[frame_index].map(0)?.write_slice(std::slice::from_ref(&ubo));
                 self.ubo_buffers[frame_index].flush()?;
 
+                self.post_process_uniforms.write(frame_index, PostProcessUbo {
+                    taa_feedback: self.settings.taa.feedback,
+                    vignette: self.settings.post_process.vignette,
+                })?;
+
+                // The frame's fence was waited for, its last readback is done
+                if let Some(picked_object) = self.picking_system.take_result(frame_index)? {
+                    log::info!("Picked object: {:?}", picked_object);
+                    self.picked_object = picked_object;
+                }
+
+                if std::mem::take(&mut self.pick_requested) {
+                    if let Some(cursor) = input.cursor_position() {
+                        // The scene may be rendered at another resolution than the window
+                        // and letterboxed inside of it
+                        let scene_extent = self.renderer.get_scene_extent();
+                        let [min, max] = self.renderer.scene_region();
+                        let x = (cursor.x - min.x as f32) / (max.x - min.x) as f32;
+                        let y = (cursor.y - min.y as f32) / (max.y - min.y) as f32;
+
+                        if (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y) {
+                            let pixel = ((x * scene_extent.width as f32) as u32, (y * scene_extent.height as f32) as u32);
+
+                            self.picking_system.pick(&frame_info, scene_extent, pixel)?;
+                        }
+                    }
+                }
+
                 // render
-                self.renderer.begin_swapchain_render_pass(command_buffer);
+                if self.settings.ssao.enabled {
+                    self.ssao_system.render(&frame_info, &self.settings.ssao)?;
+                }
+
+                if self.reflection_bound && self.settings.debug_view == DebugView::None {
+                    self.reflection_system.render(&frame_info, &ubo)?;
+                }
+
+                let weighted_transparency = self.settings.transparency == TransparencyMode::WeightedBlended && self.settings.debug_view == DebugView::None;
+
+                if weighted_transparency {
+                    self.oit_system.accumulate(&frame_info, self.renderer.get_scene_extent())?;
+                }
+
+                let depth_window_open = self.settings.depth_window.open && self.depth_view_texture.is_some() && self.ui_visible;
+
+                if self.settings.debug_view == DebugView::ShadowDepth || depth_window_open {
+                    // Without lights the camera stands in, so the view still shows something
+                    let light_position = frame_info.game_objects
+                        .values()
+                        .find(|obj| obj.point_light.is_some())
+                        .map_or(frame_info.camera.view_matrix.inverse().w_axis.truncate(), |obj| obj.transform.translation());
+
+                    self.shadow_caster_system.render(&frame_info, ShadowCasterSystem::point_light_view_projection(light_position));
+                }
+
+                if depth_window_open {
+                    let depth_window = &self.settings.depth_window;
+                    self.depth_view_system.render(&frame_info, depth_window.linear, depth_window.near, depth_window.far);
+                }
+
+                if self.settings.debug_view != DebugView::None {
+                    match self.settings.debug_view {
+                        DebugView::Overdraw => self.overdraw_system.accumulate(&frame_info, self.renderer.get_scene_extent())?,
+                        DebugView::GBuffer => self.gbuffer_system.render(&frame_info, self.renderer.get_scene_extent())?,
+                        DebugView::ShadowDepth | DebugView::None => {},
+                    }
+
+                    self.renderer.begin_swapchain_render_pass(command_buffer, ash::vk::SubpassContents::INLINE);
+
+                    match self.settings.debug_view {
+                        DebugView::Overdraw => self.overdraw_system.render(&frame_info),
+                        DebugView::GBuffer => self.gbuffer_system.render_debug(&frame_info),
+                        DebugView::ShadowDepth => self.shadow_caster_system.render_debug(&frame_info),
+                        DebugView::None => {},
+                    }
+
+                    self.gizmo_system.render(&frame_info, self.renderer.get_scene_extent(), &self.settings.gizmo);
+                    if let Some(debug_text_system) = &mut self.debug_text_system {
+                        debug_text_system.render(&frame_info, self.renderer.get_scene_extent(), &debug_text, &self.settings.debug_text)?;
+                    }
+                } else if self.settings.recording_threads > 0 {
+                    self.renderer.begin_swapchain_render_pass(command_buffer, ash::vk::SubpassContents::SECONDARY_COMMAND_BUFFERS);
+
+                    self.simple_render_system.render_parallel(
+                        &frame_info,
+                        self.renderer.secondary_recording_info(),
+                        self.settings.backface_debug,
+                        !weighted_transparency,
+                    )?;
+
+                    // The pass only accepts secondary command buffers now
+                    let secondary_command_buffer = self.renderer.begin_secondary_command_buffer()?;
+
+                    let secondary_frame_info = FrameInfo {
+                        command_buffer: secondary_command_buffer,
+                        ..frame_info
+                    };
+
+                    self.skinned_render_system.render(&secondary_frame_info)?;
+                    if self.settings.decals.enabled {
+                        self.decal_system.render(&secondary_frame_info);
+                    }
+                    if weighted_transparency {
+                        self.oit_system.composite(&secondary_frame_info);
+                    }
+                    self.wireframe_system.render(&secondary_frame_info, &self.settings.wireframe, self.picked_object);
+                    self.bounds_debug_system.render(&secondary_frame_info, self.settings.show_bounds);
+                    self.point_light_system.render(&secondary_frame_info, self.settings.light_billboard_segments);
+                    self.gizmo_system.render(&secondary_frame_info, self.renderer.get_scene_extent(), &self.settings.gizmo);
+                    if let Some(debug_text_system) = &mut self.debug_text_system {
+                        debug_text_system.render(&secondary_frame_info, self.renderer.get_scene_extent(), &debug_text, &self.settings.debug_text)?;
+                    }
+
+                    self.renderer.end_secondary_command_buffer(secondary_command_buffer)?;
+                } else {
+                    self.renderer.begin_swapchain_render_pass(command_buffer, ash::vk::SubpassContents::INLINE);
+
+                    self.simple_render_system.render(
+                        &frame_info,
+                        self.settings.backface_debug,
+                        !weighted_transparency,
+                    );
+
+                    self.skinned_render_system.render(&frame_info)?;
+
+                    if self.settings.decals.enabled {
+                        self.decal_system.render(&frame_info);
+                    }
+
+                    if weighted_transparency {
+                        self.oit_system.composite(&frame_info);
+                    }
+
+                    self.wireframe_system.render(&frame_info, &self.settings.wireframe, self.picked_object);
+
+                    self.bounds_debug_system.render(&frame_info, self.settings.show_bounds);
+
+                    self.point_light_system.render(
+                        &frame_info,
+                        self.settings.light_billboard_segments,
+                    );
+
+                    self.gizmo_system.render(&frame_info, self.renderer.get_scene_extent(), &self.settings.gizmo);
+                    if let Some(debug_text_system) = &mut self.debug_text_system {
+                        debug_text_system.render(&frame_info, self.renderer.get_scene_extent(), &debug_text, &self.settings.debug_text)?;
+                    }
+                }
+
+                self.renderer.end_swapchain_render_pass(command_buffer)?;
+
+                let scene_resolve_active = self.renderer.is_scene_resolve_active();
+
+                if let Some(fullscreen_effect_system) = self.fullscreen_effect_system.as_mut().filter(|_| scene_resolve_active) {
+                    let scene_target = self.renderer.scene_target().unwrap();
+                    let resolved = fullscreen_effect_system.render(&frame_info, scene_target, &self.pipeline_cache)?;
+                    self.renderer.blit_to_swapchain(command_buffer, resolved)?;
+                } else if self.is_taa_active() {
+                    let scene_target = self.renderer.scene_target().unwrap();
+                    let resolved = self.taa_system.resolve(&frame_info, scene_target)?;
+                    self.renderer.blit_to_swapchain(command_buffer, resolved)?;
+                }
+
+                if !self.ui_visible {
+                    // Still transitions the swapchain image for presenting
+                    if let Some(egui_integration) = &mut self.egui_integration {
+                        egui_integration.paint(command_buffer, self.renderer.get_image_index(), Vec::new())?;
+                    }
+                } else if self.egui_integration.is_some() {
+                    let egui_ctx = {
+                        let egui_integration = self.egui_integration.as_mut().unwrap();
+                        egui_integration.begin_frame(&self.window);
+                        egui_integration.egui_ctx.clone()
+                    };
+
+                    self.build_ui(&egui_ctx, frame_stats);
+
+                    let egui_integration = self.egui_integration.as_mut().unwrap();
+                    let (shapes, needs_repaint) = egui_integration.end_frame(&mut self.window);
+                    if needs_repaint {
+                        self.redraw_frames = self.redraw_frames.max(1);
+                    }
+
+                    let clipped_meshes = egui_integration.egui_ctx.tessellate(shapes);
+
+                    egui_integration
+                        .paint(command_buffer, self.renderer.get_image_index(), clipped_meshes)?;
+                }
 
-                self.simple_render_system.render(
-                    &frame_info,
-                );
+                self.renderer.end_frame()?;
+            }
+            None => {
+                // The renderer had to rebuild an out of date swapchain
+                self.frame_dropped = true;
+                self.update_swapchain_dependents()?;
+            }
+        })
+    }
 
-                self.point_light_system.render(
-                    &frame_info,
-                );
+    /// Statistics for the debug text overlay, empty without one.
+    fn debug_text_lines(&self, frame_stats: &FrameStats) -> Vec<String> {
+        if self.debug_text_system.is_none() {
+            return Vec::new();
+        }
 
-                self.renderer.end_swapchain_render_pass(command_buffer);
+        let extent = self.renderer.get_scene_extent();
 
-                self.egui_integration.begin_frame(&self.window);
+        vec![
+            format!("FPS: {:.0} ({} last second, {} dropped)", frame_stats.smoothed_fps(), frame_stats.fps, frame_stats.dropped),
+            format!("Frame: {:.2} ms", frame_stats.smoothed_frame_time * 1000.0),
+            format!("Objects: {}", self.game_objects.len()),
+            format!("Resolution: {}x{}", extent.width, extent.height),
+        ]
+    }
 
-                egui::TopBottomPanel::top("top_panel").show(&self.egui_integration.egui_ctx, |ui| {
-                    egui::menu::bar(ui, |ui| {
-                        ui.menu_button("File", |ui| {
-                            if ui.button("Test").clicked() {
-                                
-                            }
-                        });
-                    });
+    fn build_ui(&mut self, egui_ctx: &egui::CtxRef, frame_stats: &FrameStats) {
+        egui::TopBottomPanel::top("top_panel").show(egui_ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Test").clicked() {
+                        
+                    }
+
+                    if ui.button("Reload shaders").clicked() {
+                        // Pipelines can't be swapped while this frame is being recorded
+                        self.reload_shaders_requested = true;
+                    }
                 });
+            });
+        });
+
+        egui::SidePanel::left("my_side_panel").show(egui_ctx, |ui| {
+            ui.heading("Gentoo");
+            ui.separator();
+            egui::ComboBox::from_label("Window Mode")
+                .selected_text(format!("{:?}", self.window.mode))
+                .show_ui(ui, |ui| {
+                    if ui.selectable_value(&mut self.window.mode, WindowMode::Windowed, "Windowed").clicked() {
+                        self.window.update_mode();
+                    }
+
+                    if ui.selectable_value(&mut self.window.mode, WindowMode::Borderless, "Borderless").clicked() {
+                        self.window.update_mode();
+                    }
+
+                    if ui.selectable_value(&mut self.window.mode, WindowMode::Exclusive, "Exclusive").clicked() {
+                        self.window.update_mode();
+                    }
+                });
+
+            if self.window.monitor.is_none() {
+                self.window.monitor = self.window.current_monitor();
+            }
 
-                egui::SidePanel::left("my_side_panel").show(&self.egui_integration.egui_ctx, |ui| {
-                    ui.heading("Gentoo");
-                    ui.separator();
-                    egui::ComboBox::from_label("Window Mode")
-                        .selected_text(format!("{:?}", self.window.mode))
-                        .show_ui(ui, |ui| {
-                            if ui.selectable_value(&mut self.window.mode, WindowMode::Windowed, "Windowed").clicked() {
+            egui::ComboBox::from_label("Monitor")
+                .selected_text(self.window.monitor.as_ref().map_or("None", |monitor| &monitor.name))
+                .show_ui(ui, |ui| {
+                    for monitor in self.window.available_monitors() {
+                        let name = monitor.name.clone();
+                        if ui.selectable_value(&mut self.window.monitor, Some(monitor), name).clicked() {
+                            // The previous mode belongs to another monitor
+                            self.window.exclusive_video_mode = None;
+
+                            if self.window.mode != WindowMode::Windowed {
                                 self.window.update_mode();
                             }
+                        }
+                    }
+                });
 
-                            if ui.selectable_value(&mut self.window.mode, WindowMode::Borderless, "Borderless").clicked() {
-                                self.window.update_mode();
+            egui::ComboBox::from_label("Video mode")
+                .selected_text(self.window.exclusive_video_mode.as_ref().map_or("Best".to_string(), |mode| mode.to_string()))
+                .show_ui(ui, |ui| {
+                    let video_modes = self.window.monitor.as_ref().map(Window::video_modes).unwrap_or_default();
+
+                    if video_modes.is_empty() {
+                        ui.label("No video modes");
+                    }
+
+                    for video_mode in video_modes {
+                        let label = video_mode.to_string();
+                        if ui.selectable_value(&mut self.window.exclusive_video_mode, Some(video_mode), label).clicked()
+                            && self.window.mode == WindowMode::Exclusive
+                        {
+                            self.window.update_mode();
+                        }
+                    }
+                });
+            ui.end_row();
+            ui.separator();
+            ui.label(format!("FPS: {:.0} ({:.2} ms)", frame_stats.smoothed_fps(), frame_stats.smoothed_frame_time * 1000.0));
+            ui.label(format!("Frames last second: {}", frame_stats.fps));
+
+            if frame_stats.dropped > 0 {
+                ui.colored_label(egui::Color32::YELLOW, format!("Dropped last second: {}", frame_stats.dropped));
+            }
+
+            for heap in self.renderer.device.memory_budget().iter().filter(|heap| heap.device_local) {
+                const MIB: f32 = 1024.0 * 1024.0;
+
+                match heap.usage {
+                    Some(usage) => {
+                        ui.add(egui::ProgressBar::new(usage as f32 / heap.budget as f32)
+                            .text(format!("VRAM: {:.0} / {:.0} MiB", usage as f32 / MIB, heap.budget as f32 / MIB)));
+                    }
+                    None => {
+                        ui.label(format!("VRAM: {:.0} MiB", heap.budget as f32 / MIB));
+                    }
+                }
+            }
+
+            ui.label(format!("Cached models: {}", self.model_cache.model_count()));
+
+            ui.label(match self.picked_object {
+                Some(id) => format!("Picked: Object {}", id),
+                None => "Picked: None".to_string(),
+            });
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("Reset camera").clicked() {
+                    self.reset_camera();
+                }
+                if ui.button("Recreate swapchain").clicked() {
+                    self.renderer.request_recreate();
+                }
+            });
+            ui.checkbox(&mut self.settings.camera.six_dof, "Six degrees of freedom camera");
+            egui::ComboBox::from_label("Projection")
+                .selected_text(format!("{:?}", self.settings.camera.projection))
+                .show_ui(ui, |ui| {
+                    for mode in ProjectionMode::ALL {
+                        ui.selectable_value(&mut self.settings.camera.projection, mode, format!("{:?}", mode));
+                    }
+                });
+            if self.settings.camera.projection == ProjectionMode::Orthographic {
+                ui.add(egui::Slider::new(&mut self.settings.camera.ortho_view_size, 0.5..=50.0).logarithmic(true).text("View size"));
+            }
+            ui.checkbox(&mut self.settings.pulse_ambient, "Pulse ambient");
+            ui.checkbox(&mut self.settings.redraw_on_demand, "Redraw on demand");
+            ui.checkbox(&mut self.settings.logarithmic_depth, "Logarithmic depth");
+            ui.add(egui::Slider::new(&mut self.settings.exposure, 0.1..=10.0).logarithmic(true).text("Exposure"));
+
+            ui.checkbox(&mut self.settings.backface_debug, "Highlight back faces");
+            ui.checkbox(&mut self.settings.show_bounds, "Bounding boxes");
+            ui.horizontal(|ui| {
+                let mut fan = self.settings.light_billboard_segments.is_some();
+                if ui.checkbox(&mut fan, "Light fans").changed() {
+                    self.settings.light_billboard_segments = fan.then_some(16);
+                }
+                if let Some(segments) = &mut self.settings.light_billboard_segments {
+                    ui.add(egui::Slider::new(segments, MIN_BILLBOARD_SEGMENTS..=MAX_BILLBOARD_SEGMENTS).text("Segments"));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.settings.decals.enabled, format!("Decals ({})", self.decal_system.decal_count()));
+                if ui.button("Clear").clicked() {
+                    self.decal_system.clear();
+                }
+            });
+            if self.settings.decals.enabled {
+                let max_lod_bias = self.renderer.device.properties.limits.max_sampler_lod_bias;
+                // Applied at the start of the next frame, the sampler is in use until then
+                ui.add(egui::Slider::new(&mut self.settings.decals.mip_lod_bias, -max_lod_bias..=max_lod_bias).text("Decal mip bias"));
+            }
+            ui.checkbox(&mut self.settings.wireframe.enabled, "Wireframe overlay");
+            if self.settings.wireframe.enabled {
+                ui.checkbox(&mut self.settings.wireframe.selected_only, "Picked object only");
+
+                let mut color = self.settings.wireframe.color.to_array();
+                ui.horizontal(|ui| {
+                    if ui.color_edit_button_rgba_premultiplied(&mut color).changed() {
+                        self.settings.wireframe.color = glam::Vec4::from(color);
+                    }
+                    ui.label("Edge color");
+                });
+            }
+
+            // Applied at the start of the next frame, the scene target is in use until then
+            ui.checkbox(&mut self.settings.fixed_resolution.enabled, "Fixed resolution");
+            if self.settings.fixed_resolution.enabled {
+                let fixed_resolution = &mut self.settings.fixed_resolution;
+                ui.add(egui::Slider::new(&mut fixed_resolution.dimensions.width, 64..=1920).text("Width"));
+                ui.add(egui::Slider::new(&mut fixed_resolution.dimensions.height, 64..=1080).text("Height"));
+
+                let mut letterbox_color = fixed_resolution.letterbox_color.to_array();
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut letterbox_color);
+                    ui.label("Letterbox color");
+                });
+                fixed_resolution.letterbox_color = glam::Vec3::from(letterbox_color);
+            } else {
+                ui.add(egui::Slider::new(&mut self.settings.render_scale, 0.5..=MAX_RENDER_SCALE).text("Render scale"));
+            }
+            // Applied at the start of the next frame, dragging it would move the slider under the cursor
+            ui.add(egui::Slider::new(&mut self.settings.ui_scale, UI_SCALE_RANGE).text("UI scale"));
+
+            let preset = AntiAliasingPreset::matching(&self.settings);
+            egui::ComboBox::from_label("Anti-aliasing")
+                .selected_text(preset.map_or("Custom".to_string(), |preset| format!("{:?}", preset)))
+                .show_ui(ui, |ui| {
+                    for option in AntiAliasingPreset::ALL {
+                        if ui.selectable_label(preset == Some(option), format!("{:?}", option)).clicked() {
+                            option.apply(&mut self.settings);
+                        }
+                    }
+                });
+
+            ui.checkbox(&mut self.settings.taa.enabled, "Temporal AA");
+            if self.settings.taa.enabled {
+                ui.add(egui::Slider::new(&mut self.settings.taa.feedback, 0.5..=0.98).text("TAA history weight"));
+            }
+
+            if self.fullscreen_effect_system.is_some() {
+                ui.add(egui::Slider::new(&mut self.settings.post_process.vignette, 0.0..=1.0).text("Vignette"));
+            }
+
+            if self.settings.environment.irradiance_map.is_some() {
+                ui.add(egui::Slider::new(&mut self.settings.environment.irradiance_intensity, 0.0..=2.0).text("Irradiance"));
+            }
+
+            ui.checkbox(&mut self.settings.environment.fog_enabled, "Fog");
+            if self.settings.environment.fog_enabled {
+                let mut fog_color = self.settings.environment.fog_color.to_array();
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(&mut fog_color);
+                    ui.label("Fog color");
+                });
+                self.settings.environment.fog_color = glam::Vec3::from(fog_color);
+
+                ui.add(egui::Slider::new(&mut self.settings.environment.fog_density, 0.0..=1.0).text("Fog density"));
+            }
+
+            ui.checkbox(&mut self.settings.ssao.enabled, "SSAO");
+            if self.settings.ssao.enabled {
+                ui.add(egui::Slider::new(&mut self.settings.ssao.radius, 0.05..=2.0).text("SSAO radius"));
+                ui.add(egui::Slider::new(&mut self.settings.ssao.intensity, 0.0..=1.0).text("SSAO intensity"));
+            }
+
+            ui.checkbox(&mut self.settings.reflections.enabled, "Planar reflections");
+            if self.settings.reflections.enabled {
+                ui.add(egui::Slider::new(&mut self.settings.reflections.resolution_scale, 0.25..=1.0).text("Reflection resolution"));
+            }
+
+            egui::ComboBox::from_label("Transparency")
+                .selected_text(format!("{:?}", self.settings.transparency))
+                .show_ui(ui, |ui| {
+                    for mode in TransparencyMode::ALL {
+                        ui.selectable_value(&mut self.settings.transparency, mode, format!("{:?}", mode));
+                    }
+                });
+
+            ui.checkbox(&mut self.settings.gizmo.enabled, "Axis gizmo");
+            if self.settings.gizmo.enabled {
+                ui.add(egui::Slider::new(&mut self.settings.gizmo.size, 32..=256).text("Gizmo size"));
+
+                egui::ComboBox::from_label("Gizmo corner")
+                    .selected_text(format!("{:?}", self.settings.gizmo.corner))
+                    .show_ui(ui, |ui| {
+                        for corner in [ScreenCorner::TopLeft, ScreenCorner::TopRight, ScreenCorner::BottomLeft, ScreenCorner::BottomRight] {
+                            ui.selectable_value(&mut self.settings.gizmo.corner, corner, format!("{:?}", corner));
+                        }
+                    });
+            }
+
+            egui::ComboBox::from_label("Debug view")
+                .selected_text(format!("{:?}", self.settings.debug_view))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.settings.debug_view, DebugView::None, "None");
+                    ui.selectable_value(&mut self.settings.debug_view, DebugView::Overdraw, "Overdraw");
+                    ui.selectable_value(&mut self.settings.debug_view, DebugView::GBuffer, "G-buffer");
+                    ui.selectable_value(&mut self.settings.debug_view, DebugView::ShadowDepth, "Shadow depth");
+                });
+            if self.depth_view_texture.is_some() {
+                ui.checkbox(&mut self.settings.depth_window.open, "Shadow map window");
+            }
+            ui.separator();
+
+            egui::CollapsingHeader::new("Inspector")
+                .default_open(true)
+                .show(ui, |ui| {
+                    let light_count = self.game_objects.values().filter(|obj| obj.point_light.is_some()).count();
+
+                    // The ubo has room for MAX_LIGHTS lights
+                    if ui.add_enabled(light_count < MAX_LIGHTS, egui::Button::new("+ Add light")).clicked() {
+                        match GameObject::make_point_light(0.2, 0.1, glam::Vec3::ONE) {
+                            Ok(mut point_light) => {
+                                point_light.transform.set_translation(self.viewer_object.transform.translation());
+                                self.game_objects.insert(point_light.id, point_light);
                             }
+                            Err(err) => log::error!("Failed to add a light: {}", err),
+                        }
+                    }
+
+                    let edge_color = self.settings.wireframe.color;
+
+                    let mut ids = self.game_objects.keys().copied().collect::<Vec<_>>();
+                    ids.sort_unstable();
+
+                    let mut removed = None;
+
+                    for id in ids {
+                        let obj = self.game_objects.get_mut(&id).unwrap();
+
+                        let name = match obj.point_light {
+                            Some(_) => format!("Point Light {}", id),
+                            None => format!("Object {}", id),
+                        };
+
+                        egui::CollapsingHeader::new(name)
+                            .id_source(id)
+                            .show(ui, |ui| {
+                                ui.checkbox(&mut obj.visible, "Visible");
+
+                                ui.horizontal(|ui| {
+                                    for (name, layer) in RENDER_LAYERS {
+                                        let mut enabled = obj.render_mask & layer != 0;
+                                        if ui.checkbox(&mut enabled, name).changed() {
+                                            obj.render_mask ^= layer;
+                                        }
+                                    }
+                                });
+
+                                if obj.model.is_some() {
+                                    ui.checkbox(&mut obj.material.double_sided, "Double sided");
+                                    ui.checkbox(&mut obj.material.tessellated, "Tessellated");
+                                    ui.checkbox(&mut obj.material.unlit, "Unlit");
+                                    ui.add(egui::Slider::new(&mut obj.material.reflectivity, 0.0..=1.0).text("Reflectivity"));
+                                    ui.add(egui::Slider::new(&mut obj.material.opacity, 0.0..=1.0).text("Opacity"));
+
+                                    let mut in_front = obj.material.depth_range != FULL_DEPTH_RANGE;
+                                    if ui.checkbox(&mut in_front, "Draw in front").changed() {
+                                        obj.material.depth_range = if in_front { IN_FRONT_DEPTH_RANGE } else { FULL_DEPTH_RANGE };
+                                    }
+
+                                    egui::ComboBox::from_id_source(("depth_test", id))
+                                        .selected_text(format!("Depth test: {:?}", obj.material.depth_test))
+                                        .show_ui(ui, |ui| {
+                                            for depth_test in DepthTest::ALL {
+                                                ui.selectable_value(&mut obj.material.depth_test, depth_test, format!("{:?}", depth_test));
+                                            }
+                                        });
+
+                                    ui.checkbox(&mut obj.debug.wireframe, "Wireframe");
+                                    Self::color_override_ui(ui, &mut obj.debug.wireframe_color, edge_color, "Edge color");
+                                    ui.checkbox(&mut obj.debug.show_bounds, "Bounding box");
+                                    Self::color_override_ui(ui, &mut obj.debug.bounds_color, BOUNDS_COLOR, "Bounds color");
+                                }
+
+                                if let Some(animation) = &mut obj.animation {
+                                    ui.add(egui::Slider::new(&mut animation.speed, 0.0..=2.0).text("Animation speed"));
+                                }
+
+                                if obj.point_light.is_some() && ui.button("Remove light").clicked() {
+                                    removed = Some(id);
+                                }
+                            });
+                    }
+
+                    // The ubo light array is rebuilt every frame, so the light is off from the next one
+                    if let Some(id) = removed {
+                        self.game_objects.remove(&id);
+                    }
+                });
+        });
 
-                            // if ui.selectable_value(&mut self.window.mode, WindowMode::Exclusive, "Exclusive").clicked() {
-                            //     self.window.update_mode();
-                            // }
-                        });
-                    ui.end_row();
-                    ui.separator();
-                    ui.label(format!("FPS: {}", fps));
+        if let Some(texture_id) = self.depth_view_texture {
+            let depth_window = &mut self.settings.depth_window;
+            let extent = self.depth_view_system.extent();
+
+            egui::Window::new("Shadow map")
+                .open(&mut depth_window.open)
+                .resizable(false)
+                .show(egui_ctx, |ui| {
+                    ui.checkbox(&mut depth_window.linear, "Linear");
+                    if depth_window.linear {
+                        ui.add(egui::Slider::new(&mut depth_window.near, 0.01..=10.0).logarithmic(true).text("Near"));
+                        ui.add(egui::Slider::new(&mut depth_window.far, 1.0..=1000.0).logarithmic(true).text("Far"));
+                    }
+
+                    // The image is undefined until the first conversion
+                    if self.depth_view_system.has_rendered() {
+                        ui.image(texture_id, egui::vec2(extent.width as f32, extent.height as f32));
+                    }
                 });
+        }
 
-                let shapes = self.egui_integration.end_frame(&mut self.window);
-                let clipped_meshes = self.egui_integration.egui_ctx.tessellate(shapes);
+        let mut dismissed = false;
 
-                self.egui_integration
-                    .paint(command_buffer, self.renderer.get_image_index(), clipped_meshes)?;
+        if let Some(error) = &self.shader_error {
+            egui::Window::new("Shader error")
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 32.0))
+                .show(egui_ctx, |ui| {
+                    ui.colored_label(egui::Color32::RED, error);
+                    ui.label("The last working pipelines are still in use.");
 
-                self.renderer.end_frame()?;
+                    dismissed = ui.button("Dismiss").clicked();
+                });
+        }
+
+        if dismissed {
+            self.shader_error = None;
+        }
+    }
+
+    /// A checkbox switching between `default` and an own premultiplied color,
+    /// with a picker for the latter.
+    fn color_override_ui(ui: &mut egui::Ui, color: &mut Option<glam::Vec4>, default: glam::Vec4, label: &str) {
+        ui.horizontal(|ui| {
+            let mut overridden = color.is_some();
+            if ui.checkbox(&mut overridden, label).changed() {
+                *color = overridden.then_some(default);
             }
-            None => { }
-        })
+
+            if let Some(color) = color {
+                let mut rgba = color.to_array();
+                if ui.color_edit_button_rgba_premultiplied(&mut rgba).changed() {
+                    *color = glam::Vec4::from(rgba);
+                }
+            }
+        });
+    }
+
+    /// The scene is rendered offscreen for temporal anti-aliasing or the
+    /// fullscreen effect to read it.
+    fn resolves_scene(settings: &Settings) -> bool {
+        settings.taa.enabled || settings.fullscreen_effect.is_some()
+    }
+
+    /// The fullscreen effect takes the place of the resolve.
+    fn is_taa_active(&self) -> bool {
+        self.settings.taa.enabled && self.fullscreen_effect_system.is_none() && self.renderer.is_scene_resolve_active()
+    }
+
+    /// Orthographic depth is already linear, the setting is ignored then
+    fn is_log_depth_active(&self) -> bool {
+        self.settings.logarithmic_depth && self.settings.camera.projection == ProjectionMode::Perspective
+    }
+
+    /// Switches the systems drawing with the simple fragment shader to or
+    /// from its variant writing the logarithmic depth.
+    fn set_log_depth(&mut self, log_depth: bool) -> anyhow::Result<(), ApplicationError> {
+        unsafe {
+            self.renderer.device.logical_device.device_wait_idle().map_err(GentooRenderError::from)?;
+        }
+
+        let render_pass = self.renderer.get_swapchain_render_pass();
+
+        self.simple_render_system.set_log_depth(log_depth, &render_pass, &self.pipeline_cache)?;
+        self.skinned_render_system.set_log_depth(log_depth, &render_pass, &self.pipeline_cache)?;
+        self.reflection_system.set_log_depth(log_depth, &self.pipeline_cache)?;
+        self.oit_system.set_log_depth(log_depth, &self.pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// Rebuilds every pipeline from the shaders on disk. A failure is kept for
+    /// the error overlay while the systems continue with their last good pipelines.
+    fn reload_shaders(&mut self) -> anyhow::Result<(), ApplicationError> {
+        unsafe {
+            self.renderer.device.logical_device.device_wait_idle().map_err(GentooRenderError::from)?;
+        }
+
+        let render_pass = self.renderer.get_swapchain_render_pass();
+
+        let result = self.simple_render_system
+            .reload_pipelines(&render_pass, &self.pipeline_cache)
+            .and_then(|_| self.skinned_render_system.reload_pipelines(&render_pass, &self.pipeline_cache))
+            .and_then(|_| self.point_light_system.reload_pipelines(&render_pass, &self.pipeline_cache))
+            .and_then(|_| self.reflection_system.reload_pipelines(&self.pipeline_cache))
+            .and_then(|_| self.oit_system.reload_pipelines(&self.pipeline_cache))
+            .and_then(|_| self.depth_view_system.reload_pipelines(&self.pipeline_cache))
+            .and_then(|_| match &mut self.fullscreen_effect_system {
+                Some(fullscreen_effect_system) => fullscreen_effect_system.reload_pipeline(&self.pipeline_cache),
+                None => Ok(()),
+            });
+
+        match result {
+            Ok(()) => {
+                log::info!("Shaders reloaded");
+                self.shader_error = None;
+            }
+            Err(error) => {
+                log::error!("Shader reload failed: {}", error);
+                self.shader_error = Some(error.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Points the global sets at the SSAO result while it's enabled and keeps
+    /// its targets at the scene extent.
+    fn update_occlusion_map(&mut self) -> anyhow::Result<(), ApplicationError> {
+        let enabled = self.settings.ssao.enabled;
+
+        let mut rebind = enabled != self.occlusion_bound;
+
+        if enabled {
+            rebind |= self.ssao_system.resize(self.renderer.get_scene_extent())?;
+        }
+
+        if !rebind {
+            return Ok(());
+        }
+
+        unsafe {
+            self.renderer.device.logical_device.device_wait_idle().map_err(GentooRenderError::from)?;
+        }
+
+        let image_info = match self.ssao_system.occlusion_map() {
+            Some(image_info) if enabled => image_info,
+            _ => self.occlusion_placeholder.descriptor_info(),
+        };
+
+        for set in &self.global_descriptor_sets {
+            DescriptorSetWriter::new(self.global_set_layout.clone(), self.global_pool.clone())
+                .write_image(2, &[image_info])
+                .overwrite(*set);
+        }
+
+        self.occlusion_bound = enabled;
+
+        Ok(())
+    }
+
+    /// Points the global sets at the planar reflection while it's enabled and
+    /// keeps its target at the scene extent.
+    fn update_reflection_map(&mut self) -> anyhow::Result<(), ApplicationError> {
+        let enabled = self.settings.reflections.enabled;
+
+        let mut rebind = enabled != self.reflection_bound;
+
+        if enabled {
+            self.reflection_system.set_resolution_scale(self.settings.reflections.resolution_scale);
+            rebind |= self.reflection_system.resize(
+                self.renderer.get_scene_extent(),
+                self.renderer.swapchain.swapchain_image_format,
+                &self.pipeline_cache,
+            )?;
+        }
+
+        if !rebind {
+            return Ok(());
+        }
+
+        unsafe {
+            self.renderer.device.logical_device.device_wait_idle().map_err(GentooRenderError::from)?;
+        }
+
+        let image_info = match self.reflection_system.reflection_map() {
+            Some(image_info) if enabled => image_info,
+            _ => self.occlusion_placeholder.descriptor_info(),
+        };
+
+        for set in &self.global_descriptor_sets {
+            DescriptorSetWriter::new(self.global_set_layout.clone(), self.global_pool.clone())
+                .write_image(3, &[image_info])
+                .overwrite(*set);
+        }
+
+        self.reflection_bound = enabled;
+
+        Ok(())
+    }
+
+    pub fn reset_camera(&mut self) {
+        self.viewer_object.transform.set_translation(self.settings.camera.translation);
+        self.viewer_object.transform.set_rotation(self.settings.camera.rotation);
+        self.camera_controller.reset();
+        self.taa_system.reset();
+    }
+
+    pub fn request_frame_capture(&mut self) {
+        self.renderer.request_frame_capture();
+    }
+
+    /// Saves the next frame to `screenshot-<unix time>.png` in the working
+    /// directory
+    pub fn request_screenshot(&mut self) {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        self.renderer.request_screenshot(format!("screenshot-{}.png", time.as_millis()).into());
+    }
+
+    /// Whether the next frame would differ from the last one. Always true
+    /// unless `redraw_on_demand` is set.
+    pub fn needs_redraw(&self, input: &Input) -> bool {
+        !self.settings.redraw_on_demand
+            || self.redraw_frames > 0
+            || self.pending_resize.is_some()
+            || self.settings.pulse_ambient
+            // Held keys keep moving the camera
+            || input.any_key_held()
+            || self.game_objects.values().any(|obj| obj.animation.is_some())
+    }
+
+    pub fn frame_dropped(&self) -> bool {
+        self.frame_dropped
+    }
+
+    pub fn is_occluded(&self) -> bool {
+        self.occluded
+    }
+
+    /// Stops rendering while the window is hidden. The surface may have changed
+    /// in the meantime, so the swapchain is rebuilt when it becomes visible again.
+    pub fn set_occluded(&mut self, occluded: bool) -> anyhow::Result<(), ApplicationError> {
+        if occluded == self.occluded {
+            return Ok(());
+        }
+
+        log::debug!("Window {}", if occluded { "occluded" } else { "visible again" });
+
+        self.occluded = occluded;
+
+        if !occluded {
+            self.renderer.recreate_swapchain(&self.window)?;
+            self.update_swapchain_dependents()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn request_resize(&mut self) {
+        self.pending_resize = Some(Instant::now());
     }
 
     pub fn resize(&mut self) -> anyhow::Result<(), ApplicationError> {
+        let extent = Renderer::get_window_extent(&self.window);
+
+        if extent == self.renderer.swapchain.swapchain_extent {
+            return Ok(());
+        }
+
         self.renderer.recreate_swapchain(&self.window)?;
-        self.egui_integration.update_swapchain(&self.window, &self.renderer.swapchain, self.renderer.swapchain.swapchain_image_format, &self.pipeline_cache)?;
+        self.update_swapchain_dependents()?;
 
         Ok(())
     }
 
-    fn load_game_objects(device: Rc<Device>) -> anyhow::Result<HashMap<u8, GameObject>, GentooRenderError> {
-        let mut game_objects = HashMap::new();
+    fn update_swapchain_dependents(&mut self) -> anyhow::Result<(), ApplicationError> {
+        if self.renderer.take_format_change() {
+            self.recreate_pipelines_for_format()?;
+        }
 
-        let smooth_vase = Model::from_file(device.clone(), "models/smooth_vase.obj")?;
+        if let Some(egui_integration) = &mut self.egui_integration {
+            egui_integration.update_swapchain(&self.renderer.swapchain, self.renderer.swapchain.swapchain_image_format, &self.pipeline_cache)?;
+        }
 
-        let smooth_vase_transform = Some(TransformComponent {
-            translation: glam::vec3(0.5, 0.5, -5.0),
-            scale: glam::vec3(3.0, 1.5, 3.0),
-            rotation: glam::vec3(0.0, 0.0, 0.0),
-        });
+        Ok(())
+    }
+
+    /// Rebuilds every pipeline drawn into the scene pass for the render pass
+    /// of a swapchain whose formats changed. egui follows in `update_swapchain`.
+    fn recreate_pipelines_for_format(&mut self) -> anyhow::Result<(), ApplicationError> {
+        log::info!("Recreating pipelines for the new swapchain formats");
+
+        let render_pass = self.renderer.get_swapchain_render_pass();
+
+        self.simple_render_system.reload_pipelines(&render_pass, &self.pipeline_cache)?;
+        self.skinned_render_system.reload_pipelines(&render_pass, &self.pipeline_cache)?;
+        self.point_light_system.reload_pipelines(&render_pass, &self.pipeline_cache)?;
+        self.overdraw_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.gbuffer_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.shadow_caster_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.wireframe_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.bounds_debug_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.decal_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.oit_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        self.gizmo_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+
+        if let Some(debug_text_system) = &mut self.debug_text_system {
+            debug_text_system.recreate_pipeline_for_format(&render_pass, &self.pipeline_cache)?;
+        }
+
+        Ok(())
+    }
+
+    fn load_decal_texture(device: Rc<Device>, settings: &Settings) -> anyhow::Result<Rc<Texture>, GentooRenderError> {
+        match settings.decals.texture {
+            Some(file_path) => Texture::from_file(device, file_path, true),
+            None => Texture::from_memory(device, include_bytes!("../textures/soft_edge.png"), true),
+        }
+    }
 
-        let smooth_vase_game_object = GameObject::new(Some(smooth_vase), None, smooth_vase_transform);
+    /// Without a configured map a black placeholder is bound, the shaders
+    /// then use the flat ambient color.
+    fn load_irradiance_map(device: Rc<Device>, settings: &Settings) -> anyhow::Result<Rc<Texture>, GentooRenderError> {
+        match settings.environment.irradiance_map {
+            Some(file_paths) => Texture::cubemap_from_files(device, file_paths),
+            None => {
+                let black = [0, 0, 0, 255];
+                Texture::new_cubemap(device, [&black; 6], 1)
+            }
+        }
+    }
+
+    fn load_game_objects(device: Rc<Device>, model_cache: &mut ModelCache, settings: &Settings) -> anyhow::Result<HashMap<GameObjectId, GameObject>, GentooRenderError> {
+        let mut game_objects = HashMap::new();
+
+        // The scene meshes share one vertex and index buffer
+        let mut meshes = model_cache.load_packed(&[
+            ("models/smooth_vase.obj", MeshOptions::default()),
+            ("models/flat_vase.obj", MeshOptions::default()),
+            ("models/quad.obj", MeshOptions::default()),
+            // Exported with its normals pointing down, lit like the floor
+            // once they're flipped
+            ("models/inverted_quad.obj", MeshOptions { flip_normals: true, ..Default::default() }),
+        ])?.into_iter();
+
+        let smooth_vase = meshes.next().unwrap();
+
+        let smooth_vase_transform = Some(TransformComponent::new(
+            glam::vec3(0.5, 0.5, -5.0),
+            glam::vec3(3.0, 1.5, 3.0),
+            glam::vec3(0.0, 0.0, 0.0),
+        ));
+
+        let mut smooth_vase_game_object = GameObject::new(Some(smooth_vase), None, smooth_vase_transform)?;
+        smooth_vase_game_object.material.tessellated = true;
         game_objects.insert(smooth_vase_game_object.id, smooth_vase_game_object);
 
-        let flat_vase = Model::from_file(device.clone(), "models/flat_vase.obj")?;
+        let flat_vase = meshes.next().unwrap();
 
-        let flat_vase_transform = Some(TransformComponent {
-            translation: glam::vec3(-0.5, 0.5, -5.0),
-            scale: glam::vec3(3.0, 1.5, 3.0),
-            rotation: glam::vec3(0.0, 0.0, 0.0),
-        });
+        let flat_vase_transform = Some(TransformComponent::new(
+            glam::vec3(-0.5, 0.5, -5.0),
+            glam::vec3(3.0, 1.5, 3.0),
+            glam::vec3(0.0, 0.0, 0.0),
+        ));
 
-        let flat_vase_game_object = GameObject::new(Some(flat_vase), None, flat_vase_transform);
+        let flat_vase_game_object = GameObject::new(Some(flat_vase), None, flat_vase_transform)?;
         game_objects.insert(flat_vase_game_object.id, flat_vase_game_object);
 
-        let floor = Model::from_file(device.clone(), "models/quad.obj")?;
+        let floor = meshes.next().unwrap();
 
-        let floor_transform = Some(TransformComponent {
-            translation: glam::vec3(0.0, 0.5, -5.0),
-            scale: glam::vec3(10.0, 1.0, 10.0),
-            rotation: glam::vec3(0.0, 0.0, 0.0),
-        });
+        let floor_transform = Some(TransformComponent::new(
+            glam::vec3(0.0, 0.5, -5.0),
+            glam::vec3(10.0, 1.0, 10.0),
+            glam::vec3(0.0, 0.0, 0.0),
+        ));
 
-        let floor_game_object = GameObject::new(Some(floor), None, floor_transform);
+        let mut floor_game_object = GameObject::new(Some(floor), None, floor_transform)?;
+        floor_game_object.material.reflectivity = 0.3;
         game_objects.insert(floor_game_object.id, floor_game_object);
 
+        let tile = meshes.next().unwrap();
+
+        let tile_transform = Some(TransformComponent::new(
+            glam::vec3(1.5, 0.25, -5.5),
+            glam::vec3(0.4, 1.0, 0.4),
+            glam::vec3(0.0, 0.0, 0.0),
+        ));
+
+        let tile_game_object = GameObject::new(Some(tile), None, tile_transform)?;
+        game_objects.insert(tile_game_object.id, tile_game_object);
+
+        // Both crates draw the one cached model
+        for z in [-5.5, -4.5] {
+            let crate_model = model_cache.load("models/colored_cube.obj", MeshOptions::default())?;
+
+            let crate_transform = Some(TransformComponent::new(
+                glam::vec3(-1.5, 0.35, z),
+                glam::vec3(0.15, 0.15, 0.15),
+                glam::vec3(0.0, 0.0, 0.0),
+            ));
+
+            let crate_game_object = GameObject::new(Some(crate_model), None, crate_transform)?;
+            game_objects.insert(crate_game_object.id, crate_game_object);
+        }
+
+        if let Some(file_path) = settings.skinned_model {
+            let skinned_model = SkinnedModel::from_gltf(device.clone(), file_path)?;
+
+            let skinned_transform = Some(TransformComponent::new(
+                glam::vec3(0.0, 0.5, -4.0),
+                glam::vec3(1.0, 1.0, 1.0),
+                glam::vec3(0.0, 0.0, 0.0),
+            ));
+
+            let mut skinned_game_object = GameObject::new(Some(skinned_model.model), None, skinned_transform)?;
+            skinned_game_object.animation = Some(AnimationComponent::new(
+                skinned_model.skeleton,
+                skinned_model.clips.first().cloned(),
+            ));
+            game_objects.insert(skinned_game_object.id, skinned_game_object);
+        }
+
         let light_colors = vec![
             glam::vec3(1.0, 0.1, 0.1),
             glam::vec3(0.1, 0.1, 1.0),
@@ -299,11 +1598,11 @@ impl Application {
         ];
 
         for (i, color) in light_colors.iter().enumerate() {
-            let mut point_light = GameObject::make_point_light(0.2, 0.1, *color);
+            let mut point_light = GameObject::make_point_light(0.2, 0.1, *color)?;
 
             let rotate_light = glam::Mat4::from_axis_angle(glam::vec3(0.0, -1.0, 0.0), i as f32 * (PI * 2.0) / light_colors.len() as f32);
             let xyz = rotate_light * glam::vec4(-1.0, -1.0, -1.0, 1.0);
-            point_light.transform.translation = glam::vec3(xyz.x, xyz.y + 1.0, xyz.z - 5.0);
+            point_light.transform.set_translation(glam::vec3(xyz.x, xyz.y + 1.0, xyz.z - 5.0));
             game_objects.insert(point_light.id, point_light);
         }
 
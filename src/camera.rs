@@ -1,11 +1,22 @@
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
+    /// Includes the jitter
     pub projection_matrix: glam::Mat4,
     pub view_matrix: glam::Mat4,
+    /// Sub-pixel offset of the projection in normalized device coordinates
+    pub jitter: glam::Vec2,
+}
+
+impl Camera {
+    pub fn unjittered_projection(&self) -> glam::Mat4 {
+        glam::Mat4::from_translation(-self.jitter.extend(0.0)) * self.projection_matrix
+    }
 }
 
 pub struct CameraBuilder {
     pub projection_matrix: glam::Mat4,
     pub view_matrix: glam::Mat4,
+    pub jitter: glam::Vec2,
 }
 
 impl CameraBuilder {
@@ -13,6 +24,7 @@ impl CameraBuilder {
         CameraBuilder {
             projection_matrix: glam::Mat4::IDENTITY,
             view_matrix: glam::Mat4::IDENTITY,
+            jitter: glam::Vec2::ZERO,
         }
     }
 
@@ -30,6 +42,27 @@ impl CameraBuilder {
         self
     }
 
+    /// Orthographic projection centered on the view direction, `height`
+    /// world units high and `height * aspect` wide.
+    pub fn set_orthographic_view<'a>(
+        &'a mut self,
+        height: f32,
+        aspect: f32,
+        near: f32,
+        far: f32,
+    ) -> &'a mut Self {
+        if aspect.is_nan() || aspect <= 0.0 {
+            log::warn!("Ignoring orthographic projection with aspect ratio {}", aspect);
+            return self;
+        }
+
+        let half_height = 0.5 * height;
+        let half_width = half_height * aspect;
+
+        // Same orientation as `set_perspective_projection`
+        self.set_orthographic_projection(-half_width, half_width, half_height, -half_height, near, far)
+    }
+
     pub fn set_perspective_projection<'a>(
         &'a mut self,
         fovy: f32,
@@ -37,33 +70,64 @@ impl CameraBuilder {
         near: f32,
         far: f32,
     ) -> &'a mut Self {
-        assert!((aspect - std::f32::EPSILON).abs() > 0.0);
+        // A minimized window has no area, the last projection stays valid
+        if aspect.is_nan() || aspect <= 0.0 {
+            log::warn!("Ignoring perspective projection with aspect ratio {}", aspect);
+            return self;
+        }
 
         self.projection_matrix = glam::Mat4::perspective_rh(fovy, aspect, near, far);
 
         self
     }
 
+    /// `rotation` is the yaw around y in x, the pitch in y and the roll
+    /// around the view direction in z, see `orientation_xyz`.
     pub fn set_view_xyz<'a>(
         &'a mut self,
         position: glam::Vec3,
         rotation: glam::Vec3,
     ) -> &'a mut CameraBuilder {
-        let dir = glam::Vec3::new(
-            rotation.y.cos() * rotation.x.sin(),
-            rotation.y.sin(),
-            rotation.y.cos() * rotation.x.cos(),
-        );
+        self.set_view_quat(position, Self::orientation_xyz(rotation))
+    }
+
+    /// The identity looks down -z with y up.
+    pub fn set_view_quat<'a>(
+        &'a mut self,
+        position: glam::Vec3,
+        orientation: glam::Quat,
+    ) -> &'a mut CameraBuilder {
+        self.view_matrix = glam::Mat4::from_rotation_translation(orientation, position).inverse();
+
+        self
+    }
+
+    /// The orientation `set_view_xyz` looks in. The yaw is applied first,
+    /// then the pitch and the roll last, each around the already rotated axes.
+    pub fn orientation_xyz(rotation: glam::Vec3) -> glam::Quat {
+        glam::Quat::from_euler(glam::EulerRot::YXZ, rotation.x, -rotation.y, rotation.z)
+    }
+
+    /// Inverse of `orientation_xyz`, with the pitch in -90 to 90 degrees.
+    pub fn rotation_xyz(orientation: glam::Quat) -> glam::Vec3 {
+        let (yaw, pitch, roll) = orientation.to_euler(glam::EulerRot::YXZ);
+
+        glam::vec3(yaw, -pitch, roll)
+    }
 
-        self.view_matrix = glam::Mat4::look_at_rh(position, position - dir, glam::Vec3::new(0.0, 1.0, 0.0));
+    /// Shifts the projection by `jitter` in normalized device coordinates,
+    /// two divided by the extent per pixel. Used for temporal anti-aliasing.
+    pub fn set_jitter<'a>(&'a mut self, jitter: glam::Vec2) -> &'a mut Self {
+        self.jitter = jitter;
 
         self
     }
 
     pub fn build(&self) -> Camera {
         Camera {
-            projection_matrix: self.projection_matrix,
+            projection_matrix: glam::Mat4::from_translation(self.jitter.extend(0.0)) * self.projection_matrix,
             view_matrix: self.view_matrix,
+            jitter: self.jitter,
         }
     }
 }
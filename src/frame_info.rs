@@ -1,17 +1,18 @@
 use std::collections::HashMap;
 
-use crate::{camera::Camera, GameObject};
+use crate::{camera::Camera, GameObject, GameObjectId};
 
 pub const MAX_LIGHTS: usize = 10;
 
 #[derive(Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct PointLight {
+    /// w is the radius of the light's billboard
     pub position: glam::Vec4,
     pub color: glam::Vec4,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct GlobalUbo {
     pub projection: glam::Mat4,
@@ -19,13 +20,48 @@ pub struct GlobalUbo {
     pub ambient_light_color: glam::Vec4,
     pub point_lights: [PointLight; MAX_LIGHTS],
     pub num_lights: u32,
+    /// Seconds since the application started
+    pub time: f32,
+    /// Swapchain extent in pixels
+    pub resolution: glam::Vec2,
+    /// Amplitude of the demo ambient pulse, 0 disables it
+    pub ambient_pulse: f32,
+    /// Scales the irradiance cubemap, 0 uses `ambient_light_color` instead
+    pub irradiance_intensity: f32,
+    /// How much the SSAO term darkens the ambient light, 0 disables it
+    pub ambient_occlusion: f32,
+    /// `1 / log2(far + 1)` for a logarithmic depth buffer, 0 uses the
+    /// regular perspective depth
+    pub log_depth_coefficient: f32,
+    /// w is the exponential fog density, 0 disables fog
+    pub fog_color: glam::Vec4,
+    /// Scales the lit scene color before it is written
+    pub exposure: f32,
+    /// Scales the material reflectivity, 0 while planar reflections are off
+    pub reflections: f32,
+}
+
+/// Parameters of the passes after the scene pass, one buffer per frame in
+/// flight like the `GlobalUbo`. Laid out for std140, new members have to
+/// keep their alignment.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct PostProcessUbo {
+    /// Weight of the TAA history, the rest comes from the current frame
+    pub taa_feedback: f32,
+    /// How much the example fullscreen effect darkens the corners
+    pub vignette: f32,
 }
 
 pub struct FrameInfo<'a> {
     pub frame_index: usize,
     pub frame_time: f32,
     pub command_buffer: ash::vk::CommandBuffer,
+    /// Extent of the scene render pass
+    pub extent: ash::vk::Extent2D,
     pub camera: Camera,
-    pub game_objects: &'a HashMap<u8, GameObject>,
+    pub game_objects: &'a HashMap<GameObjectId, GameObject>,
     pub global_descriptor_set: ash::vk::DescriptorSet,
+    /// The frame's `PostProcessUbo`
+    pub post_process_descriptor_set: ash::vk::DescriptorSet,
 }
@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+/// Time constant of the smoothed frame time, roughly how long a change in
+/// frame rate takes to show up.
+const SMOOTHING_TIME: f32 = 0.25;
+
+/// Frame rate statistics for the performance HUD.
+pub struct FrameStats {
+    /// Frames counted over the last full second, the most accurate average
+    /// but only updated once per second
+    pub fps: usize,
+    /// Frames skipped without a swapchain image over the last full second,
+    /// not included in `fps`
+    pub dropped: usize,
+    /// Exponential moving average of the frame time in seconds, updated every frame
+    pub smoothed_frame_time: f32,
+    frames: usize,
+    dropped_frames: usize,
+    counting_since: Instant,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            fps: 0,
+            dropped: 0,
+            smoothed_frame_time: 0.0,
+            frames: 0,
+            dropped_frames: 0,
+            counting_since: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, frame_time: f32) {
+        if self.smoothed_frame_time == 0.0 {
+            self.smoothed_frame_time = frame_time;
+        } else {
+            // Weighing by the frame time keeps the smoothing independent of the frame rate
+            let factor = 1.0 - (-frame_time / SMOOTHING_TIME).exp();
+            self.smoothed_frame_time += (frame_time - self.smoothed_frame_time) * factor;
+        }
+
+        self.frames += 1;
+
+        if self.counting_since.elapsed() > Duration::from_secs(1) {
+            self.counting_since = Instant::now();
+            self.fps = self.frames.saturating_sub(self.dropped_frames);
+            self.dropped = self.dropped_frames;
+            self.frames = 0;
+            self.dropped_frames = 0;
+        }
+    }
+
+    /// Marks the frame of the last `update` as not presented.
+    pub fn frame_dropped(&mut self) {
+        self.dropped_frames += 1;
+    }
+
+    pub fn smoothed_fps(&self) -> f32 {
+        if self.smoothed_frame_time > 0.0 {
+            1.0 / self.smoothed_frame_time
+        } else {
+            0.0
+        }
+    }
+}
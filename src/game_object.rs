@@ -1,36 +1,253 @@
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc, sync::atomic::{AtomicU32, Ordering}};
 
-use crate::vulkan::Model;
+use crate::vulkan::{Model, Skeleton, AnimationClip, DepthRange, FULL_DEPTH_RANGE, GentooRenderError};
 
+/// The matrices are cached, so the fields are only changed through the
+/// setters, which invalidate them.
 pub struct TransformComponent {
-    pub translation: glam::Vec3,
-    pub scale: glam::Vec3,
-    pub rotation: glam::Vec3,
+    translation: glam::Vec3,
+    scale: glam::Vec3,
+    /// Euler angles in radians around x, y and z. Applied as a yaw around y,
+    /// then a pitch around the rotated x and a roll around the rotated z.
+    rotation: glam::Vec3,
+    /// Model and normal matrix, None after a change until they are read
+    matrices: Cell<Option<(glam::Mat4, glam::Mat4)>>,
 }
 
 impl TransformComponent {
+    pub fn new(translation: glam::Vec3, scale: glam::Vec3, rotation: glam::Vec3) -> Self {
+        Self {
+            translation,
+            scale,
+            rotation,
+            matrices: Cell::new(None),
+        }
+    }
+
+    pub fn translation(&self) -> glam::Vec3 {
+        self.translation
+    }
+
+    pub fn scale(&self) -> glam::Vec3 {
+        self.scale
+    }
+
+    pub fn rotation(&self) -> glam::Vec3 {
+        self.rotation
+    }
+
+    pub fn set_translation(&mut self, translation: glam::Vec3) {
+        self.translation = translation;
+        self.matrices.set(None);
+    }
+
+    pub fn set_scale(&mut self, scale: glam::Vec3) {
+        self.scale = scale;
+        self.matrices.set(None);
+    }
+
+    pub fn set_rotation(&mut self, rotation: glam::Vec3) {
+        self.rotation = rotation;
+        self.matrices.set(None);
+    }
+
     pub fn mat4(&self) -> glam::Mat4 {
-        let quat = glam::quat(self.rotation.x, self.rotation.y, self.rotation.z, 0.0);
-        glam::Mat4::from_scale_rotation_translation(self.scale, quat, self.translation)
+        self.matrices().0
     }
 
     pub fn normal_matrix(&self) -> glam::Mat4 {
-        glam::Mat4::from_scale(1.0 / self.scale)
+        self.matrices().1
     }
+
+    /// Computed on the first read after a change, every pass after that
+    /// reuses them.
+    fn matrices(&self) -> (glam::Mat4, glam::Mat4) {
+        if let Some(matrices) = self.matrices.get() {
+            return matrices;
+        }
+
+        let quat = glam::Quat::from_euler(glam::EulerRot::YXZ, self.rotation.y, self.rotation.x, self.rotation.z);
+
+        // The inverse transpose of the model matrix without translation
+        let matrices = (
+            glam::Mat4::from_scale_rotation_translation(self.scale, quat, self.translation),
+            glam::Mat4::from_quat(quat) * glam::Mat4::from_scale(1.0 / self.scale),
+        );
+
+        self.matrices.set(Some(matrices));
+
+        matrices
+    }
+}
+
+impl Default for TransformComponent {
+    fn default() -> Self {
+        Self::new(glam::Vec3::ZERO, glam::Vec3::ONE, glam::Vec3::ZERO)
+    }
+}
+
+/// Depth comparison of an object against what is already drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthTest {
+    /// `LESS`, for regular geometry
+    Default,
+    /// `ALWAYS`, drawn over everything, e.g. in-world UI
+    Overlay,
+    /// `LESS_OR_EQUAL`, passes on coplanar geometry it lies on
+    Decal,
+}
+
+impl DepthTest {
+    pub const ALL: [DepthTest; 3] = [DepthTest::Default, DepthTest::Overlay, DepthTest::Decal];
+
+    pub fn compare_op(self) -> ash::vk::CompareOp {
+        match self {
+            DepthTest::Default => ash::vk::CompareOp::LESS,
+            DepthTest::Overlay => ash::vk::CompareOp::ALWAYS,
+            DepthTest::Decal => ash::vk::CompareOp::LESS_OR_EQUAL,
+        }
+    }
+}
+
+pub struct MaterialComponent {
+    /// Rendered without back-face culling, for thin geometry like foliage or sheets.
+    pub double_sided: bool,
+    /// A range near 0, e.g. 0 to 0.1, draws the object in front of everything
+    /// else, like a first person weapon that would otherwise clip into walls.
+    pub depth_range: DepthRange,
+    /// Smoothed into curved PN triangles on the GPU, needs smooth vertex
+    /// normals. Ignored without tessellation support.
+    pub tessellated: bool,
+    /// Outputs the vertex color as is, skipping lighting and fog. For markers
+    /// and debug geometry. Skinned objects are always lit.
+    pub unlit: bool,
+    /// How much of the planar reflection is blended over the lit color, see
+    /// `ReflectionSystem`. Only for flat objects, like a floor.
+    pub reflectivity: f32,
+    pub depth_test: DepthTest,
+    /// Below 1 the object is drawn after the opaque ones without writing
+    /// depth, see `TransparencyMode`. Transparent objects always use
+    /// `DepthTest::Default` and aren't tessellated, skinned objects are
+    /// always opaque.
+    pub opacity: f32,
+}
+
+impl MaterialComponent {
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0
+    }
+}
+
+impl Default for MaterialComponent {
+    fn default() -> Self {
+        Self {
+            double_sided: false,
+            depth_range: FULL_DEPTH_RANGE,
+            tessellated: false,
+            unlit: false,
+            reflectivity: 0.0,
+            depth_test: DepthTest::Default,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Per-object debug visualizations, shown on top of the global ones. All off
+/// by default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DebugRenderComponent {
+    /// Draws the edges even while the wireframe overlay is off
+    pub wireframe: bool,
+    /// Premultiplied, the alpha is the line opacity. None uses the overlay's color
+    pub wireframe_color: Option<glam::Vec4>,
+    /// Draws the bounding box even while they are hidden globally
+    pub show_bounds: bool,
+    /// Premultiplied, None uses `BOUNDS_COLOR`
+    pub bounds_color: Option<glam::Vec4>,
 }
 
 pub struct PointLightComponent {
     pub light_intensity: f32,
 }
 
-static mut CURRENT_ID: u8 = 0;
+/// Plays a looping clip on a skinned model, drawn by the skinned render system.
+pub struct AnimationComponent {
+    pub skeleton: Rc<Skeleton>,
+    /// None holds the rest pose
+    pub clip: Option<Rc<AnimationClip>>,
+    /// Seconds into the clip
+    pub time: f32,
+    pub speed: f32,
+}
+
+impl AnimationComponent {
+    pub fn new(skeleton: Rc<Skeleton>, clip: Option<Rc<AnimationClip>>) -> Self {
+        Self {
+            skeleton,
+            clip,
+            time: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    pub fn advance(&mut self, frame_time: f32) {
+        let duration = self.clip.as_ref().map_or(0.0, |clip| clip.duration);
+
+        if duration > 0.0 {
+            self.time = (self.time + frame_time * self.speed).rem_euclid(duration);
+        }
+    }
+
+    pub fn joint_matrices(&self) -> Vec<glam::Mat4> {
+        self.skeleton.joint_matrices(self.clip.as_deref(), self.time)
+    }
+}
+
+/// The lit scene, drawn by the simple, skinned and point light systems
+pub const RENDER_LAYER_MAIN: u32 = 1 << 0;
+/// The depth and normal prepass the SSAO is computed from
+pub const RENDER_LAYER_PREPASS: u32 = 1 << 1;
+pub const RENDER_LAYER_GBUFFER: u32 = 1 << 2;
+/// Debug visualizations like the overdraw heatmap
+pub const RENDER_LAYER_DEBUG: u32 = 1 << 3;
+/// Objects that can be selected by clicking on them
+pub const RENDER_LAYER_PICKING: u32 = 1 << 4;
+/// Depth only passes from a light's point of view
+pub const RENDER_LAYER_SHADOW: u32 = 1 << 5;
+/// The mirrored scene of planar reflections
+pub const RENDER_LAYER_REFLECTION: u32 = 1 << 6;
+pub const RENDER_LAYER_ALL: u32 = u32::MAX;
+
+/// Named layers, in the order the inspector lists them
+pub const RENDER_LAYERS: [(&str, u32); 7] = [
+    ("Main", RENDER_LAYER_MAIN),
+    ("Prepass", RENDER_LAYER_PREPASS),
+    ("G-buffer", RENDER_LAYER_GBUFFER),
+    ("Debug", RENDER_LAYER_DEBUG),
+    ("Picking", RENDER_LAYER_PICKING),
+    ("Shadow", RENDER_LAYER_SHADOW),
+    ("Reflection", RENDER_LAYER_REFLECTION),
+];
+
+pub type GameObjectId = u32;
+
+/// Ids are never reused, `u32::MAX` stays free so the picking pass can
+/// offset the ids by one
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
 
 pub struct GameObject {
-    pub id: u8,
+    pub id: GameObjectId,
     pub model: Option<Rc<Model>>,
     pub color: glam::Vec3,
     pub transform: TransformComponent,
+    pub material: MaterialComponent,
+    pub debug: DebugRenderComponent,
     pub point_light: Option<PointLightComponent>,
+    pub animation: Option<AnimationComponent>,
+    /// Hides the object from every pass regardless of `render_mask`
+    pub visible: bool,
+    /// `RENDER_LAYER_*` bits of the passes the object is drawn in
+    pub render_mask: u32,
 }
 
 impl GameObject {
@@ -38,52 +255,51 @@ impl GameObject {
         model: Option<Rc<Model>>,
         color: Option<glam::Vec3>,
         transform: Option<TransformComponent>,
-    ) -> Self {
+    ) -> anyhow::Result<Self, GentooRenderError> {
         let color = match color {
             Some(c) => c,
             None => glam::vec3(0.0, 0.0, 0.0),
         };
 
-        let transform = match transform {
-            Some(t) => t,
-            None => TransformComponent {
-                translation: glam::vec3(0.0, 0.0, 0.0),
-                scale: glam::vec3(1.0, 1.0, 1.0),
-                rotation: glam::vec3(0.0, 0.0, 0.0),
-            }
-        };
-
-        let id = unsafe {
-            CURRENT_ID
-        };
+        let transform = transform.unwrap_or_default();
 
-        unsafe {
-            CURRENT_ID += 1;
-        }
+        let id = NEXT_ID
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| id.checked_add(1).filter(|next| *next < u32::MAX))
+            .map_err(|_| GentooRenderError::OutOfGameObjectIds)?;
 
-        Self {
+        Ok(Self {
             id,
             model,
             color,
             transform,
+            material: MaterialComponent::default(),
+            debug: DebugRenderComponent::default(),
             point_light: None,
-        }
+            animation: None,
+            visible: true,
+            render_mask: RENDER_LAYER_ALL,
+        })
+    }
+
+    /// Whether passes drawing `layer` should draw this object.
+    pub fn is_rendered_in(&self, layer: u32) -> bool {
+        self.visible && self.render_mask & layer != 0
     }
 
-    pub fn make_point_light(intensity: f32, radius: f32, color: glam::Vec3) -> Self {
+    pub fn make_point_light(intensity: f32, radius: f32, color: glam::Vec3) -> anyhow::Result<Self, GentooRenderError> {
         let mut game_object = Self::new(
             None,
             Some(color),
-            Some(TransformComponent {
-                translation: glam::vec3(0.0, 0.0, 0.0),
-                scale: glam::vec3(radius, 0.0, 0.0),
-                rotation: glam::vec3(0.0, 0.0, 0.0),
-            }));
+            Some(TransformComponent::new(
+                glam::vec3(0.0, 0.0, 0.0),
+                glam::vec3(radius, 0.0, 0.0),
+                glam::vec3(0.0, 0.0, 0.0),
+            )))?;
 
         game_object.point_light = Some(PointLightComponent {
             light_intensity: intensity,
         });
 
-        game_object
+        Ok(game_object)
     }
 }
@@ -1,15 +1,20 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fs::File, io::{BufRead, BufReader, BufWriter, Write}, path::PathBuf};
 
-use winit::event::{VirtualKeyCode, KeyboardInput, ElementState, WindowEvent};
+use serde::{Deserialize, Serialize};
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::{VirtualKeyCode, KeyboardInput, ElementState, WindowEvent, ModifiersState, MouseButton, MouseScrollDelta, TouchPhase, DeviceId}};
 
+#[derive(Debug, Clone, Default)]
 pub struct Input {
     keymap: HashMap<VirtualKeyCode, bool>,
+    /// Physical pixels from the top left of the window, None while outside of it
+    cursor_position: Option<glam::Vec2>,
 }
 
 impl Input {
     pub fn new() -> Self {
         Self {
             keymap: HashMap::new(),
+            cursor_position: None,
         }
     }
 
@@ -21,22 +26,397 @@ impl Input {
         }
     }
 
+    pub fn any_key_held(&self) -> bool {
+        self.keymap.values().any(|held| *held)
+    }
+
+    pub fn cursor_position(&self) -> Option<glam::Vec2> {
+        self.cursor_position
+    }
+
     pub fn update_key(&mut self, input: &KeyboardInput) {
-        input.virtual_keycode.map(|keycode| {
-            self.keymap.insert(
-                keycode,
-                match input.state {
-                    ElementState::Pressed => true,
-                    _ => false,
-                }
-            );
-        });
+        if let Some(keycode) = input.virtual_keycode {
+            self.keymap.insert(keycode, input.state == ElementState::Pressed);
+        }
     }
 
     pub fn update(&mut self, event: &WindowEvent) {
         match event {
             WindowEvent::KeyboardInput { input, .. } => self.update_key(input),
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(glam::vec2(position.x as f32, position.y as f32));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
             _ => (),
         }
     }
 }
+
+/// Whether input is recorded or replayed, chosen with `--record-input <file>`
+/// or `--replay-input <file>` on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputRecording {
+    Off,
+    /// Writes the input events and frame time of every frame to the file
+    Record(PathBuf),
+    /// Runs the frames of a recorded file instead of the live input and
+    /// exits after the last one
+    Replay(PathBuf),
+}
+
+impl InputRecording {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut recording = InputRecording::Off;
+
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--record-input", Some(path)) => recording = InputRecording::Record(path.into()),
+                ("--replay-input", Some(path)) => recording = InputRecording::Replay(path.into()),
+                _ => log::warn!("Ignoring unknown argument {}", arg),
+            }
+        }
+
+        recording
+    }
+}
+
+/// A window event `Input` or the application reacts to, in a form that can
+/// be recorded. Window management events besides resizes aren't recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Keyboard(KeyboardInput),
+    Character(char),
+    Modifiers(ModifiersState),
+    CursorMoved { x: f64, y: f64 },
+    CursorEntered,
+    CursorLeft,
+    MouseInput { state: ElementState, button: MouseButton },
+    MouseWheel { delta: MouseScrollDelta, phase: TouchPhase },
+    Focused(bool),
+    Resized { width: u32, height: u32 },
+}
+
+impl RecordedEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::KeyboardInput { input, .. } => RecordedEvent::Keyboard(*input),
+            WindowEvent::ReceivedCharacter(character) => RecordedEvent::Character(*character),
+            WindowEvent::ModifiersChanged(modifiers) => RecordedEvent::Modifiers(*modifiers),
+            WindowEvent::CursorMoved { position, .. } => RecordedEvent::CursorMoved { x: position.x, y: position.y },
+            WindowEvent::CursorEntered { .. } => RecordedEvent::CursorEntered,
+            WindowEvent::CursorLeft { .. } => RecordedEvent::CursorLeft,
+            WindowEvent::MouseInput { state, button, .. } => RecordedEvent::MouseInput { state: *state, button: *button },
+            WindowEvent::MouseWheel { delta, phase, .. } => RecordedEvent::MouseWheel { delta: *delta, phase: *phase },
+            WindowEvent::Focused(focused) => RecordedEvent::Focused(*focused),
+            WindowEvent::Resized(size) => RecordedEvent::Resized { width: size.width, height: size.height },
+            _ => return None,
+        })
+    }
+
+    /// The event as winit would send it, with a placeholder device
+    #[allow(deprecated)]
+    pub fn to_window_event(&self) -> WindowEvent<'static> {
+        // Only compared against other device ids, never passed to winit
+        let device_id = unsafe { DeviceId::dummy() };
+
+        match self {
+            RecordedEvent::Keyboard(input) => WindowEvent::KeyboardInput { device_id, input: *input, is_synthetic: false },
+            RecordedEvent::Character(character) => WindowEvent::ReceivedCharacter(*character),
+            RecordedEvent::Modifiers(modifiers) => WindowEvent::ModifiersChanged(*modifiers),
+            RecordedEvent::CursorMoved { x, y } => WindowEvent::CursorMoved {
+                device_id,
+                position: PhysicalPosition::new(*x, *y),
+                modifiers: ModifiersState::empty(),
+            },
+            RecordedEvent::CursorEntered => WindowEvent::CursorEntered { device_id },
+            RecordedEvent::CursorLeft => WindowEvent::CursorLeft { device_id },
+            RecordedEvent::MouseInput { state, button } => WindowEvent::MouseInput {
+                device_id,
+                state: *state,
+                button: *button,
+                modifiers: ModifiersState::empty(),
+            },
+            RecordedEvent::MouseWheel { delta, phase } => WindowEvent::MouseWheel {
+                device_id,
+                delta: *delta,
+                phase: *phase,
+                modifiers: ModifiersState::empty(),
+            },
+            RecordedEvent::Focused(focused) => WindowEvent::Focused(*focused),
+            RecordedEvent::Resized { width, height } => WindowEvent::Resized(PhysicalSize::new(*width, *height)),
+        }
+    }
+
+    /// Everything but resizes comes from the user, and is ignored live while
+    /// replaying
+    pub fn is_user_input(&self) -> bool {
+        !matches!(self, RecordedEvent::Resized { .. })
+    }
+}
+
+/// One frame of a recording, one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub frame_time: f32,
+    /// Received since the previous frame, in order
+    pub events: Vec<RecordedEvent>,
+}
+
+/// Where the input of each frame comes from, see `InputRecording`. Doesn't
+/// need a window, the caller forwards the window events.
+pub trait InputSource {
+    /// Called for every live window event. Returns false if the application
+    /// has to ignore it.
+    fn handle_event(&mut self, event: &WindowEvent) -> bool;
+
+    /// Advances to the next frame with the measured `frame_time`. The events
+    /// of the returned frame have to reach the application before it runs
+    /// the frame with its frame time. None once a replay has no frames left.
+    fn next_frame(&mut self, frame_time: f32) -> Option<InputFrame>;
+
+    /// The input state of the current frame
+    fn current(&self) -> &Input;
+
+    /// Whether frames have to be drawn even without any input
+    fn wants_frames(&self) -> bool {
+        false
+    }
+}
+
+impl InputSource for Input {
+    fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        self.update(event);
+
+        true
+    }
+
+    fn next_frame(&mut self, frame_time: f32) -> Option<InputFrame> {
+        Some(InputFrame {
+            frame_time,
+            events: Vec::new(),
+        })
+    }
+
+    fn current(&self) -> &Input {
+        self
+    }
+}
+
+/// Live input that writes every frame to `writer` for `InputReplay`.
+pub struct InputRecorder<W: Write> {
+    input: Input,
+    /// Events of the frame in progress
+    events: Vec<RecordedEvent>,
+    writer: W,
+}
+
+impl InputRecorder<BufWriter<File>> {
+    pub fn new(path: &std::path::Path) -> std::io::Result<Self> {
+        log::info!("Recording input to {}", path.display());
+
+        Ok(Self::from_writer(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> InputRecorder<W> {
+    pub fn from_writer(writer: W) -> Self {
+        Self {
+            input: Input::new(),
+            events: Vec::new(),
+            writer,
+        }
+    }
+}
+
+impl<W: Write> InputSource for InputRecorder<W> {
+    fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        self.input.update(event);
+        self.events.extend(RecordedEvent::from_window_event(event));
+
+        true
+    }
+
+    fn next_frame(&mut self, frame_time: f32) -> Option<InputFrame> {
+        let frame = InputFrame {
+            frame_time,
+            events: std::mem::take(&mut self.events),
+        };
+
+        let written = serde_json::to_writer(&mut self.writer, &frame)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+
+        // Losing the recording shouldn't stop the application
+        if let Err(err) = written {
+            log::error!("Failed to record input: {}", err);
+        }
+
+        // The events already reached the application live
+        Some(InputFrame {
+            frame_time,
+            events: Vec::new(),
+        })
+    }
+
+    fn current(&self) -> &Input {
+        &self.input
+    }
+}
+
+impl<W: Write> Drop for InputRecorder<W> {
+    fn drop(&mut self) {
+        log::debug!("Dropping input recorder");
+
+        if let Err(err) = self.writer.flush() {
+            log::error!("Failed to flush the input recording: {}", err);
+        }
+    }
+}
+
+/// Plays back a recording of `InputRecorder` with the recorded frame times,
+/// so the controllers move exactly like they did while recording. Live user
+/// input is ignored, live resizes still reach the application.
+pub struct InputReplay {
+    frames: std::vec::IntoIter<InputFrame>,
+    input: Input,
+}
+
+impl InputReplay {
+    pub fn new(path: &std::path::Path) -> std::io::Result<Self> {
+        let replay = Self::from_reader(BufReader::new(File::open(path)?))?;
+
+        log::info!("Replaying {} frames of input from {}", replay.frames.len(), path.display());
+
+        Ok(replay)
+    }
+
+    pub fn from_reader(reader: impl BufRead) -> std::io::Result<Self> {
+        let mut frames = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            frames.push(serde_json::from_str(&line)?);
+        }
+
+        Ok(Self {
+            frames: frames.into_iter(),
+            input: Input::new(),
+        })
+    }
+}
+
+impl InputSource for InputReplay {
+    fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        !RecordedEvent::from_window_event(event).is_some_and(|event| event.is_user_input())
+    }
+
+    fn next_frame(&mut self, _frame_time: f32) -> Option<InputFrame> {
+        let frame = self.frames.next()?;
+
+        for event in &frame.events {
+            self.input.update(&event.to_window_event());
+        }
+
+        Some(frame)
+    }
+
+    fn current(&self) -> &Input {
+        &self.input
+    }
+
+    fn wants_frames(&self) -> bool {
+        self.frames.len() > 0
+    }
+}
+
+pub fn create_input_source(recording: &InputRecording) -> std::io::Result<Box<dyn InputSource>> {
+    Ok(match recording {
+        InputRecording::Off => Box::new(Input::new()),
+        InputRecording::Record(path) => Box::new(InputRecorder::new(path)?),
+        InputRecording::Replay(path) => Box::new(InputReplay::new(path)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(deprecated)]
+    fn key(keycode: VirtualKeyCode, state: ElementState) -> RecordedEvent {
+        RecordedEvent::Keyboard(KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(keycode),
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let live_frames = [
+            (0.016, vec![key(VirtualKeyCode::W, ElementState::Pressed), RecordedEvent::CursorMoved { x: 10.0, y: 20.5 }]),
+            (0.017, vec![]),
+            (0.015, vec![
+                key(VirtualKeyCode::W, ElementState::Released),
+                RecordedEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left },
+                RecordedEvent::Resized { width: 640, height: 480 },
+                RecordedEvent::CursorLeft,
+            ]),
+        ];
+
+        let mut recording = Vec::new();
+        let mut recorded_states = Vec::new();
+
+        {
+            let mut recorder = InputRecorder::from_writer(&mut recording);
+
+            for (frame_time, events) in &live_frames {
+                for event in events {
+                    assert!(recorder.handle_event(&event.to_window_event()));
+                }
+
+                let frame = recorder.next_frame(*frame_time).unwrap();
+                assert!(frame.events.is_empty());
+
+                let input = recorder.current();
+                recorded_states.push((frame.frame_time, input.key_held(VirtualKeyCode::W), input.cursor_position()));
+            }
+        }
+
+        let mut replay = InputReplay::from_reader(recording.as_slice()).unwrap();
+
+        // Live user input doesn't interfere, live resizes still apply
+        assert!(!replay.handle_event(&key(VirtualKeyCode::S, ElementState::Pressed).to_window_event()));
+        assert!(replay.handle_event(&RecordedEvent::Resized { width: 1, height: 1 }.to_window_event()));
+
+        for ((frame_time, events), expected) in live_frames.iter().zip(recorded_states) {
+            assert!(replay.wants_frames());
+
+            let frame = replay.next_frame(1.0).unwrap();
+            assert_eq!(frame.frame_time, *frame_time);
+            assert_eq!(&frame.events, events);
+
+            let input = replay.current();
+            assert_eq!((frame.frame_time, input.key_held(VirtualKeyCode::W), input.cursor_position()), expected);
+            assert!(!input.key_held(VirtualKeyCode::S));
+        }
+
+        assert!(!replay.wants_frames());
+        assert!(replay.next_frame(1.0).is_none());
+    }
+
+    #[test]
+    fn recording_arguments() {
+        let args = |args: &[&str]| InputRecording::from_args(args.iter().map(|arg| arg.to_string()));
+
+        assert_eq!(args(&[]), InputRecording::Off);
+        assert_eq!(args(&["--record-input", "a.jsonl"]), InputRecording::Record("a.jsonl".into()));
+        assert_eq!(args(&["--replay-input", "b.jsonl"]), InputRecording::Replay("b.jsonl".into()));
+        assert_eq!(args(&["--replay-input"]), InputRecording::Off);
+    }
+}
@@ -3,11 +3,16 @@ use std::f32::EPSILON;
 use winit::event::VirtualKeyCode;
 
 use crate::GameObject;
+use crate::camera::CameraBuilder;
 use crate::input::Input;
 
+/// Arrows look around, Z and C roll, WASD moves and Q and E move up and down.
 pub struct KeyboardMovementController {
     move_speed: f32,
     look_speed: f32,
+    /// Where the viewer looks while moving freely, see `move_free`. None
+    /// while moving in the plane, which uses the Euler angles of the transform.
+    orientation: Option<glam::Quat>,
 }
 
 impl KeyboardMovementController {
@@ -24,15 +29,32 @@ impl KeyboardMovementController {
         Self {
             move_speed,
             look_speed,
+            orientation: None,
         }
     }
 
+    /// Set while moving freely, the camera should look this way instead of
+    /// using the transform's Euler angles.
+    pub fn orientation(&self) -> Option<glam::Quat> {
+        self.orientation
+    }
+
+    /// Makes the next `move_free` start from the transform's rotation again,
+    /// e.g. after it was reset.
+    pub fn reset(&mut self) {
+        self.orientation = None;
+    }
+
+    /// Yaws around the world y axis and keeps the pitch within 86 degrees,
+    /// so the view can't flip over. Moves along the ground plane.
     pub fn move_in_plane_xz(
-        &self,
+        &mut self,
         input: &Input,
         dt: f32,
         game_object: &mut GameObject,
     ) {
+        self.orientation = None;
+
         let mut rotate = glam::Vec3::ZERO;
 
         if input.key_held(VirtualKeyCode::Right) {
@@ -47,18 +69,24 @@ impl KeyboardMovementController {
         if input.key_held(VirtualKeyCode::Down) {
             rotate[1] -= 1.0
         }
+        rotate[2] = Self::roll_input(input);
+
+        let mut rotation = game_object.transform.rotation();
 
         if rotate.dot(rotate) > EPSILON {
-            game_object.transform.rotation += self.look_speed * dt * rotate.normalize();
+            rotation += self.look_speed * dt * rotate.normalize();
         }
 
-        game_object.transform.rotation.y = game_object.transform.rotation.y.clamp(-1.5, 1.5);
-        game_object.transform.rotation.x = game_object.transform.rotation.x % (2.0 * PI);
+        rotation.y = rotation.y.clamp(-1.5, 1.5);
+        rotation.x = rotation.x % (2.0 * PI);
+        rotation.z = rotation.z % (2.0 * PI);
+
+        game_object.transform.set_rotation(rotation);
 
         let look_dir = glam::Vec3::new(
-            game_object.transform.rotation.y.cos() * game_object.transform.rotation.x.sin(),
-            game_object.transform.rotation.y.sin(),
-            game_object.transform.rotation.y.cos() * game_object.transform.rotation.x.cos(),
+            rotation.y.cos() * rotation.x.sin(),
+            rotation.y.sin(),
+            rotation.y.cos() * rotation.x.cos(),
         );
 
         let up = glam::Vec3::new(0.0, 1.0, 0.0);
@@ -96,8 +124,95 @@ impl KeyboardMovementController {
         }
 
         if velocity.dot(velocity) > EPSILON {
-            game_object.transform.translation +=
-                self.move_speed * dt * velocity.normalize();
+            let translation = game_object.transform.translation() + self.move_speed * dt * velocity.normalize();
+            game_object.transform.set_translation(translation);
+        }
+    }
+
+    /// Six degrees of freedom, every rotation and movement is relative to the
+    /// current view, like a spaceship. The orientation is kept as a quaternion,
+    /// so no angle locks up, and mirrored into the transform's rotation.
+    pub fn move_free(
+        &mut self,
+        input: &Input,
+        dt: f32,
+        game_object: &mut GameObject,
+    ) {
+        let mut orientation = self.orientation.unwrap_or_else(|| CameraBuilder::orientation_xyz(game_object.transform.rotation()));
+
+        let mut rotate = glam::Vec3::ZERO;
+
+        if input.key_held(VirtualKeyCode::Right) {
+            rotate.y -= 1.0
+        }
+        if input.key_held(VirtualKeyCode::Left) {
+            rotate.y += 1.0
+        }
+        // Positive pitch looks towards -y, like in `move_in_plane_xz`
+        if input.key_held(VirtualKeyCode::Up) {
+            rotate.x -= 1.0
+        }
+        if input.key_held(VirtualKeyCode::Down) {
+            rotate.x += 1.0
+        }
+        rotate.z = Self::roll_input(input);
+
+        if rotate.dot(rotate) > EPSILON {
+            let angle = self.look_speed * dt;
+            let rotate = rotate.normalize();
+
+            orientation = (orientation
+                * glam::Quat::from_rotation_y(rotate.y * angle)
+                * glam::Quat::from_rotation_x(rotate.x * angle)
+                * glam::Quat::from_rotation_z(rotate.z * angle))
+                .normalize();
+
+            game_object.transform.set_rotation(CameraBuilder::rotation_xyz(orientation));
+        }
+
+        self.orientation = Some(orientation);
+
+        let forward = orientation * -glam::Vec3::Z;
+        let right = orientation * glam::Vec3::X;
+        let up = orientation * glam::Vec3::Y;
+
+        let mut velocity = glam::Vec3::ZERO;
+
+        if input.key_held(VirtualKeyCode::W) {
+            velocity += forward;
+        }
+        if input.key_held(VirtualKeyCode::S) {
+            velocity -= forward;
+        }
+        if input.key_held(VirtualKeyCode::A) {
+            velocity -= right;
+        }
+        if input.key_held(VirtualKeyCode::D) {
+            velocity += right;
+        }
+        if input.key_held(VirtualKeyCode::E) {
+            velocity -= up;
+        }
+        if input.key_held(VirtualKeyCode::Q) {
+            velocity += up;
+        }
+
+        if velocity.dot(velocity) > EPSILON {
+            let translation = game_object.transform.translation() + self.move_speed * dt * velocity.normalize();
+            game_object.transform.set_translation(translation);
         }
     }
+
+    fn roll_input(input: &Input) -> f32 {
+        let mut roll = 0.0;
+
+        if input.key_held(VirtualKeyCode::Z) {
+            roll += 1.0
+        }
+        if input.key_held(VirtualKeyCode::C) {
+            roll -= 1.0
+        }
+
+        roll
+    }
 }
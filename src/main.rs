@@ -1,18 +1,19 @@
-use std::time::{Instant, Duration};
+use std::time::Instant;
 
-use input::Input;
-use winit::{dpi::PhysicalSize, event::{Event, WindowEvent}, event_loop::ControlFlow};
+use winit::{dpi::PhysicalSize, event::{Event, WindowEvent, KeyboardInput, ElementState, VirtualKeyCode}, event_loop::ControlFlow};
 
-use crate::application::Application;
+use crate::{application::Application, settings::Settings, frame_stats::FrameStats, input::{InputRecording, RecordedEvent}};
 
 mod application;
 mod window;
 mod vulkan;
 mod frame_info;
+mod frame_stats;
 mod camera;
 mod keyboard_movement_controller;
 mod input;
 mod game_object;
+mod settings;
 
 pub use frame_info::*;
 pub use game_object::*;
@@ -20,57 +21,123 @@ pub use game_object::*;
 fn main() {
     simple_logger::SimpleLogger::new().without_timestamps().init().unwrap();
 
-    let (mut application, event_loop) = Application::new().unwrap();
+    let settings = Settings::default();
 
-    let mut current_time = Instant::now();
+    let recording = InputRecording::from_args(std::env::args().skip(1));
+    let mut input = input::create_input_source(&recording).unwrap();
+
+    let (mut application, event_loop) = Application::new(settings).unwrap();
 
-    let mut input = Input::new();
+    let mut current_time = Instant::now();
 
-    let mut frame_count_check_tp = Instant::now();
-    let mut frames = 0;
-    let mut fps = 0;
+    let mut frame_stats = FrameStats::new();
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
-
         let app = &mut application;
 
+        let wants_redraw = input.wants_frames() || app.needs_redraw(input.current());
+
+        *control_flow = if app.is_occluded() || !wants_redraw { ControlFlow::Wait } else { ControlFlow::Poll };
+
         match event {
-            Event::WindowEvent { event, .. } => {
-                input.update(&event);
-                app.update(&event);
+            // Replays drop the live user input
+            Event::WindowEvent { event, .. } if input.handle_event(&event) => {
+                handle_window_event(app, &event, control_flow);
+            }
+            // winit 0.26 has no `WindowEvent::Occluded`, suspension is the
+            // closest it reports. Forward `Occluded` here once it does.
+            Event::Suspended => {
+                app.set_occluded(true).unwrap();
+            }
+            Event::Resumed => {
+                app.set_occluded(false).unwrap();
 
-                match event {
-                    WindowEvent::CloseRequested => {
-                        *control_flow = ControlFlow::Exit
-                    }
-                    WindowEvent::Resized(PhysicalSize { width, height }) => {
-                        log::debug!("Resizing window");
-                        log::info!("New window size: {}x{}", width, height);
-                        app.resize().unwrap();
-                    }
-                    _ => ()
-                }
+                // Don't count the hidden time as one long frame
+                current_time = Instant::now();
             }
             Event::MainEventsCleared => {
-                app.window.raw_window.request_redraw();
+                if !app.is_occluded() && wants_redraw {
+                    app.window.raw_window.request_redraw();
+                } else {
+                    // Don't count the idle time as one long frame
+                    current_time = Instant::now();
+                }
             },
             Event::RedrawRequested(_) => {
-                let frame_time = current_time.elapsed().as_secs_f32();
+                let frame = match input.next_frame(current_time.elapsed().as_secs_f32()) {
+                    Some(frame) => frame,
+                    None => {
+                        log::info!("Input replay finished");
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                };
                 current_time = Instant::now();
-                app.run(&input, frame_time, fps).unwrap();
 
-                frames += 1;
+                for event in &frame.events {
+                    match event {
+                        // Resizing the window sends the live event that
+                        // recreates the swapchain
+                        RecordedEvent::Resized { width, height } => {
+                            app.window.raw_window.set_inner_size(PhysicalSize::new(*width, *height));
+                        }
+                        event => {
+                            handle_window_event(app, &event.to_window_event(), control_flow);
+                        }
+                    }
+                }
 
-                if frame_count_check_tp.elapsed() > Duration::from_secs(1) {
-                    frame_count_check_tp = Instant::now();
-                    fps = frames;
+                frame_stats.update(frame.frame_time);
+                app.run(input.current(), frame.frame_time, &frame_stats).unwrap();
 
-                    frames = 0;
+                if app.frame_dropped() {
+                    frame_stats.frame_dropped();
                 }
-
             }
             _ => (),
         }
     });
 }
+
+/// Passes a live or replayed window event to the application
+fn handle_window_event(app: &mut Application, event: &WindowEvent, control_flow: &mut ControlFlow) {
+    app.update(event);
+
+    match event {
+        WindowEvent::CloseRequested => {
+            *control_flow = ControlFlow::Exit
+        }
+        WindowEvent::Resized(PhysicalSize { width, height }) => {
+            log::debug!("Resizing window");
+            log::info!("New window size: {}x{}", width, height);
+            app.request_resize();
+        }
+        WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+            // Moving to a monitor with a different DPI changes the physical
+            // size, which doesn't always come with a Resized event
+            log::info!("New scale factor: {} ({}x{})", scale_factor, new_inner_size.width, new_inner_size.height);
+            app.request_resize();
+        }
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::F11),
+                ..
+            },
+            ..
+        } => {
+            app.request_frame_capture();
+        }
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::F12),
+                ..
+            },
+            ..
+        } => {
+            app.request_screenshot();
+        }
+        _ => ()
+    }
+}
@@ -0,0 +1,432 @@
+use std::time::Duration;
+
+use winit::event::VirtualKeyCode;
+
+use crate::{window::{Dimensions, WindowSettings}, vulkan::{CLEAR_COLOR, DEFAULT_FENCE_TIMEOUT, GpuPreference, pipeline::DepthBias, systems::{SHADOW_NEAR, SHADOW_FAR}}};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub window: WindowSettings,
+    /// When disabled no egui resources are created and the scene pass presents directly.
+    pub enable_egui: bool,
+    /// Hides and shows the egui UI, e.g. for screenshots without it
+    pub ui_toggle_key: Option<VirtualKeyCode>,
+    /// Multiplies the window's scale factor for the UI only, changed with
+    /// Ctrl +/-. The scene is scaled by `render_scale` instead.
+    pub ui_scale: f32,
+    /// Picks between the GPUs of e.g. a laptop, only read at startup
+    pub gpu_preference: GpuPreference,
+    /// Textures loaded from image files are downscaled to fit, e.g. to save
+    /// memory. They always fit the device limits.
+    pub max_texture_dimension: Option<u32>,
+    /// Extra checks of the validation layer, e.g. `GPU_ASSISTED` for out of
+    /// bounds descriptor indexing or `BEST_PRACTICES` for performance
+    /// warnings. Slows rendering down noticeably, only read at startup.
+    pub validation_features: &'static [ash::vk::ValidationFeatureEnableEXT],
+    /// Loads and saves compiled pipelines in `pipeline_cache.bin`, disabled
+    /// pipelines are compiled from scratch on every run
+    pub persistent_pipeline_cache: bool,
+    pub camera: CameraSettings,
+    /// Demo effect, slowly pulses the ambient light using the UBO time
+    pub pulse_ambient: bool,
+    /// Threads recording the scene draws into secondary command buffers,
+    /// 0 records everything on the main thread
+    pub recording_threads: usize,
+    /// Fraction of the window resolution the scene is rendered at, the UI
+    /// always renders at full resolution. Above 1 supersamples, e.g. 2 for
+    /// high quality stills.
+    pub render_scale: f32,
+    pub fixed_resolution: FixedResolutionSettings,
+    /// How long a frame may take on the GPU before the wait is logged and
+    /// retried, a hung GPU ends in an error instead of a frozen window
+    pub fence_timeout: Duration,
+    /// Depth the scene pass clears to, 0 for a reversed depth range
+    pub clear_depth: f32,
+    /// Stencil value the scene pass clears to
+    pub clear_stencil: u32,
+    /// Spreads the depth precision evenly over the view distance, for scenes
+    /// spanning huge distances. The lit scene shader writes its own depth,
+    /// which disables early depth testing for it, and per-object depth ranges
+    /// are ignored while this is enabled.
+    pub logarithmic_depth: bool,
+    /// Manual exposure the lit scene is multiplied with, 1 leaves it unchanged
+    pub exposure: f32,
+    /// Only redraws after input, UI changes or while something animates, and
+    /// sleeps until the next event otherwise. Saves power in static scenes.
+    pub redraw_on_demand: bool,
+    pub environment: EnvironmentSettings,
+    pub debug_view: DebugView,
+    pub gizmo: GizmoSettings,
+    pub wireframe: WireframeSettings,
+    /// Draws the simple render system's objects unculled with back faces
+    /// tinted red, so inverted winding shows up as red patches.
+    pub backface_debug: bool,
+    /// Draws the world space bounding box of every object
+    pub show_bounds: bool,
+    /// Draws the light billboards as fans of this many triangles, instead of
+    /// quads with a smooth disc cut out in the fragment shader
+    pub light_billboard_segments: Option<u32>,
+    pub decals: DecalSettings,
+    /// Only drawn without egui, which shows the same statistics
+    pub debug_text: DebugTextSettings,
+    pub ssao: SsaoSettings,
+    pub reflections: ReflectionSettings,
+    pub depth_window: DepthWindowSettings,
+    pub transparency: TransparencyMode,
+    pub taa: TaaSettings,
+    pub post_process: PostProcessSettings,
+    /// Rigged glTF file added to the scene, its first animation loops
+    pub skinned_model: Option<&'static str>,
+    /// Compiled fragment shader drawn over the whole scene, see
+    /// `FullscreenEffectSystem`. Replaces the temporal anti-aliasing resolve.
+    pub fullscreen_effect: Option<&'static str>,
+    /// Extra uses of the swapchain images, screenshots read them back with
+    /// `TRANSFER_SRC`. Flags the surface doesn't support are dropped with a
+    /// warning, `COLOR_ATTACHMENT` is always included.
+    pub swapchain_image_usage: ash::vk::ImageUsageFlags,
+    /// Presents in the extended linear sRGB (scRGB) color space on HDR
+    /// displays, falls back to SDR where the surface doesn't offer it. Only
+    /// read at startup.
+    pub hdr_output: bool,
+}
+
+/// Renders the scene at a fixed resolution, scaled up by whole pixels, e.g.
+/// for pixel art. Replaces `render_scale` while enabled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedResolutionSettings {
+    pub enabled: bool,
+    pub dimensions: Dimensions,
+    /// Fills the window around the scaled scene
+    pub letterbox_color: glam::Vec3,
+}
+
+/// Screen space ambient occlusion, darkens the ambient light in creases.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    /// Sample hemisphere radius in world units
+    pub radius: f32,
+    /// Depth difference below which samples don't count, avoids self occlusion acne
+    pub bias: f32,
+    /// 0 leaves the ambient light unchanged, 1 applies the full occlusion
+    pub intensity: f32,
+}
+
+/// An egui window showing the shadow map depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthWindowSettings {
+    pub open: bool,
+    /// Colorizes the view distance between `near` and `far` instead of
+    /// showing the raw depth, which crowds near 1
+    pub linear: bool,
+    pub near: f32,
+    pub far: f32,
+}
+
+/// Planar reflection on objects with a reflectivity, like the floor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReflectionSettings {
+    pub enabled: bool,
+    /// Fraction of the scene resolution the mirrored scene is rendered at
+    pub resolution_scale: f32,
+}
+
+/// Parameters of the passes after the scene, see `PostProcessUbo`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcessSettings {
+    /// Corner darkening of the example `shaders/vignette_effect.frag`
+    pub vignette: f32,
+}
+
+/// Temporal anti-aliasing. Needs swapchain images that can be blitted to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaaSettings {
+    pub enabled: bool,
+    /// Weight of the accumulated history, higher is smoother but blurrier in motion
+    pub feedback: f32,
+}
+
+/// Quality levels over the individual anti-aliasing settings. Temporal AA is
+/// the only technique the renderer has, so the presets trade its ghosting
+/// against its smoothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasingPreset {
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+impl AntiAliasingPreset {
+    pub const ALL: [AntiAliasingPreset; 4] = [
+        AntiAliasingPreset::Off,
+        AntiAliasingPreset::Low,
+        AntiAliasingPreset::Medium,
+        AntiAliasingPreset::High,
+    ];
+
+    pub fn taa(self) -> TaaSettings {
+        let (enabled, feedback) = match self {
+            AntiAliasingPreset::Off => (false, 0.9),
+            AntiAliasingPreset::Low => (true, 0.8),
+            AntiAliasingPreset::Medium => (true, 0.9),
+            AntiAliasingPreset::High => (true, 0.95),
+        };
+
+        TaaSettings { enabled, feedback }
+    }
+
+    /// Overwrites the anti-aliasing settings, the renderer picks them up at
+    /// the start of the next frame.
+    pub fn apply(self, settings: &mut Settings) {
+        settings.taa = self.taa();
+    }
+
+    /// The preset `settings` match, None after individual changes.
+    pub fn matching(settings: &Settings) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| {
+            let taa = preset.taa();
+            taa.enabled == settings.taa.enabled && (!taa.enabled || taa.feedback == settings.taa.feedback)
+        })
+    }
+}
+
+/// How objects with `MaterialComponent::opacity` below 1 are blended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Drawn back to front by the distance of their origins, wrong where
+    /// transparent objects intersect or surround each other
+    Sorted,
+    /// Order independent, accumulated in their own pass and composited over
+    /// the opaque scene, see `OitSystem`. An approximation that loses some
+    /// contrast between overlapping layers.
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub const ALL: [TransparencyMode; 2] = [TransparencyMode::Sorted, TransparencyMode::WeightedBlended];
+}
+
+/// Axis gizmo showing the camera orientation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GizmoSettings {
+    pub enabled: bool,
+    /// Side length of the gizmo viewport in pixels
+    pub size: u32,
+    pub corner: ScreenCorner,
+}
+
+/// Edges drawn over the shaded scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WireframeSettings {
+    pub enabled: bool,
+    /// Premultiplied edge color
+    pub color: glam::Vec4,
+    /// Only draws the edges of the picked object
+    pub selected_only: bool,
+}
+
+/// Textured quads placed on surfaces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecalSettings {
+    pub enabled: bool,
+    /// Keeps the decals in front of the surface they lie on, applied when the
+    /// decal pipeline is created
+    pub depth_bias: DepthBias,
+    /// Added to the mip level the decal texture is sampled at, negative
+    /// values sharpen and positive ones soften. Clamped to the device limit.
+    pub mip_lod_bias: f32,
+    /// Image file with straight alpha, the embedded `textures/soft_edge.png`
+    /// when unset
+    pub texture: Option<&'static str>,
+}
+
+/// Bitmap font statistics overlay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugTextSettings {
+    pub enabled: bool,
+    /// Integer scale of the 8x8 pixel glyphs
+    pub scale: u32,
+    pub corner: ScreenCorner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Replaces the lit scene with a debug visualization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    None,
+    /// Heatmap of how many fragments were drawn per pixel
+    Overdraw,
+    /// The G-buffer position, normal and albedo side by side
+    GBuffer,
+    /// Shadow map of the first point light, looking at the scene origin
+    ShadowDepth,
+}
+
+/// Lighting that doesn't come from the scene objects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvironmentSettings {
+    /// w is the intensity
+    pub ambient_light_color: glam::Vec4,
+    /// Image files of the +x, -x, +y, -y, +z and -z faces of a (blurred)
+    /// irradiance cubemap. Ambient light falls back to `ambient_light_color` without one.
+    pub irradiance_map: Option<[&'static str; 6]>,
+    pub irradiance_intensity: f32,
+    pub fog_enabled: bool,
+    pub fog_color: glam::Vec3,
+    /// Exponential squared density, per world unit
+    pub fog_density: f32,
+}
+
+/// Starting pose of the viewer, also used when resetting the camera.
+///
+/// World space has y pointing down. A zero rotation looks towards -z,
+/// `rotation.x` is the yaw and `rotation.y` the pitch, both in radians.
+/// `rotation.z` is unused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSettings {
+    pub translation: glam::Vec3,
+    /// Yaw, pitch and roll, see `CameraBuilder::set_view_xyz`
+    pub rotation: glam::Vec3,
+    /// Looks and moves relative to the current view in every direction, like
+    /// a spaceship, instead of walking in the ground plane
+    pub six_dof: bool,
+    pub projection: ProjectionMode,
+    /// World units the orthographic projection shows vertically, the width
+    /// follows from the aspect ratio
+    pub ortho_view_size: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionMode {
+    Perspective,
+    /// Parallel view without foreshortening. The depth is linear in the view
+    /// distance, logarithmic depth is ignored.
+    Orthographic,
+}
+
+impl ProjectionMode {
+    pub const ALL: [ProjectionMode; 2] = [ProjectionMode::Perspective, ProjectionMode::Orthographic];
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window: WindowSettings {
+                title: "Gentoo",
+                dimensions: Dimensions {
+                    width: 800,
+                    height: 600,
+                },
+                min_dimensions: Some(Dimensions {
+                    width: 200,
+                    height: 150,
+                }),
+                resizable: true,
+            },
+            enable_egui: true,
+            ui_toggle_key: Some(VirtualKeyCode::F1),
+            ui_scale: 1.0,
+            gpu_preference: GpuPreference::HighPerformance,
+            max_texture_dimension: None,
+            validation_features: &[],
+            persistent_pipeline_cache: true,
+            camera: CameraSettings {
+                translation: glam::vec3(0.0, 0.0, -2.5),
+                rotation: glam::Vec3::ZERO,
+                six_dof: false,
+                projection: ProjectionMode::Perspective,
+                ortho_view_size: 5.0,
+            },
+            pulse_ambient: false,
+            recording_threads: 0,
+            render_scale: 1.0,
+            fixed_resolution: FixedResolutionSettings {
+                enabled: false,
+                dimensions: Dimensions {
+                    width: 320,
+                    height: 240,
+                },
+                letterbox_color: glam::Vec3::ZERO,
+            },
+            fence_timeout: DEFAULT_FENCE_TIMEOUT,
+            clear_depth: 1.0,
+            clear_stencil: 0,
+            logarithmic_depth: false,
+            exposure: 1.0,
+            redraw_on_demand: false,
+            environment: EnvironmentSettings {
+                ambient_light_color: glam::vec4(1.0, 1.0, 1.0, 0.02),
+                irradiance_map: None,
+                irradiance_intensity: 0.1,
+                fog_enabled: false,
+                // Fades into the background
+                fog_color: glam::vec3(CLEAR_COLOR[0], CLEAR_COLOR[1], CLEAR_COLOR[2]),
+                fog_density: 0.1,
+            },
+            debug_view: DebugView::None,
+            gizmo: GizmoSettings {
+                enabled: true,
+                size: 96,
+                corner: ScreenCorner::BottomLeft,
+            },
+            wireframe: WireframeSettings {
+                enabled: false,
+                color: glam::vec4(0.0, 0.0, 0.0, 1.0),
+                selected_only: false,
+            },
+            backface_debug: false,
+            show_bounds: false,
+            light_billboard_segments: None,
+            decals: DecalSettings {
+                enabled: true,
+                depth_bias: DepthBias {
+                    constant_factor: -2.0,
+                    slope_factor: -2.0,
+                },
+                mip_lod_bias: 0.0,
+                texture: None,
+            },
+            debug_text: DebugTextSettings {
+                enabled: true,
+                scale: 2,
+                corner: ScreenCorner::TopLeft,
+            },
+            ssao: SsaoSettings {
+                enabled: false,
+                radius: 0.5,
+                bias: 0.025,
+                intensity: 1.0,
+            },
+            reflections: ReflectionSettings {
+                enabled: true,
+                resolution_scale: 0.5,
+            },
+            depth_window: DepthWindowSettings {
+                open: false,
+                linear: true,
+                near: SHADOW_NEAR,
+                far: SHADOW_FAR,
+            },
+            transparency: TransparencyMode::Sorted,
+            taa: TaaSettings {
+                enabled: false,
+                feedback: 0.9,
+            },
+            post_process: PostProcessSettings {
+                vignette: 0.5,
+            },
+            skinned_model: None,
+            fullscreen_effect: None,
+            swapchain_image_usage: ash::vk::ImageUsageFlags::COLOR_ATTACHMENT | ash::vk::ImageUsageFlags::TRANSFER_SRC,
+            hdr_output: false,
+        }
+    }
+}
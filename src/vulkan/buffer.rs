@@ -7,7 +7,7 @@ where
     T: PartialEq,
 {
     device: Rc<Device>,
-    pub mapped: Option<*mut c_void>,
+    persistent: Option<*mut c_void>,
     pub buffer: ash::vk::Buffer,
     memory: ash::vk::DeviceMemory,
     capacity: usize,
@@ -35,7 +35,7 @@ where
 
         Ok(Self {
             device,
-            mapped: None,
+            persistent: None,
             buffer,
             memory,
             capacity: size,
@@ -56,39 +56,56 @@ where
         }
     }
 
-    pub fn map(&mut self, element_offset: usize) -> anyhow::Result<(), GentooRenderError> {
+    /// Maps the elements from `element_offset` on, the returned guard unmaps
+    /// them when dropped unless the buffer is persistently mapped.
+    pub fn map(&mut self, element_offset: usize) -> anyhow::Result<MappedBuffer<'_, T>, GentooRenderError> {
+        if element_offset > self.capacity {
+            return Err(GentooRenderError::InvalidUsage("Cannot map buffer:: element offset is past the end of the buffer"));
+        }
+
+        let len = self.capacity - element_offset;
+
+        let (ptr, persistent) = match self.persistent {
+            Some(ptr) => (unsafe { (ptr as *mut T).add(element_offset) }, true),
+            None => (self.map_memory(element_offset)? as *mut T, false),
+        };
+
+        Ok(MappedBuffer {
+            buffer: self,
+            ptr,
+            len,
+            persistent,
+        })
+    }
+
+    /// Keeps the whole buffer mapped until it is dropped, for buffers written
+    /// every frame. Later `map` calls reuse the mapping.
+    pub fn map_persistent(&mut self) -> anyhow::Result<(), GentooRenderError> {
+        if self.persistent.is_none() {
+            self.persistent = Some(self.map_memory(0)?);
+        }
+
+        Ok(())
+    }
+
+    fn map_memory(&self, element_offset: usize) -> anyhow::Result<*mut c_void, GentooRenderError> {
         let size = self.capacity - element_offset;
         let mem_size = (std::mem::size_of::<T>() * size) as u64;
         let mem_offset = (std::mem::size_of::<T>() * element_offset) as u64;
 
         Ok(unsafe {
-            self.mapped = Some(self.device.logical_device.map_memory(
+            self.device.logical_device.map_memory(
                 self.memory,
                 mem_offset,
                 mem_size,
                 ash::vk::MemoryMapFlags::empty()
-            )?)
+            )?
         })
     }
 
-    pub fn unmap(&mut self) {
-        match self.mapped {
-            Some(_) => {
-                unsafe {
-                    self.device.logical_device.unmap_memory(self.memory);
-                }
-
-                self.mapped = None;
-            },
-            None => { },
-        }
-    }
-
-    pub fn write_to_buffer(&mut self, elements: &[T]) {
+    fn unmap_memory(&self) {
         unsafe {
-            elements
-                .as_ptr()
-                .copy_to_nonoverlapping(self.mapped.unwrap() as *mut _, elements.len());
+            self.device.logical_device.unmap_memory(self.memory);
         }
     }
 
@@ -120,10 +137,63 @@ where
     fn drop(&mut self) {
         log::debug!("Dropping buffer");
 
+        if self.persistent.take().is_some() {
+            self.unmap_memory();
+        }
+
+        unsafe {
+            self.device.destroy_buffer(self.buffer, self.memory);
+        }
+    }
+}
+
+/// Host access to a mapped range of a [`Buffer`], borrowing it so the pointer
+/// can't outlive the mapping. Only plain data can be accessed, the elements
+/// are copied as bytes.
+pub struct MappedBuffer<'a, T>
+where
+    T: PartialEq,
+{
+    buffer: &'a mut Buffer<T>,
+    ptr: *mut T,
+    len: usize,
+    persistent: bool,
+}
+
+impl<'a, T> MappedBuffer<'a, T>
+where
+    T: PartialEq + Copy,
+{
+    /// Copies `elements` to the start of the mapped range.
+    pub fn write_slice(&mut self, elements: &[T]) {
+        assert!(elements.len() <= self.len, "writing {} elements into a mapped range of {}", elements.len(), self.len);
+
+        unsafe {
+            self.ptr.copy_from_nonoverlapping(elements.as_ptr(), elements.len());
+        }
+    }
+
+    /// The GPU writes have to be finished and visible to the host.
+    pub fn as_slice(&self) -> &[T] {
         unsafe {
-            self.unmap();
-            self.device.logical_device.destroy_buffer(self.buffer, None);
-            self.device.logical_device.free_memory(self.memory, None);
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [T] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.ptr, self.len)
+        }
+    }
+}
+
+impl<'a, T> Drop for MappedBuffer<'a, T>
+where
+    T: PartialEq,
+{
+    fn drop(&mut self) {
+        if !self.persistent {
+            self.buffer.unmap_memory();
         }
     }
 }
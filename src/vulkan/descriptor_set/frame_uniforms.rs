@@ -0,0 +1,76 @@
+use std::rc::Rc;
+
+use crate::vulkan::{Device, GentooRenderError, Buffer, MAX_FRAMES_IN_FLIGHT};
+
+use super::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter};
+
+/// A system's own uniform buffer and descriptor set, one of each per frame in
+/// flight, so the system's parameters don't have to go through the `GlobalUbo`.
+/// The set is bound at whatever set index the system's pipeline layout puts
+/// `set_layout` at.
+pub struct FrameUniforms<T>
+where
+    T: PartialEq,
+{
+    pub set_layout: Rc<DescriptorSetLayout>,
+    buffers: Vec<Buffer<T>>,
+    descriptor_sets: Vec<ash::vk::DescriptorSet>,
+}
+
+impl<T> FrameUniforms<T>
+where
+    T: PartialEq + Copy,
+{
+    /// Allocates `MAX_FRAMES_IN_FLIGHT` sets from `pool`, e.g. the global pool,
+    /// which has to have room for them.
+    pub fn new(
+        device: Rc<Device>,
+        pool: &Rc<DescriptorPool>,
+        stage_flags: ash::vk::ShaderStageFlags,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::UNIFORM_BUFFER, stage_flags, 1)
+            .build()?;
+
+        let mut buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        let mut descriptor_sets = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let mut buffer = Buffer::new(
+                device.clone(),
+                1,
+                ash::vk::BufferUsageFlags::UNIFORM_BUFFER,
+                ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+            )?;
+
+            buffer.map_persistent()?;
+
+            let descriptor_set = pool.allocate_descriptor(&[set_layout.layout])?;
+
+            DescriptorSetWriter::new(set_layout.clone(), pool.clone())
+                .write_to_buffer(0, &[buffer.descriptor_info()])
+                .overwrite(descriptor_set);
+
+            buffers.push(buffer);
+            descriptor_sets.push(descriptor_set);
+        }
+
+        Ok(Self {
+            set_layout,
+            buffers,
+            descriptor_sets,
+        })
+    }
+
+    /// The GPU is done with the frame's previous uniforms once its fence was
+    /// waited for, so this is safe after `begin_frame`.
+    pub fn write(&mut self, frame_index: usize, uniforms: T) -> anyhow::Result<(), GentooRenderError> {
+        self.buffers[frame_index].map(0)?.write_slice(&[uniforms]);
+
+        Ok(())
+    }
+
+    pub fn descriptor_set(&self, frame_index: usize) -> ash::vk::DescriptorSet {
+        self.descriptor_sets[frame_index]
+    }
+}
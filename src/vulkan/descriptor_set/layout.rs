@@ -11,6 +11,8 @@ pub struct DescriptorSetLayout {
 pub struct DescriptorSetLayoutBuilder {
     device: Rc<Device>,
     bindings: HashMap<u32, ash::vk::DescriptorSetLayoutBinding>,
+    /// Reported by `build`
+    duplicate_binding: Option<u32>,
 }
 
 impl DescriptorSetLayout {
@@ -20,6 +22,7 @@ impl DescriptorSetLayout {
         DescriptorSetLayoutBuilder {
             device,
             bindings: HashMap::new(),
+            duplicate_binding: None,
         }
     }
 }
@@ -42,11 +45,9 @@ impl DescriptorSetLayoutBuilder {
         stage_flags: ash::vk::ShaderStageFlags,
         descriptor_count: u32,
     ) -> Self {
-        assert_eq!(
-            self.bindings.keys().filter(|&b| *b == binding).count(),
-            0,
-            "Binding already in use",
-        );
+        if self.bindings.contains_key(&binding) {
+            self.duplicate_binding.get_or_insert(binding);
+        }
 
         let layout_binding = ash::vk::DescriptorSetLayoutBinding {
             binding,
@@ -64,9 +65,14 @@ impl DescriptorSetLayoutBuilder {
     pub fn build(self) -> anyhow::Result<Rc<DescriptorSetLayout>, GentooRenderError> {
         let DescriptorSetLayoutBuilder {
             device,
-            bindings
+            bindings,
+            duplicate_binding,
         } = self;
 
+        if let Some(binding) = duplicate_binding {
+            return Err(GentooRenderError::DuplicateBinding(binding));
+        }
+
         let mut set_layout_bindings = Vec::new();
         for binding in bindings.values() {
             set_layout_bindings.push(*binding);
@@ -1,7 +1,9 @@
 mod layout;
 mod pool;
 mod writer;
+mod frame_uniforms;
 
 pub use layout::*;
 pub use pool::*;
 pub use writer::*;
+pub use frame_uniforms::*;
@@ -1,10 +1,12 @@
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc};
 
-use crate::vulkan::{Device, GentooRenderError};
+use crate::vulkan::{Device, GentooRenderError, ResourceKind};
 
 pub struct DescriptorPool {
     pub device: Rc<Device>,
     pub pool: ash::vk::DescriptorPool,
+    /// Sets currently allocated, they are freed along with the pool
+    allocated: Cell<usize>,
 }
 
 pub struct DescriptorPoolBuilder {
@@ -35,32 +37,46 @@ impl DescriptorPool {
             .set_layouts(layouts)
             .build();
 
-        Ok(unsafe {
+        let set = unsafe {
             self.device.logical_device.allocate_descriptor_sets(
                 &alloc_info,
             )?[0]
-        })
+        };
+
+        self.allocated.set(self.allocated.get() + 1);
+        self.device.resource_tracker.created(ResourceKind::DescriptorSet, 1);
+
+        Ok(set)
     }
 
     pub fn free_descriptors(
         &self,
         descriptors: &Vec<ash::vk::DescriptorSet>
     ) -> anyhow::Result<(), GentooRenderError> {
-        Ok(unsafe {
+        unsafe {
             self.device.logical_device.free_descriptor_sets(
                 self.pool,
                 descriptors,
             )?
-        })
+        }
+
+        self.allocated.set(self.allocated.get() - descriptors.len());
+        self.device.resource_tracker.destroyed(ResourceKind::DescriptorSet, descriptors.len());
+
+        Ok(())
     }
 
     pub fn reset_pool(&self) -> anyhow::Result<(), GentooRenderError> {
-        Ok(unsafe {
+        unsafe {
             self.device.logical_device.reset_descriptor_pool(
                 self.pool,
                 ash::vk::DescriptorPoolResetFlags::empty(),
             )?
-        })
+        }
+
+        self.device.resource_tracker.destroyed(ResourceKind::DescriptorSet, self.allocated.take());
+
+        Ok(())
     }
 }
 
@@ -71,6 +87,8 @@ impl Drop for DescriptorPool {
         unsafe {
             self.device.logical_device.destroy_descriptor_pool(self.pool, None)
         }
+
+        self.device.resource_tracker.destroyed(ResourceKind::DescriptorSet, self.allocated.get());
     }
 }
 
@@ -128,6 +146,7 @@ impl DescriptorPoolBuilder {
         Ok(Rc::new(DescriptorPool {
             device,
             pool,
+            allocated: Cell::new(0),
         }))
     }
 }
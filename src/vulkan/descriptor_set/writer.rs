@@ -25,19 +25,10 @@ impl DescriptorSetWriter {
         binding: u32,
         buffer_info: &[ash::vk::DescriptorBufferInfo],
     ) -> Self {
-        assert_eq!(
-            self.set_layout.bindings.keys().filter(|&b| *b == binding).count(),
-            1,
-            "Layout does not contain specified binding",
-        );
-
-        let binding_description = self.set_layout.bindings[&binding];
-
-        assert_eq!(
-            binding_description.descriptor_count,
-            1,
-            "Binding single descriptor info, but binding expects multiple",
-        );
+        let binding_description = match self.binding_description(binding) {
+            Some(binding_description) => binding_description,
+            None => return self,
+        };
 
         let write = ash::vk::WriteDescriptorSet::builder()
             .descriptor_type(binding_description.descriptor_type)
@@ -55,18 +46,10 @@ impl DescriptorSetWriter {
         binding: u32,
         image_info: &[ash::vk::DescriptorImageInfo],
     ) -> Self {
-        assert!(
-            self.set_layout.bindings.keys().filter(|&b| *b == binding).count() == 1,
-            "Layout does not contain specified binding",
-        );
-
-        let binding_description = self.set_layout.bindings[&binding];
-
-        assert_eq!(
-            binding_description.descriptor_count,
-            1,
-            "Binding single descriptor info, but binding expects multiple",
-        );
+        let binding_description = match self.binding_description(binding) {
+            Some(binding_description) => binding_description,
+            None => return self,
+        };
 
         let write = ash::vk::WriteDescriptorSet::builder()
             .descriptor_type(binding_description.descriptor_type)
@@ -79,6 +62,24 @@ impl DescriptorSetWriter {
         self
     }
 
+    /// Logs and skips the write when the layout has no single descriptor at `binding`.
+    fn binding_description(&self, binding: u32) -> Option<ash::vk::DescriptorSetLayoutBinding> {
+        let binding_description = match self.set_layout.bindings.get(&binding) {
+            Some(binding_description) => *binding_description,
+            None => {
+                log::error!("Layout does not contain binding {}, the write is skipped", binding);
+                return None;
+            }
+        };
+
+        if binding_description.descriptor_count != 1 {
+            log::error!("Binding {} expects {} descriptors but a single one is written, the write is skipped", binding, binding_description.descriptor_count);
+            return None;
+        }
+
+        Some(binding_description)
+    }
+
     pub fn build(&mut self) -> Option<ash::vk::DescriptorSet> {
         let result = self.pool.allocate_descriptor(&[self.set_layout.layout]);
 
@@ -1,6 +1,6 @@
-use std::{ffi::CStr, rc::Rc};
+use std::{cell::Cell, ffi::CStr, rc::Rc};
 
-use super::{GentooRenderError, Instance, ENABLE_VALIDATION_LAYERS, QueueFamilies};
+use super::{GentooRenderError, Instance, ENABLE_VALIDATION_LAYERS, QueueFamilies, ResourceTracker, ResourceKind};
 
 pub struct SwapchainSupportDetails {
     pub capabilities: ash::vk::SurfaceCapabilitiesKHR,
@@ -8,30 +8,99 @@ pub struct SwapchainSupportDetails {
     pub present_modes: Vec<ash::vk::PresentModeKHR>,
 }
 
+/// One side of an image blit, a single mip level of a color image.
+#[derive(Debug, Clone, Copy)]
+pub struct BlitTarget {
+    pub image: ash::vk::Image,
+    pub format: ash::vk::Format,
+    /// Layout the image is in before the blit, `UNDEFINED` discards the contents
+    pub layout: ash::vk::ImageLayout,
+    /// Layout the image is transitioned to after the blit
+    pub final_layout: ash::vk::ImageLayout,
+    pub mip_level: u32,
+    /// Corners of the blitted region
+    pub region: [ash::vk::Offset3D; 2],
+}
+
+/// Memory heap usage in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryHeapBudget {
+    pub device_local: bool,
+    /// None without `VK_EXT_memory_budget`
+    pub usage: Option<u64>,
+    /// What the process can allocate from the heap, the whole heap size
+    /// without `VK_EXT_memory_budget`
+    pub budget: u64,
+}
+
+/// Which GPU to render on when there are several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuPreference {
+    /// Prefers discrete GPUs
+    HighPerformance,
+    /// Prefers integrated GPUs, e.g. to save battery on laptops
+    LowPower,
+    /// The device at this index in the enumeration order, falls back to
+    /// `HighPerformance` when it doesn't exist or isn't suitable
+    Index(usize),
+}
+
+impl GpuPreference {
+    /// Higher is better, CPU implementations come last either way.
+    fn score(self, device_type: ash::vk::PhysicalDeviceType) -> u32 {
+        match (self, device_type) {
+            (GpuPreference::LowPower, ash::vk::PhysicalDeviceType::INTEGRATED_GPU) => 3,
+            (GpuPreference::LowPower, ash::vk::PhysicalDeviceType::DISCRETE_GPU) => 2,
+            (_, ash::vk::PhysicalDeviceType::DISCRETE_GPU) => 3,
+            (_, ash::vk::PhysicalDeviceType::INTEGRATED_GPU) => 2,
+            (_, ash::vk::PhysicalDeviceType::VIRTUAL_GPU) => 1,
+            _ => 0,
+        }
+    }
+}
+
 pub struct Device {
     pub instance: Instance,
     surface: ash::extensions::khr::Surface,
     pub surface_khr: ash::vk::SurfaceKHR,
     physical_device: ash::vk::PhysicalDevice,
     pub properties: ash::vk::PhysicalDeviceProperties,
+    /// Optional features that were available and got enabled
+    pub enabled_features: ash::vk::PhysicalDeviceFeatures,
+    /// `VK_EXT_memory_budget` is enabled
+    memory_budget_supported: bool,
     pub logical_device: ash::Device,
     pub queue_families: QueueFamilies,
     pub command_pool: ash::vk::CommandPool,
+    /// Checked for leaks when the device is dropped
+    pub resource_tracker: ResourceTracker,
+    /// Loaded textures above it are downscaled, see `set_max_texture_dimension`
+    max_texture_dimension: Cell<Option<u32>>,
 }
 
 impl Device {
-    pub fn new(window: &winit::window::Window) -> anyhow::Result<Rc<Self>, GentooRenderError> {
-        let instance = Instance::new()?;
+    pub fn new(
+        window: &winit::window::Window,
+        gpu_preference: GpuPreference,
+        validation_features: &[ash::vk::ValidationFeatureEnableEXT],
+        hdr_output: bool,
+    ) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let instance = Instance::new(validation_features, hdr_output)?;
         log::debug!("Vulkan Instance created");
         let (surface, surface_khr) = Self::create_surface(&instance, window)?;
         log::debug!("Vulkan Surface created");
-        let (physical_device, properties) = Self::pick_physical_device(&instance, &surface, surface_khr)?;
+        let (physical_device, properties) = Self::pick_physical_device(&instance, &surface, surface_khr, gpu_preference)?;
         log::debug!("Vulkan Physical Device created");
         let queue_families = QueueFamilies::new(&instance, &surface, surface_khr, physical_device)?;
         log::debug!("Vulkan Queue Families created");
-        let logical_device = Self::create_logical_device(&instance, physical_device, &queue_families)?;
+        let memory_budget_supported = Self::is_extension_supported(&instance, physical_device, ash::vk::ExtMemoryBudgetFn::name())?;
+        let (logical_device, enabled_features) = Self::create_logical_device(&instance, physical_device, &queue_families, memory_budget_supported)?;
         log::debug!("Vulkan Logical Device created");
-        let command_pool = Self::create_command_pool(&logical_device, &queue_families)?;
+        let command_pool = Self::new_command_pool(
+            &logical_device,
+            &queue_families,
+            ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER | ash::vk::CommandPoolCreateFlags::TRANSIENT,
+        )?;
         log::debug!("Vulkan Command Pool created");
 
         Ok(Rc::new(Self {
@@ -40,16 +109,89 @@ impl Device {
             surface_khr,
             physical_device,
             properties,
+            enabled_features,
+            memory_budget_supported,
             logical_device,
             queue_families,
             command_pool,
+            resource_tracker: ResourceTracker::default(),
+            max_texture_dimension: Cell::new(None),
         }))
     }
 
+    /// Textures loaded from images afterwards are downscaled to fit, to save
+    /// memory. None only keeps them within the device limits.
+    pub fn set_max_texture_dimension(&self, max_texture_dimension: Option<u32>) {
+        self.max_texture_dimension.set(max_texture_dimension);
+    }
+
+    /// Largest side a loaded 2D texture or cubemap face may have.
+    pub fn max_texture_dimension(&self, cubemap: bool) -> u32 {
+        let limit = if cubemap {
+            self.properties.limits.max_image_dimension_cube
+        } else {
+            self.properties.limits.max_image_dimension2_d
+        };
+
+        self.max_texture_dimension.get().map_or(limit, |max| max.min(limit))
+    }
+
     pub fn get_swapchain_support(&self) -> anyhow::Result<SwapchainSupportDetails, GentooRenderError> {
         Ok(Self::query_swapchain_support(&self.surface, self.surface_khr, self.physical_device)?)
     }
 
+    /// Usage and budget of every memory heap, falls back to the heap sizes
+    /// when the driver can't report usage.
+    pub fn memory_budget(&self) -> Vec<MemoryHeapBudget> {
+        let mut budget_properties = ash::vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = ash::vk::PhysicalDeviceMemoryProperties2::builder();
+
+        if self.memory_budget_supported {
+            properties = properties.push_next(&mut budget_properties);
+        }
+
+        let mut properties = properties.build();
+
+        unsafe {
+            self.instance.instance.get_physical_device_memory_properties2(self.physical_device, &mut properties);
+        }
+
+        let memory_properties = properties.memory_properties;
+
+        memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| MemoryHeapBudget {
+                device_local: heap.flags.contains(ash::vk::MemoryHeapFlags::DEVICE_LOCAL),
+                usage: self.memory_budget_supported.then(|| budget_properties.heap_usage[index]),
+                budget: if self.memory_budget_supported { budget_properties.heap_budget[index] } else { heap.size },
+            })
+            .collect()
+    }
+
+    /// Turns out of memory results into `OutOfMemory` and logs the heap
+    /// budgets, other results are passed through.
+    fn allocation_error(&self, result: ash::vk::Result, size: Option<ash::vk::DeviceSize>, resource: String) -> GentooRenderError {
+        let memory = match result {
+            ash::vk::Result::ERROR_OUT_OF_HOST_MEMORY => "host",
+            ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => "device",
+            _ => return result.into(),
+        };
+
+        let error = GentooRenderError::OutOfMemory { memory, size, resource };
+
+        log::error!("{}", error);
+
+        for (index, heap) in self.memory_budget().iter().enumerate() {
+            match heap.usage {
+                Some(usage) => log::error!("Heap {} (device local: {}): {} of {} bytes used", index, heap.device_local, usage, heap.budget),
+                None => log::error!("Heap {} (device local: {}): {} bytes", index, heap.device_local, heap.budget),
+            }
+        }
+
+        error
+    }
+
     pub fn find_memory_type(
         &self,
         type_filter: u32,
@@ -106,8 +248,11 @@ impl Device {
             .usage(usage)
             .sharing_mode(ash::vk::SharingMode::EXCLUSIVE);
 
+        let resource = || format!("a buffer with {:?}", usage);
+
         let buffer = unsafe {
-            self.logical_device.create_buffer(&create_info, None)?
+            self.logical_device.create_buffer(&create_info, None)
+                .map_err(|result| self.allocation_error(result, Some(size), resource()))?
         };
 
         let mem_requirements = unsafe {
@@ -121,16 +266,33 @@ impl Device {
         );
 
         let buffer_memory = unsafe {
-            self.logical_device.allocate_memory(&alloc_info, None)?
+            self.logical_device.allocate_memory(&alloc_info, None)
+                .map_err(|result| {
+                    self.logical_device.destroy_buffer(buffer, None);
+                    self.allocation_error(result, Some(mem_requirements.size), resource())
+                })?
         };
 
         unsafe {
             self.logical_device.bind_buffer_memory(buffer, buffer_memory, 0)?
         };
 
+        self.resource_tracker.created(ResourceKind::Buffer, 1);
+
         Ok((buffer, buffer_memory))
     }
 
+    /// Destroys a buffer from `create_buffer` and frees its memory. Null
+    /// handles are ignored, like Vulkan does.
+    pub unsafe fn destroy_buffer(&self, buffer: ash::vk::Buffer, memory: ash::vk::DeviceMemory) {
+        self.logical_device.destroy_buffer(buffer, None);
+        self.logical_device.free_memory(memory, None);
+
+        if buffer != ash::vk::Buffer::null() {
+            self.resource_tracker.destroyed(ResourceKind::Buffer, 1);
+        }
+    }
+
     pub fn begin_single_time_commands(&self) -> anyhow::Result<ash::vk::CommandBuffer, GentooRenderError> {
         let alloc_info = ash::vk::CommandBufferAllocateInfo::builder()
             .level(ash::vk::CommandBufferLevel::PRIMARY)
@@ -170,13 +332,22 @@ impl Device {
         Ok(())
     }
 
+    /// Creates a graphics command pool independent of `command_pool`.
+    ///
+    /// Command pools are externally synchronized: a pool and every command
+    /// buffer allocated from it may only be used by one thread at a time.
+    /// The caller owns the pool and has to destroy it before the device.
+    pub fn create_command_pool(&self, flags: ash::vk::CommandPoolCreateFlags) -> anyhow::Result<ash::vk::CommandPool, GentooRenderError> {
+        Self::new_command_pool(&self.logical_device, &self.queue_families, flags)
+    }
+
     pub fn copy_buffer(
         &self,
         src_buffer: ash::vk::Buffer,
         dst_buffer: ash::vk::Buffer,
         size: ash::vk::DeviceSize,
     ) -> anyhow::Result<(), GentooRenderError> {
-        let command_buffer = self.begin_single_time_commands().unwrap(); // fix unwrap
+        let command_buffer = self.begin_single_time_commands()?;
 
         let copy_region = ash::vk::BufferCopy::builder()
             .src_offset(0)
@@ -193,13 +364,149 @@ impl Device {
         Ok(())
     }
 
+    /// Blits between two images in a one-off command buffer and waits for it to finish.
+    pub fn blit_image(
+        &self,
+        src: BlitTarget,
+        dst: BlitTarget,
+        filter: ash::vk::Filter,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let command_buffer = self.begin_single_time_commands()?;
+
+        self.cmd_blit_image(command_buffer, src, dst, filter)?;
+
+        self.end_single_time_commands(command_buffer)
+    }
+
+    /// Records a blit between two images, including the layout transitions
+    /// into the transfer layouts and on to the final layouts.
+    pub fn cmd_blit_image(
+        &self,
+        command_buffer: ash::vk::CommandBuffer,
+        src: BlitTarget,
+        dst: BlitTarget,
+        filter: ash::vk::Filter,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let mut src_features = ash::vk::FormatFeatureFlags::BLIT_SRC;
+
+        if filter == ash::vk::Filter::LINEAR {
+            src_features |= ash::vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        }
+
+        self.check_format_features(src.format, src_features)?;
+        self.check_format_features(dst.format, ash::vk::FormatFeatureFlags::BLIT_DST)?;
+
+        let barrier = |target: &BlitTarget, old_layout, new_layout| {
+            ash::vk::ImageMemoryBarrier::builder()
+                .image(target.image)
+                .subresource_range(
+                    ash::vk::ImageSubresourceRange {
+                        aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                        base_mip_level: target.mip_level,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    }
+                )
+                .src_access_mask(ash::vk::AccessFlags::MEMORY_READ | ash::vk::AccessFlags::MEMORY_WRITE)
+                .dst_access_mask(ash::vk::AccessFlags::MEMORY_READ | ash::vk::AccessFlags::MEMORY_WRITE)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .build()
+        };
+
+        let subresource = |target: &BlitTarget| {
+            ash::vk::ImageSubresourceLayers::builder()
+                .aspect_mask(ash::vk::ImageAspectFlags::COLOR)
+                .mip_level(target.mip_level)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build()
+        };
+
+        unsafe {
+            self.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::ALL_COMMANDS,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(&src, src.layout, ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                    barrier(&dst, dst.layout, ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                ],
+            );
+
+            self.logical_device.cmd_blit_image(
+                command_buffer,
+                src.image,
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.image,
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[ash::vk::ImageBlit::builder()
+                    .src_subresource(subresource(&src))
+                    .src_offsets(src.region)
+                    .dst_subresource(subresource(&dst))
+                    .dst_offsets(dst.region)
+                    .build()],
+                filter,
+            );
+
+            self.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::PipelineStageFlags::ALL_COMMANDS,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(&src, ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL, src.final_layout),
+                    barrier(&dst, ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL, dst.final_layout),
+                ],
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn check_format_features(
+        &self,
+        format: ash::vk::Format,
+        features: ash::vk::FormatFeatureFlags,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let properties = unsafe {
+            self.instance.instance.get_physical_device_format_properties(self.physical_device, format)
+        };
+
+        if !properties.optimal_tiling_features.contains(features) {
+            log::error!("Format {:?} does not support {:?}", format, features);
+
+            return Err(GentooRenderError::UnsupportedFormatFeature {
+                format,
+                feature: features & !properties.optimal_tiling_features,
+            });
+        }
+
+        Ok(())
+    }
+
     pub fn create_image_with_info(
         &self,
         image_info: &ash::vk::ImageCreateInfo,
         properties: ash::vk::MemoryPropertyFlags,
     ) -> anyhow::Result<(ash::vk::Image, ash::vk::DeviceMemory), GentooRenderError> {
+        let resource = || format!(
+            "a {}x{} {:?} image with {:?}",
+            image_info.extent.width,
+            image_info.extent.height,
+            image_info.format,
+            image_info.usage,
+        );
+
         let image = unsafe {
-            self.logical_device.create_image(image_info, None)?
+            self.logical_device.create_image(image_info, None)
+                .map_err(|result| self.allocation_error(result, None, resource()))?
         };
 
         let mem_requirements = unsafe {
@@ -211,16 +518,54 @@ impl Device {
             .memory_type_index(self.find_memory_type(mem_requirements.memory_type_bits, properties).unwrap());
 
         let image_memory = unsafe {
-            self.logical_device.allocate_memory(&alloc_info, None)?
+            self.logical_device.allocate_memory(&alloc_info, None)
+                .map_err(|result| {
+                    self.logical_device.destroy_image(image, None);
+                    self.allocation_error(result, Some(mem_requirements.size), resource())
+                })?
         };
 
         unsafe {
             self.logical_device.bind_image_memory(image, image_memory, 0)?
         }
 
+        self.resource_tracker.created(ResourceKind::Image, 1);
+
         Ok((image, image_memory))
     }
 
+    /// Destroys an image from `create_image_with_info` and frees its memory.
+    /// Null handles are ignored, like Vulkan does.
+    pub unsafe fn destroy_image(&self, image: ash::vk::Image, memory: ash::vk::DeviceMemory) {
+        self.logical_device.destroy_image(image, None);
+        self.logical_device.free_memory(memory, None);
+
+        if image != ash::vk::Image::null() {
+            self.resource_tracker.destroyed(ResourceKind::Image, 1);
+        }
+    }
+
+    pub fn create_image_view(
+        &self,
+        view_info: &ash::vk::ImageViewCreateInfo,
+    ) -> anyhow::Result<ash::vk::ImageView, GentooRenderError> {
+        let view = unsafe {
+            self.logical_device.create_image_view(view_info, None)?
+        };
+
+        self.resource_tracker.created(ResourceKind::ImageView, 1);
+
+        Ok(view)
+    }
+
+    pub unsafe fn destroy_image_view(&self, view: ash::vk::ImageView) {
+        self.logical_device.destroy_image_view(view, None);
+
+        if view != ash::vk::ImageView::null() {
+            self.resource_tracker.destroyed(ResourceKind::ImageView, 1);
+        }
+    }
+
     fn create_surface(
         instance: &Instance,
         window: &winit::window::Window,
@@ -238,6 +583,7 @@ impl Device {
         instance: &Instance,
         surface: &ash::extensions::khr::Surface,
         surface_khr: ash::vk::SurfaceKHR,
+        gpu_preference: GpuPreference,
     ) -> anyhow::Result<(ash::vk::PhysicalDevice, ash::vk::PhysicalDeviceProperties), GentooRenderError> {
         let physical_devices = unsafe {
             instance.instance.enumerate_physical_devices()?
@@ -245,16 +591,56 @@ impl Device {
 
         log::debug!("Physical Device count: {}", physical_devices.len());
 
-        let physical_device = physical_devices
-            .into_iter()
-            .find(|physical_device| Self::is_physical_device_suitable(instance, surface, surface_khr, *physical_device).unwrap()) // TODO: fix unwrap?
-            .expect("No suitable physical device found");
+        let mut suitable_devices = Vec::with_capacity(physical_devices.len());
+        for (index, physical_device) in physical_devices.into_iter().enumerate() {
+            if Self::is_physical_device_suitable(instance, surface, surface_khr, physical_device)? {
+                let properties = unsafe { instance.instance.get_physical_device_properties(physical_device) };
 
-        let physical_device_properties = unsafe { instance.instance.get_physical_device_properties(physical_device) };
+                suitable_devices.push((index, physical_device, properties));
+            }
+        }
 
-        log::debug!("Selected Physical Device: {:?}", unsafe {
-            CStr::from_ptr(physical_device_properties.device_name.as_ptr())
-        });
+        let forced = match gpu_preference {
+            GpuPreference::Index(forced_index) => {
+                let forced = suitable_devices.iter().find(|(index, _, _)| *index == forced_index);
+
+                if forced.is_none() {
+                    log::warn!("Physical device {} doesn't exist or isn't suitable, falling back to the high performance preference", forced_index);
+                }
+
+                forced
+            },
+            _ => None,
+        };
+
+        let (selected, reason) = match forced {
+            Some(selected) => (selected, "forced by index"),
+            None => {
+                let selected = suitable_devices
+                    .iter()
+                    // Ties go to the device enumerated first
+                    .max_by_key(|(index, _, properties)| (gpu_preference.score(properties.device_type), std::cmp::Reverse(*index)))
+                    .expect("No suitable physical device found");
+
+                let reason = match gpu_preference {
+                    GpuPreference::LowPower => "low power preference",
+                    _ => "high performance preference",
+                };
+
+                (selected, reason)
+            },
+        };
+
+        let (index, physical_device, physical_device_properties) = *selected;
+
+        log::info!(
+            "Selected physical device {}: {:?} ({:?}, {} of {} suitable)",
+            index,
+            unsafe { CStr::from_ptr(physical_device_properties.device_name.as_ptr()) },
+            physical_device_properties.device_type,
+            reason,
+            suitable_devices.len(),
+        );
 
         Ok((physical_device, physical_device_properties))
     }
@@ -293,12 +679,28 @@ impl Device {
         instance: &Instance,
         physical_device: ash::vk::PhysicalDevice,
         queue_families: &QueueFamilies,
-    ) -> anyhow::Result<ash::Device, GentooRenderError> {
+        memory_budget_supported: bool,
+    ) -> anyhow::Result<(ash::Device, ash::vk::PhysicalDeviceFeatures), GentooRenderError> {
         let queue_create_infos = queue_families.get_vec().unwrap();
 
-        let physical_device_features = ash::vk::PhysicalDeviceFeatures::builder();
+        let supported_features = unsafe {
+            instance.instance.get_physical_device_features(physical_device)
+        };
+
+        // Pipelines asking for depth clamp fall back to clipping without it
+        let physical_device_features = ash::vk::PhysicalDeviceFeatures::builder()
+            .depth_clamp(supported_features.depth_clamp != 0)
+            .sampler_anisotropy(supported_features.sampler_anisotropy != 0)
+            .tessellation_shader(supported_features.tessellation_shader != 0)
+            .fill_mode_non_solid(supported_features.fill_mode_non_solid != 0)
+            .build();
+
+        let (_, mut logical_device_extensions_ptrs) = Self::get_device_extensions();
 
-        let (_, logical_device_extensions_ptrs) = Self::get_device_extensions();
+        // Optional, only reports memory usage
+        if memory_budget_supported {
+            logical_device_extensions_ptrs.push(ash::vk::ExtMemoryBudgetFn::name().as_ptr());
+        }
 
         let mut create_info = ash::vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_create_infos)
@@ -315,19 +717,17 @@ impl Device {
             instance.instance.create_device(physical_device, &create_info, None)?
         };
     
-        Ok(logical_device)
+        Ok((logical_device, physical_device_features))
     }
 
-    fn create_command_pool(
+    fn new_command_pool(
         logical_device: &ash::Device,
         queue_families: &QueueFamilies,
+        flags: ash::vk::CommandPoolCreateFlags,
     ) -> anyhow::Result<ash::vk::CommandPool, GentooRenderError> {
         let create_info = ash::vk::CommandPoolCreateInfo::builder()
             .queue_family_index(queue_families.graphics.unwrap() as u32)
-            .flags(
-                ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER
-                    | ash::vk::CommandPoolCreateFlags::TRANSIENT,
-            );
+            .flags(flags);
 
         Ok(unsafe {
             logical_device.create_command_pool(&create_info, None)?
@@ -356,13 +756,7 @@ impl Device {
         let (required_extensions, _) = Self::get_device_extensions();
 
         for extension in required_extensions.iter() {
-            let found = available_extensions.iter().any(|ext| {
-                let name = unsafe {
-                    CStr::from_ptr(ext.extension_name.as_ptr())
-                };
-
-                extension == &name
-            });
+            let found = Self::is_extension_available(&available_extensions, extension);
 
             if !found {
                 log::error!(
@@ -377,6 +771,28 @@ impl Device {
         Ok(true)
     }
 
+    fn is_extension_supported(
+        instance: &Instance,
+        physical_device: ash::vk::PhysicalDevice,
+        extension: &CStr,
+    ) -> anyhow::Result<bool, GentooRenderError> {
+        let available_extensions = unsafe {
+            instance.instance.enumerate_device_extension_properties(physical_device)?
+        };
+
+        Ok(Self::is_extension_available(&available_extensions, extension))
+    }
+
+    fn is_extension_available(available_extensions: &[ash::vk::ExtensionProperties], extension: &CStr) -> bool {
+        available_extensions.iter().any(|ext| {
+            let name = unsafe {
+                CStr::from_ptr(ext.extension_name.as_ptr())
+            };
+
+            extension == name
+        })
+    }
+
     fn query_swapchain_support(
         surface: &ash::extensions::khr::Surface,
         surface_khr: ash::vk::SurfaceKHR,
@@ -413,5 +829,8 @@ impl Drop for Device {
 
             self.surface.destroy_surface(self.surface_khr, None);
         }
+
+        // Everything holding the device is gone, so all objects should be too
+        self.resource_tracker.check_balanced();
     }
 }
@@ -1,6 +1,9 @@
-use std::{rc::Rc, ffi::c_void};
+use std::rc::Rc;
 
-use crate::{window::Window, vulkan::{Swapchain, Device, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}, GentooRenderError, pipeline::{Pipeline, PipelineCache}, Buffer}};
+use crate::{window::Window, vulkan::{Swapchain, Device, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}, GentooRenderError, ShaderSource, VertexLayout, VertexSemantic, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, Buffer, Sampler}};
+
+/// Swapchain images are single sampled, see `create_render_pass`
+const UI_SAMPLE_COUNT: ash::vk::SampleCountFlags = ash::vk::SampleCountFlags::TYPE_1;
 
 pub struct EGuiIntegration {
     pub egui_ctx: egui::CtxRef,
@@ -9,13 +12,15 @@ pub struct EGuiIntegration {
     physical_width: u32,
     physical_height: u32,
     scale_factor: f64,
+    /// Multiplies `scale_factor`, see `set_ui_scale`
+    ui_scale: f32,
 
     device: Rc<Device>,
     descriptor_pool: Rc<DescriptorPool>,
     descriptor_set_layouts: Vec<Rc<DescriptorSetLayout>>,
     pipeline_layout: ash::vk::PipelineLayout,
     pipeline: Pipeline,
-    sampler: ash::vk::Sampler,
+    sampler: Rc<Sampler>,
     render_pass: ash::vk::RenderPass,
     framebuffer_color_image_views: Vec<ash::vk::ImageView>,
     framebuffers: Vec<ash::vk::Framebuffer>,
@@ -82,24 +87,12 @@ impl EGuiIntegration {
             4 * std::mem::size_of::<f32>() as u32 + 4 * std::mem::size_of::<u8>() as u32,
         )?;
 
-        let sampler = unsafe {
-            device.logical_device.create_sampler(
-                &ash::vk::SamplerCreateInfo::builder()
-                .address_mode_u(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
-                .address_mode_v(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
-                .address_mode_w(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
-                .anisotropy_enable(false)
-                .min_filter(ash::vk::Filter::LINEAR)
-                .mag_filter(ash::vk::Filter::LINEAR)
-                .mipmap_mode(ash::vk::SamplerMipmapMode::LINEAR)
-                .min_lod(0.0)
-                .max_lod(ash::vk::LOD_CLAMP_NONE), 
-                None)?
-        };
+        let sampler = Sampler::new(device.clone())
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
 
         let (framebuffer_color_image_views, framebuffers) = Self::create_framebuffers(
             device.clone(),
-            window,
             swapchain,
             render_pass,
             surface_format,
@@ -123,8 +116,8 @@ impl EGuiIntegration {
                 ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
             )?;
 
-            vertex_buffer.map(0)?;
-            index_buffer.map(0)?;
+            vertex_buffer.map_persistent()?;
+            index_buffer.map_persistent()?;
 
             vertex_buffers.push(vertex_buffer);
             index_buffers.push(index_buffer);
@@ -148,9 +141,10 @@ impl EGuiIntegration {
             egui_ctx,
             egui_winit: egui_winit::State::new(&window.raw_window),
             
-            physical_width: window.raw_window.inner_size().width,
-            physical_height: window.raw_window.inner_size().height,
+            physical_width: swapchain.width(),
+            physical_height: swapchain.height(),
             scale_factor: window.raw_window.scale_factor(),
+            ui_scale: 1.0,
 
             device,
             descriptor_pool,
@@ -184,7 +178,51 @@ impl EGuiIntegration {
     }
 
     pub fn begin_frame(&mut self, window: &Window) {
-        self.egui_ctx.begin_frame(self.egui_winit.take_egui_input(&window.raw_window));
+        let mut raw_input = self.egui_winit.take_egui_input(&window.raw_window);
+
+        // egui_winit only knows the window's scale factor and would size the
+        // screen with last frame's scale after a change
+        let pixels_per_point = self.pixels_per_point();
+        raw_input.pixels_per_point = Some(pixels_per_point);
+        raw_input.screen_rect = raw_input.screen_rect.map(|_| egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(self.physical_width as f32, self.physical_height as f32) / pixels_per_point,
+        ));
+
+        self.egui_ctx.begin_frame(raw_input);
+    }
+
+    /// Makes a color image view usable with `egui::Image`. It is sampled in
+    /// `SHADER_READ_ONLY_OPTIMAL` layout and has to outlive the integration.
+    pub fn register_user_texture(&mut self, image_view: ash::vk::ImageView) -> anyhow::Result<egui::TextureId, GentooRenderError> {
+        let set = DescriptorSetWriter::new(self.user_texture_layout.clone(), self.descriptor_pool.clone())
+            .write_image(0, &[ash::vk::DescriptorImageInfo::builder()
+                .image_view(image_view)
+                .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .sampler(self.sampler.sampler)
+                .build()])
+            .build()
+            .ok_or(GentooRenderError::InvalidUsage("Out of egui user texture descriptor sets"))?;
+
+        self.user_textures.push(Some(set));
+
+        Ok(egui::TextureId::User(self.user_textures.len() as u64 - 1))
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+
+    /// Scales the UI on top of the window's scale factor, e.g. to enlarge it
+    /// on high DPI screens. Applied from the next frame, the font atlas is
+    /// rebuilt for the new size.
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.ui_scale = ui_scale;
+    }
+
+    /// Physical pixels per egui point
+    fn pixels_per_point(&self) -> f32 {
+        self.scale_factor as f32 * self.ui_scale
     }
 
     pub fn on_event(&mut self, event: &winit::event::WindowEvent) -> bool {
@@ -198,11 +236,13 @@ impl EGuiIntegration {
         self.egui_winit.on_event(&self.egui_ctx, event)
     }
 
-    pub fn end_frame(&mut self, window: &Window) -> Vec<egui::epaint::ClippedShape> {
+    /// Also returns whether egui needs another frame, e.g. for an animation.
+    pub fn end_frame(&mut self, window: &Window) -> (Vec<egui::epaint::ClippedShape>, bool) {
         let (egui_output, shapes) = self.egui_ctx.end_frame();
+        let needs_repaint = egui_output.needs_repaint;
         self.egui_winit.handle_output(&window.raw_window, &self.egui_ctx, egui_output);
 
-        shapes
+        (shapes, needs_repaint)
     }
 
     pub fn paint(
@@ -212,21 +252,10 @@ impl EGuiIntegration {
         clipped_meshes: Vec<egui::ClippedMesh>,
     ) -> anyhow::Result<(), GentooRenderError> {
         let index = swapchain_image_index;
+        let pixels_per_point = self.pixels_per_point();
 
         self.upload_font_texture(command_buffer, &self.egui_ctx.font_image())?;
 
-        let mut vertex_buffer_ptr = self.vertex_buffers[index].mapped.unwrap();
-
-        let vertex_buffer_ptr_end = unsafe {
-            vertex_buffer_ptr.add(Self::vertex_buffer_size() as usize)
-        };
-
-        let mut index_buffer_ptr = self.index_buffers[index].mapped.unwrap();
-
-        let index_buffer_ptr_end = unsafe {
-            index_buffer_ptr.add(Self::index_buffer_size() as usize)
-        };
-
         unsafe {
             self.device.logical_device.cmd_begin_render_pass(
                 command_buffer,
@@ -267,8 +296,8 @@ impl EGuiIntegration {
                     .build()],
             );
 
-            let width_points = self.physical_width as f32 / self.scale_factor as f32;
-            let height_points = self.physical_height as f32 / self.scale_factor as f32;
+            let width_points = self.physical_width as f32 / pixels_per_point;
+            let height_points = self.physical_height as f32 / pixels_per_point;
             
             self.device.logical_device.cmd_push_constants(
                 command_buffer,
@@ -287,6 +316,9 @@ impl EGuiIntegration {
             );
         }
 
+        let mut vertices = self.vertex_buffers[index].map(0)?;
+        let mut indices = self.index_buffers[index].map(0)?;
+
         let mut vertex_base = 0;
         let mut index_base = 0;
 
@@ -325,43 +357,22 @@ impl EGuiIntegration {
                 continue;
             }
 
-            // self.vertex_buffers[index].write_to_buffer(&mesh.vertices);
-            // self.index_buffers[index].write_to_buffer(&mesh.indices);
+            let vertex_end = vertex_base as usize + mesh.vertices.len();
+            let index_end = index_base as usize + mesh.indices.len();
 
-            let v_slice = &mesh.vertices;
-            let v_size = std::mem::size_of_val(&v_slice[0]);
-            let v_copy_size = v_slice.len() * v_size;
-
-            let i_slice = &mesh.indices;
-            let i_size = std::mem::size_of_val(&i_slice[0]);
-            let i_copy_size = i_slice.len() * i_size;
-
-            let vertex_buffer_ptr_next = unsafe {
-                vertex_buffer_ptr.add(v_copy_size)
-            };
-
-            let index_buffer_ptr_next = unsafe {
-                index_buffer_ptr.add(i_copy_size)
-            };
-
-            if vertex_buffer_ptr_next >= vertex_buffer_ptr_end || index_buffer_ptr_next >= index_buffer_ptr_end {
-                panic!("egui paint out of memory");
+            if vertex_end > vertices.as_slice().len() || index_end > indices.as_slice().len() {
+                log::warn!("egui meshes don't fit into the vertex and index buffers, the rest of the UI is skipped this frame");
+                break;
             }
 
-            // map memory
-            unsafe {
-                vertex_buffer_ptr.copy_from(v_slice.as_ptr() as *const c_void, v_copy_size);
-                index_buffer_ptr.copy_from(i_slice.as_ptr() as *const c_void, i_copy_size);
-            };
-
-            vertex_buffer_ptr = vertex_buffer_ptr_next;
-            index_buffer_ptr = index_buffer_ptr_next;
+            vertices.as_slice_mut()[vertex_base as usize..vertex_end].copy_from_slice(&mesh.vertices);
+            indices.as_slice_mut()[index_base as usize..index_end].copy_from_slice(&mesh.indices);
 
             unsafe {
                 let min = rect.min;
                 let min = egui::Pos2 {
-                    x: min.x * self.scale_factor as f32,
-                    y: min.y * self.scale_factor as f32,
+                    x: min.x * pixels_per_point,
+                    y: min.y * pixels_per_point,
                 };
 
                 let min = egui::Pos2 {
@@ -371,8 +382,8 @@ impl EGuiIntegration {
 
                 let max = rect.max;
                 let max = egui::Pos2 {
-                    x: max.x * self.scale_factor as f32,
-                    y: max.y * self.scale_factor as f32,
+                    x: max.x * pixels_per_point,
+                    y: max.y * pixels_per_point,
                 };
 
                 let max = egui::Pos2 {
@@ -436,17 +447,20 @@ impl EGuiIntegration {
         }
 
         let dimensions = (texture.width as u64, texture.height as u64);
+        // The font pixels are linear coverage. Replicating them gives premultiplied
+        // white, the same texels as egui's `srgba_pixels(1.0)` once decoded. It has
+        // to stay in a UNORM image so sampling doesn't apply the sRGB curve on top.
+        // Straight alpha white would fringe with `BlendMode::PremultipliedAlpha`.
         let data = texture
             .pixels
             .iter()
-            .flat_map(|&r| vec![r, r, r, r])
+            .flat_map(|&coverage| [coverage; 4])
             .collect::<Vec<u8>>();
 
         unsafe {
-            self.device.logical_device.destroy_image_view(self.font_image_view, None);
+            self.device.destroy_image_view(self.font_image_view);
 
-            self.device.logical_device.destroy_image(self.font_image.0, None);
-            self.device.logical_device.free_memory(self.font_image.1, None);
+            self.device.destroy_image(self.font_image.0, self.font_image.1);
         }
 
         self.font_image_staging_buffer = Buffer::new(
@@ -477,24 +491,21 @@ impl EGuiIntegration {
             )?
         };
 
-        self.font_image_view = unsafe {
-            self.device.logical_device.create_image_view(
-                &ash::vk::ImageViewCreateInfo::builder()
-                    .image(self.font_image.0)
-                    .format(ash::vk::Format::R8G8B8A8_UNORM)
-                    .view_type(ash::vk::ImageViewType::TYPE_2D)
-                    .subresource_range(
-                        ash::vk::ImageSubresourceRange {
-                            aspect_mask: ash::vk::ImageAspectFlags::COLOR,
-                            base_mip_level: 0,
-                            level_count: 1,
-                            base_array_layer: 0,
-                            layer_count: 1,
-                        }
-                    ),
-                None,
-            )?
-        };
+        self.font_image_view = self.device.create_image_view(
+            &ash::vk::ImageViewCreateInfo::builder()
+                .image(self.font_image.0)
+                .format(ash::vk::Format::R8G8B8A8_UNORM)
+                .view_type(ash::vk::ImageViewType::TYPE_2D)
+                .subresource_range(
+                    ash::vk::ImageSubresourceRange {
+                        aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    }
+                ),
+        )?;
 
         self.font_image_size = dimensions;
         self.font_image_version = texture.version;
@@ -505,15 +516,14 @@ impl EGuiIntegration {
                 .write_image(0, &[ash::vk::DescriptorImageInfo::builder()
                     .image_view(self.font_image_view)
                     .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                    .sampler(self.sampler)
+                    .sampler(self.sampler.sampler)
                     .build()])
                 .build().unwrap();
 
             self.font_descriptor_sets.push(set);
         }
 
-        self.font_image_staging_buffer.map(0)?;
-        self.font_image_staging_buffer.write_to_buffer(&data);
+        self.font_image_staging_buffer.map(0)?.write_slice(&data);
 
         unsafe {
             self.device.logical_device.cmd_pipeline_barrier(
@@ -596,20 +606,19 @@ impl EGuiIntegration {
 
     pub fn update_swapchain(
         &mut self,
-        window: &Window,
         swapchain: &Swapchain,
         surface_format: ash::vk::Format,
         pipeline_cache: &Rc<PipelineCache>,
     ) -> anyhow::Result<(), GentooRenderError> {
-        self.physical_width = window.raw_window.inner_size().width;
-        self.physical_height = window.raw_window.inner_size().height;
+        self.physical_width = swapchain.width();
+        self.physical_height = swapchain.height();
 
         unsafe {
             self.device.logical_device.destroy_render_pass(self.render_pass, None);
 
             self.framebuffer_color_image_views
                 .iter()
-                .for_each(|iv| self.device.logical_device.destroy_image_view(*iv, None));
+                .for_each(|iv| self.device.destroy_image_view(*iv));
 
             self.framebuffers
                 .iter()
@@ -628,7 +637,6 @@ impl EGuiIntegration {
 
         let (framebuffer_color_image_views, framebuffers) = Self::create_framebuffers(
             self.device.clone(),
-            window,
             swapchain,
             self.render_pass,
             surface_format,
@@ -640,6 +648,9 @@ impl EGuiIntegration {
         Ok(())
     }
 
+    /// Draws over the final swapchain image, after any multisampled scene was
+    /// resolved into it. The UI is never multisampled, its attachment, framebuffer
+    /// and pipeline all use `UI_SAMPLE_COUNT` whatever the scene uses.
     fn create_render_pass(
         device: &Rc<Device>,
         surface_format: ash::vk::Format,
@@ -649,7 +660,7 @@ impl EGuiIntegration {
                 &ash::vk::RenderPassCreateInfo::builder()
                     .attachments(&[ash::vk::AttachmentDescription {
                         format: surface_format,
-                        samples: ash::vk::SampleCountFlags::TYPE_1,
+                        samples: UI_SAMPLE_COUNT,
                         load_op: ash::vk::AttachmentLoadOp::LOAD,
                         store_op: ash::vk::AttachmentStoreOp::STORE,
                         stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
@@ -686,53 +697,34 @@ impl EGuiIntegration {
     ) -> anyhow::Result<Pipeline, GentooRenderError> {
         Ok(Pipeline::new(
             device,
-            "shaders/egui.vert.spv",
-            "shaders/egui.frag.spv",
+            ShaderSource::Path("shaders/egui.vert.spv"),
+            ShaderSource::Path("shaders/egui.frag.spv"),
             render_pass,
             pipeline_layout,
             pipeline_cache,
-            &[
-                ash::vk::VertexInputBindingDescription {
-                    binding: 0,
-                    stride: vertex_stride,
-                    input_rate: ash::vk::VertexInputRate::VERTEX,
-                },
-            ],
-            &[
-                ash::vk::VertexInputAttributeDescription {
-                    binding: 0,
-                    location: 0,
-                    format: ash::vk::Format::R32G32_SFLOAT,
-                    offset: 0,
-                },
-                ash::vk::VertexInputAttributeDescription {
-                    binding: 0,
-                    location: 1,
-                    format: ash::vk::Format::R32G32_SFLOAT,
-                    offset: 8,
-                },
-                ash::vk::VertexInputAttributeDescription {
-                    binding: 0,
-                    location: 2,
-                    format: ash::vk::Format::R8G8B8A8_UNORM,
-                    offset: 16,
-                }
-            ],
-            ash::vk::CullModeFlags::NONE,
+            &VertexLayout::new(vertex_stride)
+                .add_attribute(VertexSemantic::Position, ash::vk::Format::R32G32_SFLOAT, 0)
+                .add_attribute(VertexSemantic::Uv, ash::vk::Format::R32G32_SFLOAT, 8)
+                .add_attribute(VertexSemantic::Color, ash::vk::Format::R8G8B8A8_UNORM, 16),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::PremultipliedAlpha,
+                sample_count: UI_SAMPLE_COUNT,
+                ..Default::default()
+            },
         )?)
     }
 
     fn create_framebuffers(
         device: Rc<Device>,
-        window: &Window,
         swapchain: &Swapchain,
         render_pass: ash::vk::RenderPass,
         surface_format: ash::vk::Format,
     ) -> (Vec<ash::vk::ImageView>, Vec<ash::vk::Framebuffer>) {
         let framebuffer_color_image_views = swapchain.swapchain_images
             .iter()
-            .map(|swapchain_image| unsafe {
-                device.logical_device
+            .map(|swapchain_image| {
+                device
                     .create_image_view(
                         &ash::vk::ImageViewCreateInfo::builder()
                         .image(swapchain_image.clone())
@@ -747,7 +739,6 @@ impl EGuiIntegration {
                                 layer_count: 1,
                             }
                         ),
-                        None,
                     )
                     .expect("Failed to create image view")
             })
@@ -762,8 +753,8 @@ impl EGuiIntegration {
                         &ash::vk::FramebufferCreateInfo::builder()
                             .render_pass(render_pass)
                             .attachments(attachments)
-                            .width(window.raw_window.inner_size().width)
-                            .height(window.raw_window.inner_size().height)
+                            .width(swapchain.width())
+                            .height(swapchain.height())
                             .layers(1),
                         None,
                     )
@@ -778,21 +769,19 @@ impl EGuiIntegration {
 impl Drop for EGuiIntegration {
     fn drop(&mut self) {
         unsafe {
-            self.device.logical_device.destroy_image_view(self.font_image_view, None);
+            self.device.destroy_image_view(self.font_image_view);
 
-            self.device.logical_device.destroy_image(self.font_image.0, None);
-            self.device.logical_device.free_memory(self.font_image.1, None);
+            self.device.destroy_image(self.font_image.0, self.font_image.1);
 
             self.framebuffer_color_image_views
                 .iter()
-                .for_each(|iv| self.device.logical_device.destroy_image_view(*iv, None));
+                .for_each(|iv| self.device.destroy_image_view(*iv));
 
             self.framebuffers
                 .iter()
                 .for_each(|f|  self.device.logical_device.destroy_framebuffer(*f, None));
 
             self.device.logical_device.destroy_render_pass(self.render_pass, None);
-            self.device.logical_device.destroy_sampler(self.sampler, None);
             
             self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
         }
@@ -0,0 +1,65 @@
+use super::GentooRenderError;
+
+/// Converts tightly packed pixels read back from an image of `format` into
+/// RGBA8, e.g. for screenshots or color id picking.
+///
+/// The bytes are kept as they are encoded, for swapchain images that is the
+/// sRGB encoded color the display shows for both the `_SRGB` and `_UNORM` formats.
+/// Only the channel order differs between the supported formats.
+pub fn format_to_rgba8(bytes: &[u8], format: ash::vk::Format) -> anyhow::Result<Vec<u8>, GentooRenderError> {
+    let swap_red_blue = swaps_red_blue(format)?;
+
+    debug_assert_eq!(bytes.len() % 4, 0, "Pixel data must be tightly packed");
+
+    let mut rgba = bytes.to_vec();
+
+    if swap_red_blue {
+        rgba.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+    }
+
+    Ok(rgba)
+}
+
+/// Whether `format` stores blue first, errors for formats `format_to_rgba8`
+/// can't convert, e.g. to check a readback before recording it.
+pub fn swaps_red_blue(format: ash::vk::Format) -> anyhow::Result<bool, GentooRenderError> {
+    match format {
+        ash::vk::Format::R8G8B8A8_SRGB | ash::vk::Format::R8G8B8A8_UNORM => Ok(false),
+        ash::vk::Format::B8G8R8A8_SRGB | ash::vk::Format::B8G8R8A8_UNORM => Ok(true),
+        _ => {
+            log::error!("Unable to convert {:?} pixels to RGBA8", format);
+            Err(GentooRenderError::UnsupportedReadbackFormat(format))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bgra_to_rgba() {
+        let bgra = [10, 20, 30, 255, 1, 2, 3, 4];
+
+        for format in [ash::vk::Format::B8G8R8A8_SRGB, ash::vk::Format::B8G8R8A8_UNORM] {
+            assert_eq!(format_to_rgba8(&bgra, format).unwrap(), vec![30, 20, 10, 255, 3, 2, 1, 4]);
+        }
+    }
+
+    #[test]
+    fn rgba_is_kept() {
+        let rgba = [10, 20, 30, 255];
+
+        for format in [ash::vk::Format::R8G8B8A8_SRGB, ash::vk::Format::R8G8B8A8_UNORM] {
+            assert_eq!(format_to_rgba8(&rgba, format).unwrap(), rgba.to_vec());
+        }
+    }
+
+    #[test]
+    fn unsupported_format() {
+        assert!(matches!(
+            format_to_rgba8(&[0; 8], ash::vk::Format::R16G16B16A16_SFLOAT),
+            Err(GentooRenderError::UnsupportedReadbackFormat(ash::vk::Format::R16G16B16A16_SFLOAT)),
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+#[cfg(feature = "renderdoc")]
+use renderdoc::{RenderDoc, V110};
+
+/// Single frame RenderDoc captures, a no-op when the application wasn't
+/// launched through RenderDoc or the `renderdoc` feature is disabled.
+pub struct FrameCapture {
+    #[cfg(feature = "renderdoc")]
+    api: Option<RenderDoc<V110>>,
+    requested: bool,
+    capturing: bool,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        #[cfg(feature = "renderdoc")]
+        let api = match RenderDoc::new() {
+            Ok(api) => {
+                log::info!("RenderDoc attached, press F11 to capture a frame");
+                Some(api)
+            }
+            Err(error) => {
+                log::debug!("RenderDoc not available: {}", error);
+                None
+            }
+        };
+
+        Self {
+            #[cfg(feature = "renderdoc")]
+            api,
+            requested: false,
+            capturing: false,
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.api.is_some();
+
+        #[cfg(not(feature = "renderdoc"))]
+        return false;
+    }
+
+    /// Captures the next frame that is started
+    pub fn request(&mut self) {
+        if !self.is_available() {
+            log::warn!("Frame capture requested but RenderDoc is not attached");
+            return;
+        }
+
+        self.requested = true;
+    }
+
+    pub fn begin(&mut self) {
+        if !self.requested {
+            return;
+        }
+
+        self.requested = false;
+
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = &mut self.api {
+            // Null device and window capture whatever is presented next
+            api.start_frame_capture(std::ptr::null(), std::ptr::null());
+            self.capturing = true;
+        }
+    }
+
+    pub fn end(&mut self) {
+        if !self.capturing {
+            return;
+        }
+
+        self.capturing = false;
+
+        #[cfg(feature = "renderdoc")]
+        if let Some(api) = &mut self.api {
+            api.end_frame_capture(std::ptr::null(), std::ptr::null());
+            log::info!("Frame captured");
+        }
+    }
+}
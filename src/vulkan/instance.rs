@@ -31,10 +31,16 @@ pub struct Instance {
     pub entry: ash::Entry,
     pub instance: ash::Instance,
     debug_messenger: Option<(ash::extensions::ext::DebugUtils, ash::vk::DebugUtilsMessengerEXT)>,
+    /// `VK_EXT_swapchain_colorspace` is enabled, surfaces may offer HDR color spaces
+    pub swapchain_colorspace_enabled: bool,
 }
 
 impl Instance {
-    pub fn new() -> anyhow::Result<Self, GentooRenderError> {
+    /// `validation_features` are enabled on top of the standard validation,
+    /// e.g. `GPU_ASSISTED` or `BEST_PRACTICES`. They are ignored with a
+    /// warning when the validation layer doesn't support them. `hdr_output`
+    /// enables `VK_EXT_swapchain_colorspace` if it is available.
+    pub fn new(validation_features: &[ash::vk::ValidationFeatureEnableEXT], hdr_output: bool) -> anyhow::Result<Self, GentooRenderError> {
         let entry = unsafe {
             ash::Entry::load()?
         };
@@ -49,7 +55,29 @@ impl Instance {
             .engine_version(ash::vk::make_api_version(0, 0, 1, 0))
             .api_version(ash::vk::make_api_version(0, 1, 2, 0));
 
-        let extensions = Self::get_required_extensions();
+        let validation_features = if ENABLE_VALIDATION_LAYERS {
+            Self::supported_validation_features(&entry, validation_features)?
+        } else {
+            Vec::new()
+        };
+
+        let mut extensions = Self::get_required_extensions();
+
+        if !validation_features.is_empty() {
+            log::info!("Enabling validation features {:?}", validation_features);
+            extensions.push(ash::vk::ExtValidationFeaturesFn::name().as_ptr());
+        }
+
+        let swapchain_colorspace_enabled = hdr_output && Self::is_swapchain_colorspace_supported(&entry)?;
+
+        if swapchain_colorspace_enabled {
+            extensions.push(ash::vk::ExtSwapchainColorspaceFn::name().as_ptr());
+        } else if hdr_output {
+            log::warn!("{} is not supported, presenting in SDR", ash::vk::ExtSwapchainColorspaceFn::name().to_string_lossy());
+        }
+
+        let mut validation_features_info = ash::vk::ValidationFeaturesEXT::builder()
+            .enabled_validation_features(&validation_features);
 
         let mut create_info = ash::vk::InstanceCreateInfo::builder()
             .application_info(&application_info)
@@ -61,10 +89,14 @@ impl Instance {
             if Self::check_validation_layer_support(&entry)? {
                 create_info = create_info.enabled_layer_names(&layer_name_ptrs);
             } else {
-                panic!("Validation layers requested, but not available!");
+                log::warn!("Validation layers requested, but not available, continuing without them");
             }
         }
 
+        if !validation_features.is_empty() {
+            create_info = create_info.push_next(&mut validation_features_info);
+        }
+
         let instance = unsafe {
             entry.create_instance(&create_info, None)?
         };
@@ -79,9 +111,16 @@ impl Instance {
             debug_messenger,
             entry,
             instance,
+            swapchain_colorspace_enabled,
         })
     }
 
+    fn is_swapchain_colorspace_supported(entry: &ash::Entry) -> anyhow::Result<bool, GentooRenderError> {
+        Ok(entry.enumerate_instance_extension_properties()?.iter().any(|extension| unsafe {
+            CStr::from_ptr(extension.extension_name.as_ptr()) == ash::vk::ExtSwapchainColorspaceFn::name()
+        }))
+    }
+
     fn setup_debug_messenger(
         entry: &ash::Entry,
         instance: &ash::Instance,
@@ -106,6 +145,62 @@ impl Instance {
         Ok((debug_report, debug_report_callback))
     }
 
+    /// Filters out what the validation layer can't enable, GPU-assisted
+    /// validation and debug printf also exclude each other.
+    fn supported_validation_features(
+        entry: &ash::Entry,
+        requested: &[ash::vk::ValidationFeatureEnableEXT],
+    ) -> anyhow::Result<Vec<ash::vk::ValidationFeatureEnableEXT>, GentooRenderError> {
+        if requested.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !Self::check_validation_layer_support(entry)? || !Self::is_validation_features_supported(entry)? {
+            log::warn!("{} is not supported, ignoring validation features {:?}", ash::vk::ExtValidationFeaturesFn::name().to_string_lossy(), requested);
+            return Ok(Vec::new());
+        }
+
+        let mut features = requested.to_vec();
+
+        if features.contains(&ash::vk::ValidationFeatureEnableEXT::GPU_ASSISTED) && features.contains(&ash::vk::ValidationFeatureEnableEXT::DEBUG_PRINTF) {
+            log::warn!("GPU-assisted validation and debug printf can't be enabled together, ignoring debug printf");
+            features.retain(|feature| *feature != ash::vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+        }
+
+        if features.contains(&ash::vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT) && !features.contains(&ash::vk::ValidationFeatureEnableEXT::GPU_ASSISTED) {
+            log::warn!("Reserving a binding slot needs GPU-assisted validation, ignoring it");
+            features.retain(|feature| *feature != ash::vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+
+        Ok(features)
+    }
+
+    /// `VK_EXT_validation_features` is provided by the validation layer, so
+    /// it has to be looked up in the layer's extensions.
+    fn is_validation_features_supported(entry: &ash::Entry) -> anyhow::Result<bool, GentooRenderError> {
+        let layer_name = CString::new(VALIDATION_LAYERS[0]).unwrap();
+
+        let mut count = 0;
+
+        unsafe {
+            entry.fp_v1_0()
+                .enumerate_instance_extension_properties(layer_name.as_ptr(), &mut count, std::ptr::null_mut())
+                .result()?;
+        }
+
+        let mut properties = vec![ash::vk::ExtensionProperties::default(); count as usize];
+
+        unsafe {
+            entry.fp_v1_0()
+                .enumerate_instance_extension_properties(layer_name.as_ptr(), &mut count, properties.as_mut_ptr())
+                .result()?;
+        }
+
+        Ok(properties.iter().any(|extension| unsafe {
+            CStr::from_ptr(extension.extension_name.as_ptr()) == ash::vk::ExtValidationFeaturesFn::name()
+        }))
+    }
+
     fn check_validation_layer_support(entry: &ash::Entry) -> anyhow::Result<bool, GentooRenderError> {
         let layer_properties = entry
             .enumerate_instance_layer_properties()?;
@@ -2,10 +2,20 @@ mod device;
 mod swapchain;
 mod renderer;
 mod model;
+mod model_cache;
 mod buffer;
 mod instance;
 mod shader;
 mod queue;
+mod frame_capture;
+mod texture;
+mod recording_workers;
+mod offscreen;
+mod format;
+mod vertex_layout;
+mod skinning;
+mod sampler;
+mod resource_tracker;
 
 pub mod pipeline;
 pub mod descriptor_set;
@@ -16,10 +26,20 @@ pub use device::*;
 pub use swapchain::*;
 pub use renderer::*;
 pub use model::*;
+pub use model_cache::*;
 pub use buffer::*;
 pub use instance::*;
 pub use shader::*;
 pub use queue::*;
+pub use frame_capture::*;
+pub use texture::*;
+pub use recording_workers::*;
+pub use offscreen::*;
+pub use format::*;
+pub use vertex_layout::*;
+pub use skinning::*;
+pub use sampler::*;
+pub use resource_tracker::*;
 
 #[repr(align(16))]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -33,4 +53,83 @@ pub enum GentooRenderError {
     LoadingError(#[from] ash::LoadingError),
     #[error("Swapchain image or depth format has changed")]
     CompareSwapFormatsError,
+    #[error("Failed to read shader {path}: {source}")]
+    ShaderReadError {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to create shader module from {path}: {result}")]
+    ShaderModuleError {
+        path: String,
+        result: ash::vk::Result,
+    },
+    #[error("Invalid SPIR-V: {reason}")]
+    InvalidSpirvError {
+        reason: &'static str,
+    },
+    #[error("Failed to create graphics pipeline from {vert_shader} and {frag_shader}: {result}")]
+    PipelineCreationError {
+        vert_shader: String,
+        frag_shader: String,
+        result: ash::vk::Result,
+    },
+    #[error("Failed to load model: {0}")]
+    ModelLoadError(#[from] tobj::LoadError),
+    #[error("Failed to load image: {0}")]
+    ImageLoadError(#[from] image::ImageError),
+    #[error("Cubemap faces must be square and of equal size")]
+    CubemapFaceSizeError,
+    #[error("{len} bytes aren't tightly packed RGBA8 pixels for a {width}x{height} texture with {layers} layers")]
+    TextureSizeError {
+        width: u32,
+        height: u32,
+        layers: u32,
+        len: usize,
+    },
+    #[error("Pixels in {0:?} can't be converted to RGBA8")]
+    UnsupportedReadbackFormat(ash::vk::Format),
+    #[error("Format {format:?} does not support {feature:?}")]
+    UnsupportedFormatFeature {
+        format: ash::vk::Format,
+        feature: ash::vk::FormatFeatureFlags,
+    },
+    #[error("Vertex shader {shader} reads location {location} which the vertex layout doesn't provide")]
+    VertexLayoutError {
+        shader: String,
+        location: u32,
+    },
+    #[error("Failed to load glTF file: {0}")]
+    GltfLoadError(#[from] gltf::Error),
+    #[error("Unsupported glTF file: {0}")]
+    GltfContentError(&'static str),
+    #[error("Tessellation shaders are not supported by the device")]
+    TessellationNotSupported,
+    #[error("Frame {frame} did not finish on the GPU in time")]
+    FenceTimeout {
+        frame: usize,
+    },
+    #[error("No swapchain image could be acquired for {frames} frames in a row, the window surface may be lost")]
+    SwapchainUnavailable {
+        frames: u32,
+    },
+    #[error("Out of {memory} memory creating {resource}{}, try a lower render scale or smaller textures", .size.map(|size| format!(" ({} bytes)", size)).unwrap_or_default())]
+    OutOfMemory {
+        /// "host" or "device"
+        memory: &'static str,
+        /// None when the object itself couldn't be created
+        size: Option<u64>,
+        resource: String,
+    },
+    /// A call the renderer or a builder can't carry out in its current state,
+    /// e.g. ending a frame that wasn't started. Nothing was recorded.
+    #[error("{0}")]
+    InvalidUsage(&'static str),
+    #[error("Invalid mesh: {0}")]
+    InvalidMesh(&'static str),
+    #[error("Descriptor set layout binding {0} is used more than once")]
+    DuplicateBinding(u32),
+    #[error("Every game object id is in use")]
+    OutOfGameObjectIds,
+    #[error("Failed to spawn a recording thread: {0}")]
+    ThreadSpawnError(std::io::Error),
 }
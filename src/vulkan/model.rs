@@ -2,7 +2,7 @@ use std::rc::Rc;
 
 use memoffset::offset_of;
 
-use super::{Device, GentooRenderError, Buffer};
+use super::{Device, GentooRenderError, Buffer, VertexLayout, VertexSemantic};
 
 #[derive(PartialEq)]
 pub struct Vertex {
@@ -13,89 +13,283 @@ pub struct Vertex {
 }
 
 impl Vertex {
-    pub fn get_binding_descriptions() -> Vec<ash::vk::VertexInputBindingDescription> {
-        let vertex_size = std::mem::size_of::<Vertex>() as u32;
-
-        vec![
-            ash::vk::VertexInputBindingDescription {
-                binding: 0,
-                stride: vertex_size,
-                input_rate: ash::vk::VertexInputRate::VERTEX,
-            },
-        ]
-    }
-
-    pub fn get_attribute_descriptions() -> Vec<ash::vk::VertexInputAttributeDescription> {
-        vec![
-            ash::vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 0,
-                format: ash::vk::Format::R32G32B32_SFLOAT,
-                offset: offset_of!(Vertex, position) as u32,
-            }, ash::vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 1,
-                format: ash::vk::Format::R32G32B32_SFLOAT,
-                offset: offset_of!(Vertex, color) as u32,
+    pub fn layout() -> VertexLayout {
+        VertexLayout::new(std::mem::size_of::<Vertex>() as u32)
+            .add_attribute(VertexSemantic::Position, ash::vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, position) as u32)
+            .add_attribute(VertexSemantic::Color, ash::vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, color) as u32)
+            .add_attribute(VertexSemantic::Normal, ash::vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, normal) as u32)
+            .add_attribute(VertexSemantic::Uv, ash::vk::Format::R32G32_SFLOAT, offset_of!(Vertex, uv) as u32)
+    }
+
+    fn as_bytes(vertices: &[Vertex]) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(vertices.as_ptr() as *const u8, std::mem::size_of_val(vertices))
+        }
+    }
+}
+
+/// Raw buffer handles of a model, for recording on threads that can't hold the `Rc<Model>`.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelBuffers {
+    pub vertex_buffer: ash::vk::Buffer,
+    pub index_buffer: Option<ash::vk::Buffer>,
+    pub count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+}
+
+impl ModelBuffers {
+    pub unsafe fn bind_and_draw(&self, logical_device: &ash::Device, command_buffer: ash::vk::CommandBuffer) {
+        logical_device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+
+        match self.index_buffer {
+            Some(index_buffer) => {
+                logical_device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, ash::vk::IndexType::UINT32);
+                logical_device.cmd_draw_indexed(command_buffer, self.count, 1, self.first_index, self.vertex_offset, 0);
             },
-            ash::vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 2,
-                format: ash::vk::Format::R32G32B32_SFLOAT,
-                offset: offset_of!(Vertex, normal) as u32,
+            None => {
+                logical_device.cmd_draw(command_buffer, self.count, 1, 0, 0);
+            }
+        }
+    }
+}
+
+/// The range of a shared index buffer that belongs to one mesh, the indices
+/// are relative to the mesh's first vertex at `vertex_offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshSlice {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    /// None without points.
+    pub fn from_points(points: impl Iterator<Item = glam::Vec3>) -> Option<Self> {
+        points.fold(None, |bounds: Option<Aabb>, point| Some(match bounds {
+            Some(bounds) => Aabb {
+                min: bounds.min.min(point),
+                max: bounds.max.max(point),
             },
-            ash::vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 3,
-                format: ash::vk::Format::R32G32_SFLOAT,
-                offset: offset_of!(Vertex, uv) as u32,
+            None => Aabb {
+                min: point,
+                max: point,
             },
-        ]
+        }))
+    }
+
+    /// The box around the transformed box, which can be larger than the
+    /// transformed contents under rotation.
+    pub fn transformed(&self, matrix: &glam::Mat4) -> Self {
+        let center = matrix.transform_point3((self.min + self.max) * 0.5);
+        let half_extent = (self.max - self.min) * 0.5;
+
+        let extent = matrix.x_axis.truncate().abs() * half_extent.x
+            + matrix.y_axis.truncate().abs() * half_extent.y
+            + matrix.z_axis.truncate().abs() * half_extent.z;
+
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
     }
 }
 
+/// Fixes for obj files that are lit inside out, applied after loading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshOptions {
+    /// Ignores the file's normals and computes them from the faces, which
+    /// point out of the counter clockwise side
+    pub recompute_normals: bool,
+    /// Negates every normal, after recomputing them. The winding is kept, so
+    /// the same faces are culled.
+    pub flip_normals: bool,
+}
+
 pub struct Model {
-    vertex_buffer: Buffer<Vertex>,
+    /// Shared between the models created by `Model::pack`
+    vertex_buffer: Rc<Buffer<u8>>,
     pub vertex_count: u32,
-    indices: Option<(Buffer<u32>, u32)>,
+    /// Bounds of the vertex positions in model space, None without a
+    /// `R32G32B32_SFLOAT` position attribute
+    pub bounds: Option<Aabb>,
+    /// The systems draw models in the `Vertex::layout()`, other layouts need
+    /// a pipeline created for them
+    pub vertex_layout: VertexLayout,
+    indices: Option<(Rc<Buffer<u32>>, MeshSlice)>,
 }
 
 impl Model {
     pub fn new(device: Rc<Device>, vertices: &Vec<Vertex>, indices: Option<&Vec<u32>>) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        Self::with_layout(device, Vertex::as_bytes(vertices), Vertex::layout(), indices)
+    }
+
+    /// `vertices` are interleaved vertices of `vertex_layout.stride` bytes each,
+    /// e.g. position and color only for a point cloud.
+    pub fn with_layout(
+        device: Rc<Device>,
+        vertices: &[u8],
+        vertex_layout: VertexLayout,
+        indices: Option<&Vec<u32>>,
+    ) -> anyhow::Result<Rc<Self>, GentooRenderError> {
         let (vertex_buffer, vertex_count) =
-            Self::create_vertex_buffers(&device, vertices)?;
+            Self::create_vertex_buffers(&device, vertices, vertex_layout.stride)?;
+
+        let bounds = Self::compute_bounds(vertices, &vertex_layout);
 
         match indices {
             Some(indices) => {
-                let indices = Self::create_index_buffers(&device, indices)?;
+                let (index_buffer, index_count) = Self::create_index_buffers(&device, indices)?;
+
+                let slice = MeshSlice {
+                    first_index: 0,
+                    index_count,
+                    vertex_offset: 0,
+                };
 
                 return Ok(Rc::new(Self {
-                    vertex_buffer: vertex_buffer,
+                    vertex_buffer: Rc::new(vertex_buffer),
                     vertex_count,
-                    indices: Some(indices),
+                    bounds,
+                    vertex_layout,
+                    indices: Some((Rc::new(index_buffer), slice)),
                 }));
             },
             None => { }
         }
 
         Ok(Rc::new(Self {
-            vertex_buffer,
+            vertex_buffer: Rc::new(vertex_buffer),
             vertex_count,
+            bounds,
+            vertex_layout,
             indices: None,
         }))
     }
 
+    /// Packs several indexed meshes into one vertex and one index buffer.
+    /// Every returned model draws its own `MeshSlice` of the shared buffers,
+    /// so a run of them can be drawn without rebinding.
+    pub fn pack(device: Rc<Device>, meshes: &[(Vec<Vertex>, Vec<u32>)]) -> anyhow::Result<Vec<Rc<Self>>, GentooRenderError> {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut slices = Vec::with_capacity(meshes.len());
+
+        for (mesh_vertices, mesh_indices) in meshes {
+            slices.push(MeshSlice {
+                first_index: indices.len() as u32,
+                index_count: mesh_indices.len() as u32,
+                vertex_offset: (vertices.len() / std::mem::size_of::<Vertex>()) as i32,
+            });
+
+            vertices.extend_from_slice(Vertex::as_bytes(mesh_vertices));
+            indices.extend_from_slice(mesh_indices);
+        }
+
+        let vertex_layout = Vertex::layout();
+
+        let (vertex_buffer, _) = Self::create_vertex_buffers(&device, &vertices, vertex_layout.stride)?;
+        let (index_buffer, _) = Self::create_index_buffers(&device, &indices)?;
+
+        let vertex_buffer = Rc::new(vertex_buffer);
+        let index_buffer = Rc::new(index_buffer);
+
+        Ok(meshes.iter().zip(slices).map(|((mesh_vertices, _), slice)| Rc::new(Self {
+            vertex_buffer: vertex_buffer.clone(),
+            vertex_count: mesh_vertices.len() as u32,
+            bounds: Self::compute_bounds(Vertex::as_bytes(mesh_vertices), &vertex_layout),
+            vertex_layout: vertex_layout.clone(),
+            indices: Some((index_buffer.clone(), slice)),
+        })).collect())
+    }
+
+    /// Loads several obj files into shared buffers, see `Model::pack`.
+    pub fn from_files_packed(device: Rc<Device>, files: &[(&str, MeshOptions)]) -> anyhow::Result<Vec<Rc<Self>>, GentooRenderError> {
+        let meshes = files
+            .iter()
+            .map(|(file_path, options)| Self::load_obj_mesh(file_path, *options))
+            .collect::<anyhow::Result<Vec<_>, GentooRenderError>>()?;
+
+        Self::pack(device, &meshes)
+    }
+
+    fn compute_bounds(vertices: &[u8], vertex_layout: &VertexLayout) -> Option<Aabb> {
+        let position = vertex_layout.attributes
+            .iter()
+            .find(|attribute| attribute.semantic == VertexSemantic::Position && attribute.format == ash::vk::Format::R32G32B32_SFLOAT)?;
+
+        let offset = position.offset as usize;
+
+        Aabb::from_points(vertices.chunks_exact(vertex_layout.stride as usize).map(|vertex| {
+            let component = |i: usize| {
+                let start = offset + 4 * i;
+                f32::from_ne_bytes(vertex[start..start + 4].try_into().unwrap())
+            };
+
+            glam::vec3(component(0), component(1), component(2))
+        }))
+    }
+
     pub fn from_file(device: Rc<Device>, file_path: &str) -> anyhow::Result<Rc<Self>, GentooRenderError> {
-        let (models, _) = tobj::load_obj(
-            file_path,
-            &tobj::LoadOptions {
-                single_index: true,
-                triangulate: true,
-                ..Default::default()
-            },
-        ).unwrap();
+        Self::from_file_with_options(device, file_path, MeshOptions::default())
+    }
+
+    pub fn from_file_with_options(device: Rc<Device>, file_path: &str, options: MeshOptions) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let (vertices, indices) = Self::load_obj_mesh(file_path, options)?;
+
+        Model::new(device, &vertices, Some(&indices))
+    }
+
+    fn load_obj_mesh(file_path: &str, options: MeshOptions) -> anyhow::Result<(Vec<Vertex>, Vec<u32>), GentooRenderError> {
+        let (models, _) = tobj::load_obj(file_path, &Self::obj_load_options())?;
+
+        Self::obj_mesh(&models, options)
+    }
+
+    /// Loads an obj from memory, e.g. one embedded with `include_bytes!`.
+    /// Materials are ignored as there is no directory to resolve them from.
+    pub fn from_obj_bytes(device: Rc<Device>, bytes: &[u8]) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let (vertices, indices) = Self::obj_bytes_mesh(bytes)?;
 
-        let mesh = &models[0].mesh;
+        Model::new(device, &vertices, Some(&indices))
+    }
+
+    fn obj_bytes_mesh(bytes: &[u8]) -> anyhow::Result<(Vec<Vertex>, Vec<u32>), GentooRenderError> {
+        let (models, _) = tobj::load_obj_buf(
+            &mut std::io::BufReader::new(bytes),
+            &Self::obj_load_options(),
+            |_| Ok(Default::default()),
+        )?;
+
+        Self::obj_mesh(&models, MeshOptions::default())
+    }
+
+    fn obj_load_options() -> tobj::LoadOptions {
+        tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        }
+    }
+
+    fn obj_mesh(models: &[tobj::Model], options: MeshOptions) -> anyhow::Result<(Vec<Vertex>, Vec<u32>), GentooRenderError> {
+        // tobj returns a model without faces for an empty file
+        let model = match models.first().filter(|model| !model.mesh.indices.is_empty()) {
+            Some(model) => model,
+            None => {
+                log::error!("Obj file has no faces");
+                return Err(GentooRenderError::InvalidMesh("the obj file has no faces"));
+            }
+        };
+
+        let mesh = &model.mesh;
 
         let positions = mesh.positions.as_slice();
         let colors = mesh.vertex_color.as_slice();
@@ -104,37 +298,48 @@ impl Model {
 
         let vertex_count = mesh.positions.len() / 3;
 
+        // Exporters may leave out normals and texture coordinates
+        let computed_normals;
+        let normals = if options.recompute_normals || normals.len() < 3 * vertex_count {
+            if !options.recompute_normals {
+                log::warn!("Model {} has no normals, computing them from its faces", model.name);
+            }
+            computed_normals = Self::compute_normals(positions, &mesh.indices);
+            computed_normals.as_slice()
+        } else {
+            normals
+        };
+
+        let has_coords = coords.len() >= 2 * vertex_count;
+        // Only some OBJ files have a color after each position, white otherwise
+        let has_colors = colors.len() == positions.len();
+        let normal_sign = if options.flip_normals { -1.0 } else { 1.0 };
+
         let mut vertices = Vec::with_capacity(vertex_count);
         for i in 0..vertex_count {
             let x = positions[3 * i + 0];
             let y = positions[3 * i + 1];
             let z = positions[3 * i + 2];
 
-            let color_x;
-            let color_y;
-            let color_z;
-
-            let color_index = 3 * i + 2;
-            if color_index < colors.len() {
-                color_x = colors[3 * i - 2];
-                color_y = colors[3 * i - 1];
-                color_z = colors[3 * i - 0];
+            let color = if has_colors {
+                glam::vec3(colors[3 * i + 0], colors[3 * i + 1], colors[3 * i + 2])
             } else {
-                color_x = 1.0;
-                color_y = 1.0;
-                color_z = 1.0;
-            }
+                glam::Vec3::ONE
+            };
 
-            let normal_x = normals[3 * i + 0];
-            let normal_y = normals[3 * i + 1];
-            let normal_z = normals[3 * i + 2];
+            let normal_x = normals[3 * i + 0] * normal_sign;
+            let normal_y = normals[3 * i + 1] * normal_sign;
+            let normal_z = normals[3 * i + 2] * normal_sign;
 
-            let u = coords[2 * i + 0];
-            let v = coords[2 * i + 1];
+            let (u, v) = if has_coords {
+                (coords[2 * i + 0], coords[2 * i + 1])
+            } else {
+                (0.0, 0.0)
+            };
 
             let vertex = Vertex {
                 position: glam::vec3(x, y, z),
-                color: glam::vec3(color_x, color_y, color_z),
+                color,
                 normal: glam::vec3(normal_x, normal_y, normal_z),
                 uv: glam::vec2(u, v),
             };
@@ -142,13 +347,37 @@ impl Model {
             vertices.push(vertex);
         }
 
-        Ok(Model::new(device, &vertices, Some(&mesh.indices.clone()))?)
+        Ok((vertices, mesh.indices.clone()))
+    }
+
+    /// Smooth per-vertex normals, the sum of the adjacent face normals
+    /// weighted by the face area. Flattened like `tobj::Mesh::normals`.
+    fn compute_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+        let position = |index: u32| glam::Vec3::from_slice(&positions[3 * index as usize..]);
+
+        let mut normals = vec![glam::Vec3::ZERO; positions.len() / 3];
+
+        for triangle in indices.chunks_exact(3) {
+            let (a, b, c) = (position(triangle[0]), position(triangle[1]), position(triangle[2]));
+
+            // The cross product's length is twice the triangle's area
+            let face_normal = (b - a).cross(c - a);
+
+            for index in triangle {
+                normals[*index as usize] += face_normal;
+            }
+        }
+
+        normals
+            .into_iter()
+            .flat_map(|normal| normal.normalize_or_zero().to_array())
+            .collect()
     }
 
     pub unsafe fn draw(&self, logical_device: &ash::Device, command_buffer: ash::vk::CommandBuffer) {
         match &self.indices {
-            Some((_index_buffer, index_count)) => {
-                logical_device.cmd_draw_indexed(command_buffer, *index_count, 1, 0, 0, 0);
+            Some((_index_buffer, slice)) => {
+                logical_device.cmd_draw_indexed(command_buffer, slice.index_count, 1, slice.first_index, slice.vertex_offset, 0);
             },
             None => {
                 logical_device.cmd_draw(command_buffer, self.vertex_count, 1, 0, 0);
@@ -156,11 +385,31 @@ impl Model {
         }
     }
 
+    /// The buffers stay valid for as long as this model is alive.
+    pub fn buffers(&self) -> ModelBuffers {
+        match &self.indices {
+            Some((index_buffer, slice)) => ModelBuffers {
+                vertex_buffer: self.vertex_buffer.buffer,
+                index_buffer: Some(index_buffer.buffer),
+                count: slice.index_count,
+                first_index: slice.first_index,
+                vertex_offset: slice.vertex_offset,
+            },
+            None => ModelBuffers {
+                vertex_buffer: self.vertex_buffer.buffer,
+                index_buffer: None,
+                count: self.vertex_count,
+                first_index: 0,
+                vertex_offset: 0,
+            },
+        }
+    }
+
     pub unsafe fn bind(&self, command_buffer: ash::vk::CommandBuffer) {
         self.vertex_buffer.bind_vertex(command_buffer);
 
         match &self.indices {
-            Some((index_buffer, _index_count)) => {
+            Some((index_buffer, _slice)) => {
                 index_buffer.bind_index(command_buffer, ash::vk::IndexType::UINT32);
             },
             None => { }
@@ -169,30 +418,33 @@ impl Model {
 
     fn create_vertex_buffers(
         device: &Rc<Device>,
-        vertices: &Vec<Vertex>,
-    ) -> anyhow::Result<(Buffer<Vertex>, u32), GentooRenderError> {
-        let vertex_count = vertices.len();
+        vertices: &[u8],
+        stride: u32,
+    ) -> anyhow::Result<(Buffer<u8>, u32), GentooRenderError> {
+        if stride == 0 || !vertices.len().is_multiple_of(stride as usize) {
+            return Err(GentooRenderError::InvalidMesh("Vertex data must be a whole number of vertices"));
+        }
+
+        let vertex_count = vertices.len() / stride as usize;
 
-        assert!(
-            vertex_count >= 3,
-            "Vertex count must be at least 3",
-        );
+        if vertex_count < 3 {
+            return Err(GentooRenderError::InvalidMesh("Vertex count must be at least 3"));
+        }
 
-        let buffer_size: ash::vk::DeviceSize = (std::mem::size_of::<Vertex>() * vertex_count) as u64;
+        let buffer_size: ash::vk::DeviceSize = vertices.len() as u64;
 
         let mut staging_buffer = Buffer::new(
             device.clone(),
-            vertex_count,
+            vertices.len(),
             ash::vk::BufferUsageFlags::TRANSFER_SRC,
             ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
 
-        staging_buffer.map(0)?;
-        staging_buffer.write_to_buffer(vertices);
+        staging_buffer.map(0)?.write_slice(vertices);
 
         let vertex_buffer = Buffer::new(
             device.clone(),
-            vertex_count,
+            vertices.len(),
             ash::vk::BufferUsageFlags::VERTEX_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST,
             ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )?;
@@ -217,8 +469,7 @@ impl Model {
             ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
 
-        staging_buffer.map(0)?;
-        staging_buffer.write_to_buffer(indices);
+        staging_buffer.map(0)?.write_slice(indices);
 
         let index_buffer = Buffer::new(
             device.clone(),
@@ -232,3 +483,120 @@ impl Model {
         Ok((index_buffer, index_count as u32))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normals(vertices: &[Vertex]) -> Vec<glam::Vec3> {
+        vertices.iter().map(|vertex| vertex.normal).collect()
+    }
+
+    fn computed_normals(positions: &[f32], indices: &[u32]) -> Vec<glam::Vec3> {
+        Model::compute_normals(positions, indices)
+            .chunks_exact(3)
+            .map(glam::Vec3::from_slice)
+            .collect()
+    }
+
+    #[test]
+    fn single_triangle_normals() {
+        let positions = [
+            0.0, 0.0, 0.0,
+            2.0, 0.0, 0.0,
+            0.0, 3.0, 0.0,
+        ];
+
+        // Out of the counter clockwise side, unit length whatever the area
+        assert_eq!(computed_normals(&positions, &[0, 1, 2]), vec![glam::Vec3::Z; 3]);
+        assert_eq!(computed_normals(&positions, &[0, 2, 1]), vec![-glam::Vec3::Z; 3]);
+    }
+
+    #[test]
+    fn shared_vertex_quad_normals() {
+        let positions = [
+            0.0, 0.0, 0.0,
+            1.0, 0.0, 0.0,
+            1.0, 1.0, 0.0,
+            0.0, 1.0, 0.0,
+        ];
+
+        // Vertices 0 and 2 are shared by both triangles
+        let normals = computed_normals(&positions, &[0, 1, 2, 0, 2, 3]);
+
+        assert_eq!(normals, vec![glam::Vec3::Z; 4]);
+    }
+
+    #[test]
+    fn shared_vertex_normals_are_averaged() {
+        // Two faces folded along the edge between vertices 1 and 2, one
+        // facing +z and one facing +x
+        let positions = [
+            0.0, 0.0, 1.0,
+            1.0, 0.0, 1.0,
+            1.0, 1.0, 1.0,
+            1.0, 0.0, 0.0,
+        ];
+
+        let normals = computed_normals(&positions, &[0, 1, 2, 1, 3, 2]);
+
+        let edge = glam::vec3(1.0, 0.0, 1.0).normalize();
+
+        assert!(normals[0].abs_diff_eq(glam::Vec3::Z, 1e-6));
+        assert!(normals[1].abs_diff_eq(edge, 1e-6));
+        assert!(normals[2].abs_diff_eq(edge, 1e-6));
+        assert!(normals[3].abs_diff_eq(glam::Vec3::X, 1e-6));
+        assert!(normals.iter().all(|normal| (normal.length() - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn obj_vertex_colors() {
+        let colored = b"v 0 0 0 1.0 0.5 0.25\nv 1 0 0 0.0 1.0 0.0\nv 0 1 0 0.0 0.0 1.0\nf 1 2 3\n";
+
+        let (vertices, _) = Model::obj_bytes_mesh(colored).unwrap();
+        let colors = vertices.iter().map(|vertex| vertex.color).collect::<Vec<_>>();
+
+        assert_eq!(colors, vec![glam::vec3(1.0, 0.5, 0.25), glam::Vec3::Y, glam::Vec3::Z]);
+    }
+
+    #[test]
+    fn obj_without_vertex_colors_is_white() {
+        let uncolored = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+
+        let (vertices, _) = Model::obj_bytes_mesh(uncolored).unwrap();
+
+        assert_eq!(vertices.len(), 3);
+        assert!(vertices.iter().all(|vertex| vertex.color == glam::Vec3::ONE));
+    }
+
+    #[test]
+    fn empty_obj_is_an_error() {
+        assert!(matches!(Model::obj_bytes_mesh(b""), Err(GentooRenderError::InvalidMesh(_))));
+        assert!(matches!(Model::obj_bytes_mesh(b"v 0 0 0\nv 1 0 0\n"), Err(GentooRenderError::InvalidMesh(_))));
+    }
+
+    #[test]
+    fn recomputed_normals_match_the_export() {
+        let recompute = MeshOptions { recompute_normals: true, ..Default::default() };
+
+        let (exported, _) = Model::load_obj_mesh("models/quad.obj", MeshOptions::default()).unwrap();
+        let (recomputed, _) = Model::load_obj_mesh("models/quad.obj", recompute).unwrap();
+
+        assert_eq!(normals(&recomputed), normals(&exported));
+    }
+
+    #[test]
+    fn flipped_normals_match_the_correct_export() {
+        let (quad, quad_indices) = Model::load_obj_mesh("models/quad.obj", MeshOptions::default()).unwrap();
+
+        let (inverted, _) = Model::load_obj_mesh("models/inverted_quad.obj", MeshOptions::default()).unwrap();
+        assert_ne!(normals(&inverted), normals(&quad));
+
+        let flip = MeshOptions { flip_normals: true, ..Default::default() };
+        let (flipped, flipped_indices) = Model::load_obj_mesh("models/inverted_quad.obj", flip).unwrap();
+
+        assert_eq!(normals(&flipped), normals(&quad));
+        // Flipping keeps the winding, so the same side is culled
+        assert_eq!(flipped_indices, quad_indices);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::{Device, GentooRenderError, Model, MeshOptions};
+
+type LoadFiles<T> = Box<dyn Fn(&[(&str, MeshOptions)]) -> anyhow::Result<Vec<Rc<T>>, GentooRenderError>>;
+
+/// Loaded obj models by file path and mesh options. Loading a file again
+/// returns the same model, so every instance of a prop shares its buffers.
+pub struct ModelCache<T = Model> {
+    load_files: LoadFiles<T>,
+    models: HashMap<(String, MeshOptions), Rc<T>>,
+}
+
+impl ModelCache {
+    pub fn new(device: Rc<Device>) -> Self {
+        Self::with_loader(move |files| Model::from_files_packed(device.clone(), files))
+    }
+}
+
+impl<T> ModelCache<T> {
+    /// Caches what `load_files` returns instead of obj models, one model per
+    /// file in order.
+    pub fn with_loader(
+        load_files: impl Fn(&[(&str, MeshOptions)]) -> anyhow::Result<Vec<Rc<T>>, GentooRenderError> + 'static,
+    ) -> Self {
+        Self {
+            load_files: Box::new(load_files),
+            models: HashMap::new(),
+        }
+    }
+
+    /// The same file loaded with different options is a different model
+    pub fn load(&mut self, file_path: &str, options: MeshOptions) -> anyhow::Result<Rc<T>, GentooRenderError> {
+        Ok(self.load_packed(&[(file_path, options)])?.remove(0))
+    }
+
+    /// Like `Model::from_files_packed`, the files that aren't cached yet are
+    /// packed into one vertex and index buffer.
+    pub fn load_packed(&mut self, files: &[(&str, MeshOptions)]) -> anyhow::Result<Vec<Rc<T>>, GentooRenderError> {
+        let mut missing = files
+            .iter()
+            .copied()
+            .filter(|(file_path, options)| !self.models.contains_key(&(file_path.to_string(), *options)))
+            .collect::<Vec<_>>();
+
+        missing.sort_unstable();
+        missing.dedup();
+
+        if !missing.is_empty() {
+            let models = (self.load_files)(&missing)?;
+
+            for ((file_path, options), model) in missing.into_iter().zip(models) {
+                self.models.insert((file_path.to_string(), options), model);
+            }
+        }
+
+        Ok(files.iter().map(|(file_path, options)| self.models[&(file_path.to_string(), *options)].clone()).collect())
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.models.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Caches the file paths instead of models, counting the loaded files
+    fn path_cache(loaded: Rc<Cell<usize>>) -> ModelCache<String> {
+        ModelCache::with_loader(move |files| {
+            loaded.set(loaded.get() + files.len());
+            Ok(files.iter().map(|(file_path, _)| Rc::new(file_path.to_string())).collect())
+        })
+    }
+
+    #[test]
+    fn loading_twice_shares_the_model() {
+        let loaded = Rc::new(Cell::new(0));
+        let mut cache = path_cache(loaded.clone());
+
+        let first = cache.load("models/cube.obj", MeshOptions::default()).unwrap();
+        let second = cache.load("models/cube.obj", MeshOptions::default()).unwrap();
+
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(loaded.get(), 1);
+        assert_eq!(cache.model_count(), 1);
+    }
+
+    #[test]
+    fn options_are_part_of_the_key() {
+        let loaded = Rc::new(Cell::new(0));
+        let mut cache = path_cache(loaded.clone());
+
+        let flip = MeshOptions { flip_normals: true, ..Default::default() };
+
+        let plain = cache.load("models/quad.obj", MeshOptions::default()).unwrap();
+        let flipped = cache.load("models/quad.obj", flip).unwrap();
+        assert!(!Rc::ptr_eq(&plain, &flipped));
+
+        // Only the missing and duplicate free files are loaded, in the requested order
+        let packed = cache.load_packed(&[
+            ("models/cube.obj", MeshOptions::default()),
+            ("models/quad.obj", flip),
+            ("models/cube.obj", MeshOptions::default()),
+        ]).unwrap();
+
+        assert!(Rc::ptr_eq(&packed[0], &packed[2]));
+        assert!(Rc::ptr_eq(&packed[1], &flipped));
+        assert_eq!(*packed[0], "models/cube.obj");
+        assert_eq!(loaded.get(), 3);
+        assert_eq!(cache.model_count(), 3);
+    }
+}
@@ -0,0 +1,544 @@
+use std::rc::Rc;
+
+use super::{Device, GentooRenderError, Swapchain, AttachmentLoadOps};
+
+/// A color and depth target the scene can be rendered into instead of the
+/// swapchain. The color image ends up in `TRANSFER_SRC_OPTIMAL`, ready to be
+/// blitted, both images can also be sampled.
+/// Loaded attachments are cleared once at creation, so the first frame loads
+/// black and the far plane.
+pub struct OffscreenTarget {
+    device: Rc<Device>,
+    pub render_pass: ash::vk::RenderPass,
+    pub framebuffer: ash::vk::Framebuffer,
+    pub extent: ash::vk::Extent2D,
+    pub color_format: ash::vk::Format,
+    pub color_image: ash::vk::Image,
+    color_image_memory: ash::vk::DeviceMemory,
+    pub color_image_view: ash::vk::ImageView,
+    pub depth_image: ash::vk::Image,
+    depth_image_memory: ash::vk::DeviceMemory,
+    pub depth_image_view: ash::vk::ImageView,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: Rc<Device>,
+        color_format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        load_ops: AttachmentLoadOps,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let depth_format = Swapchain::find_depth_format(&device);
+
+        let clear_usage = |loaded| if loaded {
+            ash::vk::ImageUsageFlags::TRANSFER_DST
+        } else {
+            ash::vk::ImageUsageFlags::empty()
+        };
+
+        let (color_image, color_image_memory, color_image_view) = Self::create_attachment(
+            &device,
+            color_format,
+            extent,
+            ash::vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | ash::vk::ImageUsageFlags::TRANSFER_SRC
+                | ash::vk::ImageUsageFlags::SAMPLED
+                | clear_usage(load_ops.loads_color()),
+            ash::vk::ImageAspectFlags::COLOR,
+        )?;
+
+        let (depth_image, depth_image_memory, depth_image_view) = Self::create_attachment(
+            &device,
+            depth_format,
+            extent,
+            ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | ash::vk::ImageUsageFlags::SAMPLED
+                | clear_usage(load_ops.loads_depth()),
+            ash::vk::ImageAspectFlags::DEPTH,
+        )?;
+
+        let render_pass = Swapchain::create_render_pass(
+            &device,
+            color_format,
+            ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            load_ops,
+        )?;
+
+        if load_ops.loads_color() {
+            Self::clear_attachment(
+                &device,
+                color_image,
+                ash::vk::ImageAspectFlags::COLOR,
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            )?;
+        }
+
+        if load_ops.loads_depth() {
+            Self::clear_attachment(
+                &device,
+                depth_image,
+                ash::vk::ImageAspectFlags::DEPTH,
+                ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            )?;
+        }
+
+        let framebuffer = unsafe {
+            device.logical_device.create_framebuffer(
+                &ash::vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&[color_image_view, depth_image_view])
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1),
+                None,
+            )?
+        };
+
+        log::debug!("Offscreen target created: {}x{}", extent.width, extent.height);
+
+        Ok(Self {
+            device,
+            render_pass,
+            framebuffer,
+            extent,
+            color_format,
+            color_image,
+            color_image_memory,
+            color_image_view,
+            depth_image,
+            depth_image_memory,
+            depth_image_view,
+        })
+    }
+
+    /// Clears a freshly created image and moves it to `layout`, e.g. where a
+    /// loading render pass expects it. Needs `TRANSFER_DST` usage.
+    pub fn clear_attachment(
+        device: &Rc<Device>,
+        image: ash::vk::Image,
+        aspect_mask: ash::vk::ImageAspectFlags,
+        layout: ash::vk::ImageLayout,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let subresource_range = ash::vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let command_buffer = device.begin_single_time_commands()?;
+
+        unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TOP_OF_PIPE,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .image(image)
+                    .old_layout(ash::vk::ImageLayout::UNDEFINED)
+                    .new_layout(ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .dst_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(subresource_range)
+                    .build()],
+            );
+
+            if aspect_mask.contains(ash::vk::ImageAspectFlags::DEPTH) {
+                device.logical_device.cmd_clear_depth_stencil_image(
+                    command_buffer,
+                    image,
+                    ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &ash::vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+                    &[subresource_range],
+                );
+            } else {
+                device.logical_device.cmd_clear_color_image(
+                    command_buffer,
+                    image,
+                    ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &ash::vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] },
+                    &[subresource_range],
+                );
+            }
+
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::PipelineStageFlags::ALL_GRAPHICS,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .image(image)
+                    .old_layout(ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(layout)
+                    .src_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(subresource_range)
+                    .build()],
+            );
+        }
+
+        device.end_single_time_commands(command_buffer)
+    }
+
+    pub fn create_attachment(
+        device: &Rc<Device>,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        usage: ash::vk::ImageUsageFlags,
+        aspect_mask: ash::vk::ImageAspectFlags,
+    ) -> anyhow::Result<(ash::vk::Image, ash::vk::DeviceMemory, ash::vk::ImageView), GentooRenderError> {
+        let (image, memory) = device.create_image_with_info(
+            &ash::vk::ImageCreateInfo::builder()
+                .image_type(ash::vk::ImageType::TYPE_2D)
+                .extent(ash::vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .format(format)
+                .tiling(ash::vk::ImageTiling::OPTIMAL)
+                .initial_layout(ash::vk::ImageLayout::UNDEFINED)
+                .usage(usage)
+                .samples(ash::vk::SampleCountFlags::TYPE_1)
+                .sharing_mode(ash::vk::SharingMode::EXCLUSIVE),
+            ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        let view = device.create_image_view(
+            &ash::vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(ash::vk::ImageViewType::TYPE_2D)
+                .format(format)
+                .subresource_range(ash::vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                }),
+        )?;
+
+        Ok((image, memory, view))
+    }
+}
+
+impl Drop for OffscreenTarget {
+    fn drop(&mut self) {
+        log::debug!("Dropping offscreen target");
+
+        unsafe {
+            self.device.logical_device.destroy_framebuffer(self.framebuffer, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+
+            self.device.destroy_image_view(self.color_image_view);
+            self.device.destroy_image(self.color_image, self.color_image_memory);
+
+            self.device.destroy_image_view(self.depth_image_view);
+            self.device.destroy_image(self.depth_image, self.depth_image_memory);
+        }
+    }
+}
+
+/// Several color targets and a depth target written by a single subpass, e.g.
+/// a G-buffer. The color images end up in `SHADER_READ_ONLY_OPTIMAL`, ready to
+/// be sampled by a later pass.
+pub struct MultiTarget {
+    device: Rc<Device>,
+    pub framebuffer: ash::vk::Framebuffer,
+    pub extent: ash::vk::Extent2D,
+    color_attachments: Vec<(ash::vk::Image, ash::vk::DeviceMemory, ash::vk::ImageView)>,
+    depth_attachment: (ash::vk::Image, ash::vk::DeviceMemory, ash::vk::ImageView),
+}
+
+impl MultiTarget {
+    /// `render_pass` has to come from `create_render_pass` with the same formats.
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        color_formats: &[ash::vk::Format],
+        extent: ash::vk::Extent2D,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let color_attachments = color_formats
+            .iter()
+            .map(|format| OffscreenTarget::create_attachment(
+                &device,
+                *format,
+                extent,
+                ash::vk::ImageUsageFlags::COLOR_ATTACHMENT | ash::vk::ImageUsageFlags::SAMPLED,
+                ash::vk::ImageAspectFlags::COLOR,
+            ))
+            .collect::<anyhow::Result<Vec<_>, GentooRenderError>>()?;
+
+        let depth_attachment = OffscreenTarget::create_attachment(
+            &device,
+            Swapchain::find_depth_format(&device),
+            extent,
+            ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ash::vk::ImageAspectFlags::DEPTH,
+        )?;
+
+        let mut attachments = color_attachments.iter().map(|(_, _, view)| *view).collect::<Vec<_>>();
+        attachments.push(depth_attachment.2);
+
+        let framebuffer = unsafe {
+            device.logical_device.create_framebuffer(
+                &ash::vk::FramebufferCreateInfo::builder()
+                    .render_pass(*render_pass)
+                    .attachments(&attachments)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1),
+                None,
+            )?
+        };
+
+        log::debug!("Multi target created: {} color attachments, {}x{}", color_formats.len(), extent.width, extent.height);
+
+        Ok(Self {
+            device,
+            framebuffer,
+            extent,
+            color_attachments,
+            depth_attachment,
+        })
+    }
+
+    /// Color attachments in the order of the formats, followed by depth. Every
+    /// color attachment is cleared and stored, depth is only used for testing.
+    pub fn create_render_pass(
+        device: &Rc<Device>,
+        color_formats: &[ash::vk::Format],
+    ) -> anyhow::Result<ash::vk::RenderPass, GentooRenderError> {
+        let mut attachments = color_formats
+            .iter()
+            .map(|format| ash::vk::AttachmentDescription {
+                format: *format,
+                samples: ash::vk::SampleCountFlags::TYPE_1,
+                load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                store_op: ash::vk::AttachmentStoreOp::STORE,
+                stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: ash::vk::ImageLayout::UNDEFINED,
+                final_layout: ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        attachments.push(ash::vk::AttachmentDescription {
+            format: Swapchain::find_depth_format(device),
+            samples: ash::vk::SampleCountFlags::TYPE_1,
+            load_op: ash::vk::AttachmentLoadOp::CLEAR,
+            store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+            stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: ash::vk::ImageLayout::UNDEFINED,
+            final_layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ..Default::default()
+        });
+
+        let color_attachment_refs = (0..color_formats.len() as u32)
+            .map(|attachment| ash::vk::AttachmentReference {
+                attachment,
+                layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect::<Vec<_>>();
+
+        let depth_attachment_ref = ash::vk::AttachmentReference {
+            attachment: color_formats.len() as u32,
+            layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let subpass = ash::vk::SubpassDescription::builder()
+            .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_ref)
+            .build();
+
+        let dependencies = [
+            // The previous frame may still be sampling the targets
+            ash::vk::SubpassDependency {
+                src_subpass: ash::vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                dst_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE | ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            ash::vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: ash::vk::SUBPASS_EXTERNAL,
+                src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            },
+        ];
+
+        Ok(unsafe {
+            device.logical_device.create_render_pass(
+                &ash::vk::RenderPassCreateInfo::builder()
+                    .attachments(&attachments)
+                    .subpasses(&[subpass])
+                    .dependencies(&dependencies),
+                None,
+            )?
+        })
+    }
+
+    pub fn color_view(&self, index: usize) -> ash::vk::ImageView {
+        self.color_attachments[index].2
+    }
+
+    pub fn color_attachment_count(&self) -> usize {
+        self.color_attachments.len()
+    }
+}
+
+impl Drop for MultiTarget {
+    fn drop(&mut self) {
+        log::debug!("Dropping multi target");
+
+        unsafe {
+            self.device.logical_device.destroy_framebuffer(self.framebuffer, None);
+
+            for (image, memory, view) in self.color_attachments.iter().chain(std::iter::once(&self.depth_attachment)) {
+                self.device.destroy_image_view(*view);
+                self.device.destroy_image(*image, *memory);
+            }
+        }
+    }
+}
+
+/// A lone depth target, e.g. a shadow map. The image ends up in
+/// `DEPTH_STENCIL_READ_ONLY_OPTIMAL`, ready to be sampled.
+pub struct DepthTarget {
+    device: Rc<Device>,
+    pub render_pass: ash::vk::RenderPass,
+    pub framebuffer: ash::vk::Framebuffer,
+    pub extent: ash::vk::Extent2D,
+    pub image: ash::vk::Image,
+    memory: ash::vk::DeviceMemory,
+    pub image_view: ash::vk::ImageView,
+}
+
+impl DepthTarget {
+    pub fn new(
+        device: Rc<Device>,
+        extent: ash::vk::Extent2D,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let format = Swapchain::find_depth_format(&device);
+
+        let (image, memory, image_view) = OffscreenTarget::create_attachment(
+            &device,
+            format,
+            extent,
+            ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ash::vk::ImageUsageFlags::SAMPLED,
+            ash::vk::ImageAspectFlags::DEPTH,
+        )?;
+
+        let render_pass = Self::create_depth_only_render_pass(&device, format)?;
+
+        let framebuffer = unsafe {
+            device.logical_device.create_framebuffer(
+                &ash::vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&[image_view])
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1),
+                None,
+            )?
+        };
+
+        log::debug!("Depth target created: {}x{}", extent.width, extent.height);
+
+        Ok(Self {
+            device,
+            render_pass,
+            framebuffer,
+            extent,
+            image,
+            memory,
+            image_view,
+        })
+    }
+
+    /// A single subpass with only a depth attachment, cleared and stored.
+    pub fn create_depth_only_render_pass(
+        device: &Rc<Device>,
+        depth_format: ash::vk::Format,
+    ) -> anyhow::Result<ash::vk::RenderPass, GentooRenderError> {
+        let dependencies = [
+            // The previous frame may still be sampling the target
+            ash::vk::SubpassDependency {
+                src_subpass: ash::vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_stage_mask: ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                dst_access_mask: ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            ash::vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: ash::vk::SUBPASS_EXTERNAL,
+                src_stage_mask: ash::vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            },
+        ];
+
+        Ok(unsafe {
+            device.logical_device.create_render_pass(
+                &ash::vk::RenderPassCreateInfo::builder()
+                    .attachments(&[ash::vk::AttachmentDescription {
+                        format: depth_format,
+                        samples: ash::vk::SampleCountFlags::TYPE_1,
+                        load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                        store_op: ash::vk::AttachmentStoreOp::STORE,
+                        stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+                        stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                        initial_layout: ash::vk::ImageLayout::UNDEFINED,
+                        final_layout: ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                        ..Default::default()
+                    }])
+                    .subpasses(&[ash::vk::SubpassDescription::builder()
+                        .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS)
+                        .depth_stencil_attachment(&ash::vk::AttachmentReference {
+                            attachment: 0,
+                            layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                        })
+                        .build()])
+                    .dependencies(&dependencies),
+                None,
+            )?
+        })
+    }
+}
+
+impl Drop for DepthTarget {
+    fn drop(&mut self) {
+        log::debug!("Dropping depth target");
+
+        unsafe {
+            self.device.logical_device.destroy_framebuffer(self.framebuffer, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+            self.device.destroy_image_view(self.image_view);
+            self.device.destroy_image(self.image, self.memory);
+        }
+    }
+}
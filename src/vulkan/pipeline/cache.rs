@@ -2,48 +2,115 @@ use std::rc::Rc;
 
 use crate::vulkan::{Device, GentooRenderError};
 
+const CACHE_FILE: &str = "pipeline_cache.bin";
+
+/// Size of the version one pipeline cache header: length, version, vendor id,
+/// device id and the 16 byte pipeline cache uuid.
+const CACHE_HEADER_SIZE: usize = 32;
+
 pub struct PipelineCache {
     device: Rc<Device>,
     pub cache: ash::vk::PipelineCache,
+    /// Written back to `CACHE_FILE` on drop
+    persistent: bool,
 }
 
 impl PipelineCache {
     pub fn new(device: Rc<Device>) -> anyhow::Result<Rc<Self>, GentooRenderError> {
-        let data = match std::fs::read("pipeline_cache.bin") {
-            Ok(data) => {
+        let data = match std::fs::read(CACHE_FILE) {
+            Ok(data) if Self::is_compatible(&device, &data) => {
                 log::debug!("Loaded pipeline cache");
                 data
             },
+            Ok(_) => {
+                log::warn!("Ignoring pipeline cache {}: corrupt or from another device", CACHE_FILE);
+                Vec::new()
+            },
             Err(_) => {
                 log::debug!("Failed to load pipeline cache");
                 Vec::new()
             },
         };
 
-        let cache_info = ash::vk::PipelineCacheCreateInfo::builder()
-            .initial_data(&data);
-
-        let cache = unsafe {
-            device.logical_device.create_pipeline_cache(&cache_info, None)?
+        let cache = match Self::create(&device, &data) {
+            Ok(cache) => cache,
+            Err(result) if !data.is_empty() => {
+                log::warn!("Pipeline cache rejected by the driver ({:?}), starting empty", result);
+                Self::create(&device, &[])?
+            },
+            Err(result) => return Err(result.into()),
         };
 
         Ok(Rc::new(Self {
             device,
             cache,
+            persistent: true,
         }))
     }
+
+    /// Starts empty and is never written to disk, for read only environments
+    /// or when debugging pipeline creation.
+    pub fn new_transient(device: Rc<Device>) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let cache = Self::create(&device, &[])?;
+
+        Ok(Rc::new(Self {
+            device,
+            cache,
+            persistent: false,
+        }))
+    }
+
+    fn create(device: &Rc<Device>, data: &[u8]) -> Result<ash::vk::PipelineCache, ash::vk::Result> {
+        let cache_info = ash::vk::PipelineCacheCreateInfo::builder()
+            .initial_data(data);
+
+        unsafe {
+            device.logical_device.create_pipeline_cache(&cache_info, None)
+        }
+    }
+
+    /// Drivers should ignore data they can't use, but a truncated file has
+    /// crashed some in the past, so check the header before handing it over.
+    fn is_compatible(device: &Rc<Device>, data: &[u8]) -> bool {
+        if data.len() < CACHE_HEADER_SIZE {
+            return false;
+        }
+
+        let read_u32 = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+
+        read_u32(0) as usize >= CACHE_HEADER_SIZE
+            && read_u32(4) == ash::vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && read_u32(8) == device.properties.vendor_id
+            && read_u32(12) == device.properties.device_id
+            && data[16..32] == device.properties.pipeline_cache_uuid
+    }
+
+    /// Failing to persist only costs startup time on the next run, e.g. when
+    /// running from a read only directory.
+    fn save(&self) {
+        let data = match unsafe { self.device.logical_device.get_pipeline_cache_data(self.cache) } {
+            Ok(data) => data,
+            Err(result) => {
+                log::error!("Failed to read pipeline cache data: {:?}", result);
+                return;
+            },
+        };
+
+        if let Err(error) = std::fs::write(CACHE_FILE, data) {
+            log::error!("Failed to write pipeline cache {}: {}", CACHE_FILE, error);
+        }
+    }
 }
 
 impl Drop for PipelineCache {
     fn drop(&mut self) {
         log::debug!("Dropping pipeline cache");
 
-        unsafe {
-            let data = self.device.logical_device.get_pipeline_cache_data(self.cache).unwrap();
+        if self.persistent {
+            self.save();
+        }
 
-            std::fs::write("pipeline_cache.bin", data)
-                .expect("Failed to write pipeline cache");
-            
+        unsafe {
             self.device.logical_device.destroy_pipeline_cache(self.cache, None);
         }
     }
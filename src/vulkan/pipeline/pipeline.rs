@@ -1,49 +1,228 @@
 use std::{ffi::CString, rc::Rc};
 
-use crate::vulkan::{ShaderModule, GentooRenderError, Device};
+use crate::vulkan::{ShaderModule, ShaderSource, GentooRenderError, Device, VertexLayout, ResourceKind};
 
 use super::PipelineCache;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// No blending, the fragment replaces the destination
+    Opaque,
+    /// Premultiplied color and coverage, what egui outputs. The destination
+    /// alpha accumulates coverage like the color channels do.
+    PremultipliedAlpha,
+    /// Adds the source onto the destination, e.g. to count fragments
+    Additive,
+    /// Scales the destination by one minus the source, e.g. the revealage of
+    /// weighted blended transparency
+    Revealage,
+    /// Writes no color, e.g. for depth only draws into a pass with color attachments
+    Masked,
+}
+
+impl BlendMode {
+    fn attachment_state(self) -> ash::vk::PipelineColorBlendAttachmentState {
+        let (src_color_blend_factor, dst_color_blend_factor, src_alpha_blend_factor, dst_alpha_blend_factor) = match self {
+            BlendMode::PremultipliedAlpha => (ash::vk::BlendFactor::ONE, ash::vk::BlendFactor::ONE_MINUS_SRC_ALPHA, ash::vk::BlendFactor::ONE_MINUS_DST_ALPHA, ash::vk::BlendFactor::ONE),
+            BlendMode::Revealage => (ash::vk::BlendFactor::ZERO, ash::vk::BlendFactor::ONE_MINUS_SRC_COLOR, ash::vk::BlendFactor::ZERO, ash::vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Opaque | BlendMode::Additive | BlendMode::Masked => (ash::vk::BlendFactor::ONE, ash::vk::BlendFactor::ONE, ash::vk::BlendFactor::ONE, ash::vk::BlendFactor::ONE),
+        };
+
+        ash::vk::PipelineColorBlendAttachmentState {
+            blend_enable: if matches!(self, BlendMode::Opaque | BlendMode::Masked) { ash::vk::FALSE } else { ash::vk::TRUE },
+            src_color_blend_factor,
+            dst_color_blend_factor,
+            color_blend_op: ash::vk::BlendOp::ADD,
+            src_alpha_blend_factor,
+            dst_alpha_blend_factor,
+            alpha_blend_op: ash::vk::BlendOp::ADD,
+            color_write_mask: if self == BlendMode::Masked { ash::vk::ColorComponentFlags::empty() } else { ash::vk::ColorComponentFlags::RGBA },
+        }
+    }
+}
+
+/// Offsets the depth of the rasterized fragments, e.g. to draw edges on top
+/// of the faces they belong to. Negative values move towards the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+}
+
+/// Fixed function state of a pipeline, the default is an opaque, back face
+/// culled and depth tested triangle list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipelineConfig {
+    pub topology: ash::vk::PrimitiveTopology,
+    /// `LINE` and `POINT` fall back to `FILL` with a warning when the device
+    /// doesn't support them
+    pub polygon_mode: ash::vk::PolygonMode,
+    pub cull_mode: ash::vk::CullModeFlags,
+    /// Winding of front faces, counter clockwise for geometry drawn mirrored
+    pub front_face: ash::vk::FrontFace,
+    /// Applied to every color attachment
+    pub blend_mode: BlendMode,
+    /// Replaces `blend_mode` per color attachment when not empty, one for
+    /// each of `color_attachment_count`
+    pub attachment_blend_modes: &'static [BlendMode],
+    /// Color attachments of the subpass, e.g. the targets of a G-buffer
+    pub color_attachment_count: u32,
+    /// Clamps depth instead of clipping at the near and far planes, ignored
+    /// with a warning when the device doesn't support it
+    pub depth_clamp: bool,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    /// `LESS_OR_EQUAL` lets coplanar geometry pass against the depth it sits on
+    pub depth_compare_op: ash::vk::CompareOp,
+    pub depth_bias: Option<DepthBias>,
+    /// Has to match the samples of the render pass attachments
+    pub sample_count: ash::vk::SampleCountFlags,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            topology: ash::vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: ash::vk::PolygonMode::FILL,
+            cull_mode: ash::vk::CullModeFlags::BACK,
+            front_face: ash::vk::FrontFace::CLOCKWISE,
+            blend_mode: BlendMode::Opaque,
+            attachment_blend_modes: &[],
+            color_attachment_count: 1,
+            depth_clamp: false,
+            depth_test: true,
+            depth_write: true,
+            depth_compare_op: ash::vk::CompareOp::LESS,
+            depth_bias: None,
+            sample_count: ash::vk::SampleCountFlags::TYPE_1,
+        }
+    }
+}
+
+/// Tessellation control and evaluation stages of a pipeline.
+#[derive(Debug, Clone, Copy)]
+pub struct TessellationShaders<'a> {
+    pub control: ShaderSource<'a>,
+    pub evaluation: ShaderSource<'a>,
+    /// Vertices per patch, 3 to tessellate triangles
+    pub patch_control_points: u32,
+}
+
 pub struct Pipeline {
     device: Rc<Device>,
     pub graphics_pipeline: ash::vk::Pipeline,
-    vert_shader_module: Rc<ShaderModule>,
-    frag_shader_module: Rc<ShaderModule>,
 }
 
 impl Pipeline {
     pub fn new(
         device: Rc<Device>,
-        vert_file_path: &str,
-        frag_file_path: &str,
+        vert_shader: ShaderSource,
+        frag_shader: ShaderSource,
         render_pass: &ash::vk::RenderPass,
         pipeline_layout: &ash::vk::PipelineLayout,
         pipeline_cache: &Rc<PipelineCache>,
-        binding_descriptions: &[ash::vk::VertexInputBindingDescription],
-        attribute_descriptions: &[ash::vk::VertexInputAttributeDescription],
-        cull_mode: ash::vk::CullModeFlags,
+        vertex_layout: &VertexLayout,
+        config: PipelineConfig,
     ) -> anyhow::Result<Self, GentooRenderError> {
-        let (
-            graphics_pipeline,
-            vert_shader_module,
-            frag_shader_module,
-        ) = Self::create_graphics_pipeline(
+        Self::create(
+            device,
+            vert_shader,
+            None,
+            Some(frag_shader),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            vertex_layout,
+            config,
+        )
+    }
+
+    /// A pipeline without fragment shader or color attachments, only writing
+    /// depth, e.g. for shadow casters. `config.color_attachment_count` is ignored.
+    pub fn new_depth_only(
+        device: Rc<Device>,
+        vert_shader: ShaderSource,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        vertex_layout: &VertexLayout,
+        config: PipelineConfig,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        Self::create(
+            device,
+            vert_shader,
+            None,
+            None,
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            vertex_layout,
+            PipelineConfig {
+                color_attachment_count: 0,
+                ..config
+            },
+        )
+    }
+
+    /// A pipeline with tessellation stages, drawing patch lists regardless of
+    /// `config.topology`. Fails if the device doesn't support tessellation.
+    pub fn new_tessellated(
+        device: Rc<Device>,
+        vert_shader: ShaderSource,
+        tessellation_shaders: TessellationShaders,
+        frag_shader: ShaderSource,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        vertex_layout: &VertexLayout,
+        config: PipelineConfig,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        if device.enabled_features.tessellation_shader == 0 {
+            return Err(GentooRenderError::TessellationNotSupported);
+        }
+
+        Self::create(
+            device,
+            vert_shader,
+            Some(tessellation_shaders),
+            Some(frag_shader),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            vertex_layout,
+            PipelineConfig {
+                topology: ash::vk::PrimitiveTopology::PATCH_LIST,
+                ..config
+            },
+        )
+    }
+
+    fn create(
+        device: Rc<Device>,
+        vert_shader: ShaderSource,
+        tessellation_shaders: Option<TessellationShaders>,
+        frag_shader: Option<ShaderSource>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        vertex_layout: &VertexLayout,
+        config: PipelineConfig,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let graphics_pipeline = Self::create_graphics_pipeline(
             &device,
-            vert_file_path,
-            frag_file_path,
+            vert_shader,
+            tessellation_shaders,
+            frag_shader,
             render_pass,
             pipeline_layout,
             pipeline_cache,
-            binding_descriptions,
-            attribute_descriptions,
-            cull_mode,
+            vertex_layout,
+            config,
         )?;
 
         Ok(Self {
             device,
             graphics_pipeline,
-            vert_shader_module,
-            frag_shader_module,
         })
     }
 
@@ -57,58 +236,116 @@ impl Pipeline {
 
     fn create_graphics_pipeline(
         device: &Rc<Device>,
-        vert_file_path: &str,
-        frag_file_path: &str,
+        vert_shader: ShaderSource,
+        tessellation_shaders: Option<TessellationShaders>,
+        frag_shader: Option<ShaderSource>,
         render_pass: &ash::vk::RenderPass,
         pipeline_layout: &ash::vk::PipelineLayout,
         pipeline_cache: &Rc<PipelineCache>,
-        binding_descriptions: &[ash::vk::VertexInputBindingDescription],
-        attribute_descriptions: &[ash::vk::VertexInputAttributeDescription],
-        cull_mode: ash::vk::CullModeFlags,
-    ) -> anyhow::Result<(ash::vk::Pipeline, Rc<ShaderModule>, Rc<ShaderModule>), GentooRenderError> {
-        assert_ne!(
+        vertex_layout: &VertexLayout,
+        config: PipelineConfig,
+    ) -> anyhow::Result<ash::vk::Pipeline, GentooRenderError> {
+        debug_assert_ne!(
             pipeline_layout,
             &ash::vk::PipelineLayout::null(),
             "Cannot create graphics pipeline:: no pipeline_layout provided"
         );
 
-        assert_ne!(
+        debug_assert_ne!(
             render_pass,
             &ash::vk::RenderPass::null(),
             "Cannot create graphics pipeline:: no render_pass provided"
         );
 
-        let vert_shader_module = ShaderModule::new(device.clone(), vert_file_path)?;
-        let frag_shader_module = ShaderModule::new(device.clone(), frag_file_path)?;
+        let depth_clamp = if config.depth_clamp && device.enabled_features.depth_clamp == 0 {
+            log::warn!("Depth clamp is not supported by the device, {} will be clipped at the near and far planes", vert_shader);
+            false
+        } else {
+            config.depth_clamp
+        };
+
+        let polygon_mode = if config.polygon_mode != ash::vk::PolygonMode::FILL && device.enabled_features.fill_mode_non_solid == 0 {
+            log::warn!("{:?} polygon mode is not supported by the device, {} will be filled", config.polygon_mode, vert_shader);
+            ash::vk::PolygonMode::FILL
+        } else {
+            config.polygon_mode
+        };
+
+        let depth_bias = config.depth_bias.unwrap_or(DepthBias {
+            constant_factor: 0.0,
+            slope_factor: 0.0,
+        });
+
+        let vert_shader_module = ShaderModule::from_source(device.clone(), vert_shader)?;
+        let frag_shader_module = match frag_shader {
+            Some(frag_shader) => Some(ShaderModule::from_source(device.clone(), frag_shader)?),
+            None => None,
+        };
+
+        vertex_layout.validate(&vert_shader_module, &vert_shader.to_string())?;
+
+        let tessellation_shader_modules = match tessellation_shaders {
+            Some(shaders) => Some((
+                ShaderModule::from_source(device.clone(), shaders.control)?,
+                ShaderModule::from_source(device.clone(), shaders.evaluation)?,
+            )),
+            None => None,
+        };
+
+        let binding_descriptions = vertex_layout.binding_descriptions();
+        let attribute_descriptions = vertex_layout.attribute_descriptions();
+
+        let blend_attachment_states = if config.attachment_blend_modes.is_empty() {
+            vec![config.blend_mode.attachment_state(); config.color_attachment_count as usize]
+        } else {
+            if config.attachment_blend_modes.len() != config.color_attachment_count as usize {
+                return Err(GentooRenderError::InvalidUsage("Cannot create graphics pipeline:: a blend mode is needed for every color attachment"));
+            }
+
+            config.attachment_blend_modes.iter().map(|blend_mode| blend_mode.attachment_state()).collect()
+        };
 
         let entry_point_name = CString::new("main").unwrap();
 
+        let mut stages = vec![(ash::vk::ShaderStageFlags::VERTEX, vert_shader_module.module)];
+
+        if let Some(frag_shader_module) = &frag_shader_module {
+            stages.push((ash::vk::ShaderStageFlags::FRAGMENT, frag_shader_module.module));
+        }
+
+        if let Some((control_module, evaluation_module)) = &tessellation_shader_modules {
+            stages.push((ash::vk::ShaderStageFlags::TESSELLATION_CONTROL, control_module.module));
+            stages.push((ash::vk::ShaderStageFlags::TESSELLATION_EVALUATION, evaluation_module.module));
+        }
+
+        let stages = stages
+            .into_iter()
+            .map(|(stage, module)| ash::vk::PipelineShaderStageCreateInfo {
+                stage,
+                module,
+                p_name: entry_point_name.as_ptr() as _,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
         let pipeline_info = &[
             ash::vk::GraphicsPipelineCreateInfo::builder()
-                .stages(&[
-                    ash::vk::PipelineShaderStageCreateInfo {
-                        stage: ash::vk::ShaderStageFlags::VERTEX,
-                        module: vert_shader_module.module,
-                        p_name: entry_point_name.as_ptr() as _,
-                        ..Default::default()
-                    },
-                    ash::vk::PipelineShaderStageCreateInfo {
-                        stage: ash::vk::ShaderStageFlags::FRAGMENT,
-                        module: frag_shader_module.module,
-                        p_name: entry_point_name.as_ptr() as _,
-                        ..Default::default()
-                    },
-                ])
+                .stages(&stages)
                 .vertex_input_state(
                     &ash::vk::PipelineVertexInputStateCreateInfo::builder()
-                        .vertex_binding_descriptions(binding_descriptions)
-                        .vertex_attribute_descriptions(attribute_descriptions)
+                        .vertex_binding_descriptions(&binding_descriptions)
+                        .vertex_attribute_descriptions(&attribute_descriptions)
                 )
                 .input_assembly_state(
                     &ash::vk::PipelineInputAssemblyStateCreateInfo::builder()
-                        .topology(ash::vk::PrimitiveTopology::TRIANGLE_LIST)
+                        .topology(config.topology)
                         .primitive_restart_enable(false)
                 )
+                // Ignored without tessellation stages
+                .tessellation_state(
+                    &ash::vk::PipelineTessellationStateCreateInfo::builder()
+                        .patch_control_points(tessellation_shaders.map_or(0, |shaders| shaders.patch_control_points))
+                )
                 .viewport_state(
                     &ash::vk::PipelineViewportStateCreateInfo::builder()
                         .viewport_count(1)
@@ -116,40 +353,31 @@ impl Pipeline {
                 )
                 .rasterization_state(
                     &ash::vk::PipelineRasterizationStateCreateInfo::builder()
-                        .depth_clamp_enable(false)
+                        .depth_clamp_enable(depth_clamp)
                         .rasterizer_discard_enable(false)
-                        .polygon_mode(ash::vk::PolygonMode::FILL)
+                        .polygon_mode(polygon_mode)
                         .line_width(1.0)
-                        .cull_mode(cull_mode) 
-                        .front_face(ash::vk::FrontFace::CLOCKWISE) 
-                        .depth_bias_enable(false)
+                        .cull_mode(config.cull_mode) 
+                        .front_face(config.front_face)
+                        .depth_bias_enable(config.depth_bias.is_some())
+                        .depth_bias_constant_factor(depth_bias.constant_factor)
+                        .depth_bias_slope_factor(depth_bias.slope_factor)
                 )
                 .multisample_state(
                     &ash::vk::PipelineMultisampleStateCreateInfo::builder()
                         .sample_shading_enable(false)
-                        .rasterization_samples(ash::vk::SampleCountFlags::TYPE_1)
+                        .rasterization_samples(config.sample_count)
                 )
                 .color_blend_state(
                     &ash::vk::PipelineColorBlendStateCreateInfo::builder()
                         .logic_op_enable(false)
-                        .attachments(
-                            &[ash::vk::PipelineColorBlendAttachmentState {
-                                blend_enable: ash::vk::TRUE,
-                                src_color_blend_factor: ash::vk::BlendFactor::ONE,
-                                dst_color_blend_factor: ash::vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                                color_blend_op: ash::vk::BlendOp::ADD,
-                                src_alpha_blend_factor: ash::vk::BlendFactor::ONE,
-                                dst_alpha_blend_factor: ash::vk::BlendFactor::ZERO,
-                                alpha_blend_op: ash::vk::BlendOp::ADD,
-                                color_write_mask: ash::vk::ColorComponentFlags::RGBA,
-                            }]
-                        )
+                        .attachments(&blend_attachment_states)
                 )
                 .depth_stencil_state(
                     &ash::vk::PipelineDepthStencilStateCreateInfo::builder()
-                        .depth_write_enable(true)
-                        .depth_compare_op(ash::vk::CompareOp::LESS)
-                        .depth_test_enable(true)
+                        .depth_write_enable(config.depth_write)
+                        .depth_compare_op(config.depth_compare_op)
+                        .depth_test_enable(config.depth_test)
                         .stencil_test_enable(false)
                 )
                 .dynamic_state(
@@ -169,11 +397,21 @@ impl Pipeline {
         let graphics_pipeline = unsafe {
             device.logical_device
                 .create_graphics_pipelines(pipeline_cache.cache, pipeline_info, None)
-                .map_err(|e| log::error!("Unable to create graphics pipeline: {:?}", e))
-                .unwrap()[0] // fix unwrap?
+                .map_err(|(_, result)| {
+                    log::error!("Unable to create graphics pipeline: {:?}", result);
+
+                    GentooRenderError::PipelineCreationError {
+                        vert_shader: vert_shader.to_string(),
+                        frag_shader: frag_shader.map_or("none".to_string(), |frag_shader| frag_shader.to_string()),
+                        result,
+                    }
+                })?[0]
         };
 
-        Ok((graphics_pipeline, vert_shader_module, frag_shader_module))
+        device.resource_tracker.created(ResourceKind::Pipeline, 1);
+
+        // The shader modules aren't needed once the pipeline is created
+        Ok(graphics_pipeline)
     }
 }
 
@@ -184,5 +422,7 @@ impl Drop for Pipeline {
         unsafe {
             self.device.logical_device.destroy_pipeline(self.graphics_pipeline, None);
         }
+
+        self.device.resource_tracker.destroyed(ResourceKind::Pipeline, 1);
     }
 }
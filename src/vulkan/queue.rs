@@ -19,15 +19,11 @@ impl QueueFamilies {
         surface_khr: ash::vk::SurfaceKHR,
         physical_device: ash::vk::PhysicalDevice,
     ) -> anyhow::Result<Self, GentooRenderError> {
-        let mut queue_families = Self {
-            present: None,
-            graphics: None,
-        };
-
         let queue_family_properties = unsafe {
             instance.instance.get_physical_device_queue_family_properties(physical_device)
         };
 
+        let mut support = Vec::with_capacity(queue_family_properties.len());
         for (index, queue_family_property) in queue_family_properties.into_iter().enumerate() {
             let present_support = unsafe {
                 surface.get_physical_device_surface_support(physical_device, index as u32, surface_khr)?
@@ -35,6 +31,30 @@ impl QueueFamilies {
 
             let graphics_support = queue_family_property.queue_flags.contains(ash::vk::QueueFlags::GRAPHICS);
 
+            support.push((present_support, graphics_support));
+        }
+
+        Ok(Self::select(support))
+    }
+
+    /// Families picked by hand, e.g. to exercise the separate present and
+    /// graphics family path on hardware that has a single family.
+    pub fn from_indices(present: usize, graphics: usize) -> Self {
+        Self {
+            present: Some(present),
+            graphics: Some(graphics),
+        }
+    }
+
+    /// Picks the first family with present and the first with graphics support
+    /// from `(present_support, graphics_support)` per family index.
+    pub fn select<I: IntoIterator<Item = (bool, bool)>>(support: I) -> Self {
+        let mut queue_families = Self {
+            present: None,
+            graphics: None,
+        };
+
+        for (index, (present_support, graphics_support)) in support.into_iter().enumerate() {
             if present_support && queue_families.present.is_none() {
                 queue_families.present = Some(index);
             }
@@ -48,26 +68,34 @@ impl QueueFamilies {
             }
         }
 
-        Ok(queue_families)
+        queue_families
     }
 
     pub fn get_vec(&self) -> Option<Vec<ash::vk::DeviceQueueCreateInfo>> {
-        if self.same()? {
-            Some(vec![ash::vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(self.present.unwrap() as u32)
+        Some(self.unique_indices()?
+            .into_iter()
+            .map(|index| ash::vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(index)
                 .queue_priorities(&PRIORITY)
-                .build(),
-            ])
+                .build())
+            .collect())
+    }
+
+    /// The distinct family indices, a family may only be requested once at device creation.
+    pub fn unique_indices(&self) -> Option<Vec<u32>> {
+        if self.same()? {
+            Some(vec![self.present? as u32])
         } else {
-            Some(vec![ash::vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(self.present.unwrap() as u32)
-                .queue_priorities(&PRIORITY)
-                .build(),
-            ash::vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(self.present.unwrap() as u32)
-                .queue_priorities(&PRIORITY)
-                .build(),
-            ])
+            Some(vec![self.present? as u32, self.graphics? as u32])
+        }
+    }
+
+    /// Images used by both queues have to be shared concurrently when the families differ.
+    pub fn image_sharing_mode(&self) -> Option<(ash::vk::SharingMode, Vec<u32>)> {
+        if self.same()? {
+            Some((ash::vk::SharingMode::EXCLUSIVE, Vec::new()))
+        } else {
+            Some((ash::vk::SharingMode::CONCURRENT, self.unique_indices()?))
         }
     }
 
@@ -98,3 +126,56 @@ impl QueueFamilies {
         Some(self.present? == self.graphics?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_shared_family() {
+        let queue_families = QueueFamilies::select([(false, false), (true, true), (true, true)]);
+
+        assert_eq!(queue_families.present, Some(1));
+        assert_eq!(queue_families.graphics, Some(1));
+        assert_eq!(queue_families.same(), Some(true));
+        assert_eq!(queue_families.unique_indices(), Some(vec![1]));
+        assert_eq!(queue_families.image_sharing_mode(), Some((ash::vk::SharingMode::EXCLUSIVE, Vec::new())));
+    }
+
+    #[test]
+    fn select_dedicated_families() {
+        let queue_families = QueueFamilies::select([(false, true), (true, false), (true, true)]);
+
+        assert_eq!(queue_families.present, Some(1));
+        assert_eq!(queue_families.graphics, Some(0));
+        assert_eq!(queue_families.same(), Some(false));
+        assert_eq!(queue_families.unique_indices(), Some(vec![1, 0]));
+        assert_eq!(queue_families.image_sharing_mode(), Some((ash::vk::SharingMode::CONCURRENT, vec![1, 0])));
+    }
+
+    #[test]
+    fn select_without_present_support() {
+        let queue_families = QueueFamilies::select([(false, true), (false, false)]);
+
+        assert!(!queue_families.finished());
+        assert_eq!(queue_families.present, None);
+        assert_eq!(queue_families.graphics, Some(0));
+        assert_eq!(queue_families.same(), None);
+        assert_eq!(queue_families.unique_indices(), None);
+        assert_eq!(queue_families.image_sharing_mode(), None);
+        assert!(queue_families.get_vec().is_none());
+    }
+
+    #[test]
+    fn queue_create_infos_per_unique_family() {
+        let shared = QueueFamilies::from_indices(2, 2).get_vec().unwrap();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].queue_family_index, 2);
+        assert_eq!(shared[0].queue_count, 1);
+
+        let dedicated = QueueFamilies::from_indices(1, 0).get_vec().unwrap();
+        let indices = dedicated.iter().map(|info| info.queue_family_index).collect::<Vec<_>>();
+        assert_eq!(indices, vec![1, 0]);
+        assert!(dedicated.iter().all(|info| info.queue_count == 1));
+    }
+}
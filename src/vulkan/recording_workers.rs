@@ -0,0 +1,159 @@
+use std::{rc::Rc, sync::mpsc, thread::JoinHandle};
+
+use super::{Device, GentooRenderError, MAX_FRAMES_IN_FLIGHT};
+
+/// Records into the worker's command buffer, which is reset but not begun.
+pub type RecordingJob = Box<dyn FnOnce(&ash::Device, ash::vk::CommandBuffer) -> anyhow::Result<(), GentooRenderError> + Send>;
+
+type JobResult = anyhow::Result<ash::vk::CommandBuffer, GentooRenderError>;
+
+struct Worker {
+    /// Jobs with the frame index whose pool they record with, None once
+    /// the worker is told to stop
+    jobs: Option<mpsc::Sender<(usize, RecordingJob)>>,
+    results: mpsc::Receiver<JobResult>,
+    thread: Option<JoinHandle<()>>,
+    /// One per frame in flight, only the worker thread touches them
+    command_pools: Vec<ash::vk::CommandPool>,
+}
+
+/// Threads that live as long as the system using them, each with a command
+/// pool and a single command buffer for every frame in flight.
+pub struct RecordingWorkers {
+    device: Rc<Device>,
+    workers: Vec<Worker>,
+}
+
+impl RecordingWorkers {
+    pub fn new(
+        device: Rc<Device>,
+        threads: usize,
+        level: ash::vk::CommandBufferLevel,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let mut workers = Vec::with_capacity(threads);
+
+        for index in 0..threads {
+            let mut command_pools = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+            let mut command_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+
+            for _ in 0..MAX_FRAMES_IN_FLIGHT {
+                let command_pool = device.create_command_pool(ash::vk::CommandPoolCreateFlags::TRANSIENT)?;
+
+                command_pools.push(command_pool);
+
+                let alloc_info = ash::vk::CommandBufferAllocateInfo::builder()
+                    .level(level)
+                    .command_pool(command_pool)
+                    .command_buffer_count(1);
+
+                command_buffers.push(unsafe {
+                    device.logical_device.allocate_command_buffers(&alloc_info)?[0]
+                });
+            }
+
+            let (job_sender, jobs) = mpsc::channel::<(usize, RecordingJob)>();
+            let (result_sender, results) = mpsc::channel();
+
+            let logical_device = device.logical_device.clone();
+            let pools = command_pools.clone();
+
+            let thread = std::thread::Builder::new()
+                .name(format!("recording worker {}", index))
+                .spawn(move || {
+                    for (frame_index, job) in jobs {
+                        let result = Self::run_job(&logical_device, pools[frame_index], command_buffers[frame_index], job);
+
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .map_err(GentooRenderError::ThreadSpawnError)?;
+
+            workers.push(Worker {
+                jobs: Some(job_sender),
+                results,
+                thread: Some(thread),
+                command_pools,
+            });
+        }
+
+        Ok(Self {
+            device,
+            workers,
+        })
+    }
+
+    fn run_job(
+        logical_device: &ash::Device,
+        command_pool: ash::vk::CommandPool,
+        command_buffer: ash::vk::CommandBuffer,
+        job: RecordingJob,
+    ) -> JobResult {
+        // The previous submission of this frame has finished before the next
+        // job for it arrives
+        unsafe {
+            logical_device.reset_command_pool(command_pool, ash::vk::CommandPoolResetFlags::empty())?;
+        }
+
+        job(logical_device, command_buffer)?;
+
+        Ok(command_buffer)
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Runs each job on its own worker with the worker's command buffer of
+    /// `frame_index` and waits for all of them. Returns the recorded command
+    /// buffers in the order of `jobs`, at most one job per worker. The
+    /// previous submission of this frame has to be finished.
+    pub fn record(&self, frame_index: usize, jobs: Vec<RecordingJob>) -> anyhow::Result<Vec<ash::vk::CommandBuffer>, GentooRenderError> {
+        if jobs.len() > self.workers.len() {
+            return Err(GentooRenderError::InvalidUsage("More recording jobs than recording threads"));
+        }
+
+        let workers = &self.workers[..jobs.len()];
+
+        for (worker, job) in workers.iter().zip(jobs) {
+            worker.jobs
+                .as_ref()
+                .and_then(|jobs| jobs.send((frame_index, job)).ok())
+                .expect("Recording thread panicked");
+        }
+
+        // Wait for every worker before returning an error, so none is still
+        // recording into a command buffer of this frame
+        let results = workers
+            .iter()
+            .map(|worker| worker.results.recv().expect("Recording thread panicked"))
+            .collect::<Vec<_>>();
+
+        results.into_iter().collect()
+    }
+}
+
+impl Drop for RecordingWorkers {
+    fn drop(&mut self) {
+        log::debug!("Dropping recording workers");
+
+        for worker in &mut self.workers {
+            // Closing the channel ends the worker's loop
+            worker.jobs = None;
+
+            if let Some(thread) = worker.thread.take() {
+                if thread.join().is_err() {
+                    log::error!("Recording thread panicked");
+                }
+            }
+
+            unsafe {
+                // Destroying the pools frees their command buffers
+                for &command_pool in &worker.command_pools {
+                    self.device.logical_device.destroy_command_pool(command_pool, None);
+                }
+            }
+        }
+    }
+}
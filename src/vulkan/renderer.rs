@@ -1,38 +1,159 @@
-use std::rc::Rc;
+use std::{path::PathBuf, rc::Rc, time::Duration};
 
 use crate::window::Window;
 
-use super::{Device, Swapchain, GentooRenderError, MAX_FRAMES_IN_FLIGHT};
+use super::{Device, Swapchain, GentooRenderError, FrameCapture, OffscreenTarget, AttachmentLoadOps, BlitTarget, Buffer, MAX_FRAMES_IN_FLIGHT, DEFAULT_FENCE_TIMEOUT, format_to_rgba8, swaps_red_blue};
+
+/// Color the scene pass clears to.
+pub const CLEAR_COLOR: [f32; 4] = [0.01, 0.01, 0.01, 1.0];
+
+/// Supersampling limit, at 2 the downsampling blit is a 2x2 box filter
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// Consecutive frames without a swapchain image before it is logged, and
+/// before `begin_frame` gives up with `SwapchainUnavailable`. Each skipped
+/// frame after the first limit waits a bit longer, up to `MAX_SKIP_BACKOFF`,
+/// so a long resize doesn't spin.
+const SKIPPED_FRAMES_WARNING: u32 = 60;
+const MAX_SKIPPED_FRAMES: u32 = 600;
+const MAX_SKIP_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Viewport depth range, the normalized depth of a draw is mapped into it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+pub const FULL_DEPTH_RANGE: DepthRange = DepthRange { min: 0.0, max: 1.0 };
+
+/// Everything a secondary command buffer needs to continue the swapchain
+/// render pass. Only holds handles, so it can be sent to recording threads.
+#[derive(Debug, Clone, Copy)]
+pub struct SecondaryRecordingInfo {
+    pub render_pass: ash::vk::RenderPass,
+    pub framebuffer: ash::vk::Framebuffer,
+    pub extent: ash::vk::Extent2D,
+}
+
+impl SecondaryRecordingInfo {
+    /// Begins `command_buffer` inside the render pass and sets the dynamic
+    /// viewport and scissor, which aren't inherited from the primary.
+    pub unsafe fn begin(&self, logical_device: &ash::Device, command_buffer: ash::vk::CommandBuffer) -> anyhow::Result<(), GentooRenderError> {
+        let inheritance_info = ash::vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(self.render_pass)
+            .subpass(0)
+            .framebuffer(self.framebuffer);
+
+        let begin_info = ash::vk::CommandBufferBeginInfo::builder()
+            .flags(ash::vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE | ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .inheritance_info(&inheritance_info);
+
+        logical_device.begin_command_buffer(command_buffer, &begin_info)?;
+
+        Renderer::set_viewport_and_scissor(logical_device, command_buffer, self.extent);
+
+        Ok(())
+    }
+}
 
 pub struct Renderer {
     pub device: Rc<Device>,
     pub swapchain: Swapchain,
     command_buffers: Vec<ash::vk::CommandBuffer>,
+    secondary_command_buffers: Vec<ash::vk::CommandBuffer>,
     current_image_index: usize,
     current_frame_index: usize,
     is_frame_started: bool,
+    /// Set when acquiring or presenting reported a suboptimal or out of date
+    /// swapchain, it is recreated at the start of the next frame
+    swapchain_outdated: bool,
+    /// Set by `request_recreate`, the swapchain is rebuilt at the start of
+    /// the next frame
+    recreate_requested: bool,
+    /// Frames in a row `begin_frame` couldn't start, see `SKIPPED_FRAMES_WARNING`
+    skipped_frames: u32,
+    /// Set when a recreated swapchain came with another color or depth
+    /// format, see `take_format_change`
+    format_changed: bool,
+    final_layout: ash::vk::ImageLayout,
+    /// Requested swapchain image usage, see `Swapchain::new`
+    image_usage: ash::vk::ImageUsageFlags,
+    frame_capture: FrameCapture,
+    render_scale: f32,
+    /// The scene is resolved by the caller before it reaches the swapchain
+    resolve_scene: bool,
+    /// Only exists while the scene renders at another resolution than the
+    /// swapchain or is resolved by the caller
+    scene_target: Option<OffscreenTarget>,
+    /// Per attempt limit of the frame fence waits
+    fence_timeout: Duration,
+    /// What `begin_swapchain_render_pass` clears the depth and stencil to
+    depth_stencil_clear: ash::vk::ClearDepthStencilValue,
+    /// Scene resolution independent of the window, replaces `render_scale`
+    fixed_resolution: Option<ash::vk::Extent2D>,
+    /// Fills the swapchain image around the integer scaled fixed resolution scene
+    letterbox_color: [f32; 4],
+    /// Where `end_frame` saves the presented image, see `request_screenshot`
+    screenshot_path: Option<PathBuf>,
 }
 
 impl Renderer {
-    pub fn new(device: Rc<Device>, window: &Window) -> anyhow::Result<Self, GentooRenderError> {
+    /// `final_layout` is the layout the swapchain render pass leaves the image in,
+    /// `PRESENT_SRC_KHR` if nothing else draws to the swapchain image after the scene.
+    pub fn new(
+        device: Rc<Device>,
+        window: &Window,
+        final_layout: ash::vk::ImageLayout,
+        image_usage: ash::vk::ImageUsageFlags,
+    ) -> anyhow::Result<Self, GentooRenderError> {
         let window_extent = Self::get_window_extent(window);
 
-        let swapchain = Swapchain::new(device.clone(), window_extent, None)?;
+        let swapchain = Swapchain::new(device.clone(), window_extent, None, final_layout, image_usage)?;
 
-        let command_buffers = Self::create_command_buffers(&device.logical_device, device.command_pool)?;
+        let command_buffers = Self::create_command_buffers(
+            &device.logical_device,
+            device.command_pool,
+            ash::vk::CommandBufferLevel::PRIMARY,
+        )?;
+
+        let secondary_command_buffers = Self::create_command_buffers(
+            &device.logical_device,
+            device.command_pool,
+            ash::vk::CommandBufferLevel::SECONDARY,
+        )?;
 
         Ok(Self {
             device,
             swapchain,
             command_buffers,
+            secondary_command_buffers,
             current_image_index: 0,
             current_frame_index: 0,
             is_frame_started: false,
+            swapchain_outdated: false,
+            recreate_requested: false,
+            skipped_frames: 0,
+            format_changed: false,
+            final_layout,
+            image_usage,
+            frame_capture: FrameCapture::new(),
+            render_scale: 1.0,
+            resolve_scene: false,
+            scene_target: None,
+            fence_timeout: DEFAULT_FENCE_TIMEOUT,
+            depth_stencil_clear: ash::vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+            fixed_resolution: None,
+            letterbox_color: [0.0, 0.0, 0.0, 1.0],
+            screenshot_path: None,
         })
     }
 
     pub fn get_frame_index(&self) -> usize {
-        assert!(
+        debug_assert!(
             self.is_frame_started,
             "Cannot get frame index when frame is not in progress"
         );
@@ -40,12 +161,41 @@ impl Renderer {
         self.current_frame_index
     }
 
+    /// Checks in debug builds that the GPU is done with the resources of
+    /// `frame_index`, call it before writing per frame buffers. Catches the
+    /// flickering that follows from too few copies or a stale frame index.
+    pub fn validate_frame_resources(&self, frame_index: usize) -> anyhow::Result<(), GentooRenderError> {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+
+        assert!(
+            self.is_frame_started && frame_index == self.current_frame_index,
+            "Frame {} resources written outside of its frame",
+            frame_index
+        );
+
+        assert_eq!(
+            self.swapchain.current_frame(),
+            self.current_frame_index,
+            "Renderer and swapchain frame indices diverged"
+        );
+
+        assert!(
+            self.swapchain.is_frame_complete(frame_index)?,
+            "Frame {} resources are still in use by the GPU",
+            frame_index
+        );
+
+        Ok(())
+    }
+
     pub fn get_image_index(&self) -> usize {
         self.current_image_index
     }
 
     pub fn get_current_command_buffer(&self) -> ash::vk::CommandBuffer {
-        assert!(
+        debug_assert!(
             self.is_frame_started,
             "Cannot get command buffer when frame not in progress"
         );
@@ -58,40 +208,232 @@ impl Renderer {
     }
 
     pub fn get_aspect_ratio(&self) -> f32 {
-        self.swapchain.extent_aspect_ratio()
+        match &self.scene_target {
+            Some(target) => target.extent.width as f32 / target.extent.height as f32,
+            None => self.swapchain.extent_aspect_ratio(),
+        }
+    }
+
+    /// Resolution the scene is rendered at, the swapchain extent times the
+    /// render scale or the fixed resolution
+    pub fn get_scene_extent(&self) -> ash::vk::Extent2D {
+        match &self.scene_target {
+            Some(target) => target.extent,
+            None => self.swapchain.swapchain_extent,
+        }
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// How long waiting for the GPU to finish a frame may take before it is
+    /// logged and retried. A few rounds later the frame fails with `FenceTimeout`.
+    pub fn set_fence_timeout(&mut self, fence_timeout: Duration) {
+        self.fence_timeout = fence_timeout;
+    }
+
+    /// Clear values of the scene pass' depth and stencil, e.g. a depth of 0
+    /// for pipelines testing with a reversed depth range.
+    pub fn set_depth_stencil_clear(&mut self, depth: f32, stencil: u32) {
+        self.depth_stencil_clear = ash::vk::ClearDepthStencilValue { depth, stencil };
+    }
+
+    /// Renders the scene at `render_scale` times the swapchain resolution and
+    /// scales it onto the swapchain image. Above 1 the scene is supersampled,
+    /// up to `MAX_RENDER_SCALE`. Must not be called during a frame.
+    pub fn set_render_scale(&mut self, render_scale: f32) -> anyhow::Result<(), GentooRenderError> {
+        if self.is_frame_started {
+            return Err(GentooRenderError::InvalidUsage("Can't change the render scale while frame is in progress"));
+        }
+
+        self.render_scale = render_scale.clamp(0.1, MAX_RENDER_SCALE);
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?
+        };
+
+        self.scene_target = self.create_scene_target()?;
+
+        Ok(())
+    }
+
+    pub fn fixed_resolution(&self) -> Option<ash::vk::Extent2D> {
+        self.fixed_resolution
+    }
+
+    /// Renders the scene at `resolution` whatever the window size is, and
+    /// scales it onto the swapchain by the largest integer factor that fits
+    /// with nearest filtering, e.g. for pixel art. The image is centered and
+    /// surrounded by the letterbox color. Must not be called during a frame.
+    pub fn set_fixed_resolution(&mut self, resolution: Option<ash::vk::Extent2D>) -> anyhow::Result<(), GentooRenderError> {
+        if self.is_frame_started {
+            return Err(GentooRenderError::InvalidUsage("Can't change the fixed resolution while frame is in progress"));
+        }
+
+        self.fixed_resolution = resolution;
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?
+        };
+
+        self.scene_target = self.create_scene_target()?;
+
+        Ok(())
+    }
+
+    pub fn set_letterbox_color(&mut self, color: [f32; 4]) {
+        self.letterbox_color = color;
+    }
+
+    pub fn resolve_scene(&self) -> bool {
+        self.resolve_scene
+    }
+
+    /// Whether `end_swapchain_render_pass` leaves the scene in `scene_target`
+    /// for the caller to resolve and `blit_to_swapchain`.
+    pub fn is_scene_resolve_active(&self) -> bool {
+        self.resolve_scene && self.scene_target.is_some()
+    }
+
+    /// Renders the scene into an offscreen target with a sampleable color and
+    /// depth, e.g. for temporal anti-aliasing or a fullscreen effect, see
+    /// `is_scene_resolve_active`. Must not be called during a frame.
+    pub fn set_resolve_scene(&mut self, resolve_scene: bool) -> anyhow::Result<(), GentooRenderError> {
+        if self.is_frame_started {
+            return Err(GentooRenderError::InvalidUsage("Can't toggle the scene resolve while frame is in progress"));
+        }
+
+        self.resolve_scene = resolve_scene;
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?
+        };
+
+        self.scene_target = self.create_scene_target()?;
+
+        Ok(())
+    }
+
+    /// The target the scene is drawn into when it isn't drawn to the swapchain directly
+    pub fn scene_target(&self) -> Option<&OffscreenTarget> {
+        self.scene_target.as_ref()
+    }
+
+    fn create_scene_target(&self) -> anyhow::Result<Option<OffscreenTarget>, GentooRenderError> {
+        if self.render_scale == 1.0 && !self.resolve_scene && self.fixed_resolution.is_none() {
+            return Ok(None);
+        }
+
+        if !self.swapchain.swapchain_image_usage.contains(ash::vk::ImageUsageFlags::TRANSFER_DST) {
+            log::warn!("Swapchain images can't be blitted to, rendering at full resolution without resolving the scene");
+            return Ok(None);
+        }
+
+        // Supersampling a large window can exceed the image size limit, the
+        // scale is lowered for both sides to keep the aspect ratio
+        let max_dimension = self.device.properties.limits.max_image_dimension2_d as f32;
+        let render_scale = self.render_scale.min(max_dimension / self.swapchain.width().max(self.swapchain.height()) as f32);
+
+        let extent = match self.fixed_resolution {
+            Some(resolution) => ash::vk::Extent2D {
+                width: resolution.width.clamp(1, max_dimension as u32),
+                height: resolution.height.clamp(1, max_dimension as u32),
+            },
+            None => ash::vk::Extent2D {
+                width: ((self.swapchain.width() as f32 * render_scale) as u32).max(1),
+                height: ((self.swapchain.height() as f32 * render_scale) as u32).max(1),
+            },
+        };
+
+        let load_ops = AttachmentLoadOps {
+            store_depth: self.resolve_scene,
+            ..Default::default()
+        };
+
+        Ok(Some(OffscreenTarget::new(self.device.clone(), self.swapchain.swapchain_image_format, extent, load_ops)?))
+    }
+
+    /// Render pass, framebuffer and extent the scene is drawn into this frame
+    fn scene_pass(&self) -> (ash::vk::RenderPass, ash::vk::Framebuffer, ash::vk::Extent2D) {
+        match &self.scene_target {
+            Some(target) => (target.render_pass, target.framebuffer, target.extent),
+            None => (
+                self.swapchain.render_pass,
+                self.swapchain.swapchain_framebuffers[self.current_image_index],
+                self.swapchain.swapchain_extent,
+            ),
+        }
+    }
+
+    /// Rebuilds the swapchain at the start of the next frame, for settings it
+    /// depends on. `begin_frame` returns None then, like after a resize, and
+    /// the caller updates what depends on the swapchain.
+    pub fn request_recreate(&mut self) {
+        self.recreate_requested = true;
+    }
+
+    /// Captures the next frame in RenderDoc, if it is attached.
+    pub fn request_frame_capture(&mut self) {
+        self.frame_capture.request();
+    }
+
+    /// Saves the next presented frame, with the UI, as a PNG. Needs
+    /// `TRANSFER_SRC` in the swapchain image usage and an 8 bit RGBA or BGRA
+    /// swapchain format.
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        if !self.swapchain.swapchain_image_usage.contains(ash::vk::ImageUsageFlags::TRANSFER_SRC) {
+            log::warn!("Screenshots need TRANSFER_SRC in the swapchain image usage");
+            return;
+        }
+
+        self.screenshot_path = Some(path);
     }
 
     pub fn begin_frame(&mut self, window: &Window) -> anyhow::Result<Option<ash::vk::CommandBuffer>, GentooRenderError> {
-        assert!(
-            !self.is_frame_started,
-            "Can't call begin_frame while already in progress"
-        );
+        if self.is_frame_started {
+            return Err(GentooRenderError::InvalidUsage("Can't call begin_frame while already in progress"));
+        }
+
+        if self.swapchain_outdated || self.recreate_requested {
+            if self.recreate_requested {
+                log::debug!("Swapchain recreation requested");
+            } else {
+                log::debug!("Swapchain is suboptimal for the surface");
+            }
+
+            self.recreate_swapchain(window)?;
+            return self.skip_frame();
+        }
 
         let result = unsafe {
-            self.swapchain.acquire_next_image(&self.device.logical_device)?
+            self.swapchain.acquire_next_image(&self.device.logical_device, self.fence_timeout)?
         };
 
         match result {
             Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                 log::error!("Out of date KHR!");
                 self.recreate_swapchain(window)?;
-                return Ok(None);
+                return self.skip_frame();
             }
-            Err(_) => {
-                log::error!("Unable to acquire next image");
-                panic!("Unable to handle this error")
+            Err(result) => {
+                log::error!("Unable to acquire next image: {}", result);
+                return Err(result.into());
             }
-            Ok((current_image_index, _is_subopt)) => {
-                // match is_subopt {
-                //     true => {
-                //         log::warn!("Swapchain is suboptimal for surface");
-                //         self.recreate_swapchain(window);
-                //     }
-                //     false => { }
-                // }
+            Ok((current_image_index, is_suboptimal)) => {
+                // The image is still presentable and its semaphore is pending,
+                // so finish this frame and recreate before the next one
+                self.swapchain_outdated |= is_suboptimal;
+
+                if self.skipped_frames >= SKIPPED_FRAMES_WARNING {
+                    log::info!("Acquired a swapchain image again after {} skipped frames", self.skipped_frames);
+                }
 
+                self.skipped_frames = 0;
                 self.is_frame_started = true;
                 self.current_image_index = current_image_index as usize;
+
+                self.frame_capture.begin();
             }
         }
 
@@ -106,28 +448,57 @@ impl Renderer {
         Ok(Some(command_buffer))
     }
 
+    /// Counts a frame `begin_frame` couldn't start and backs off while they
+    /// keep failing. Errors once the swapchain stayed unavailable for too long.
+    fn skip_frame(&mut self) -> anyhow::Result<Option<ash::vk::CommandBuffer>, GentooRenderError> {
+        self.skipped_frames += 1;
+
+        if self.skipped_frames == SKIPPED_FRAMES_WARNING {
+            log::warn!("{} frames in a row were skipped without a swapchain image, retrying with a delay", self.skipped_frames);
+        }
+
+        if self.skipped_frames >= MAX_SKIPPED_FRAMES {
+            log::error!("Giving up on acquiring a swapchain image after {} frames", self.skipped_frames);
+            return Err(GentooRenderError::SwapchainUnavailable { frames: self.skipped_frames });
+        }
+
+        if self.skipped_frames > SKIPPED_FRAMES_WARNING {
+            let backoff = Duration::from_millis(u64::from(self.skipped_frames - SKIPPED_FRAMES_WARNING));
+            std::thread::sleep(backoff.min(MAX_SKIP_BACKOFF));
+        }
+
+        Ok(None)
+    }
+
     pub fn end_frame(&mut self) -> anyhow::Result<(), GentooRenderError> {
-        assert!(
-            self.is_frame_started,
-            "Can't call end_frame while frame is not in progress"
-        );
+        if !self.is_frame_started {
+            return Err(GentooRenderError::InvalidUsage("Can't call end_frame while frame is not in progress"));
+        }
 
         let command_buffer = self.get_current_command_buffer();
 
+        let screenshot = match self.screenshot_path.take() {
+            Some(path) => self.record_screenshot(command_buffer)?.map(|buffer| (path, buffer)),
+            None => None,
+        };
+
         unsafe {
             self.device.logical_device.end_command_buffer(command_buffer)?
         };
 
         let queues = self.device.queue_families.get_queues(&self.device).unwrap();
 
-        self.swapchain.submit_command_buffers(
+        self.swapchain_outdated |= self.swapchain.submit_command_buffers(
             &self.device.logical_device,
             &queues.present,
             &queues.graphics,
             command_buffer,
             self.current_image_index,
+            self.fence_timeout,
         )?;
 
+        self.frame_capture.end();
+
         unsafe {
             self.device.logical_device.device_wait_idle()?
         };
@@ -135,44 +506,170 @@ impl Renderer {
         self.is_frame_started = false;
         self.current_frame_index = (self.current_frame_index + 1) % MAX_FRAMES_IN_FLIGHT;
 
+        // The device is idle, so the copy is finished
+        if let Some((path, mut buffer)) = screenshot {
+            // A failed screenshot shouldn't end the application
+            match self.save_screenshot(&path, &mut buffer) {
+                Ok(()) => log::info!("Saved screenshot {}", path.display()),
+                Err(err) => log::error!("Failed to save screenshot {}: {}", path.display(), err),
+            }
+        }
+
         Ok(())
     }
 
-    pub fn begin_swapchain_render_pass(&self, command_buffer: ash::vk::CommandBuffer) {
-        assert!(
+    /// Copies the presentable swapchain image into a host visible buffer.
+    /// None if the swapchain format can't be converted to RGBA8.
+    fn record_screenshot(&self, command_buffer: ash::vk::CommandBuffer) -> anyhow::Result<Option<Buffer<u8>>, GentooRenderError> {
+        if swaps_red_blue(self.swapchain.swapchain_image_format).is_err() {
+            log::warn!("Screenshots of {:?} swapchain images aren't supported", self.swapchain.swapchain_image_format);
+            return Ok(None);
+        }
+
+        let extent = self.swapchain.swapchain_extent;
+        let image = self.swapchain.swapchain_images[self.current_image_index];
+
+        let buffer = Buffer::<u8>::new(
+            self.device.clone(),
+            4 * extent.width as usize * extent.height as usize,
+            ash::vk::BufferUsageFlags::TRANSFER_DST,
+            ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        let subresource_range = ash::vk::ImageSubresourceRange {
+            aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let layout_barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            ash::vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)
+                .build()
+        };
+
+        let logical_device = &self.device.logical_device;
+
+        unsafe {
+            // The last pass left the image ready for presentation
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[layout_barrier(
+                    ash::vk::ImageLayout::PRESENT_SRC_KHR,
+                    ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    ash::vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+
+            logical_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer.buffer,
+                &[ash::vk::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: ash::vk::ImageSubresourceLayers {
+                        aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: ash::vk::Offset3D { x: 0, y: 0, z: 0 },
+                    image_extent: ash::vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+                }],
+            );
+
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::PipelineStageFlags::HOST | ash::vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[ash::vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(ash::vk::AccessFlags::HOST_READ)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(buffer.buffer)
+                    .offset(0)
+                    .size(ash::vk::WHOLE_SIZE)
+                    .build()],
+                &[layout_barrier(
+                    ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    ash::vk::ImageLayout::PRESENT_SRC_KHR,
+                    ash::vk::AccessFlags::TRANSFER_READ,
+                    ash::vk::AccessFlags::empty(),
+                )],
+            );
+        }
+
+        Ok(Some(buffer))
+    }
+
+    fn save_screenshot(&self, path: &std::path::Path, buffer: &mut Buffer<u8>) -> anyhow::Result<(), GentooRenderError> {
+        let extent = self.swapchain.swapchain_extent;
+
+        // Swapchain formats are BGRA more often than not
+        let rgba = format_to_rgba8(buffer.map(0)?.as_slice(), self.swapchain.swapchain_image_format)?;
+
+        image::save_buffer(path, &rgba, extent.width, extent.height, image::ColorType::Rgba8)?;
+
+        Ok(())
+    }
+
+    /// `contents` must be `SECONDARY_COMMAND_BUFFERS` if the pass is recorded
+    /// with secondary command buffers, which then can't be mixed with inline commands.
+    pub fn begin_swapchain_render_pass(&self, command_buffer: ash::vk::CommandBuffer, contents: ash::vk::SubpassContents) {
+        debug_assert!(
             self.is_frame_started,
             "Can't call begin_swpachain_render_pass while frame is not in progress"
         );
 
-        assert_eq!(
+        debug_assert_eq!(
             command_buffer,
             self.get_current_command_buffer(),
             "Can't begin render pass on a command buffer from a different frame"
         );
 
+        let (render_pass, framebuffer, extent) = self.scene_pass();
+
         let render_area = ash::vk::Rect2D {
             offset: ash::vk::Offset2D { x: 0, y: 0 },
-            extent: self.swapchain.swapchain_extent,
+            extent,
         };
 
         let color_clear = ash::vk::ClearValue {
             color: ash::vk::ClearColorValue {
-                float32: [0.01, 0.01, 0.01, 1.0],
+                float32: CLEAR_COLOR,
             },
         };
 
         let depth_clear = ash::vk::ClearValue {
-            depth_stencil: ash::vk::ClearDepthStencilValue {
-                depth: 1.0,
-                stencil: 0,
-            },
+            depth_stencil: self.depth_stencil_clear,
         };
 
         let clear_values = [color_clear, depth_clear];
 
         let render_pass_info = ash::vk::RenderPassBeginInfo::builder()
-            .render_pass(self.swapchain.render_pass)
-            .framebuffer(self.swapchain.swapchain_framebuffers[self.current_image_index])
+            .render_pass(render_pass)
+            .framebuffer(framebuffer)
             .render_area(render_area)
             .clear_values(&clear_values);
 
@@ -180,47 +677,241 @@ impl Renderer {
             self.device.logical_device.cmd_begin_render_pass(
                 command_buffer,
                 &render_pass_info,
-                ash::vk::SubpassContents::INLINE,
+                contents,
             );
 
-            let viewports = [ash::vk::Viewport {
-                x: 0.0,
-                y: 0.0,
-                width: self.swapchain.width() as f32,
-                height: self.swapchain.height() as f32,
-                min_depth: 0.0,
-                max_depth: 1.0,
-            }];
-
-            let scissors = [ash::vk::Rect2D {
-                offset: ash::vk::Offset2D { x: 0, y: 0 },
-                extent: self.swapchain.swapchain_extent,
-            }];
-
-            self.device
-                .logical_device
-                .cmd_set_viewport(command_buffer, 0, &viewports);
-            self.device
-                .logical_device
-                .cmd_set_scissor(command_buffer, 0, &scissors);
+            if contents == ash::vk::SubpassContents::INLINE {
+                Self::set_viewport_and_scissor(&self.device.logical_device, command_buffer, extent);
+            }
         }
     }
 
-    pub fn end_swapchain_render_pass(&self, command_buffer: ash::vk::CommandBuffer) {
-        assert!(
+    pub fn secondary_recording_info(&self) -> SecondaryRecordingInfo {
+        debug_assert!(
             self.is_frame_started,
-            "Can't call end_swpachain_render_pass while frame is not in progress"
+            "Can't get secondary recording info while frame is not in progress"
         );
 
-        assert_eq!(
-            command_buffer,
-            self.get_current_command_buffer(),
-            "Can't end render pass on a command buffer from a different frame"
-        );
+        let (render_pass, framebuffer, extent) = self.scene_pass();
+
+        SecondaryRecordingInfo {
+            render_pass,
+            framebuffer,
+            extent,
+        }
+    }
+
+    /// Begins this frame's main thread secondary command buffer, for systems
+    /// that record inline while the swapchain pass expects secondary buffers.
+    pub fn begin_secondary_command_buffer(&self) -> anyhow::Result<ash::vk::CommandBuffer, GentooRenderError> {
+        let command_buffer = self.secondary_command_buffers[self.current_frame_index];
+
+        unsafe {
+            self.secondary_recording_info().begin(&self.device.logical_device, command_buffer)?;
+        }
+
+        Ok(command_buffer)
+    }
+
+    /// Ends a secondary command buffer from `begin_secondary_command_buffer`
+    /// and executes it in the current frame's primary command buffer.
+    pub fn end_secondary_command_buffer(&self, command_buffer: ash::vk::CommandBuffer) -> anyhow::Result<(), GentooRenderError> {
+        unsafe {
+            self.device.logical_device.end_command_buffer(command_buffer)?;
+            self.device.logical_device.cmd_execute_commands(self.get_current_command_buffer(), &[command_buffer]);
+        }
+
+        Ok(())
+    }
+
+    pub unsafe fn set_viewport_and_scissor(
+        logical_device: &ash::Device,
+        command_buffer: ash::vk::CommandBuffer,
+        extent: ash::vk::Extent2D,
+    ) {
+        Self::set_viewport(logical_device, command_buffer, extent, FULL_DEPTH_RANGE);
+
+        let scissors = [ash::vk::Rect2D {
+            offset: ash::vk::Offset2D { x: 0, y: 0 },
+            extent,
+        }];
+
+        logical_device.cmd_set_scissor(command_buffer, 0, &scissors);
+    }
+
+    /// Covers `extent` and maps depth into `depth_range`, the scissor is left unchanged.
+    pub unsafe fn set_viewport(
+        logical_device: &ash::Device,
+        command_buffer: ash::vk::CommandBuffer,
+        extent: ash::vk::Extent2D,
+        depth_range: DepthRange,
+    ) {
+        let viewports = [ash::vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: depth_range.min,
+            max_depth: depth_range.max,
+        }];
+
+        logical_device.cmd_set_viewport(command_buffer, 0, &viewports);
+    }
+
+    /// Ends the scene pass, upscaling the scene onto the swapchain image if it
+    /// was rendered at another resolution. When the scene is resolved the
+    /// caller blits the result instead.
+    pub fn end_swapchain_render_pass(&self, command_buffer: ash::vk::CommandBuffer) -> anyhow::Result<(), GentooRenderError> {
+        if !self.is_frame_started {
+            return Err(GentooRenderError::InvalidUsage("Can't call end_swpachain_render_pass while frame is not in progress"));
+        }
+
+        if command_buffer != self.get_current_command_buffer() {
+            return Err(GentooRenderError::InvalidUsage("Can't end render pass on a command buffer from a different frame"));
+        }
 
         unsafe {
             self.device.logical_device.cmd_end_render_pass(command_buffer);
         }
+
+        match &self.scene_target {
+            Some(target) if !self.resolve_scene => self.blit_to_swapchain(command_buffer, BlitTarget {
+                image: target.color_image,
+                format: target.color_format,
+                layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                final_layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                mip_level: 0,
+                region: Self::blit_region(target.extent),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Scales `src` onto the whole swapchain image, which ends up in the
+    /// layout the swapchain render pass would have left it in. The linear
+    /// filter averages 2x2 pixels when downsampling by exactly 2. At a fixed
+    /// resolution it is letterboxed instead, see `scene_region`.
+    pub fn blit_to_swapchain(&self, command_buffer: ash::vk::CommandBuffer, src: BlitTarget) -> anyhow::Result<(), GentooRenderError> {
+        let image = self.swapchain.swapchain_images[self.current_image_index];
+
+        let (layout, region, filter) = if self.is_letterboxed() {
+            self.clear_letterbox(command_buffer, image);
+
+            (
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                Self::letterbox_region(src.region, self.swapchain.swapchain_extent),
+                ash::vk::Filter::NEAREST,
+            )
+        } else {
+            (
+                ash::vk::ImageLayout::UNDEFINED,
+                Self::blit_region(self.swapchain.swapchain_extent),
+                ash::vk::Filter::LINEAR,
+            )
+        };
+
+        self.device.cmd_blit_image(
+            command_buffer,
+            src,
+            BlitTarget {
+                image,
+                format: self.swapchain.swapchain_image_format,
+                layout,
+                final_layout: self.final_layout,
+                mip_level: 0,
+                region,
+            },
+            filter,
+        )
+    }
+
+    /// Part of the swapchain image the scene ends up in, e.g. to map the
+    /// cursor into the scene
+    pub fn scene_region(&self) -> [ash::vk::Offset3D; 2] {
+        match &self.scene_target {
+            Some(target) if self.is_letterboxed() => {
+                Self::letterbox_region(Self::blit_region(target.extent), self.swapchain.swapchain_extent)
+            }
+            _ => Self::blit_region(self.swapchain.swapchain_extent),
+        }
+    }
+
+    fn is_letterboxed(&self) -> bool {
+        self.fixed_resolution.is_some() && self.scene_target.is_some()
+    }
+
+    /// Largest integer multiple of `src` centered in `dst`. A `dst` smaller
+    /// than `src` gets the largest fit with the same aspect ratio instead.
+    fn letterbox_region(src: [ash::vk::Offset3D; 2], dst: ash::vk::Extent2D) -> [ash::vk::Offset3D; 2] {
+        let src_width = (src[1].x - src[0].x).max(1) as u32;
+        let src_height = (src[1].y - src[0].y).max(1) as u32;
+
+        let scale = (dst.width / src_width).min(dst.height / src_height);
+
+        let (width, height) = if scale > 0 {
+            (src_width * scale, src_height * scale)
+        } else {
+            let fit = (dst.width as f32 / src_width as f32).min(dst.height as f32 / src_height as f32);
+
+            (((src_width as f32 * fit) as u32).max(1), ((src_height as f32 * fit) as u32).max(1))
+        };
+
+        let x = ((dst.width - width) / 2) as i32;
+        let y = ((dst.height - height) / 2) as i32;
+
+        [
+            ash::vk::Offset3D { x, y, z: 0 },
+            ash::vk::Offset3D { x: x + width as i32, y: y + height as i32, z: 1 },
+        ]
+    }
+
+    /// Clears the whole swapchain image to the letterbox color, leaving it in
+    /// `TRANSFER_DST_OPTIMAL` for the blit of the scene
+    fn clear_letterbox(&self, command_buffer: ash::vk::CommandBuffer, image: ash::vk::Image) {
+        let subresource_range = ash::vk::ImageSubresourceRange {
+            aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        unsafe {
+            self.device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::ALL_COMMANDS,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .image(image)
+                    .old_layout(ash::vk::ImageLayout::UNDEFINED)
+                    .new_layout(ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .src_access_mask(ash::vk::AccessFlags::MEMORY_READ | ash::vk::AccessFlags::MEMORY_WRITE)
+                    .dst_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(subresource_range)
+                    .build()],
+            );
+
+            self.device.logical_device.cmd_clear_color_image(
+                command_buffer,
+                image,
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &ash::vk::ClearColorValue { float32: self.letterbox_color },
+                &[subresource_range],
+            );
+        }
+    }
+
+    /// Corners of a whole image, for `BlitTarget::region`
+    pub fn blit_region(extent: ash::vk::Extent2D) -> [ash::vk::Offset3D; 2] {
+        [
+            ash::vk::Offset3D { x: 0, y: 0, z: 0 },
+            ash::vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: 1 },
+        ]
     }
 
     pub fn recreate_swapchain(&mut self, window: &Window) -> anyhow::Result<(), GentooRenderError> {
@@ -239,15 +930,42 @@ impl Renderer {
         };
 
         let new_swapchain =
-            Swapchain::new(self.device.clone(), extent, self.swapchain.swapchain_khr.take())?;
+            Swapchain::new(self.device.clone(), extent, self.swapchain.swapchain_khr.take(), self.final_layout, self.image_usage)?;
+
+        // E.g. after moving the window to a monitor with other supported
+        // formats, the caller rebuilds what depends on them
+        if self.swapchain.compare_swap_formats(&new_swapchain).is_err() {
+            log::info!(
+                "Swapchain formats changed from {:?} and {:?} to {:?} and {:?}",
+                self.swapchain.swapchain_image_format,
+                self.swapchain.swapchain_depth_format,
+                new_swapchain.swapchain_image_format,
+                new_swapchain.swapchain_depth_format,
+            );
 
-        self.swapchain.compare_swap_formats(&new_swapchain)?;
+            self.format_changed = true;
+        }
 
         self.swapchain = new_swapchain;
+        self.swapchain_outdated = false;
+        self.recreate_requested = false;
+
+        // The new swapchain's sync objects start at frame 0, the device is
+        // idle so every frame's resources are free
+        self.current_frame_index = 0;
+
+        // The scaled target follows the swapchain size
+        self.scene_target = self.create_scene_target()?;
 
         Ok(())
     }
 
+    /// Whether the swapchain formats changed since the last call. Pipelines
+    /// created against the swapchain render pass have to be recreated then.
+    pub fn take_format_change(&mut self) -> bool {
+        std::mem::take(&mut self.format_changed)
+    }
+
     pub fn get_window_extent(window: &Window) -> ash::vk::Extent2D {
         let window_inner_size = window.raw_window.inner_size();
         ash::vk::Extent2D {
@@ -259,9 +977,10 @@ impl Renderer {
     fn create_command_buffers(
         device: &ash::Device,
         command_pool: ash::vk::CommandPool,
+        level: ash::vk::CommandBufferLevel,
     ) -> anyhow::Result<Vec<ash::vk::CommandBuffer>, GentooRenderError> {
         let alloc_info = ash::vk::CommandBufferAllocateInfo::builder()
-            .level(ash::vk::CommandBufferLevel::PRIMARY)
+            .level(level)
             .command_pool(command_pool)
             .command_buffer_count(MAX_FRAMES_IN_FLIGHT as u32);
 
@@ -280,6 +999,9 @@ impl Drop for Renderer {
         unsafe {
             self.device.logical_device.free_command_buffers(self.device.command_pool, &self.command_buffers);
             self.command_buffers.clear();
+
+            self.device.logical_device.free_command_buffers(self.device.command_pool, &self.secondary_command_buffers);
+            self.secondary_command_buffers.clear();
         }
     }
 }
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+/// Counts live Vulkan objects in debug builds to catch leaks.
+pub const ENABLE_RESOURCE_TRACKING: bool = cfg!(debug_assertions);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Buffer,
+    Image,
+    ImageView,
+    Pipeline,
+    DescriptorSet,
+}
+
+impl ResourceKind {
+    const ALL: [ResourceKind; 5] = [
+        ResourceKind::Buffer,
+        ResourceKind::Image,
+        ResourceKind::ImageView,
+        ResourceKind::Pipeline,
+        ResourceKind::DescriptorSet,
+    ];
+}
+
+/// Created minus destroyed objects per kind, all zero once everything is
+/// cleaned up. Does nothing without `ENABLE_RESOURCE_TRACKING`.
+#[derive(Default)]
+pub struct ResourceTracker {
+    live: [AtomicIsize; 5],
+}
+
+impl ResourceTracker {
+    pub fn created(&self, kind: ResourceKind, count: usize) {
+        if ENABLE_RESOURCE_TRACKING {
+            self.live[kind as usize].fetch_add(count as isize, Ordering::Relaxed);
+        }
+    }
+
+    pub fn destroyed(&self, kind: ResourceKind, count: usize) {
+        if ENABLE_RESOURCE_TRACKING {
+            self.live[kind as usize].fetch_sub(count as isize, Ordering::Relaxed);
+        }
+    }
+
+    pub fn live(&self, kind: ResourceKind) -> isize {
+        self.live[kind as usize].load(Ordering::Relaxed)
+    }
+
+    /// Logs every kind with objects left and asserts there are none.
+    pub fn check_balanced(&self) {
+        if !ENABLE_RESOURCE_TRACKING {
+            return;
+        }
+
+        let mut balanced = true;
+
+        for kind in ResourceKind::ALL {
+            let live = self.live(kind);
+
+            if live != 0 {
+                log::error!("{} {:?} objects were not destroyed", live, kind);
+                balanced = false;
+            }
+        }
+
+        // A second panic while unwinding would abort and hide the first one
+        if !std::thread::panicking() {
+            assert!(balanced, "Vulkan objects were leaked, see the log for the kinds");
+        }
+    }
+}
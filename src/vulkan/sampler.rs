@@ -0,0 +1,138 @@
+use std::rc::Rc;
+
+use super::{Device, GentooRenderError};
+
+pub struct Sampler {
+    device: Rc<Device>,
+    pub sampler: ash::vk::Sampler,
+}
+
+/// Defaults to linear filtering and mipmapping, repeating addresses, no
+/// anisotropy and the full mip chain.
+pub struct SamplerBuilder {
+    device: Rc<Device>,
+    filter: ash::vk::Filter,
+    mipmap_mode: ash::vk::SamplerMipmapMode,
+    address_mode: ash::vk::SamplerAddressMode,
+    max_anisotropy: Option<f32>,
+    min_lod: f32,
+    max_lod: f32,
+    mip_lod_bias: f32,
+}
+
+impl Sampler {
+    pub fn new(device: Rc<Device>) -> SamplerBuilder {
+        SamplerBuilder {
+            device,
+            filter: ash::vk::Filter::LINEAR,
+            mipmap_mode: ash::vk::SamplerMipmapMode::LINEAR,
+            address_mode: ash::vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+            min_lod: 0.0,
+            max_lod: ash::vk::LOD_CLAMP_NONE,
+            mip_lod_bias: 0.0,
+        }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        log::debug!("Dropping sampler");
+
+        unsafe {
+            self.device.logical_device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+impl SamplerBuilder {
+    /// Sets both the magnification and minification filter.
+    pub fn filter(mut self, filter: ash::vk::Filter) -> Self {
+        self.filter = filter;
+
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode: ash::vk::SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mipmap_mode;
+
+        self
+    }
+
+    /// Sets the u, v and w address modes.
+    pub fn address_mode(mut self, address_mode: ash::vk::SamplerAddressMode) -> Self {
+        self.address_mode = address_mode;
+
+        self
+    }
+
+    /// Clamped to the device limit, values of 1 or less disable anisotropic filtering.
+    pub fn max_anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = if max_anisotropy > 1.0 { Some(max_anisotropy) } else { None };
+
+        self
+    }
+
+    pub fn lod_range(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+
+        self
+    }
+
+    /// Added to the computed mip level, positive values blur and negative
+    /// ones sharpen. Clamped to the device limit.
+    pub fn mip_lod_bias(mut self, mip_lod_bias: f32) -> Self {
+        self.mip_lod_bias = mip_lod_bias;
+
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<Rc<Sampler>, GentooRenderError> {
+        let SamplerBuilder {
+            device,
+            filter,
+            mipmap_mode,
+            address_mode,
+            max_anisotropy,
+            min_lod,
+            max_lod,
+            mip_lod_bias,
+        } = self;
+
+        let limits = &device.properties.limits;
+
+        let max_anisotropy = match max_anisotropy {
+            Some(_) if device.enabled_features.sampler_anisotropy == 0 => {
+                log::warn!("Anisotropic filtering is not supported by the device, sampling without it");
+                None
+            },
+            Some(max_anisotropy) => Some(max_anisotropy.min(limits.max_sampler_anisotropy)),
+            None => None,
+        };
+
+        let max_lod_bias = limits.max_sampler_lod_bias;
+
+        let sampler_info = ash::vk::SamplerCreateInfo::builder()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(mipmap_mode)
+            .address_mode_u(address_mode)
+            .address_mode_v(address_mode)
+            .address_mode_w(address_mode)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .min_lod(min_lod)
+            .max_lod(max_lod)
+            .mip_lod_bias(mip_lod_bias.clamp(-max_lod_bias, max_lod_bias));
+
+        let sampler = unsafe {
+            device.logical_device.create_sampler(&sampler_info, None)?
+        };
+
+        Ok(Rc::new(Sampler {
+            device,
+            sampler,
+        }))
+    }
+}
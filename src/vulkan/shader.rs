@@ -2,41 +2,157 @@ use std::rc::Rc;
 
 use super::{Device, GentooRenderError};
 
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const SPIRV_HEADER_WORDS: usize = 5;
+const OP_DECORATE: u32 = 71;
+const OP_VARIABLE: u32 = 59;
+const DECORATION_LOCATION: u32 = 30;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const EMBEDDED_SHADER_NAME: &str = "<embedded>";
+
+/// Where a pipeline stage's SPIR-V comes from.
+#[derive(Debug, Clone, Copy)]
+pub enum ShaderSource<'a> {
+    Path(&'a str),
+    Words(&'a [u32]),
+    Bytes(&'a [u8]),
+}
+
+impl std::fmt::Display for ShaderSource<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderSource::Path(path) => write!(f, "{}", path),
+            _ => write!(f, "{}", EMBEDDED_SHADER_NAME),
+        }
+    }
+}
+
 pub struct ShaderModule {
     device: Rc<Device>,
     pub module: ash::vk::ShaderModule,
+    /// Locations of the stage inputs, the vertex attributes of a vertex shader
+    pub input_locations: Vec<u32>,
 }
 
 impl ShaderModule {
     pub fn new<P: AsRef<std::path::Path>>(device: Rc<Device>, file_path: P) -> anyhow::Result<Rc<Self>, GentooRenderError> {
-        let code = Self::read_file(file_path);
+        let code = Self::read_file(&file_path)?;
+
+        Self::create(device, &code, &file_path.as_ref().display().to_string())
+    }
+
+    /// Creates a module from SPIR-V words, e.g. a shader embedded in the binary.
+    pub fn from_words(device: Rc<Device>, words: &[u32]) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        if words.first() != Some(&SPIRV_MAGIC) {
+            return Err(Self::invalid_spirv("missing SPIR-V magic number"));
+        }
+
+        Self::create(device, words, EMBEDDED_SHADER_NAME)
+    }
+
+    /// Creates a module from little endian SPIR-V bytes, e.g. from `include_bytes!`.
+    pub fn from_bytes(device: Rc<Device>, bytes: &[u8]) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        if !bytes.len().is_multiple_of(4) {
+            return Err(Self::invalid_spirv("length is not a multiple of 4 bytes"));
+        }
+
+        // include_bytes! gives no alignment guarantee, copy into properly aligned words
+        let words = bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect::<Vec<_>>();
+
+        Self::from_words(device, &words)
+    }
 
+    pub fn from_source(device: Rc<Device>, source: ShaderSource) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        match source {
+            ShaderSource::Path(path) => Self::new(device, path),
+            ShaderSource::Words(words) => Self::from_words(device, words),
+            ShaderSource::Bytes(bytes) => Self::from_bytes(device, bytes),
+        }
+    }
+
+    fn create(device: Rc<Device>, code: &[u32], name: &str) -> anyhow::Result<Rc<Self>, GentooRenderError> {
         let create_info = ash::vk::ShaderModuleCreateInfo::builder()
-            .code(&code);
+            .code(code);
 
         let module = unsafe {
-            device.logical_device.create_shader_module(&create_info, None)?
+            device.logical_device
+                .create_shader_module(&create_info, None)
+                .map_err(|result| GentooRenderError::ShaderModuleError {
+                    path: name.to_string(),
+                    result,
+                })?
         };
 
         Ok(Rc::new(Self {
             device,
             module,
+            input_locations: Self::reflect_input_locations(code),
         }))
     }
 
-    fn read_file<P: AsRef<std::path::Path>>(file_path: P) -> Vec<u32> {
-        log::debug!(
-            "Loading shader file: {}",
-            file_path.as_ref().to_str().unwrap()
-        );
+    /// Just enough SPIR-V parsing to find the `layout (location = n) in` variables.
+    fn reflect_input_locations(code: &[u32]) -> Vec<u32> {
+        let mut locations = std::collections::HashMap::new();
+        let mut inputs = Vec::new();
+
+        let mut offset = SPIRV_HEADER_WORDS;
+        while offset < code.len() {
+            let word_count = (code[offset] >> 16) as usize;
+            let opcode = code[offset] & 0xffff;
+
+            if word_count == 0 || offset + word_count > code.len() {
+                break;
+            }
+
+            let operands = &code[offset + 1..offset + word_count];
+
+            match opcode {
+                OP_DECORATE if operands.len() >= 3 && operands[1] == DECORATION_LOCATION => {
+                    locations.insert(operands[0], operands[2]);
+                }
+                OP_VARIABLE if operands.len() >= 3 && operands[2] == STORAGE_CLASS_INPUT => {
+                    inputs.push(operands[1]);
+                }
+                _ => { }
+            }
+
+            offset += word_count;
+        }
+
+        // Built in inputs like gl_VertexIndex have no location
+        let mut input_locations = inputs
+            .iter()
+            .filter_map(|id| locations.get(id).copied())
+            .collect::<Vec<_>>();
+
+        input_locations.sort_unstable();
+        input_locations
+    }
+
+    fn invalid_spirv(reason: &'static str) -> GentooRenderError {
+        log::error!("Unable to load embedded shader: {}", reason);
+
+        GentooRenderError::InvalidSpirvError { reason }
+    }
+
+    fn read_file<P: AsRef<std::path::Path>>(file_path: P) -> anyhow::Result<Vec<u32>, GentooRenderError> {
+        let path = file_path.as_ref();
+
+        log::debug!("Loading shader file: {}", path.display());
+
+        std::fs::File::open(path)
+            .and_then(|mut file| ash::util::read_spv(&mut file))
+            .map_err(|source| {
+                log::error!("Unable to load shader {}: {}", path.display(), source);
 
-        let mut file = std::fs::File::open(file_path)
-            .map_err(|e| log::error!("Unable to open file: {}", e))
-            .unwrap();
-        
-        ash::util::read_spv(&mut file)
-            .map_err(|e| log::error!("Unable to read file: {}", e))
-            .unwrap()
+                GentooRenderError::ShaderReadError {
+                    path: path.display().to_string(),
+                    source,
+                }
+            })
     }
 }
 
@@ -0,0 +1,509 @@
+use std::rc::Rc;
+
+use memoffset::offset_of;
+
+use super::{Device, GentooRenderError, Model, VertexLayout, VertexSemantic};
+
+/// Joints a single skeleton can have, the size of a joint matrix slot.
+pub const MAX_JOINTS: usize = 128;
+
+#[derive(PartialEq)]
+#[repr(C)]
+pub struct SkinnedVertex {
+    pub position: glam::Vec3,
+    pub color: glam::Vec3,
+    pub normal: glam::Vec3,
+    pub uv: glam::Vec2,
+    pub joint_indices: glam::UVec4,
+    pub joint_weights: glam::Vec4,
+}
+
+impl SkinnedVertex {
+    /// `Vertex::layout()` followed by the joints, so the first locations match.
+    pub fn layout() -> VertexLayout {
+        VertexLayout::new(std::mem::size_of::<SkinnedVertex>() as u32)
+            .add_attribute(VertexSemantic::Position, ash::vk::Format::R32G32B32_SFLOAT, offset_of!(SkinnedVertex, position) as u32)
+            .add_attribute(VertexSemantic::Color, ash::vk::Format::R32G32B32_SFLOAT, offset_of!(SkinnedVertex, color) as u32)
+            .add_attribute(VertexSemantic::Normal, ash::vk::Format::R32G32B32_SFLOAT, offset_of!(SkinnedVertex, normal) as u32)
+            .add_attribute(VertexSemantic::Uv, ash::vk::Format::R32G32_SFLOAT, offset_of!(SkinnedVertex, uv) as u32)
+            .add_attribute(VertexSemantic::JointIndices, ash::vk::Format::R32G32B32A32_UINT, offset_of!(SkinnedVertex, joint_indices) as u32)
+            .add_attribute(VertexSemantic::JointWeights, ash::vk::Format::R32G32B32A32_SFLOAT, offset_of!(SkinnedVertex, joint_weights) as u32)
+    }
+
+    fn as_bytes(vertices: &[SkinnedVertex]) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(vertices.as_ptr() as *const u8, std::mem::size_of_val(vertices))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointPose {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl JointPose {
+    fn mat4(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    /// Index in `Skeleton::joints`
+    pub parent: Option<usize>,
+    /// Transform of the non joint nodes between this joint and its parent
+    /// joint, or above it for a root joint
+    pub parent_transform: glam::Mat4,
+    pub inverse_bind_matrix: glam::Mat4,
+    pub rest_pose: JointPose,
+}
+
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    /// In the order of the skin's joints, which the vertex joint indices refer to
+    pub joints: Vec<Joint>,
+    /// Indices into `joints` with parents before their children
+    pub evaluation_order: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelValues {
+    Translation(Vec<glam::Vec3>),
+    Rotation(Vec<glam::Quat>),
+    Scale(Vec<glam::Vec3>),
+}
+
+impl ChannelValues {
+    fn len(&self) -> usize {
+        match self {
+            ChannelValues::Translation(values) | ChannelValues::Scale(values) => values.len(),
+            ChannelValues::Rotation(values) => values.len(),
+        }
+    }
+}
+
+/// How a channel's values change between keyframes, see the glTF sampler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Holds each value until the next keyframe
+    Step,
+    Linear,
+    /// Hermite spline, every keyframe has an in tangent, the value and an
+    /// out tangent in `values`
+    CubicSpline,
+}
+
+/// Keyframes of one joint property.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl Channel {
+    /// The keyframes around `time` and how far between them it is.
+    fn keyframes(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|t| *t <= time);
+
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+
+        if next >= self.times.len() {
+            let last = self.times.len() - 1;
+            return (last, last, 0.0);
+        }
+
+        let previous = next - 1;
+        let span = self.times[next] - self.times[previous];
+        let t = if span > 0.0 { (time - self.times[previous]) / span } else { 0.0 };
+
+        (previous, next, t)
+    }
+
+    /// None if `values` has too few elements for the keyframes.
+    fn sample<T: Copy>(
+        &self,
+        values: &[T],
+        time: f32,
+        lerp: impl Fn(T, T, f32) -> T,
+        to_vec4: impl Fn(T) -> glam::Vec4,
+        from_vec4: impl Fn(glam::Vec4) -> T,
+    ) -> Option<T> {
+        if self.times.is_empty() {
+            return None;
+        }
+
+        let (a, b, t) = self.keyframes(time);
+
+        match self.interpolation {
+            Interpolation::Step => values.get(a).copied(),
+            Interpolation::Linear => Some(lerp(*values.get(a)?, *values.get(b)?, t)),
+            Interpolation::CubicSpline => {
+                let span = self.times[b] - self.times[a];
+                let start = to_vec4(*values.get(3 * a + 1)?);
+                let start_out_tangent = to_vec4(*values.get(3 * a + 2)?);
+                let end_in_tangent = to_vec4(*values.get(3 * b)?);
+                let end = to_vec4(*values.get(3 * b + 1)?);
+
+                let t2 = t * t;
+                let t3 = t2 * t;
+
+                Some(from_vec4(
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * start
+                        + (t3 - 2.0 * t2 + t) * span * start_out_tangent
+                        + (-2.0 * t3 + 3.0 * t2) * end
+                        + (t3 - t2) * span * end_in_tangent,
+                ))
+            }
+        }
+    }
+
+    fn apply(&self, time: f32, pose: &mut JointPose) {
+        match &self.values {
+            ChannelValues::Translation(values) => {
+                if let Some(translation) = self.sample(values, time, glam::Vec3::lerp, |v| v.extend(0.0), glam::Vec4::truncate) {
+                    pose.translation = translation;
+                }
+            }
+            ChannelValues::Rotation(values) => {
+                if let Some(rotation) = self.sample(values, time, glam::Quat::slerp, glam::Vec4::from, |v| glam::Quat::from_vec4(v).normalize()) {
+                    pose.rotation = rotation;
+                }
+            }
+            ChannelValues::Scale(values) => {
+                if let Some(scale) = self.sample(values, time, glam::Vec3::lerp, |v| v.extend(0.0), glam::Vec4::truncate) {
+                    pose.scale = scale;
+                }
+            }
+        }
+    }
+}
+
+impl Skeleton {
+    /// Skinning matrices of every joint with `clip` sampled at `time`, the
+    /// rest pose without a clip.
+    pub fn joint_matrices(&self, clip: Option<&AnimationClip>, time: f32) -> Vec<glam::Mat4> {
+        let mut poses = self.joints.iter().map(|joint| joint.rest_pose).collect::<Vec<_>>();
+
+        if let Some(clip) = clip {
+            for channel in &clip.channels {
+                if let Some(pose) = poses.get_mut(channel.joint) {
+                    channel.apply(time, pose);
+                }
+            }
+        }
+
+        let mut global_transforms = vec![glam::Mat4::IDENTITY; self.joints.len()];
+
+        for &index in &self.evaluation_order {
+            let joint = &self.joints[index];
+
+            let parent_transform = match joint.parent {
+                Some(parent) => global_transforms[parent] * joint.parent_transform,
+                None => joint.parent_transform,
+            };
+
+            global_transforms[index] = parent_transform * poses[index].mat4();
+        }
+
+        global_transforms
+            .iter()
+            .zip(&self.joints)
+            .map(|(global_transform, joint)| *global_transform * joint.inverse_bind_matrix)
+            .collect()
+    }
+}
+
+/// A skinned mesh loaded from glTF, with its skeleton and animations.
+pub struct SkinnedModel {
+    pub model: Rc<Model>,
+    pub skeleton: Rc<Skeleton>,
+    pub clips: Vec<Rc<AnimationClip>>,
+}
+
+impl SkinnedModel {
+    /// Loads the first skinned primitive of a glTF file and every animation
+    /// targeting its joints.
+    pub fn from_gltf<P: AsRef<std::path::Path>>(device: Rc<Device>, file_path: P) -> anyhow::Result<Self, GentooRenderError> {
+        log::debug!("Loading glTF file: {}", file_path.as_ref().display());
+
+        let (document, buffers, _) = gltf::import(file_path)?;
+
+        let node = document
+            .nodes()
+            .find(|node| node.mesh().is_some() && node.skin().is_some())
+            .ok_or(GentooRenderError::GltfContentError("no skinned mesh"))?;
+
+        let primitive = node.mesh().unwrap()
+            .primitives()
+            .next()
+            .ok_or(GentooRenderError::GltfContentError("mesh has no primitives"))?;
+
+        let vertices = Self::read_vertices(&primitive, &buffers)?;
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let indices = reader.read_indices().map(|indices| indices.into_u32().collect::<Vec<_>>());
+
+        let model = Model::with_layout(device, SkinnedVertex::as_bytes(&vertices), SkinnedVertex::layout(), indices.as_ref())?;
+
+        let skin = node.skin().unwrap();
+        let (skeleton, node_to_joint) = Self::load_skeleton(&document, &skin, &buffers)?;
+
+        let clips = document
+            .animations()
+            .map(|animation| Self::load_clip(&animation, &node_to_joint, &buffers).map(Rc::new))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            model,
+            skeleton: Rc::new(skeleton),
+            clips,
+        })
+    }
+
+    /// Every attribute besides the positions is optional, but has to have a
+    /// value for each position when present.
+    fn read_vertices(
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+    ) -> anyhow::Result<Vec<SkinnedVertex>, GentooRenderError> {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let positions = reader
+            .read_positions()
+            .ok_or(GentooRenderError::GltfContentError("mesh has no positions"))?
+            .collect::<Vec<_>>();
+
+        let count = positions.len();
+        let check_count = |len: Option<usize>, error| match len {
+            Some(len) if len != count => Err(GentooRenderError::GltfContentError(error)),
+            _ => Ok(()),
+        };
+
+        let normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>());
+        // Normalized u8, u16 or float, with or without alpha, which is dropped
+        let colors = reader.read_colors(0).map(|colors| colors.into_rgb_f32().collect::<Vec<_>>());
+        let uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect::<Vec<_>>());
+        let joints = reader.read_joints(0).map(|joints| joints.into_u16().collect::<Vec<_>>());
+        let weights = reader.read_weights(0).map(|weights| weights.into_f32().collect::<Vec<_>>());
+
+        check_count(normals.as_ref().map(Vec::len), "normal count doesn't match the positions")?;
+        check_count(colors.as_ref().map(Vec::len), "color count doesn't match the positions")?;
+        check_count(uvs.as_ref().map(Vec::len), "texture coordinate count doesn't match the positions")?;
+        check_count(joints.as_ref().map(Vec::len), "joint count doesn't match the positions")?;
+        check_count(weights.as_ref().map(Vec::len), "weight count doesn't match the positions")?;
+
+        Ok(positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| SkinnedVertex {
+                position: glam::Vec3::from(*position),
+                color: colors.as_ref().map_or(glam::Vec3::ONE, |colors| glam::Vec3::from(colors[i])),
+                normal: normals.as_ref().map_or(glam::Vec3::ZERO, |normals| glam::Vec3::from(normals[i])),
+                uv: uvs.as_ref().map_or(glam::Vec2::ZERO, |uvs| glam::Vec2::from(uvs[i])),
+                joint_indices: joints.as_ref().map_or(glam::UVec4::ZERO, |joints| {
+                    let [a, b, c, d] = joints[i];
+                    glam::uvec4(a as u32, b as u32, c as u32, d as u32)
+                }),
+                joint_weights: weights.as_ref().map_or(glam::Vec4::X, |weights| glam::Vec4::from(weights[i])),
+            })
+            .collect())
+    }
+
+    /// Returns the skeleton and the joint index of every node. The joints
+    /// keep the skin's order, the vertex joint indices refer to it.
+    fn load_skeleton(
+        document: &gltf::Document,
+        skin: &gltf::Skin,
+        buffers: &[gltf::buffer::Data],
+    ) -> anyhow::Result<(Skeleton, Vec<Option<usize>>), GentooRenderError> {
+        let node_count = document.nodes().count();
+
+        let mut parents = vec![None; node_count];
+        for node in document.nodes() {
+            for child in node.children() {
+                parents[child.index()] = Some(node.index());
+            }
+        }
+
+        let skin_joints = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+
+        if skin_joints.len() > MAX_JOINTS {
+            return Err(GentooRenderError::GltfContentError("skin has too many joints"));
+        }
+
+        let inverse_bind_matrices = skin
+            .reader(|buffer| Some(&buffers[buffer.index()]))
+            .read_inverse_bind_matrices()
+            .map(|matrices| matrices.map(|matrix| glam::Mat4::from_cols_array_2d(&matrix)).collect::<Vec<_>>())
+            .unwrap_or_else(|| vec![glam::Mat4::IDENTITY; skin_joints.len()]);
+
+        if inverse_bind_matrices.len() < skin_joints.len() {
+            return Err(GentooRenderError::GltfContentError("skin has fewer inverse bind matrices than joints"));
+        }
+
+        let nodes = document.nodes().collect::<Vec<_>>();
+        let local_transform = |node: usize| glam::Mat4::from_cols_array_2d(&nodes[node].transform().matrix());
+
+        let is_joint = |node: usize| skin_joints.contains(&node);
+        let depth = |mut node: usize| {
+            let mut depth = 0;
+            while let Some(parent) = parents[node] {
+                depth += 1;
+                node = parent;
+            }
+            depth
+        };
+
+        let mut node_to_joint = vec![None; node_count];
+        for (joint, node) in skin_joints.iter().enumerate() {
+            node_to_joint[*node] = Some(joint);
+        }
+
+        // Sorted by depth, parents are evaluated before their children
+        let mut evaluation_order = (0..skin_joints.len()).collect::<Vec<_>>();
+        evaluation_order.sort_by_key(|joint| depth(skin_joints[*joint]));
+
+        let joints = skin_joints
+            .iter()
+            .zip(&inverse_bind_matrices)
+            .map(|(node, inverse_bind_matrix)| {
+                let (translation, rotation, scale) = nodes[*node].transform().decomposed();
+
+                // The closest ancestor that is a joint, everything above it is static
+                let mut parent_transform = glam::Mat4::IDENTITY;
+                let mut parent = parents[*node];
+                while let Some(ancestor) = parent {
+                    if is_joint(ancestor) {
+                        break;
+                    }
+
+                    parent_transform = local_transform(ancestor) * parent_transform;
+                    parent = parents[ancestor];
+                }
+
+                Joint {
+                    parent: parent.and_then(|parent| node_to_joint[parent]),
+                    parent_transform,
+                    inverse_bind_matrix: *inverse_bind_matrix,
+                    rest_pose: JointPose {
+                        translation: glam::Vec3::from(translation),
+                        rotation: glam::Quat::from_array(rotation),
+                        scale: glam::Vec3::from(scale),
+                    },
+                }
+            })
+            .collect();
+
+        Ok((Skeleton { joints, evaluation_order }, node_to_joint))
+    }
+
+    fn load_clip(
+        animation: &gltf::Animation,
+        node_to_joint: &[Option<usize>],
+        buffers: &[gltf::buffer::Data],
+    ) -> anyhow::Result<AnimationClip, GentooRenderError> {
+        let mut duration: f32 = 0.0;
+        let mut channels = Vec::new();
+
+        for channel in animation.channels() {
+            let joint = match node_to_joint[channel.target().node().index()] {
+                Some(joint) => joint,
+                None => continue,
+            };
+
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let (times, outputs) = match (reader.read_inputs(), reader.read_outputs()) {
+                (Some(times), Some(outputs)) => (times.collect::<Vec<_>>(), outputs),
+                _ => continue,
+            };
+
+            let values = match outputs {
+                gltf::animation::util::ReadOutputs::Translations(values) => ChannelValues::Translation(values.map(glam::Vec3::from).collect()),
+                gltf::animation::util::ReadOutputs::Rotations(values) => ChannelValues::Rotation(values.into_f32().map(glam::Quat::from_array).collect()),
+                gltf::animation::util::ReadOutputs::Scales(values) => ChannelValues::Scale(values.map(glam::Vec3::from).collect()),
+                gltf::animation::util::ReadOutputs::MorphTargetWeights(_) => continue,
+            };
+
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+
+            let expected_values = match interpolation {
+                Interpolation::CubicSpline => 3 * times.len(),
+                _ => times.len(),
+            };
+
+            if values.len() != expected_values {
+                return Err(GentooRenderError::GltfContentError("animation sampler output count doesn't match its keyframes"));
+            }
+
+            duration = duration.max(times.last().copied().unwrap_or(0.0));
+
+            channels.push(Channel {
+                joint,
+                interpolation,
+                times,
+                values,
+            });
+        }
+
+        Ok(AnimationClip {
+            duration,
+            channels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_primitive_vertices(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> anyhow::Result<Vec<SkinnedVertex>, GentooRenderError> {
+        let primitive = document.meshes().next().unwrap().primitives().next().unwrap();
+
+        SkinnedModel::read_vertices(&primitive, buffers)
+    }
+
+    #[test]
+    fn u8_normalized_vertex_colors() {
+        let (document, buffers, _) = gltf::import("models/colored_triangle.gltf").unwrap();
+        let vertices = first_primitive_vertices(&document, &buffers).unwrap();
+
+        let colors = vertices.iter().map(|vertex| vertex.color).collect::<Vec<_>>();
+        let expected = [glam::vec3(1.0, 0.0, 0.0), glam::vec3(0.0, 1.0, 0.0), glam::vec3(0.0, 0.2, 1.0)];
+
+        assert_eq!(colors.len(), expected.len());
+        for (color, expected) in colors.iter().zip(expected) {
+            assert!(color.abs_diff_eq(expected, 1e-6), "{} != {}", color, expected);
+        }
+    }
+
+    #[test]
+    fn short_color_accessor_is_an_error() {
+        let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string("models/colored_triangle.gltf").unwrap()).unwrap();
+        json["accessors"][1]["count"] = 2.into();
+
+        let (document, buffers, _) = gltf::import_slice(serde_json::to_vec(&json).unwrap()).unwrap();
+
+        assert!(matches!(
+            first_primitive_vertices(&document, &buffers),
+            Err(GentooRenderError::GltfContentError("color count doesn't match the positions")),
+        ));
+    }
+}
@@ -1,15 +1,58 @@
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
 use super::{Device, GentooRenderError};
 
+/// Frames the CPU can record ahead of the GPU. Resources written every frame
+/// (UBOs, joint matrices, ...) need one copy per frame, indexed by the frame
+/// index, and may only be written once `begin_frame` waited for the fence of
+/// that frame's previous submission.
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
+/// Default of how long a fence wait may take before it is retried.
+pub const DEFAULT_FENCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timed out fence waits before giving up with `FenceTimeout`.
+const FENCE_WAIT_ATTEMPTS: u32 = 3;
+
+/// What a render pass does with the previous contents of its attachments.
+/// `LOAD` keeps them for effects accumulating over frames, the images then
+/// have to be in the pass' final layout when it begins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentLoadOps {
+    pub color: ash::vk::AttachmentLoadOp,
+    pub depth: ash::vk::AttachmentLoadOp,
+    /// Keeps the depth after the pass, e.g. to sample it. Loaded depth is
+    /// always kept.
+    pub store_depth: bool,
+}
+
+impl Default for AttachmentLoadOps {
+    fn default() -> Self {
+        Self {
+            color: ash::vk::AttachmentLoadOp::CLEAR,
+            depth: ash::vk::AttachmentLoadOp::CLEAR,
+            store_depth: false,
+        }
+    }
+}
+
+impl AttachmentLoadOps {
+    pub fn loads_color(&self) -> bool {
+        self.color == ash::vk::AttachmentLoadOp::LOAD
+    }
+
+    pub fn loads_depth(&self) -> bool {
+        self.depth == ash::vk::AttachmentLoadOp::LOAD
+    }
+}
+
 pub struct Swapchain {
     device: Rc<Device>,
     swapchain: ash::extensions::khr::Swapchain,
     pub swapchain_khr: Option<ash::vk::SwapchainKHR>,
     pub swapchain_image_format: ash::vk::Format,
-    swapchain_depth_format: ash::vk::Format,
+    pub swapchain_image_usage: ash::vk::ImageUsageFlags,
+    pub swapchain_depth_format: ash::vk::Format,
     pub swapchain_extent: ash::vk::Extent2D,
     pub swapchain_images: Vec<ash::vk::Image>,
     swapchain_image_views: Vec<ash::vk::ImageView>,
@@ -26,10 +69,14 @@ pub struct Swapchain {
 }
 
 impl Swapchain {
+    /// `image_usage` are the requested image uses on top of `COLOR_ATTACHMENT`,
+    /// unsupported ones are dropped. `swapchain_image_usage` holds the result.
     pub fn new(
         device: Rc<Device>,
         window_extent: ash::vk::Extent2D,
         old_swapchain: Option<ash::vk::SwapchainKHR>,
+        final_layout: ash::vk::ImageLayout,
+        image_usage: ash::vk::ImageUsageFlags,
     ) -> anyhow::Result<Self, GentooRenderError> {
         let old_swapchain = match old_swapchain {
             Some(swapchain) => swapchain,
@@ -40,17 +87,18 @@ impl Swapchain {
             swapchain_khr,
             swapchain_images,
             swapchain_image_format,
+            swapchain_image_usage,
             swapchain_extent
-        ) = Self::create_swapchain(&device, window_extent, old_swapchain)?;
+        ) = Self::create_swapchain(&device, window_extent, old_swapchain, image_usage)?;
         log::debug!("Vulkan Swapchain created");
 
         let swapchain_image_views = Self::create_image_views(
-            &device.logical_device,
+            &device,
             &swapchain_images,
             swapchain_image_format,
         );
 
-        let render_pass = Self::create_render_pass(&device, swapchain_image_format)?;
+        let render_pass = Self::create_render_pass(&device, swapchain_image_format, final_layout, AttachmentLoadOps::default())?;
         log::debug!("Vulkan Render Pass created");
         
         let (depth_images,
@@ -82,6 +130,7 @@ impl Swapchain {
             swapchain,
             swapchain_khr: Some(swapchain_khr),
             swapchain_image_format,
+            swapchain_image_usage,
             swapchain_depth_format,
             swapchain_extent,
             swapchain_images,
@@ -135,11 +184,24 @@ impl Swapchain {
         )
     }
 
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    /// Whether the last submission of `frame` has finished on the GPU.
+    pub fn is_frame_complete(&self, frame: usize) -> anyhow::Result<bool, GentooRenderError> {
+        Ok(unsafe {
+            self.device.logical_device.get_fence_status(self.in_flight_fences[frame])?
+        })
+    }
+
+    /// Waits at most `fence_timeout` for the frame to leave the GPU, see `wait_for_fence`.
     pub unsafe fn acquire_next_image(
         &self,
         logical_device: &ash::Device,
+        fence_timeout: Duration,
     ) -> anyhow::Result<Result<(u32, bool), ash::vk::Result>, GentooRenderError> {
-        logical_device.wait_for_fences(&[self.in_flight_fences[self.current_frame]], false, u64::MAX)?;
+        Self::wait_for_fence(logical_device, self.in_flight_fences[self.current_frame], self.current_frame, fence_timeout)?;
 
         Ok(self.swapchain.acquire_next_image(
             self.swapchain_khr.unwrap(),
@@ -149,6 +211,8 @@ impl Swapchain {
         ))
     }
 
+    /// Returns whether the swapchain has to be recreated, because presenting
+    /// found it suboptimal or out of date.
     pub fn submit_command_buffers(
         &mut self,
         logical_device: &ash::Device,
@@ -156,10 +220,11 @@ impl Swapchain {
         graphics_queue: &ash::vk::Queue,
         buffer: ash::vk::CommandBuffer,
         image_index: usize,
+        fence_timeout: Duration,
     ) -> anyhow::Result<bool, GentooRenderError> {
         if self.images_in_flight[image_index] != ash::vk::Fence::null() {
             unsafe {
-                logical_device.wait_for_fences(&[self.images_in_flight[image_index]], true, u64::MAX)?
+                Self::wait_for_fence(logical_device, self.images_in_flight[image_index], image_index, fence_timeout)?;
             }
         }
 
@@ -195,25 +260,29 @@ impl Swapchain {
 
         self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
 
-        Ok(unsafe {
-            self.swapchain.queue_present(*present_queue, &present_info)?
-        })
+        match unsafe { self.swapchain.queue_present(*present_queue, &present_info) } {
+            Ok(is_suboptimal) => Ok(is_suboptimal),
+            Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(result) => Err(result.into()),
+        }
     }
 
     fn create_swapchain(
         device: &Rc<Device>,
         window_extent: ash::vk::Extent2D,
         old_swapchain: ash::vk::SwapchainKHR,
+        image_usage: ash::vk::ImageUsageFlags,
     ) -> anyhow::Result<(
         ash::extensions::khr::Swapchain,
         ash::vk::SwapchainKHR,
         Vec<ash::vk::Image>,
         ash::vk::Format,
+        ash::vk::ImageUsageFlags,
         ash::vk::Extent2D,
     ), GentooRenderError> {
         let swapchain_support = device.get_swapchain_support()?;
 
-        let surface_format = Self::choose_surface_format(&swapchain_support.formats);
+        let surface_format = Self::choose_surface_format(&swapchain_support.formats, device.instance.swapchain_colorspace_enabled);
         log::debug!("Vulkan Surface Format: {:?}", surface_format);
 
         let present_mode = Self::choose_present_mode(&swapchain_support.present_modes);
@@ -227,29 +296,23 @@ impl Swapchain {
             image_count = swapchain_support.capabilities.max_image_count;
         }
 
-        let mut create_info = ash::vk::SwapchainCreateInfoKHR::builder()
+        let image_usage = Self::choose_image_usage(&swapchain_support.capabilities, image_usage);
+        log::debug!("Vulkan Swapchain Image Usage: {:?}", image_usage);
+
+        let create_info = ash::vk::SwapchainCreateInfoKHR::builder()
             .surface(device.surface_khr)
             .min_image_count(image_count)
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(ash::vk::ImageUsageFlags::COLOR_ATTACHMENT);
+            .image_usage(image_usage);
 
-        let queue_family_indices = [
-            device.queue_families.present.unwrap() as u32,
-            device.queue_families.graphics.unwrap() as u32,
-        ];
-
-        if device.queue_families.graphics != device.queue_families.present {
-            create_info = create_info
-                .image_sharing_mode(ash::vk::SharingMode::CONCURRENT)
-                .queue_family_indices(&queue_family_indices);
-        } else {
-            create_info = create_info.image_sharing_mode(ash::vk::SharingMode::EXCLUSIVE);
-        }
+        let (sharing_mode, queue_family_indices) = device.queue_families.image_sharing_mode().unwrap();
 
         let create_info = create_info
+            .image_sharing_mode(sharing_mode)
+            .queue_family_indices(&queue_family_indices)
             .pre_transform(swapchain_support.capabilities.current_transform)
             .composite_alpha(ash::vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
@@ -275,12 +338,54 @@ impl Swapchain {
             swapchain_khr,
             swapchain_images,
             swapchain_image_format,
+            image_usage,
             swapchain_extent,
         ))
     }
 
-    fn create_image_views(
+    fn choose_image_usage(
+        capabilities: &ash::vk::SurfaceCapabilitiesKHR,
+        requested: ash::vk::ImageUsageFlags,
+    ) -> ash::vk::ImageUsageFlags {
+        let supported = capabilities.supported_usage_flags;
+        let requested = requested | ash::vk::ImageUsageFlags::COLOR_ATTACHMENT;
+
+        let unsupported = requested & !supported;
+
+        if !unsupported.is_empty() {
+            log::warn!("Swapchain image usage {:?} is not supported, dropping it", unsupported);
+        }
+
+        // Transfers are needed to blit a scaled scene onto the swapchain image,
+        // render scaling falls back to full resolution without them
+        let optional = supported & ash::vk::ImageUsageFlags::TRANSFER_DST;
+
+        (requested & supported) | optional
+    }
+
+    /// Waits for `fence` in up to `FENCE_WAIT_ATTEMPTS` rounds of `timeout`,
+    /// so a hung GPU ends in an error instead of freezing the app.
+    unsafe fn wait_for_fence(
         logical_device: &ash::Device,
+        fence: ash::vk::Fence,
+        frame: usize,
+        timeout: Duration,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        for attempt in 1..=FENCE_WAIT_ATTEMPTS {
+            match logical_device.wait_for_fences(&[fence], true, timeout.as_nanos() as u64) {
+                Ok(()) => return Ok(()),
+                Err(ash::vk::Result::TIMEOUT) => {
+                    log::warn!("Frame {} is still on the GPU after {:?}, attempt {}/{}", frame, timeout, attempt, FENCE_WAIT_ATTEMPTS);
+                }
+                Err(result) => return Err(result.into()),
+            }
+        }
+
+        Err(GentooRenderError::FenceTimeout { frame })
+    }
+
+    fn create_image_views(
+        device: &Rc<Device>,
         swapchain_images: &Vec<ash::vk::Image>,
         swapchain_image_format: ash::vk::Format,
     ) -> Vec<ash::vk::ImageView> {
@@ -299,12 +404,10 @@ impl Swapchain {
                         layer_count: 1,
                     });
 
-                unsafe {
-                    logical_device
-                        .create_image_view(&view_info, None)
-                        .map_err(|e| log::error!("Unable to create image view: {}", e))
-                        .unwrap() // TODO: fix unwrap?
-                }
+                device
+                    .create_image_view(&view_info)
+                    .map_err(|e| log::error!("Unable to create image view: {:?}", e))
+                    .unwrap() // TODO: fix unwrap?
             })
             .collect::<Vec<_>>()
     }
@@ -363,42 +466,64 @@ impl Swapchain {
                         layer_count: 1,
                     });
 
-                unsafe {
-                    device.logical_device.create_image_view(&view_info, None)
-                    .map_err(|e| log::error!("Unable to create depth image view: {}", e))
+                device.create_image_view(&view_info)
+                    .map_err(|e| log::error!("Unable to create depth image view: {:?}", e))
                     .unwrap() // TODO: fix unwrap?
-                }
             })
             .collect::<Vec<_>>();
 
         (images, image_memories, image_views, depth_format)
     }
 
-    fn create_render_pass(
+    /// The scene render pass, every pass created by this is compatible with the
+    /// scene pipelines as long as `swapchain_image_format` matches.
+    /// Loaded attachments start in the layout they end in, color in
+    /// `final_layout` and depth in `DEPTH_STENCIL_ATTACHMENT_OPTIMAL`.
+    pub fn create_render_pass(
         device: &Rc<Device>,
         swapchain_image_format: ash::vk::Format,
+        final_layout: ash::vk::ImageLayout,
+        load_ops: AttachmentLoadOps,
     ) -> anyhow::Result<ash::vk::RenderPass, GentooRenderError> {
+        let (color_initial_layout, color_load_access) = if load_ops.loads_color() {
+            (final_layout, ash::vk::AccessFlags::COLOR_ATTACHMENT_READ)
+        } else {
+            (ash::vk::ImageLayout::UNDEFINED, ash::vk::AccessFlags::empty())
+        };
+
+        let (depth_initial_layout, depth_load_access) = if load_ops.loads_depth() {
+            (ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ)
+        } else {
+            (ash::vk::ImageLayout::UNDEFINED, ash::vk::AccessFlags::empty())
+        };
+
+        let depth_store_op = if load_ops.loads_depth() || load_ops.store_depth {
+            ash::vk::AttachmentStoreOp::STORE
+        } else {
+            ash::vk::AttachmentStoreOp::DONT_CARE
+        };
+
         Ok(unsafe {
             device.logical_device.create_render_pass(
                 &ash::vk::RenderPassCreateInfo::builder()
                     .attachments(&[ash::vk::AttachmentDescription {
                         format: swapchain_image_format,
                         samples: ash::vk::SampleCountFlags::TYPE_1,
-                        load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                        load_op: load_ops.color,
                         store_op: ash::vk::AttachmentStoreOp::STORE,
                         stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
                         stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
-                        initial_layout: ash::vk::ImageLayout::UNDEFINED,
-                        final_layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        initial_layout: color_initial_layout,
+                        final_layout,
                         ..Default::default()
                     }, ash::vk::AttachmentDescription {
                         format: Self::find_depth_format(device),
                         samples: ash::vk::SampleCountFlags::TYPE_1,
-                        load_op: ash::vk::AttachmentLoadOp::CLEAR,
-                        store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                        load_op: load_ops.depth,
+                        store_op: depth_store_op,
                         stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
                         stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
-                        initial_layout: ash::vk::ImageLayout::UNDEFINED,
+                        initial_layout: depth_initial_layout,
                         final_layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
                         ..Default::default()
                     }])
@@ -417,11 +542,17 @@ impl Swapchain {
                         src_subpass: ash::vk::SUBPASS_EXTERNAL,
                         dst_subpass: 0,
                         src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                            | ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                            | ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                            | ash::vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
                         dst_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
                             | ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-                        dst_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        // The previous frame's writes have to be visible to the loads
+                        src_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE
                             | ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        dst_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                            | ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                            | color_load_access
+                            | depth_load_access,
                         ..Default::default()
                     }]), None)?
         })
@@ -499,9 +630,28 @@ impl Swapchain {
         ))
     }
 
+    /// With `hdr` the extended linear sRGB (scRGB) color space is preferred.
+    /// The shaders write linear color, which its float images store as it is,
+    /// so values above 1 show brighter than SDR white. HDR10 isn't picked, it
+    /// would need every write to the swapchain encoded with the PQ curve.
     fn choose_surface_format(
         available_formats: &Vec<ash::vk::SurfaceFormatKHR>,
+        hdr: bool,
     ) -> ash::vk::SurfaceFormatKHR {
+        let hdr_format = available_formats
+            .iter()
+            .find(|available_format| {
+                available_format.format == ash::vk::Format::R16G16B16A16_SFLOAT
+                    && available_format.color_space == ash::vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+            });
+
+        if hdr {
+            match hdr_format {
+                Some(format) => return *format,
+                None => log::warn!("The surface has no HDR format, presenting in SDR"),
+            }
+        }
+
         let format = available_formats
             .iter()
             .map(|f| *f)
@@ -562,7 +712,7 @@ impl Drop for Swapchain {
         unsafe {
             self.swapchain_image_views
                 .iter()
-                .for_each(|iv| self.device.logical_device.destroy_image_view(*iv, None));
+                .for_each(|iv| self.device.destroy_image_view(*iv));
 
             if let Some(swapchain_khr) = self.swapchain_khr {
                 self.swapchain.destroy_swapchain(swapchain_khr, None);
@@ -570,15 +720,12 @@ impl Drop for Swapchain {
 
             self.depth_image_views
                 .iter()
-                .for_each(|iv| self.device.logical_device.destroy_image_view(*iv, None));
+                .for_each(|iv| self.device.destroy_image_view(*iv));
 
             self.depth_images
                 .iter()
-                .for_each(|i| self.device.logical_device.destroy_image(*i, None));
-
-            self.depth_image_memories
-                .iter()
-                .for_each(|m| self.device.logical_device.free_memory(*m, None));
+                .zip(&self.depth_image_memories)
+                .for_each(|(i, m)| self.device.destroy_image(*i, *m));
 
             self.swapchain_framebuffers
                 .iter()
@@ -0,0 +1,156 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, VertexLayout, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}}, FrameInfo, RENDER_LAYER_DEBUG};
+
+/// Premultiplied, used unless the object overrides it
+pub const BOUNDS_COLOR: glam::Vec4 = glam::const_vec4!([1.0, 0.8, 0.0, 1.0]);
+
+#[derive(Debug)]
+#[repr(C)]
+struct BoundsPushConstantData {
+    bounds_min: glam::Vec4,
+    bounds_max: glam::Vec4,
+    color: glam::Vec4,
+}
+
+impl BoundsPushConstantData {
+    unsafe fn as_bytes(&self) -> &[u8] {
+        std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
+    }
+}
+
+/// Draws the world space bounding box of every object as 12 lines, for
+/// checking placement and culling. The edges are generated in the vertex
+/// shader, so no vertex buffer is needed. Skinned objects show their rest pose.
+pub struct BoundsDebugSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+}
+
+impl BoundsDebugSystem {
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<BoundsPushConstantData>() as u32,
+        }];
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(global_set_layout)
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        let pipeline = Self::create_pipeline(device.clone(), render_pass, &pipeline_layout, pipeline_cache)?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+        })
+    }
+
+    /// Rebuilds the pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// `all` draws every object's bounds, otherwise only those of objects with
+    /// `DebugRenderComponent::show_bounds`.
+    pub fn render(&self, frame_info: &FrameInfo, all: bool) {
+        let objects = frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_DEBUG) && (all || obj.debug.show_bounds))
+            .filter_map(|obj| Some((obj.model.as_ref()?.bounds?.transformed(&obj.transform.mat4()), obj.debug.bounds_color)))
+            .collect::<Vec<_>>();
+
+        if objects.is_empty() {
+            return;
+        }
+
+        let logical_device = &self.device.logical_device;
+
+        unsafe {
+            self.pipeline.bind(logical_device, frame_info.command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+        }
+
+        for (bounds, color) in objects {
+            let push = BoundsPushConstantData {
+                bounds_min: bounds.min.extend(1.0),
+                bounds_max: bounds.max.extend(1.0),
+                color: color.unwrap_or(BOUNDS_COLOR),
+            };
+
+            unsafe {
+                logical_device.cmd_push_constants(
+                    frame_info.command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                logical_device.cmd_draw(frame_info.command_buffer, 24, 1, 0, 0);
+            }
+        }
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/bounds_debug.vert.spv"),
+            ShaderSource::Path("shaders/bounds_debug.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                topology: ash::vk::PrimitiveTopology::LINE_LIST,
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_write: false,
+                blend_mode: BlendMode::PremultipliedAlpha,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for BoundsDebugSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping bounds debug system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, Buffer, Texture, Sampler, VertexLayout, VertexSemantic, MAX_FRAMES_IN_FLIGHT, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, settings::{DebugTextSettings, ScreenCorner}, FrameInfo};
+
+/// Distance of the text from the screen edges, in pixels.
+const MARGIN: u32 = 8;
+
+const GLYPH_SIZE: u32 = 8;
+/// Glyphs per atlas row, the atlas holds the printable ASCII range
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 6;
+const FIRST_CHAR: u8 = b' ';
+
+/// Glyphs drawn per frame, further text is cut off.
+const MAX_GLYPHS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct GlyphVertex {
+    /// In pixels from the top left corner
+    position: glam::Vec2,
+    uv: glam::Vec2,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct DebugTextPushConstants {
+    extent: glam::Vec2,
+    _padding: glam::Vec2,
+    color: glam::Vec4,
+}
+
+impl DebugTextPushConstants {
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        let size_in_bytes = std::mem::size_of::<Self>();
+        let size_in_u8 = size_in_bytes / std::mem::size_of::<u8>();
+        let start_ptr = self as *const Self as *const u8;
+        std::slice::from_raw_parts(start_ptr, size_in_u8)
+    }
+}
+
+/// Monospaced bitmap font text in a screen corner, a debug HUD for builds
+/// without egui. Every glyph is a quad into an atlas of an embedded 8x8 font,
+/// all lines are drawn in a single batch.
+pub struct DebugTextSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    vertex_buffers: Vec<Buffer<GlyphVertex>>,
+    descriptor_set: ash::vk::DescriptorSet,
+    _atlas: Rc<Texture>,
+    _sampler: Rc<Sampler>,
+    _set_layout: Rc<DescriptorSetLayout>,
+    _descriptor_pool: Rc<DescriptorPool>,
+}
+
+impl DebugTextSystem {
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let atlas = Texture::new_linear(device.clone(), &Self::atlas_pixels(), ATLAS_COLUMNS * GLYPH_SIZE, ATLAS_ROWS * GLYPH_SIZE)?;
+
+        // Filtering would blur the glyphs at integer scales
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::NEAREST)
+            .mipmap_mode(ash::vk::SamplerMipmapMode::NEAREST)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        let descriptor_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)
+            .build()?;
+
+        let set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let descriptor_set = DescriptorSetWriter::new(set_layout.clone(), descriptor_pool.clone())
+            .write_image(0, &[ash::vk::DescriptorImageInfo {
+                sampler: sampler.sampler,
+                ..atlas.descriptor_info()
+            }])
+            .build()
+            .unwrap();
+
+        let mut vertex_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let mut buffer = Buffer::new(
+                device.clone(),
+                MAX_GLYPHS * 6,
+                ash::vk::BufferUsageFlags::VERTEX_BUFFER,
+                ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+            )?;
+
+            buffer.map_persistent()?;
+
+            vertex_buffers.push(buffer);
+        }
+
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<DebugTextPushConstants>() as u32,
+        }];
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[set_layout.layout])
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        let pipeline = Self::create_pipeline(device.clone(), render_pass, &pipeline_layout, pipeline_cache)?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+            vertex_buffers,
+            descriptor_set,
+            _atlas: atlas,
+            _sampler: sampler,
+            _set_layout: set_layout,
+            _descriptor_pool: descriptor_pool,
+        })
+    }
+
+    /// Rebuilds the pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// White glyphs on transparent texels, already premultiplied.
+    fn atlas_pixels() -> Vec<u8> {
+        let width = ATLAS_COLUMNS * GLYPH_SIZE;
+        let height = ATLAS_ROWS * GLYPH_SIZE;
+
+        let mut pixels = vec![0; (width * height * 4) as usize];
+
+        for (glyph, rows) in FONT.iter().enumerate() {
+            let glyph_x = (glyph as u32 % ATLAS_COLUMNS) * GLYPH_SIZE;
+            let glyph_y = (glyph as u32 / ATLAS_COLUMNS) * GLYPH_SIZE;
+
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..GLYPH_SIZE {
+                    // The lowest bit is the leftmost pixel
+                    if row & (1 << x) != 0 {
+                        let offset = (((glyph_y + y as u32) * width + glyph_x + x) * 4) as usize;
+                        pixels[offset..offset + 4].copy_from_slice(&[255; 4]);
+                    }
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// `extent` is the extent of the render pass the text is drawn into.
+    /// Characters outside of printable ASCII are drawn as `?`.
+    pub fn render(&mut self, frame_info: &FrameInfo, extent: ash::vk::Extent2D, lines: &[String], settings: &DebugTextSettings) -> anyhow::Result<(), GentooRenderError> {
+        if !settings.enabled || lines.is_empty() {
+            return Ok(());
+        }
+
+        let glyph_size = (GLYPH_SIZE * settings.scale) as f32;
+        let line_height = glyph_size + settings.scale as f32;
+
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let block_width = columns as f32 * glyph_size;
+        let block_height = lines.len() as f32 * line_height;
+
+        let origin = {
+            let left = MARGIN as f32;
+            let top = MARGIN as f32;
+            let right = extent.width as f32 - MARGIN as f32 - block_width;
+            let bottom = extent.height as f32 - MARGIN as f32 - block_height;
+
+            match settings.corner {
+                ScreenCorner::TopLeft => glam::vec2(left, top),
+                ScreenCorner::TopRight => glam::vec2(right, top),
+                ScreenCorner::BottomLeft => glam::vec2(left, bottom),
+                ScreenCorner::BottomRight => glam::vec2(right, bottom),
+            }
+        };
+
+        let uv_size = glam::vec2(1.0 / ATLAS_COLUMNS as f32, 1.0 / ATLAS_ROWS as f32);
+
+        let mut vertices = Vec::with_capacity(MAX_GLYPHS * 6);
+
+        'lines: for (row, line) in lines.iter().enumerate() {
+            for (column, char) in line.chars().enumerate() {
+                if char == ' ' {
+                    continue;
+                }
+
+                if vertices.len() == MAX_GLYPHS * 6 {
+                    break 'lines;
+                }
+
+                let glyph = match char {
+                    ' '..='~' => char as u8 - FIRST_CHAR,
+                    _ => b'?' - FIRST_CHAR,
+                } as u32;
+
+                let min = origin + glam::vec2(column as f32 * glyph_size, row as f32 * line_height);
+                let max = min + glam::Vec2::splat(glyph_size);
+
+                let uv_min = glam::vec2((glyph % ATLAS_COLUMNS) as f32, (glyph / ATLAS_COLUMNS) as f32) * uv_size;
+                let uv_max = uv_min + uv_size;
+
+                let corner = |x: bool, y: bool| GlyphVertex {
+                    position: glam::vec2(if x { max.x } else { min.x }, if y { max.y } else { min.y }),
+                    uv: glam::vec2(if x { uv_max.x } else { uv_min.x }, if y { uv_max.y } else { uv_min.y }),
+                };
+
+                vertices.extend_from_slice(&[
+                    corner(false, false), corner(true, false), corner(true, true),
+                    corner(false, false), corner(true, true), corner(false, true),
+                ]);
+            }
+        }
+
+        if vertices.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_buffer = &mut self.vertex_buffers[frame_info.frame_index];
+        vertex_buffer.map(0)?.write_slice(&vertices);
+
+        let push = DebugTextPushConstants {
+            extent: glam::vec2(extent.width as f32, extent.height as f32),
+            _padding: glam::Vec2::ZERO,
+            color: glam::vec4(1.0, 1.0, 1.0, 1.0),
+        };
+
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        unsafe {
+            self.pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push.as_bytes(),
+            );
+
+            vertex_buffer.bind_vertex(command_buffer);
+
+            logical_device.cmd_draw(command_buffer, vertices.len() as u32, 1, 0, 0);
+        }
+
+        Ok(())
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        let vertex_layout = VertexLayout::new(std::mem::size_of::<GlyphVertex>() as u32)
+            .add_attribute(VertexSemantic::Position, ash::vk::Format::R32G32_SFLOAT, memoffset::offset_of!(GlyphVertex, position) as u32)
+            .add_attribute(VertexSemantic::Uv, ash::vk::Format::R32G32_SFLOAT, memoffset::offset_of!(GlyphVertex, uv) as u32);
+
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/debug_text.vert.spv"),
+            ShaderSource::Path("shaders/debug_text.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &vertex_layout,
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::PremultipliedAlpha,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for DebugTextSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping debug text system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// The public domain font8x8 basic set from `' '` to `'~'`, one byte per row
+/// with the lowest bit on the left.
+const FONT: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
@@ -0,0 +1,284 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, Texture, Sampler, VertexLayout, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode, DepthBias}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo};
+
+/// Clamped to the device limit by the sampler
+const DECAL_MAX_ANISOTROPY: f32 = 16.0;
+
+#[derive(Debug)]
+#[repr(C)]
+struct DecalPushConstantData {
+    model_matrix: glam::Mat4,
+    color: glam::Vec4,
+}
+
+impl DecalPushConstantData {
+    unsafe fn as_bytes(&self) -> &[u8] {
+        std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
+    }
+}
+
+/// A square textured quad lying on a surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decal {
+    pub position: glam::Vec3,
+    /// The quad faces along it
+    pub normal: glam::Vec3,
+    /// Around the normal, in radians
+    pub rotation: f32,
+    /// Edge length in world units
+    pub size: f32,
+    /// Premultiplied, multiplies the texture
+    pub color: glam::Vec4,
+}
+
+impl Decal {
+    /// Placed at a hit point on a surface with the surface normal there.
+    pub fn from_hit(position: glam::Vec3, normal: glam::Vec3, size: f32) -> Self {
+        Self {
+            position,
+            normal: normal.normalize(),
+            rotation: 0.0,
+            size,
+            color: glam::Vec4::ONE,
+        }
+    }
+
+    fn matrix(&self) -> glam::Mat4 {
+        let up = if self.normal.y.abs() > 0.99 { glam::Vec3::Z } else { glam::Vec3::Y };
+
+        let rotation = glam::Quat::from_axis_angle(self.normal, self.rotation);
+        let tangent = rotation * up.cross(self.normal).normalize();
+        let bitangent = self.normal.cross(tangent);
+
+        let half_size = self.size * 0.5;
+
+        glam::Mat4::from_cols(
+            (tangent * half_size).extend(0.0),
+            (bitangent * half_size).extend(0.0),
+            self.normal.extend(0.0),
+            self.position.extend(1.0),
+        )
+    }
+}
+
+/// Draws decals on top of the opaque scene, e.g. bullet holes or stickers.
+/// The quads are pulled slightly towards the camera and depth biased, so they
+/// don't z-fight with the surface they lie on. They are unlit and don't
+/// project onto curved surfaces. Has to be recorded after the opaque objects.
+pub struct DecalSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    texture: Rc<Texture>,
+    _sampler: Rc<Sampler>,
+    set_layout: Rc<DescriptorSetLayout>,
+    pool: Rc<DescriptorPool>,
+    descriptor_set: ash::vk::DescriptorSet,
+    depth_bias: DepthBias,
+    mip_lod_bias: f32,
+    decals: Vec<Decal>,
+}
+
+impl DecalSystem {
+    /// `texture` should be premultiplied, see `Texture::from_file`.
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+        texture: Rc<Texture>,
+        depth_bias: DepthBias,
+        mip_lod_bias: f32,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)
+            .build()?;
+
+        let descriptor_set = pool.allocate_descriptor(&[set_layout.layout])?;
+
+        let sampler = Self::write_texture(&device, &set_layout, &pool, descriptor_set, &texture, mip_lod_bias)?;
+
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<DecalPushConstantData>() as u32,
+        }];
+
+        let set_layouts = [global_set_layout, &[set_layout.layout]].concat();
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&set_layouts)
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        let pipeline = Self::create_pipeline(device.clone(), render_pass, &pipeline_layout, pipeline_cache, depth_bias)?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+            texture,
+            _sampler: sampler,
+            set_layout,
+            pool,
+            descriptor_set,
+            depth_bias,
+            mip_lod_bias,
+            decals: Vec::new(),
+        })
+    }
+
+    /// Rebuilds the pipeline for a scene render pass with other attachment
+    /// formats, the placed decals are kept.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache, self.depth_bias)?;
+
+        Ok(())
+    }
+
+    pub fn mip_lod_bias(&self) -> f32 {
+        self.mip_lod_bias
+    }
+
+    /// Rebuilds the texture sampler, negative values sharpen and positive
+    /// ones soften, see `SamplerBuilder::mip_lod_bias`. Waits for the GPU, the
+    /// descriptor set may be in use.
+    pub fn set_mip_lod_bias(&mut self, mip_lod_bias: f32) -> anyhow::Result<(), GentooRenderError> {
+        unsafe {
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        self._sampler = Self::write_texture(&self.device, &self.set_layout, &self.pool, self.descriptor_set, &self.texture, mip_lod_bias)?;
+        self.mip_lod_bias = mip_lod_bias;
+
+        Ok(())
+    }
+
+    pub fn add(&mut self, decal: Decal) {
+        self.decals.push(decal);
+    }
+
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    pub fn decal_count(&self) -> usize {
+        self.decals.len()
+    }
+
+    pub fn render(&self, frame_info: &FrameInfo) {
+        if self.decals.is_empty() {
+            return;
+        }
+
+        let logical_device = &self.device.logical_device;
+
+        unsafe {
+            self.pipeline.bind(logical_device, frame_info.command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set, self.descriptor_set],
+                &[],
+            );
+        }
+
+        for decal in &self.decals {
+            let push = DecalPushConstantData {
+                model_matrix: decal.matrix(),
+                color: decal.color,
+            };
+
+            unsafe {
+                logical_device.cmd_push_constants(
+                    frame_info.command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                logical_device.cmd_draw(frame_info.command_buffer, 6, 1, 0, 0);
+            }
+        }
+    }
+
+    /// Points the descriptor set at the texture with a new sampler, which has
+    /// to be kept alive with it.
+    fn write_texture(
+        device: &Rc<Device>,
+        set_layout: &Rc<DescriptorSetLayout>,
+        pool: &Rc<DescriptorPool>,
+        descriptor_set: ash::vk::DescriptorSet,
+        texture: &Texture,
+        mip_lod_bias: f32,
+    ) -> anyhow::Result<Rc<Sampler>, GentooRenderError> {
+        // Decals mostly lie on the floor, which is seen at grazing angles
+        let sampler = Sampler::new(device.clone())
+            .max_anisotropy(DECAL_MAX_ANISOTROPY)
+            .mip_lod_bias(mip_lod_bias)
+            .build()?;
+
+        DescriptorSetWriter::new(set_layout.clone(), pool.clone())
+            .write_image(0, &[ash::vk::DescriptorImageInfo {
+                sampler: sampler.sampler,
+                ..texture.descriptor_info()
+            }])
+            .overwrite(descriptor_set);
+
+        Ok(sampler)
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        depth_bias: DepthBias,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/decal.vert.spv"),
+            ShaderSource::Path("shaders/decal.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_write: false,
+                depth_compare_op: ash::vk::CompareOp::LESS_OR_EQUAL,
+                depth_bias: Some(depth_bias),
+                blend_mode: BlendMode::PremultipliedAlpha,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for DecalSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping decal system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, MultiTarget, Sampler, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo};
+
+const DEPTH_VIEW_FORMATS: [ash::vk::Format; 1] = [ash::vk::Format::R8G8B8A8_UNORM];
+
+#[derive(Debug)]
+#[repr(C)]
+struct DepthViewPushConstantData {
+    near: f32,
+    far: f32,
+    /// 0 shows the raw depth
+    linear: u32,
+}
+
+impl DepthViewPushConstantData {
+    unsafe fn as_bytes(&self) -> &[u8] {
+        std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
+    }
+}
+
+/// Converts a depth image into a color image egui can show, see
+/// `EGuiIntegration::register_user_texture`. The depth is either shown raw
+/// in gray or linearized between a near and far plane and colorized, from
+/// yellow up close to dark blue far away. Pixels without depth stay black.
+pub struct DepthViewSystem {
+    device: Rc<Device>,
+    render_pass: ash::vk::RenderPass,
+    target: MultiTarget,
+    _sampler: Rc<Sampler>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    _set_layout: Rc<DescriptorSetLayout>,
+    _pool: Rc<DescriptorPool>,
+    descriptor_set: ash::vk::DescriptorSet,
+    /// The color image holds a converted depth, before that it is undefined
+    rendered: bool,
+}
+
+impl DepthViewSystem {
+    /// `depth_view` is sampled in `DEPTH_STENCIL_READ_ONLY_OPTIMAL` layout
+    /// and converted into a color image of `extent`.
+    pub fn new(
+        device: Rc<Device>,
+        pipeline_cache: &Rc<PipelineCache>,
+        depth_view: ash::vk::ImageView,
+        extent: ash::vk::Extent2D,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let render_pass = MultiTarget::create_render_pass(&device, &DEPTH_VIEW_FORMATS)?;
+        let target = MultiTarget::new(device.clone(), &render_pass, &DEPTH_VIEW_FORMATS, extent)?;
+
+        let set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)
+            .build()?;
+
+        let descriptor_set = pool.allocate_descriptor(&[set_layout.layout])?;
+
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::NEAREST)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        DescriptorSetWriter::new(set_layout.clone(), pool.clone())
+            .write_image(0, &[ash::vk::DescriptorImageInfo::builder()
+                .sampler(sampler.sampler)
+                .image_view(depth_view)
+                .image_layout(ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .build()])
+            .overwrite(descriptor_set);
+
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<DepthViewPushConstantData>() as u32,
+        }];
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[set_layout.layout])
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        let pipeline = Self::create_pipeline(device.clone(), &render_pass, &pipeline_layout, pipeline_cache)?;
+
+        Ok(Self {
+            device,
+            render_pass,
+            target,
+            _sampler: sampler,
+            pipeline,
+            pipeline_layout,
+            _set_layout: set_layout,
+            _pool: pool,
+            descriptor_set,
+            rendered: false,
+        })
+    }
+
+    /// The converted image, in `SHADER_READ_ONLY_OPTIMAL` layout after `render`
+    pub fn color_view(&self) -> ash::vk::ImageView {
+        self.target.color_view(0)
+    }
+
+    pub fn extent(&self) -> ash::vk::Extent2D {
+        self.target.extent
+    }
+
+    /// Whether the color image can be sampled yet
+    pub fn has_rendered(&self) -> bool {
+        self.rendered
+    }
+
+    /// Rebuilds the pipeline from the shader files on disk. On failure the
+    /// previous pipeline is kept.
+    pub fn reload_pipelines(&mut self, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), &self.render_pass, &self.pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// Converts the depth image, has to be recorded outside of a render pass
+    /// after the depth was written. `linear` maps the view distance between
+    /// `near` and `far` of the perspective projection that wrote it.
+    pub fn render(&mut self, frame_info: &FrameInfo, linear: bool, near: f32, far: f32) {
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let clear_values = [
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0],
+                },
+            },
+            ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+
+        let push = DepthViewPushConstantData {
+            near,
+            far: far.max(near + f32::EPSILON),
+            linear: linear as u32,
+        };
+
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.render_pass)
+                    .framebuffer(self.target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent: self.target.extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, self.target.extent);
+
+            self.pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                ash::vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push.as_bytes(),
+            );
+
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        self.rendered = true;
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path("shaders/depth_view.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for DepthViewSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping depth view system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, OffscreenTarget, BlitTarget, AttachmentLoadOps, Sampler, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo};
+
+/// Runs a fragment shader over the whole scene into its own target, a sandbox
+/// for post effects. The shader gets the global UBO in set 0 with the camera,
+/// `time` and `resolution`, the scene color and depth as `sampler2D`s in
+/// bindings 0 and 1 of set 1 and the `PostProcessUbo` in set 2. `fragUv` at
+/// location 0 goes from 0 to 1 over the screen. Whatever it writes to location 0 replaces the scene.
+pub struct FullscreenEffectSystem {
+    device: Rc<Device>,
+    fragment_shader: String,
+    /// Output, created with the scene target's format and extent
+    target: Option<OffscreenTarget>,
+    /// The scene target views the descriptor set was written with
+    scene_views: (ash::vk::ImageView, ash::vk::ImageView),
+    sampler: Rc<Sampler>,
+    /// Created for the output target's render pass
+    pipeline: Option<Pipeline>,
+    pipeline_layout: ash::vk::PipelineLayout,
+    set_layout: Rc<DescriptorSetLayout>,
+    pool: Rc<DescriptorPool>,
+    descriptor_set: ash::vk::DescriptorSet,
+}
+
+impl FullscreenEffectSystem {
+    /// `fragment_shader` is the path of a compiled SPIR-V shader.
+    pub fn new(
+        device: Rc<Device>,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        post_process_set_layout: ash::vk::DescriptorSetLayout,
+        fragment_shader: &str,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(1, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 2)
+            .build()?;
+
+        let descriptor_set = pool.allocate_descriptor(&[set_layout.layout])?;
+
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::LINEAR)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        let set_layouts = [global_set_layout, &[set_layout.layout, post_process_set_layout]].concat();
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&set_layouts),
+                None,
+            )?
+        };
+
+        Ok(Self {
+            device,
+            fragment_shader: fragment_shader.to_string(),
+            target: None,
+            scene_views: (ash::vk::ImageView::null(), ash::vk::ImageView::null()),
+            sampler,
+            pipeline: None,
+            pipeline_layout,
+            set_layout,
+            pool,
+            descriptor_set,
+        })
+    }
+
+    /// Reads the fragment shader again, the old pipeline is kept if it fails.
+    pub fn reload_pipeline(&mut self, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        if let Some(target) = &self.target {
+            self.pipeline = Some(self.create_pipeline(&target.render_pass, pipeline_cache)?);
+        }
+
+        Ok(())
+    }
+
+    /// Draws the effect over `scene`, outside of any render pass. The scene
+    /// target has to come from a renderer that resolves the scene, see
+    /// `Renderer::set_resolve_scene`. The returned blit source is the result.
+    pub fn render(
+        &mut self,
+        frame_info: &FrameInfo,
+        scene: &OffscreenTarget,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<BlitTarget, GentooRenderError> {
+        self.prepare_target(scene, pipeline_cache)?;
+
+        let target = self.target.as_ref().unwrap();
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let barrier = |image, aspect_mask, old_layout, new_layout, src_access_mask| {
+            ash::vk::ImageMemoryBarrier::builder()
+                .image(image)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(ash::vk::AccessFlags::SHADER_READ)
+                .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(ash::vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()
+        };
+
+        let clear_values = [
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0],
+                },
+            },
+            ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+
+        unsafe {
+            // The scene pass leaves the color ready to be blitted and depth
+            // as an attachment
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | ash::vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(
+                        scene.color_image,
+                        ash::vk::ImageAspectFlags::COLOR,
+                        ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    ),
+                    barrier(
+                        scene.depth_image,
+                        ash::vk::ImageAspectFlags::DEPTH,
+                        ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                        ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                        ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    ),
+                ],
+            );
+
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(target.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent: target.extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, target.extent);
+
+            self.pipeline.as_ref().unwrap().bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set, self.descriptor_set, frame_info.post_process_descriptor_set],
+                &[],
+            );
+
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        Ok(BlitTarget {
+            image: target.color_image,
+            format: target.color_format,
+            layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            final_layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            mip_level: 0,
+            region: Renderer::blit_region(target.extent),
+        })
+    }
+
+    /// Follows the scene target's extent and format, and points the inputs
+    /// at its images.
+    fn prepare_target(&mut self, scene: &OffscreenTarget, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        let scene_views = (scene.color_image_view, scene.depth_image_view);
+
+        if let Some(target) = &self.target {
+            if target.extent == scene.extent && target.color_format == scene.color_format && self.scene_views == scene_views {
+                return Ok(());
+            }
+        }
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        let format_changed = self.target.as_ref().map(|target| target.color_format) != Some(scene.color_format);
+
+        let target = OffscreenTarget::new(self.device.clone(), scene.color_format, scene.extent, AttachmentLoadOps::default())?;
+
+        if format_changed {
+            self.pipeline = Some(self.create_pipeline(&target.render_pass, pipeline_cache)?);
+        }
+
+        let image_info = |view, layout| ash::vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler.sampler)
+            .image_view(view)
+            .image_layout(layout)
+            .build();
+
+        DescriptorSetWriter::new(self.set_layout.clone(), self.pool.clone())
+            .write_image(0, &[image_info(scene.color_image_view, ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)])
+            .write_image(1, &[image_info(scene.depth_image_view, ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)])
+            .overwrite(self.descriptor_set);
+
+        self.target = Some(target);
+        self.scene_views = scene_views;
+
+        Ok(())
+    }
+
+    fn create_pipeline(
+        &self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            self.device.clone(),
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path(&self.fragment_shader),
+            render_pass,
+            &self.pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::Opaque,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for FullscreenEffectSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping fullscreen effect system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
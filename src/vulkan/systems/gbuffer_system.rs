@@ -0,0 +1,303 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, MultiTarget, Sampler, Vertex, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo, RENDER_LAYER_GBUFFER};
+
+use super::SimplePushConstantData;
+
+/// World position, world normal and albedo, in attachment order.
+pub const GBUFFER_FORMATS: [ash::vk::Format; 3] = [
+    ash::vk::Format::R16G16B16A16_SFLOAT,
+    ash::vk::Format::R16G16B16A16_SFLOAT,
+    ash::vk::Format::R8G8B8A8_UNORM,
+];
+
+/// Writes the position, normal and albedo of the scene into a G-buffer in a
+/// single pass, the basis for deferred shading. The debug view shows the
+/// three targets side by side in the scene pass.
+pub struct GBufferSystem {
+    device: Rc<Device>,
+    render_pass: ash::vk::RenderPass,
+    target: Option<MultiTarget>,
+    sampler: Rc<Sampler>,
+    geometry_pipeline: Pipeline,
+    geometry_pipeline_layout: ash::vk::PipelineLayout,
+    debug_pipeline: Pipeline,
+    debug_pipeline_layout: ash::vk::PipelineLayout,
+    debug_set_layout: Rc<DescriptorSetLayout>,
+    debug_pool: Rc<DescriptorPool>,
+    debug_descriptor_set: ash::vk::DescriptorSet,
+}
+
+impl GBufferSystem {
+    pub fn new(
+        device: Rc<Device>,
+        scene_render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let render_pass = MultiTarget::create_render_pass(&device, &GBUFFER_FORMATS)?;
+
+        let geometry_pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: std::mem::size_of::<SimplePushConstantData>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(global_set_layout)
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        // Without culling, double sided objects are written from both sides
+        let geometry_pipeline = Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/simple_shader.vert.spv"),
+            ShaderSource::Path("shaders/gbuffer.frag.spv"),
+            &render_pass,
+            &geometry_pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::Opaque,
+                color_attachment_count: GBUFFER_FORMATS.len() as u32,
+                ..Default::default()
+            },
+        )?;
+
+        let debug_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(1, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(2, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let debug_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, GBUFFER_FORMATS.len() as u32)
+            .build()?;
+
+        let debug_descriptor_set = debug_pool.allocate_descriptor(&[debug_set_layout.layout])?;
+
+        let debug_pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[debug_set_layout.layout]),
+                None,
+            )?
+        };
+
+        let debug_pipeline = Self::create_debug_pipeline(device.clone(), scene_render_pass, &debug_pipeline_layout, pipeline_cache)?;
+
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::NEAREST)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        Ok(Self {
+            device,
+            render_pass,
+            target: None,
+            sampler,
+            geometry_pipeline,
+            geometry_pipeline_layout,
+            debug_pipeline,
+            debug_pipeline_layout,
+            debug_set_layout,
+            debug_pool,
+            debug_descriptor_set,
+        })
+    }
+
+    /// Rebuilds the debug pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.debug_pipeline = Self::create_debug_pipeline(self.device.clone(), render_pass, &self.debug_pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// The G-buffer targets in the order of `GBUFFER_FORMATS`, in the layout
+    /// later passes sample them. None before the first `render`.
+    pub fn attachment_infos(&self) -> Option<Vec<ash::vk::DescriptorImageInfo>> {
+        let target = self.target.as_ref()?;
+
+        Some(
+            (0..target.color_attachment_count())
+                .map(|index| ash::vk::DescriptorImageInfo::builder()
+                    .sampler(self.sampler.sampler)
+                    .image_view(target.color_view(index))
+                    .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .build())
+                .collect()
+        )
+    }
+
+    /// Writes every visible object into the G-buffer, has to be recorded
+    /// outside of the scene render pass. `extent` is the scene extent.
+    pub fn render(&mut self, frame_info: &FrameInfo, extent: ash::vk::Extent2D) -> anyhow::Result<(), GentooRenderError> {
+        if self.target.as_ref().map(|target| target.extent) != Some(extent) {
+            self.recreate_target(extent)?;
+        }
+
+        let target = self.target.as_ref().unwrap();
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let mut clear_values = vec![
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: [0.0; 4],
+                },
+            };
+            GBUFFER_FORMATS.len()
+        ];
+
+        clear_values.push(ash::vk::ClearValue {
+            depth_stencil: ash::vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        });
+
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, extent);
+
+            self.geometry_pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.geometry_pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+
+            for obj in frame_info.game_objects.values().filter(|obj| obj.is_rendered_in(RENDER_LAYER_GBUFFER) && obj.model.is_some() && obj.animation.is_none()) {
+                let model = obj.model.as_ref().unwrap();
+
+                let push = SimplePushConstantData {
+                    model_matrix: obj.transform.mat4(),
+                    normal_matrix: obj.transform.normal_matrix(),
+                };
+
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.geometry_pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                model.bind(command_buffer);
+                model.draw(logical_device, command_buffer);
+            }
+
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        Ok(())
+    }
+
+    /// Shows the targets of the last `render` side by side inside the scene render pass.
+    pub fn render_debug(&self, frame_info: &FrameInfo) {
+        if self.target.is_none() {
+            return;
+        }
+
+        unsafe {
+            self.debug_pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
+
+            self.device.logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.debug_pipeline_layout,
+                0,
+                &[self.debug_descriptor_set],
+                &[],
+            );
+
+            self.device.logical_device.cmd_draw(frame_info.command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    fn recreate_target(&mut self, extent: ash::vk::Extent2D) -> anyhow::Result<(), GentooRenderError> {
+        unsafe {
+            // The debug descriptors still point at the old target
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        self.target = None;
+        self.target = Some(MultiTarget::new(self.device.clone(), &self.render_pass, &GBUFFER_FORMATS, extent)?);
+
+        let image_infos = self.attachment_infos().unwrap();
+
+        DescriptorSetWriter::new(self.debug_set_layout.clone(), self.debug_pool.clone())
+            .write_image(0, &image_infos[0..1])
+            .write_image(1, &image_infos[1..2])
+            .write_image(2, &image_infos[2..3])
+            .overwrite(self.debug_descriptor_set);
+
+        Ok(())
+    }
+
+    fn create_debug_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path("shaders/gbuffer_debug.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for GBufferSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping G-buffer system");
+
+        self.target = None;
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.geometry_pipeline_layout, None);
+            self.device.logical_device.destroy_pipeline_layout(self.debug_pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
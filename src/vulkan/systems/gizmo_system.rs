@@ -0,0 +1,164 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, VertexLayout, pipeline::{Pipeline, PipelineCache, PipelineConfig}}, settings::{GizmoSettings, ScreenCorner}, FrameInfo};
+
+/// Distance of the gizmo viewport from the screen edges, in pixels.
+const MARGIN: u32 = 8;
+
+#[derive(Debug)]
+#[repr(C)]
+struct GizmoPushConstants {
+    rotation: glam::Mat4,
+}
+
+impl GizmoPushConstants {
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        let size_in_bytes = std::mem::size_of::<Self>();
+        let size_in_u8 = size_in_bytes / std::mem::size_of::<u8>();
+        let start_ptr = self as *const Self as *const u8;
+        std::slice::from_raw_parts(start_ptr, size_in_u8)
+    }
+}
+
+/// Draws the world axes in a screen corner, rotated like the camera, on top of the scene.
+pub struct GizmoSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+}
+
+impl GizmoSystem {
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<GizmoPushConstants>() as u32,
+        }];
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        let pipeline = Self::create_pipeline(device.clone(), render_pass, &pipeline_layout, pipeline_cache)?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+        })
+    }
+
+    /// Rebuilds the pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// `extent` is the extent of the render pass the gizmo is drawn into.
+    /// Leaves the viewport and scissor covering the whole extent.
+    pub fn render(&self, frame_info: &FrameInfo, extent: ash::vk::Extent2D, settings: &GizmoSettings) {
+        let size = settings.size.min(extent.width.saturating_sub(2 * MARGIN)).min(extent.height.saturating_sub(2 * MARGIN));
+
+        if !settings.enabled || size == 0 {
+            return;
+        }
+
+        let offset = {
+            let right = (extent.width - size - MARGIN) as i32;
+            let bottom = (extent.height - size - MARGIN) as i32;
+
+            match settings.corner {
+                ScreenCorner::TopLeft => ash::vk::Offset2D { x: MARGIN as i32, y: MARGIN as i32 },
+                ScreenCorner::TopRight => ash::vk::Offset2D { x: right, y: MARGIN as i32 },
+                ScreenCorner::BottomLeft => ash::vk::Offset2D { x: MARGIN as i32, y: bottom },
+                ScreenCorner::BottomRight => ash::vk::Offset2D { x: right, y: bottom },
+            }
+        };
+
+        // Only the rotation of the view, the gizmo stays in its corner
+        let push = GizmoPushConstants {
+            rotation: glam::Mat4::from_mat3(glam::Mat3::from_mat4(frame_info.camera.view_matrix)),
+        };
+
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        unsafe {
+            logical_device.cmd_set_viewport(command_buffer, 0, &[ash::vk::Viewport {
+                x: offset.x as f32,
+                y: offset.y as f32,
+                width: size as f32,
+                height: size as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            }]);
+
+            logical_device.cmd_set_scissor(command_buffer, 0, &[ash::vk::Rect2D {
+                offset,
+                extent: ash::vk::Extent2D { width: size, height: size },
+            }]);
+
+            self.pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                ash::vk::ShaderStageFlags::VERTEX,
+                0,
+                push.as_bytes(),
+            );
+
+            logical_device.cmd_draw(command_buffer, 6, 1, 0, 0);
+
+            crate::vulkan::Renderer::set_viewport_and_scissor(logical_device, command_buffer, extent);
+        }
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/gizmo.vert.spv"),
+            ShaderSource::Path("shaders/gizmo.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                topology: ash::vk::PrimitiveTopology::LINE_LIST,
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for GizmoSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping gizmo system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -1,5 +1,37 @@
 mod simple_render_system;
 mod point_light_system;
+mod overdraw_system;
+mod gizmo_system;
+mod ssao_system;
+mod skinned_render_system;
+mod debug_text_system;
+mod gbuffer_system;
+mod picking_system;
+mod wireframe_system;
+mod taa_system;
+mod shadow_caster_system;
+mod bounds_debug_system;
+mod decal_system;
+mod fullscreen_effect_system;
+mod reflection_system;
+mod oit_system;
+mod depth_view_system;
 
 pub use simple_render_system::*;
 pub use point_light_system::*;
+pub use overdraw_system::*;
+pub use gizmo_system::*;
+pub use ssao_system::*;
+pub use skinned_render_system::*;
+pub use debug_text_system::*;
+pub use gbuffer_system::*;
+pub use picking_system::*;
+pub use wireframe_system::*;
+pub use taa_system::*;
+pub use shadow_caster_system::*;
+pub use bounds_debug_system::*;
+pub use decal_system::*;
+pub use fullscreen_effect_system::*;
+pub use reflection_system::*;
+pub use oit_system::*;
+pub use depth_view_system::*;
@@ -0,0 +1,404 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, MultiTarget, Sampler, Vertex, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo, GameObject, RENDER_LAYER_MAIN};
+
+use super::{SimplePushConstantData, SimpleRenderSystem};
+
+/// Accumulated weighted color and revealage, in attachment order.
+const OIT_FORMATS: [ash::vk::Format; 2] = [
+    ash::vk::Format::R16G16B16A16_SFLOAT,
+    ash::vk::Format::R16_SFLOAT,
+];
+
+/// Weighted blended order independent transparency, after McGuire and
+/// Bavoil. The transparent objects are accumulated into a weighted sum of
+/// their colors and the product of what they let through, the revealage,
+/// which are composited over the opaque scene in the scene pass. The opaque
+/// objects are drawn depth only first, so they still hide what's behind them.
+/// Only covers the simple render system's objects, skinned ones don't occlude.
+pub struct OitSystem {
+    device: Rc<Device>,
+    render_pass: ash::vk::RenderPass,
+    target: Option<MultiTarget>,
+    sampler: Rc<Sampler>,
+    pipeline_layout: ash::vk::PipelineLayout,
+    depth_pipeline: Pipeline,
+    /// Back face culled and double sided
+    accumulation_pipelines: (Pipeline, Pipeline),
+    composite_pipeline: Pipeline,
+    composite_pipeline_layout: ash::vk::PipelineLayout,
+    composite_set_layout: Rc<DescriptorSetLayout>,
+    composite_pool: Rc<DescriptorPool>,
+    composite_descriptor_set: ash::vk::DescriptorSet,
+    /// The last `accumulate` found transparent objects
+    accumulated: bool,
+    /// See `SimpleRenderSystem::fragment_shader`
+    log_depth: bool,
+}
+
+impl OitSystem {
+    pub fn new(
+        device: Rc<Device>,
+        scene_render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let render_pass = MultiTarget::create_render_pass(&device, &OIT_FORMATS)?;
+
+        let pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: std::mem::size_of::<SimplePushConstantData>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(global_set_layout)
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        let (depth_pipeline, accumulation_pipelines) = Self::create_pipelines(device.clone(), &render_pass, &pipeline_layout, pipeline_cache, false)?;
+
+        let composite_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(1, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let composite_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, OIT_FORMATS.len() as u32)
+            .build()?;
+
+        let composite_descriptor_set = composite_pool.allocate_descriptor(&[composite_set_layout.layout])?;
+
+        let composite_pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[composite_set_layout.layout]),
+                None,
+            )?
+        };
+
+        let composite_pipeline = Self::create_composite_pipeline(device.clone(), scene_render_pass, &composite_pipeline_layout, pipeline_cache)?;
+
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::NEAREST)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        Ok(Self {
+            device,
+            render_pass,
+            target: None,
+            sampler,
+            pipeline_layout,
+            depth_pipeline,
+            accumulation_pipelines,
+            composite_pipeline,
+            composite_pipeline_layout,
+            composite_set_layout,
+            composite_pool,
+            composite_descriptor_set,
+            accumulated: false,
+            log_depth: false,
+        })
+    }
+
+    /// Like `SimpleRenderSystem::set_log_depth`
+    pub fn set_log_depth(&mut self, log_depth: bool, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        let previous = std::mem::replace(&mut self.log_depth, log_depth);
+
+        let result = self.reload_pipelines(pipeline_cache);
+        if result.is_err() {
+            self.log_depth = previous;
+        }
+
+        result
+    }
+
+    /// Rebuilds the composite pipeline for a scene render pass with other
+    /// attachment formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.composite_pipeline = Self::create_composite_pipeline(self.device.clone(), render_pass, &self.composite_pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the pipelines using the simple shaders from the files on
+    /// disk, the old ones are kept if it fails.
+    pub fn reload_pipelines(&mut self, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        let (depth_pipeline, accumulation_pipelines) = Self::create_pipelines(self.device.clone(), &self.render_pass, &self.pipeline_layout, pipeline_cache, self.log_depth)?;
+
+        self.depth_pipeline = depth_pipeline;
+        self.accumulation_pipelines = accumulation_pipelines;
+
+        Ok(())
+    }
+
+    /// Accumulates the transparent objects, has to be recorded outside of the
+    /// scene render pass. `extent` is the scene extent. Skips the pass without
+    /// transparent objects, `composite` then draws nothing.
+    pub fn accumulate(&mut self, frame_info: &FrameInfo, extent: ash::vk::Extent2D) -> anyhow::Result<(), GentooRenderError> {
+        let is_drawn = |obj: &&GameObject| obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some() && obj.animation.is_none();
+
+        self.accumulated = frame_info.game_objects.values().filter(is_drawn).any(|obj| obj.material.is_transparent());
+
+        if !self.accumulated {
+            return Ok(());
+        }
+
+        if self.target.as_ref().map(|target| target.extent) != Some(extent) {
+            self.recreate_target(extent)?;
+        }
+
+        let target = self.target.as_ref().unwrap();
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        // Nothing accumulated and everything revealed
+        let clear_values = [
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: [0.0; 4],
+                },
+            },
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: [1.0; 4],
+                },
+            },
+            ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, extent);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+
+            self.depth_pipeline.bind(logical_device, command_buffer);
+        }
+
+        for obj in frame_info.game_objects.values().filter(is_drawn).filter(|obj| !obj.material.is_transparent()) {
+            self.draw(frame_info, obj, SimplePushConstantData::for_object(obj, false));
+        }
+
+        let (pipeline, double_sided_pipeline) = &self.accumulation_pipelines;
+
+        for (pipeline, double_sided) in [(pipeline, false), (double_sided_pipeline, true)] {
+            let mut objects = frame_info.game_objects
+                .values()
+                .filter(is_drawn)
+                .filter(|obj| obj.material.is_transparent() && obj.material.double_sided == double_sided)
+                .peekable();
+
+            if objects.peek().is_none() {
+                continue;
+            }
+
+            unsafe {
+                pipeline.bind(logical_device, command_buffer);
+            }
+
+            for obj in objects {
+                let mut push = SimplePushConstantData::for_object(obj, false);
+                // Switches the simple shader to the weighted outputs
+                push.normal_matrix.x_axis.w = 1.0;
+
+                self.draw(frame_info, obj, push);
+            }
+        }
+
+        unsafe {
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        Ok(())
+    }
+
+    /// Blends the last accumulation over the opaque scene, inside the scene
+    /// render pass after the opaque objects.
+    pub fn composite(&self, frame_info: &FrameInfo) {
+        if !self.accumulated {
+            return;
+        }
+
+        unsafe {
+            self.composite_pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
+
+            self.device.logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.composite_pipeline_layout,
+                0,
+                &[self.composite_descriptor_set],
+                &[],
+            );
+
+            self.device.logical_device.cmd_draw(frame_info.command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    fn draw(&self, frame_info: &FrameInfo, obj: &GameObject, push: SimplePushConstantData) {
+        let model = obj.model.as_ref().unwrap();
+
+        unsafe {
+            self.device.logical_device.cmd_push_constants(
+                frame_info.command_buffer,
+                self.pipeline_layout,
+                ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push.as_bytes(),
+            );
+
+            model.bind(frame_info.command_buffer);
+            model.draw(&self.device.logical_device, frame_info.command_buffer);
+        }
+    }
+
+    fn recreate_target(&mut self, extent: ash::vk::Extent2D) -> anyhow::Result<(), GentooRenderError> {
+        unsafe {
+            // The composite descriptors still point at the old target
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        self.target = None;
+        let target = MultiTarget::new(self.device.clone(), &self.render_pass, &OIT_FORMATS, extent)?;
+
+        let image_infos = (0..target.color_attachment_count())
+            .map(|index| ash::vk::DescriptorImageInfo::builder()
+                .sampler(self.sampler.sampler)
+                .image_view(target.color_view(index))
+                .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build())
+            .collect::<Vec<_>>();
+
+        DescriptorSetWriter::new(self.composite_set_layout.clone(), self.composite_pool.clone())
+            .write_image(0, &image_infos[0..1])
+            .write_image(1, &image_infos[1..2])
+            .overwrite(self.composite_descriptor_set);
+
+        self.target = Some(target);
+
+        Ok(())
+    }
+
+    /// The depth only pipeline runs the simple fragment shader anyway, so its
+    /// depth matches with logarithmic depth.
+    fn create_pipelines(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        log_depth: bool,
+    ) -> anyhow::Result<(Pipeline, (Pipeline, Pipeline)), GentooRenderError> {
+        let create_pipeline = |config| Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/simple_shader.vert.spv"),
+            SimpleRenderSystem::fragment_shader(log_depth),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                color_attachment_count: OIT_FORMATS.len() as u32,
+                ..config
+            },
+        );
+
+        // Without culling, double sided objects occlude from both sides
+        let depth_pipeline = create_pipeline(PipelineConfig {
+            cull_mode: ash::vk::CullModeFlags::NONE,
+            blend_mode: BlendMode::Masked,
+            ..Default::default()
+        })?;
+
+        let accumulation_config = PipelineConfig {
+            attachment_blend_modes: &[BlendMode::Additive, BlendMode::Revealage],
+            depth_write: false,
+            ..Default::default()
+        };
+
+        Ok((
+            depth_pipeline,
+            (
+                create_pipeline(accumulation_config)?,
+                create_pipeline(PipelineConfig {
+                    cull_mode: ash::vk::CullModeFlags::NONE,
+                    ..accumulation_config
+                })?,
+            ),
+        ))
+    }
+
+    fn create_composite_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path("shaders/oit_composite.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::PremultipliedAlpha,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for OitSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping OIT system");
+
+        self.target = None;
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.logical_device.destroy_pipeline_layout(self.composite_pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
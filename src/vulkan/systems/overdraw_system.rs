@@ -0,0 +1,362 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, OffscreenTarget, Vertex, VertexLayout, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo, RENDER_LAYER_DEBUG};
+
+use super::SimplePushConstantData;
+
+const COUNT_FORMAT: ash::vk::Format = ash::vk::Format::R16_SFLOAT;
+
+/// The per pixel fragment counts, the target of the accumulation pass.
+struct CountTarget {
+    device: Rc<Device>,
+    image: ash::vk::Image,
+    memory: ash::vk::DeviceMemory,
+    image_view: ash::vk::ImageView,
+    framebuffer: ash::vk::Framebuffer,
+    extent: ash::vk::Extent2D,
+}
+
+impl Drop for CountTarget {
+    fn drop(&mut self) {
+        log::debug!("Dropping overdraw count target");
+
+        unsafe {
+            self.device.logical_device.destroy_framebuffer(self.framebuffer, None);
+            self.device.destroy_image_view(self.image_view);
+            self.device.destroy_image(self.image, self.memory);
+        }
+    }
+}
+
+/// Debug view of how many fragments end up in every pixel. The scene is drawn
+/// additively without depth test into a count target, which is then shown as
+/// a heatmap in place of the lit scene.
+pub struct OverdrawSystem {
+    device: Rc<Device>,
+    render_pass: ash::vk::RenderPass,
+    count_target: Option<CountTarget>,
+    sampler: ash::vk::Sampler,
+    accumulate_pipeline: Pipeline,
+    accumulate_pipeline_layout: ash::vk::PipelineLayout,
+    heatmap_pipeline: Pipeline,
+    heatmap_pipeline_layout: ash::vk::PipelineLayout,
+    heatmap_set_layout: Rc<DescriptorSetLayout>,
+    heatmap_pool: Rc<DescriptorPool>,
+    heatmap_descriptor_set: ash::vk::DescriptorSet,
+}
+
+impl OverdrawSystem {
+    pub fn new(
+        device: Rc<Device>,
+        scene_render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let render_pass = Self::create_render_pass(&device)?;
+
+        let accumulate_pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: std::mem::size_of::<SimplePushConstantData>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(global_set_layout)
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        // No depth attachment in the count pass, so every fragment gets counted
+        let accumulate_pipeline = Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/simple_shader.vert.spv"),
+            ShaderSource::Path("shaders/overdraw.frag.spv"),
+            &render_pass,
+            &accumulate_pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                blend_mode: BlendMode::Additive,
+                ..Default::default()
+            },
+        )?;
+
+        let heatmap_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let heatmap_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)
+            .build()?;
+
+        let heatmap_descriptor_set = heatmap_pool.allocate_descriptor(&[heatmap_set_layout.layout])?;
+
+        let heatmap_pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[heatmap_set_layout.layout]),
+                None,
+            )?
+        };
+
+        let heatmap_pipeline = Self::create_heatmap_pipeline(device.clone(), scene_render_pass, &heatmap_pipeline_layout, pipeline_cache)?;
+
+        let sampler = unsafe {
+            device.logical_device.create_sampler(
+                &ash::vk::SamplerCreateInfo::builder()
+                    .mag_filter(ash::vk::Filter::NEAREST)
+                    .min_filter(ash::vk::Filter::NEAREST)
+                    .address_mode_u(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_v(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_w(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                None,
+            )?
+        };
+
+        Ok(Self {
+            device,
+            render_pass,
+            count_target: None,
+            sampler,
+            accumulate_pipeline,
+            accumulate_pipeline_layout,
+            heatmap_pipeline,
+            heatmap_pipeline_layout,
+            heatmap_set_layout,
+            heatmap_pool,
+            heatmap_descriptor_set,
+        })
+    }
+
+    /// Rebuilds the heatmap pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.heatmap_pipeline = Self::create_heatmap_pipeline(self.device.clone(), render_pass, &self.heatmap_pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// Counts the fragments of every visible object, has to be recorded outside
+    /// of the scene render pass. `extent` is the scene extent.
+    pub fn accumulate(&mut self, frame_info: &FrameInfo, extent: ash::vk::Extent2D) -> anyhow::Result<(), GentooRenderError> {
+        if self.count_target.as_ref().map(|target| target.extent) != Some(extent) {
+            self.recreate_count_target(extent)?;
+        }
+
+        let target = self.count_target.as_ref().unwrap();
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let clear_values = [ash::vk::ClearValue {
+            color: ash::vk::ClearColorValue {
+                float32: [0.0; 4],
+            },
+        }];
+
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            crate::vulkan::Renderer::set_viewport_and_scissor(logical_device, command_buffer, extent);
+
+            self.accumulate_pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.accumulate_pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+
+            for obj in frame_info.game_objects.values().filter(|obj| obj.is_rendered_in(RENDER_LAYER_DEBUG) && obj.model.is_some() && obj.animation.is_none()) {
+                let model = obj.model.as_ref().unwrap();
+
+                let push = SimplePushConstantData {
+                    model_matrix: obj.transform.mat4(),
+                    normal_matrix: obj.transform.normal_matrix(),
+                };
+
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.accumulate_pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                model.bind(command_buffer);
+                model.draw(logical_device, command_buffer);
+            }
+
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        Ok(())
+    }
+
+    /// Draws the heatmap of the last `accumulate` inside the scene render pass.
+    pub fn render(&self, frame_info: &FrameInfo) {
+        if self.count_target.is_none() {
+            return;
+        }
+
+        unsafe {
+            self.heatmap_pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
+
+            self.device.logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.heatmap_pipeline_layout,
+                0,
+                &[self.heatmap_descriptor_set],
+                &[],
+            );
+
+            self.device.logical_device.cmd_draw(frame_info.command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    fn recreate_count_target(&mut self, extent: ash::vk::Extent2D) -> anyhow::Result<(), GentooRenderError> {
+        unsafe {
+            // The heatmap descriptor still points at the old target
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        self.count_target = None;
+
+        let (image, memory, image_view) = OffscreenTarget::create_attachment(
+            &self.device,
+            COUNT_FORMAT,
+            extent,
+            ash::vk::ImageUsageFlags::COLOR_ATTACHMENT | ash::vk::ImageUsageFlags::SAMPLED,
+            ash::vk::ImageAspectFlags::COLOR,
+        )?;
+
+        let framebuffer = unsafe {
+            self.device.logical_device.create_framebuffer(
+                &ash::vk::FramebufferCreateInfo::builder()
+                    .render_pass(self.render_pass)
+                    .attachments(&[image_view])
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1),
+                None,
+            )?
+        };
+
+        let image_info = ash::vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler)
+            .image_view(image_view)
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build();
+
+        DescriptorSetWriter::new(self.heatmap_set_layout.clone(), self.heatmap_pool.clone())
+            .write_image(0, &[image_info])
+            .overwrite(self.heatmap_descriptor_set);
+
+        self.count_target = Some(CountTarget {
+            device: self.device.clone(),
+            image,
+            memory,
+            image_view,
+            framebuffer,
+            extent,
+        });
+
+        Ok(())
+    }
+
+    fn create_render_pass(device: &Rc<Device>) -> anyhow::Result<ash::vk::RenderPass, GentooRenderError> {
+        Ok(unsafe {
+            device.logical_device.create_render_pass(
+                &ash::vk::RenderPassCreateInfo::builder()
+                    .attachments(&[ash::vk::AttachmentDescription {
+                        format: COUNT_FORMAT,
+                        samples: ash::vk::SampleCountFlags::TYPE_1,
+                        load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                        store_op: ash::vk::AttachmentStoreOp::STORE,
+                        stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+                        stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                        initial_layout: ash::vk::ImageLayout::UNDEFINED,
+                        final_layout: ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        ..Default::default()
+                    }])
+                    .subpasses(&[ash::vk::SubpassDescription::builder()
+                        .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS)
+                        .color_attachments(&[ash::vk::AttachmentReference {
+                            attachment: 0,
+                            layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        }])
+                        .build()])
+                    .dependencies(&[ash::vk::SubpassDependency {
+                        src_subpass: 0,
+                        dst_subpass: ash::vk::SUBPASS_EXTERNAL,
+                        src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                        dst_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                        src_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        dst_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                        ..Default::default()
+                    }]), None)?
+        })
+    }
+
+    fn create_heatmap_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path("shaders/overdraw_heatmap.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for OverdrawSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping overdraw system");
+
+        self.count_target = None;
+
+        unsafe {
+            self.device.logical_device.destroy_sampler(self.sampler, None);
+            self.device.logical_device.destroy_pipeline_layout(self.accumulate_pipeline_layout, None);
+            self.device.logical_device.destroy_pipeline_layout(self.heatmap_pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
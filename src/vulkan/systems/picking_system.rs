@@ -0,0 +1,286 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, OffscreenTarget, AttachmentLoadOps, Swapchain, Buffer, Vertex, Renderer, MAX_FRAMES_IN_FLIGHT, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}}, FrameInfo, GameObjectId, RENDER_LAYER_PICKING};
+
+const ID_FORMAT: ash::vk::Format = ash::vk::Format::R32_UINT;
+
+#[derive(Debug)]
+#[repr(C)]
+struct PickingPushConstantData {
+    model_matrix: glam::Mat4,
+    object_id: u32,
+}
+
+impl PickingPushConstantData {
+    unsafe fn as_bytes(&self) -> &[u8] {
+        std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
+    }
+}
+
+/// Pixel accurate object picking. Renders the ids of the objects into an
+/// integer target and reads back the pixel under the cursor, which is ready
+/// once the frame it was recorded in has finished.
+pub struct PickingSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    render_pass: ash::vk::RenderPass,
+    target: Option<OffscreenTarget>,
+    /// One id per frame in flight
+    readback_buffers: Vec<Buffer<u32>>,
+    pending: [bool; MAX_FRAMES_IN_FLIGHT],
+}
+
+impl PickingSystem {
+    pub fn new(
+        device: Rc<Device>,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: std::mem::size_of::<PickingPushConstantData>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(global_set_layout)
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        // Compatible with the render pass of the targets, which are only
+        // created once the scene extent is known
+        let render_pass = Swapchain::create_render_pass(&device, ID_FORMAT, ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL, AttachmentLoadOps::default())?;
+
+        let pipeline = Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/picking.vert.spv"),
+            ShaderSource::Path("shaders/picking.frag.spv"),
+            &render_pass,
+            &pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                // Integer attachments can't be blended
+                blend_mode: BlendMode::Opaque,
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                ..Default::default()
+            },
+        )?;
+
+        let mut readback_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let mut buffer = Buffer::new(
+                device.clone(),
+                1,
+                ash::vk::BufferUsageFlags::TRANSFER_DST,
+                ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+            )?;
+
+            buffer.map_persistent()?;
+
+            readback_buffers.push(buffer);
+        }
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+            render_pass,
+            target: None,
+            readback_buffers,
+            pending: [false; MAX_FRAMES_IN_FLIGHT],
+        })
+    }
+
+    /// Records the id pass and the readback of `pixel`, has to be recorded
+    /// outside of the scene render pass. `extent` is the scene extent.
+    pub fn pick(
+        &mut self,
+        frame_info: &FrameInfo,
+        extent: ash::vk::Extent2D,
+        pixel: (u32, u32),
+    ) -> anyhow::Result<(), GentooRenderError> {
+        if pixel.0 >= extent.width || pixel.1 >= extent.height {
+            return Ok(());
+        }
+
+        if self.target.as_ref().map(|target| target.extent) != Some(extent) {
+            unsafe {
+                // An earlier frame may still be reading back from the old target
+                self.device.logical_device.device_wait_idle()?;
+            }
+
+            self.target = None;
+            self.target = Some(OffscreenTarget::new(self.device.clone(), ID_FORMAT, extent, AttachmentLoadOps::default())?);
+        }
+
+        let target = self.target.as_ref().unwrap();
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let clear_values = [
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    uint32: [0; 4],
+                },
+            },
+            ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(target.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, extent);
+
+            self.pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+
+            for obj in frame_info.game_objects.values().filter(|obj| obj.is_rendered_in(RENDER_LAYER_PICKING) && obj.model.is_some() && obj.animation.is_none()) {
+                let model = obj.model.as_ref().unwrap();
+
+                let push = PickingPushConstantData {
+                    model_matrix: obj.transform.mat4(),
+                    object_id: obj.id + 1,
+                };
+
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                model.bind(command_buffer);
+                model.draw(logical_device, command_buffer);
+            }
+
+            logical_device.cmd_end_render_pass(command_buffer);
+
+            // The render pass left the target in TRANSFER_SRC_OPTIMAL
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(ash::vk::AccessFlags::TRANSFER_READ)
+                    .old_layout(ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .new_layout(ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .image(target.color_image)
+                    .subresource_range(ash::vk::ImageSubresourceRange {
+                        aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .build()],
+            );
+
+            logical_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                target.color_image,
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.readback_buffers[frame_info.frame_index].buffer,
+                &[ash::vk::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: ash::vk::ImageSubresourceLayers {
+                        aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: ash::vk::Offset3D { x: pixel.0 as i32, y: pixel.1 as i32, z: 0 },
+                    image_extent: ash::vk::Extent3D { width: 1, height: 1, depth: 1 },
+                }],
+            );
+
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::PipelineStageFlags::HOST,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[ash::vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(ash::vk::AccessFlags::HOST_READ)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(self.readback_buffers[frame_info.frame_index].buffer)
+                    .offset(0)
+                    .size(ash::vk::WHOLE_SIZE)
+                    .build()],
+                &[],
+            );
+        }
+
+        self.pending[frame_info.frame_index] = true;
+
+        Ok(())
+    }
+
+    /// The result of the pick recorded in the last use of `frame_index`, once
+    /// `begin_frame` waited for it. The inner None means no object was hit.
+    pub fn take_result(&mut self, frame_index: usize) -> anyhow::Result<Option<Option<GameObjectId>>, GentooRenderError> {
+        if !std::mem::take(&mut self.pending[frame_index]) {
+            return Ok(None);
+        }
+
+        let id = self.readback_buffers[frame_index].map(0)?.as_slice()[0];
+
+        Ok(Some(id.checked_sub(1)))
+    }
+}
+
+impl Drop for PickingSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping picking system");
+
+        self.target = None;
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
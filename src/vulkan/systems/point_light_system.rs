@@ -1,24 +1,27 @@
 use std::rc::Rc;
 
-use crate::{vulkan::{GentooRenderError, Device, pipeline::{Pipeline, PipelineCache}}, FrameInfo, GlobalUbo, MAX_LIGHTS};
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, VertexLayout, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}}, FrameInfo, GlobalUbo, GameObject, PointLightComponent, MAX_LIGHTS, RENDER_LAYER_MAIN};
+
+/// Fewer can't enclose an area
+pub const MIN_BILLBOARD_SEGMENTS: u32 = 3;
+pub const MAX_BILLBOARD_SEGMENTS: u32 = 64;
 
 #[derive(Debug)]
 #[repr(C)]
-struct PointLightPushConstants {
-    position: glam::Vec4,
-    color: glam::Vec4,
-    radius: f32,
+struct PointLightPushConstantData {
+    /// 0 draws the quad
+    segments: u32,
 }
 
-impl PointLightPushConstants {
-    pub unsafe fn as_bytes(&self) -> &[u8] {
-        let size_in_bytes = std::mem::size_of::<Self>();
-        let size_in_u8 = size_in_bytes / std::mem::size_of::<u8>();
-        let start_ptr = self as *const Self as *const u8;
-        std::slice::from_raw_parts(start_ptr, size_in_u8)
+impl PointLightPushConstantData {
+    unsafe fn as_bytes(&self) -> &[u8] {
+        std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
     }
 }
 
+/// Draws a billboard per point light. By default it is a quad cut into a disc
+/// with an antialiased edge by the fragment shader, or alternatively a fan of
+/// flat triangles generated in the vertex shader.
 pub struct PointLightSystem {
     device: Rc<Device>,
     pipeline: Pipeline,
@@ -43,27 +46,41 @@ impl PointLightSystem {
         })
     }
 
+    /// Rebuilds the pipeline from the shader files on disk. On failure the
+    /// previous pipeline is kept.
+    pub fn reload_pipelines(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
     fn create_pipeline(
         device: Rc<Device>,
         render_pass: &ash::vk::RenderPass,
         pipeline_layout: &ash::vk::PipelineLayout,
         pipeline_cache: &Rc<PipelineCache>,
     ) -> anyhow::Result<Pipeline, GentooRenderError> {
-        assert!(
+        debug_assert!(
             pipeline_layout != &ash::vk::PipelineLayout::null(),
             "Cannot create pipeline before pipeline layout"
         );
 
         Ok(Pipeline::new(
             device,
-            "shaders/point_light.vert.spv",
-            "shaders/point_light.frag.spv",
+            ShaderSource::Path("shaders/point_light.vert.spv"),
+            ShaderSource::Path("shaders/point_light.frag.spv"),
             render_pass,
             pipeline_layout,
             pipeline_cache,
-            &[],
-            &[],
-            ash::vk::CullModeFlags::BACK,
+            &VertexLayout::default(),
+            PipelineConfig {
+                blend_mode: BlendMode::PremultipliedAlpha,
+                ..Default::default()
+            },
         )?)
     }
 
@@ -71,10 +88,11 @@ impl PointLightSystem {
         logical_device: &ash::Device,
         global_set_layout: &[ash::vk::DescriptorSetLayout],
     ) -> anyhow::Result<ash::vk::PipelineLayout, GentooRenderError> {
+        // Everything the billboards need is in the global ubo, besides the shape
         let push_constant_range = [ash::vk::PushConstantRange {
             stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
             offset: 0,
-            size: std::mem::size_of::<PointLightPushConstants>() as u32,
+            size: std::mem::size_of::<PointLightPushConstantData>() as u32,
         }];
 
         let pipeline_layout_info = ash::vk::PipelineLayoutCreateInfo::builder()
@@ -86,32 +104,33 @@ impl PointLightSystem {
         })
     }
 
+    /// Fills the ubo light array, `render` draws one billboard per entry.
     pub fn update(&self, frame_info: &FrameInfo, ubo: &mut GlobalUbo) {
         let mut light_index = 0;
 
-        for kv in frame_info.game_objects {
-            let obj = kv.1;
+        // Matches the billboards `render` draws
+        for (obj, point_light) in Self::visible_lights(frame_info).take(MAX_LIGHTS) {
+            let translation = obj.transform.translation();
 
-            assert!(
-                light_index < MAX_LIGHTS,
-                "Point lights exceed maximum specified",
-            );
-
-            match &obj.point_light {
-                Some(point_light) => {
-                    ubo.point_lights[light_index].position = glam::vec4(obj.transform.translation.x, obj.transform.translation.y, obj.transform.translation.z, 1.0);
-                    ubo.point_lights[light_index].color = glam::vec4(obj.color.x, obj.color.y, obj.color.z, point_light.light_intensity);
+            ubo.point_lights[light_index].position = glam::vec4(translation.x, translation.y, translation.z, obj.transform.scale().x);
+            ubo.point_lights[light_index].color = glam::vec4(obj.color.x, obj.color.y, obj.color.z, point_light.light_intensity);
 
-                    light_index += 1;
-                },
-                None => { },
-            }
+            light_index += 1;
         }
 
         ubo.num_lights = light_index as u32;
     }
 
-    pub fn render(&self, frame_info: &FrameInfo) {
+    /// `segments` draws every billboard as a fan of that many triangles with a
+    /// hard outline instead of the quad, clamped to `MIN_BILLBOARD_SEGMENTS`
+    /// and `MAX_BILLBOARD_SEGMENTS`.
+    pub fn render(&self, frame_info: &FrameInfo, segments: Option<u32>) {
+        let light_count = Self::visible_lights(frame_info).count().min(MAX_LIGHTS) as u32;
+
+        if light_count == 0 {
+            return;
+        }
+
         unsafe {
             self.pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
 
@@ -124,40 +143,38 @@ impl PointLightSystem {
                 &[],
             );
 
-            for kv in frame_info.game_objects {
-                let obj = kv.1;
-
-                match &obj.point_light {
-                    Some(point_light) => {
-                        let push = PointLightPushConstants {
-                            position: glam::vec4(obj.transform.translation.x, obj.transform.translation.y, obj.transform.translation.z, 1.0),
-                            color: glam::vec4(obj.color.x, obj.color.y, obj.color.z, point_light.light_intensity),
-                            radius: obj.transform.scale.x,
-                        };
-
-                        let push_ptr = push.as_bytes();
-
-                        self.device.logical_device.cmd_push_constants(
-                            frame_info.command_buffer,
-                            self.pipeline_layout,
-                            ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
-                            0,
-                            push_ptr,
-                        );
-
-                        self.device.logical_device.cmd_draw(
-                            frame_info.command_buffer,
-                            6,
-                            1,
-                            0,
-                            0,
-                        )
-                    },
-                    None => { },
-                }
-            }
+            let push = PointLightPushConstantData {
+                segments: segments.map_or(0, |segments| segments.clamp(MIN_BILLBOARD_SEGMENTS, MAX_BILLBOARD_SEGMENTS)),
+            };
+
+            self.device.logical_device.cmd_push_constants(
+                frame_info.command_buffer,
+                self.pipeline_layout,
+                ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push.as_bytes(),
+            );
+
+            // A quad or a triangle list fan per light, the vertex shader
+            // reads the light at gl_InstanceIndex
+            let vertex_count = if push.segments == 0 { 6 } else { 3 * push.segments };
+
+            self.device.logical_device.cmd_draw(
+                frame_info.command_buffer,
+                vertex_count,
+                light_count,
+                0,
+                0,
+            );
         }
     }
+
+    fn visible_lights<'a>(frame_info: &FrameInfo<'a>) -> impl Iterator<Item = (&'a GameObject, &'a PointLightComponent)> {
+        frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_MAIN))
+            .filter_map(|obj| obj.point_light.as_ref().map(|point_light| (obj, point_light)))
+    }
 }
 
 impl Drop for PointLightSystem {
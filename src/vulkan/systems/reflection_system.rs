@@ -0,0 +1,390 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, OffscreenTarget, AttachmentLoadOps, Buffer, Sampler, Texture, Vertex, Renderer, CLEAR_COLOR, MAX_FRAMES_IN_FLIGHT, pipeline::{Pipeline, PipelineCache, PipelineConfig}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo, GameObject, GlobalUbo, RENDER_LAYER_MAIN, RENDER_LAYER_REFLECTION};
+
+use super::{SimplePushConstantData, SimpleRenderSystem};
+
+/// Mirrors points across the plane through `point` with `normal`.
+fn reflection_matrix(point: glam::Vec3, normal: glam::Vec3) -> glam::Mat4 {
+    let normal = normal.normalize();
+    let distance = normal.dot(point);
+
+    glam::Mat4::from_cols(
+        (glam::Vec3::X - 2.0 * normal.x * normal).extend(0.0),
+        (glam::Vec3::Y - 2.0 * normal.y * normal).extend(0.0),
+        (glam::Vec3::Z - 2.0 * normal.z * normal).extend(0.0),
+        (2.0 * distance * normal).extend(1.0),
+    )
+}
+
+/// Renders the scene mirrored across the plane of the first reflective
+/// object, see `MaterialComponent::reflectivity`, into its own target. The
+/// simple shader blends it onto that object at the fragment's screen position,
+/// so the object has to be flat in its local xz plane, like the floor quad.
+/// Only static objects in `RENDER_LAYER_REFLECTION` are reflected, without
+/// tessellation, and objects behind the plane aren't clipped away.
+pub struct ReflectionSystem {
+    device: Rc<Device>,
+    /// Fraction of the scene resolution the reflection is rendered at
+    resolution_scale: f32,
+    target: Option<OffscreenTarget>,
+    sampler: Rc<Sampler>,
+    /// Back face culled and double sided, created for the target's render pass
+    pipelines: Option<(Pipeline, Pipeline)>,
+    pipeline_layout: ash::vk::PipelineLayout,
+    /// The global UBO with the mirrored view, one per frame in flight
+    ubo_buffers: Vec<Buffer<GlobalUbo>>,
+    _pool: Rc<DescriptorPool>,
+    descriptor_sets: Vec<ash::vk::DescriptorSet>,
+    /// See `SimpleRenderSystem::fragment_shader`
+    log_depth: bool,
+}
+
+impl ReflectionSystem {
+    /// The reflected objects are lit with `irradiance_map`, `placeholder` is
+    /// bound in place of the occlusion and reflection maps.
+    pub fn new(
+        device: Rc<Device>,
+        global_set_layout: &Rc<DescriptorSetLayout>,
+        irradiance_map: &Texture,
+        placeholder: &Texture,
+        resolution_scale: f32,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let pool = DescriptorPool::new(device.clone())
+            .set_max_sets(MAX_FRAMES_IN_FLIGHT as u32)
+            .add_pool_size(ash::vk::DescriptorType::UNIFORM_BUFFER, MAX_FRAMES_IN_FLIGHT as u32)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 3 * MAX_FRAMES_IN_FLIGHT as u32)
+            .build()?;
+
+        let mut ubo_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        let mut descriptor_sets = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let mut buffer = Buffer::new(
+                device.clone(),
+                1,
+                ash::vk::BufferUsageFlags::UNIFORM_BUFFER,
+                ash::vk::MemoryPropertyFlags::HOST_VISIBLE,
+            )?;
+
+            buffer.map_persistent()?;
+
+            let descriptor_set = DescriptorSetWriter::new(global_set_layout.clone(), pool.clone())
+                .write_to_buffer(0, &[buffer.descriptor_info()])
+                .write_image(1, &[irradiance_map.descriptor_info()])
+                .write_image(2, &[placeholder.descriptor_info()])
+                .write_image(3, &[placeholder.descriptor_info()])
+                .build()
+                .unwrap();
+
+            ubo_buffers.push(buffer);
+            descriptor_sets.push(descriptor_set);
+        }
+
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::LINEAR)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<SimplePushConstantData>() as u32,
+        }];
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[global_set_layout.layout])
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        Ok(Self {
+            device,
+            resolution_scale,
+            target: None,
+            sampler,
+            pipelines: None,
+            pipeline_layout,
+            ubo_buffers,
+            _pool: pool,
+            descriptor_sets,
+            log_depth: false,
+        })
+    }
+
+    /// Like `SimpleRenderSystem::set_log_depth`, the pipelines are created
+    /// with the target if there is none yet.
+    pub fn set_log_depth(&mut self, log_depth: bool, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        let previous = std::mem::replace(&mut self.log_depth, log_depth);
+
+        let result = self.reload_pipelines(pipeline_cache);
+        if result.is_err() {
+            self.log_depth = previous;
+        }
+
+        result
+    }
+
+    /// Follows the scene's extent and format, returns whether the target was
+    /// recreated. The `reflection_map` descriptor has to be rewritten in that case.
+    pub fn resize(
+        &mut self,
+        scene_extent: ash::vk::Extent2D,
+        color_format: ash::vk::Format,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<bool, GentooRenderError> {
+        let extent = ash::vk::Extent2D {
+            width: ((scene_extent.width as f32 * self.resolution_scale) as u32).max(1),
+            height: ((scene_extent.height as f32 * self.resolution_scale) as u32).max(1),
+        };
+
+        if let Some(target) = &self.target {
+            if target.extent == extent && target.color_format == color_format {
+                return Ok(false);
+            }
+        }
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        let format_changed = self.target.as_ref().map(|target| target.color_format) != Some(color_format);
+
+        self.target = None;
+        let target = OffscreenTarget::new(self.device.clone(), color_format, extent, AttachmentLoadOps::default())?;
+
+        if format_changed {
+            self.pipelines = Some(self.create_pipelines(&target.render_pass, pipeline_cache)?);
+        }
+
+        self.target = Some(target);
+
+        Ok(true)
+    }
+
+    /// Applied by the next `resize`.
+    pub fn set_resolution_scale(&mut self, resolution_scale: f32) {
+        self.resolution_scale = resolution_scale;
+    }
+
+    /// The mirrored scene, in the layout the simple shader reads it. None
+    /// before the first `resize`.
+    pub fn reflection_map(&self) -> Option<ash::vk::DescriptorImageInfo> {
+        self.target.as_ref().map(|target| ash::vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler.sampler)
+            .image_view(target.color_image_view)
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build())
+    }
+
+    /// Rebuilds the pipelines from the shader files on disk, the old ones are
+    /// kept if it fails.
+    pub fn reload_pipelines(&mut self, pipeline_cache: &Rc<PipelineCache>) -> anyhow::Result<(), GentooRenderError> {
+        if let Some(target) = &self.target {
+            self.pipelines = Some(self.create_pipelines(&target.render_pass, pipeline_cache)?);
+        }
+
+        Ok(())
+    }
+
+    /// Records the mirrored scene outside of any render pass, `ubo` is the
+    /// frame's global UBO. Has to run every frame the `reflection_map` is
+    /// bound, it leaves the target in the layout the scene pass reads it in.
+    /// Only clears it without a reflective object.
+    pub fn render(&mut self, frame_info: &FrameInfo, ubo: &GlobalUbo) -> anyhow::Result<(), GentooRenderError> {
+        let (target, (pipeline, double_sided_pipeline)) = match (&self.target, &self.pipelines) {
+            (Some(target), Some(pipelines)) => (target, pipelines),
+            _ => return Ok(()),
+        };
+
+        let mirror = frame_info.game_objects
+            .values()
+            .find(|obj| Self::is_reflective(obj))
+            .map(|obj| obj.transform.mat4());
+
+        if let Some(mirror) = mirror {
+            let normal = mirror.x_axis.truncate().cross(mirror.z_axis.truncate());
+
+            // Nothing reads the occlusion of the main view here
+            self.ubo_buffers[frame_info.frame_index].map(0)?.write_slice(&[GlobalUbo {
+                view: ubo.view * reflection_matrix(mirror.w_axis.truncate(), normal),
+                resolution: glam::vec2(target.extent.width as f32, target.extent.height as f32),
+                ambient_occlusion: 0.0,
+                reflections: 0.0,
+                ..*ubo
+            }]);
+            self.ubo_buffers[frame_info.frame_index].flush()?;
+        }
+
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let clear_values = [
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: CLEAR_COLOR,
+                },
+            },
+            ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+
+        let color_range = ash::vk::ImageSubresourceRange {
+            aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        unsafe {
+            // The last frame's scene pass may still be reading the target
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[],
+            );
+
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(target.render_pass)
+                    .framebuffer(target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent: target.extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, target.extent);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[frame_info.frame_index]],
+                &[],
+            );
+        }
+
+        for (pipeline, double_sided) in [(pipeline, false), (double_sided_pipeline, true)].into_iter().filter(|_| mirror.is_some()) {
+            let mut objects = frame_info.game_objects
+                .values()
+                .filter(|obj| {
+                    obj.is_rendered_in(RENDER_LAYER_REFLECTION)
+                        && obj.model.is_some()
+                        && obj.animation.is_none()
+                        && !Self::is_reflective(obj)
+                        && obj.material.double_sided == double_sided
+                })
+                .peekable();
+
+            if objects.peek().is_none() {
+                continue;
+            }
+
+            unsafe {
+                pipeline.bind(logical_device, command_buffer);
+            }
+
+            for obj in objects {
+                let model = obj.model.as_ref().unwrap();
+                let push = SimplePushConstantData::for_object(obj, false);
+
+                unsafe {
+                    logical_device.cmd_push_constants(
+                        command_buffer,
+                        self.pipeline_layout,
+                        ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        push.as_bytes(),
+                    );
+
+                    model.bind(command_buffer);
+                    model.draw(logical_device, command_buffer);
+                }
+            }
+        }
+
+        unsafe {
+            logical_device.cmd_end_render_pass(command_buffer);
+
+            // The pass leaves the color ready to be blitted
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .image(target.color_image)
+                    .old_layout(ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .new_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .src_access_mask(ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(ash::vk::AccessFlags::SHADER_READ)
+                    .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(color_range)
+                    .build()],
+            );
+        }
+
+        Ok(())
+    }
+
+    fn is_reflective(obj: &GameObject) -> bool {
+        obj.material.reflectivity > 0.0 && obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some()
+    }
+
+    /// The mirror flips the winding, so counter clockwise triangles face the camera.
+    fn create_pipelines(
+        &self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(Pipeline, Pipeline), GentooRenderError> {
+        let create_pipeline = |cull_mode| Pipeline::new(
+            self.device.clone(),
+            ShaderSource::Path("shaders/simple_shader.vert.spv"),
+            SimpleRenderSystem::fragment_shader(self.log_depth),
+            render_pass,
+            &self.pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                cull_mode,
+                front_face: ash::vk::FrontFace::COUNTER_CLOCKWISE,
+                ..Default::default()
+            },
+        );
+
+        Ok((
+            create_pipeline(ash::vk::CullModeFlags::BACK)?,
+            create_pipeline(ash::vk::CullModeFlags::NONE)?,
+        ))
+    }
+}
+
+impl Drop for ReflectionSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping reflection system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
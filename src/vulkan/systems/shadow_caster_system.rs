@@ -0,0 +1,250 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, DepthTarget, Sampler, Vertex, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo, RENDER_LAYER_SHADOW};
+
+/// Planes of `point_light_view_projection`
+pub const SHADOW_NEAR: f32 = 0.1;
+pub const SHADOW_FAR: f32 = 100.0;
+
+#[repr(C)]
+struct ShadowPushConstantData {
+    light_space_matrix: glam::Mat4,
+}
+
+/// Renders the depth of the shadow casters from a light's point of view into
+/// a square depth target, the shadow map. The debug view shows the map in
+/// the corner of the scene pass.
+pub struct ShadowCasterSystem {
+    device: Rc<Device>,
+    target: DepthTarget,
+    _sampler: Rc<Sampler>,
+    depth_pipeline: Pipeline,
+    depth_pipeline_layout: ash::vk::PipelineLayout,
+    debug_pipeline: Pipeline,
+    debug_pipeline_layout: ash::vk::PipelineLayout,
+    _debug_set_layout: Rc<DescriptorSetLayout>,
+    _debug_pool: Rc<DescriptorPool>,
+    debug_descriptor_set: ash::vk::DescriptorSet,
+}
+
+impl ShadowCasterSystem {
+    pub fn new(
+        device: Rc<Device>,
+        scene_render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+        size: u32,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let target = DepthTarget::new(device.clone(), ash::vk::Extent2D { width: size, height: size })?;
+
+        let depth_pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<ShadowPushConstantData>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        // Front faces are culled, the back faces are further from the light
+        // and keep lit surfaces from shadowing themselves
+        let depth_pipeline = Pipeline::new_depth_only(
+            device.clone(),
+            ShaderSource::Path("shaders/depth_only.vert.spv"),
+            &target.render_pass,
+            &depth_pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::FRONT,
+                ..Default::default()
+            },
+        )?;
+
+        let debug_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let debug_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(1)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)
+            .build()?;
+
+        let debug_descriptor_set = debug_pool.allocate_descriptor(&[debug_set_layout.layout])?;
+
+        let debug_pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[debug_set_layout.layout]),
+                None,
+            )?
+        };
+
+        let debug_pipeline = Self::create_debug_pipeline(device.clone(), scene_render_pass, &debug_pipeline_layout, pipeline_cache)?;
+
+        let sampler = Sampler::new(device.clone())
+            .filter(ash::vk::Filter::NEAREST)
+            .address_mode(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .build()?;
+
+        let image_info = ash::vk::DescriptorImageInfo::builder()
+            .sampler(sampler.sampler)
+            .image_view(target.image_view)
+            .image_layout(ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+            .build();
+
+        DescriptorSetWriter::new(debug_set_layout.clone(), debug_pool.clone())
+            .write_image(0, &[image_info])
+            .overwrite(debug_descriptor_set);
+
+        Ok(Self {
+            device,
+            target,
+            _sampler: sampler,
+            depth_pipeline,
+            depth_pipeline_layout,
+            debug_pipeline,
+            debug_pipeline_layout,
+            _debug_set_layout: debug_set_layout,
+            _debug_pool: debug_pool,
+            debug_descriptor_set,
+        })
+    }
+
+    /// Rebuilds the debug pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.debug_pipeline = Self::create_debug_pipeline(self.device.clone(), render_pass, &self.debug_pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// In `DEPTH_STENCIL_READ_ONLY_OPTIMAL` layout after `render`
+    pub fn shadow_map_view(&self) -> ash::vk::ImageView {
+        self.target.image_view
+    }
+
+    /// A 90 degree perspective from `position` towards the origin of the scene.
+    pub fn point_light_view_projection(position: glam::Vec3) -> glam::Mat4 {
+        let up = if position.x.abs() + position.z.abs() < f32::EPSILON { glam::Vec3::Z } else { glam::Vec3::Y };
+
+        glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, SHADOW_NEAR, SHADOW_FAR)
+            * glam::Mat4::look_at_rh(position, glam::Vec3::ZERO, up)
+    }
+
+    /// Writes the depth of every shadow caster into the shadow map, has to be
+    /// recorded outside of the scene render pass.
+    pub fn render(&self, frame_info: &FrameInfo, light_view_projection: glam::Mat4) {
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let clear_values = [ash::vk::ClearValue {
+            depth_stencil: ash::vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.target.render_pass)
+                    .framebuffer(self.target.framebuffer)
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent: self.target.extent,
+                    })
+                    .clear_values(&clear_values),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, self.target.extent);
+
+            self.depth_pipeline.bind(logical_device, command_buffer);
+
+            for obj in frame_info.game_objects.values().filter(|obj| obj.is_rendered_in(RENDER_LAYER_SHADOW) && obj.model.is_some() && obj.animation.is_none()) {
+                let model = obj.model.as_ref().unwrap();
+
+                let push = ShadowPushConstantData {
+                    light_space_matrix: light_view_projection * obj.transform.mat4(),
+                };
+
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.depth_pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX,
+                    0,
+                    std::slice::from_raw_parts(&push as *const _ as *const u8, std::mem::size_of::<ShadowPushConstantData>()),
+                );
+
+                model.bind(command_buffer);
+                model.draw(logical_device, command_buffer);
+            }
+
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    /// Draws the last shadow map in the top left corner, inside the scene render pass.
+    pub fn render_debug(&self, frame_info: &FrameInfo) {
+        unsafe {
+            self.debug_pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
+
+            self.device.logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.debug_pipeline_layout,
+                0,
+                &[self.debug_descriptor_set],
+                &[],
+            );
+
+            self.device.logical_device.cmd_draw(frame_info.command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    fn create_debug_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path("shaders/shadow_depth_debug.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for ShadowCasterSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping shadow caster system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.depth_pipeline_layout, None);
+            self.device.logical_device.destroy_pipeline_layout(self.debug_pipeline_layout, None);
+        }
+    }
+}
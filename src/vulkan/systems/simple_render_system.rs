@@ -1,15 +1,33 @@
 use std::rc::Rc;
 
-use crate::{vulkan::{GentooRenderError, Device, pipeline::{Pipeline, PipelineCache}, Vertex}, FrameInfo};
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode, TessellationShaders}, Vertex, ModelBuffers, SecondaryRecordingInfo, RecordingWorkers, RecordingJob, Renderer, DepthRange, FULL_DEPTH_RANGE}, FrameInfo, GameObject, DepthTest, RENDER_LAYER_MAIN};
 
 #[derive(Debug)]
 #[repr(C)]
 pub struct SimplePushConstantData {
-    model_matrix: glam::Mat4,
-    normal_matrix: glam::Mat4,
+    pub model_matrix: glam::Mat4,
+    pub normal_matrix: glam::Mat4,
 }
 
 impl SimplePushConstantData {
+    /// The push for `obj` in the simple shaders. Only the upper 3x3 of the
+    /// normal matrix transforms normals, so its fourth column's x flags
+    /// unlit materials, y back-face tinting, z is the reflectivity and w the
+    /// opacity for the fragment shader. The fourth row is left for the
+    /// `OitSystem`.
+    pub fn for_object(obj: &GameObject, backface_debug: bool) -> Self {
+        let mut normal_matrix = obj.transform.normal_matrix();
+        normal_matrix.w_axis.x = if obj.material.unlit { 1.0 } else { 0.0 };
+        normal_matrix.w_axis.y = if backface_debug { 1.0 } else { 0.0 };
+        normal_matrix.w_axis.z = obj.material.reflectivity;
+        normal_matrix.w_axis.w = obj.material.opacity;
+
+        Self {
+            model_matrix: obj.transform.mat4(),
+            normal_matrix,
+        }
+    }
+
     pub unsafe fn as_bytes(&self) -> &[u8] {
         let size_in_bytes = std::mem::size_of::<Self>();
         let size_in_u8 = size_in_bytes / std::mem::size_of::<u8>();
@@ -18,10 +36,38 @@ impl SimplePushConstantData {
     }
 }
 
+/// A draw with everything resolved to plain handles, so it can be recorded on another thread.
+struct DrawCommand {
+    pipeline: ash::vk::Pipeline,
+    push: SimplePushConstantData,
+    depth_range: DepthRange,
+    buffers: ModelBuffers,
+}
+
+/// Back face culled and double sided variants of a pipeline.
+struct DepthTestPipelines {
+    culled: Pipeline,
+    double_sided: Pipeline,
+}
+
+impl DepthTestPipelines {
+    fn get(&self, double_sided: bool) -> &Pipeline {
+        if double_sided { &self.double_sided } else { &self.culled }
+    }
+}
+
 pub struct SimpleRenderSystem {
     device: Rc<Device>,
-    pipeline: Pipeline,
+    /// Indexed by `DepthTest`
+    pipelines: Vec<DepthTestPipelines>,
+    /// Blend without writing depth, for `TransparencyMode::Sorted`
+    transparent_pipelines: DepthTestPipelines,
+    /// Indexed by `DepthTest`, None if the device doesn't support tessellation
+    tessellated_pipelines: Option<Vec<DepthTestPipelines>>,
     pipeline_layout: ash::vk::PipelineLayout,
+    recording_workers: RecordingWorkers,
+    /// The pipelines write the logarithmic depth per fragment
+    log_depth: bool,
 }
 
 impl SimpleRenderSystem {
@@ -30,40 +76,221 @@ impl SimpleRenderSystem {
         render_pass: &ash::vk::RenderPass,
         global_set_layout: &[ash::vk::DescriptorSetLayout],
         pipeline_cache: &Rc<PipelineCache>,
+        recording_threads: usize,
     ) -> anyhow::Result<Self, GentooRenderError> {
         let pipeline_layout = Self::create_pipeline_layout(&device.logical_device, global_set_layout)?;
 
-        let pipeline = Self::create_pipeline(device.clone(), render_pass, &pipeline_layout, pipeline_cache)?;
+        let pipelines = Self::create_pipelines(device.clone(), render_pass, &pipeline_layout, pipeline_cache, false)?;
+        let transparent_pipelines = Self::create_transparent_pipelines(device.clone(), render_pass, &pipeline_layout, pipeline_cache, false)?;
+
+        let tessellated_pipelines = match Self::create_tessellated_pipelines(
+            device.clone(),
+            render_pass,
+            &pipeline_layout,
+            pipeline_cache,
+            false,
+        ) {
+            Ok(pipelines) => Some(pipelines),
+            Err(GentooRenderError::TessellationNotSupported) => {
+                log::warn!("Tessellation is not supported by the device, tessellated objects are drawn as is");
+                None
+            }
+            Err(error) => return Err(error),
+        };
+
+        let recording_workers = RecordingWorkers::new(
+            device.clone(),
+            recording_threads,
+            ash::vk::CommandBufferLevel::SECONDARY,
+        )?;
 
         Ok(Self {
             device,
-            pipeline,
+            pipelines,
+            transparent_pipelines,
+            tessellated_pipelines,
             pipeline_layout,
+            recording_workers,
+            log_depth: false,
         })
     }
 
+    /// `simple_shader.frag`, writing the logarithmic depth per fragment with
+    /// `log_depth`. Only then it loses early depth testing.
+    pub fn fragment_shader(log_depth: bool) -> ShaderSource<'static> {
+        if log_depth {
+            ShaderSource::Path("shaders/simple_shader_log_depth.frag.spv")
+        } else {
+            ShaderSource::Path("shaders/simple_shader.frag.spv")
+        }
+    }
+
+    pub fn log_depth(&self) -> bool {
+        self.log_depth
+    }
+
+    /// Switches to the pipelines for a logarithmic depth buffer or back,
+    /// keeping the previous ones on failure.
+    pub fn set_log_depth(
+        &mut self,
+        log_depth: bool,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let previous = std::mem::replace(&mut self.log_depth, log_depth);
+
+        let result = self.reload_pipelines(render_pass, pipeline_cache);
+        if result.is_err() {
+            self.log_depth = previous;
+        }
+
+        result
+    }
+
+    /// Rebuilds the pipelines from the shader files on disk. On failure the
+    /// previous pipelines are kept, so the scene keeps rendering.
+    pub fn reload_pipelines(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let pipelines = Self::create_pipelines(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache, self.log_depth)?;
+        let transparent_pipelines = Self::create_transparent_pipelines(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache, self.log_depth)?;
+
+        let tessellated_pipelines = match self.tessellated_pipelines {
+            Some(_) => Some(Self::create_tessellated_pipelines(
+                self.device.clone(),
+                render_pass,
+                &self.pipeline_layout,
+                pipeline_cache,
+                self.log_depth,
+            )?),
+            None => None,
+        };
+
+        self.pipelines = pipelines;
+        self.transparent_pipelines = transparent_pipelines;
+        self.tessellated_pipelines = tessellated_pipelines;
+
+        Ok(())
+    }
+
+    fn create_pipelines(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        log_depth: bool,
+    ) -> anyhow::Result<Vec<DepthTestPipelines>, GentooRenderError> {
+        DepthTest::ALL
+            .iter()
+            .map(|depth_test| Ok(DepthTestPipelines {
+                culled: Self::create_pipeline(device.clone(), render_pass, pipeline_layout, pipeline_cache, Self::pipeline_config(ash::vk::CullModeFlags::BACK, depth_test.compare_op(), false), log_depth)?,
+                double_sided: Self::create_pipeline(device.clone(), render_pass, pipeline_layout, pipeline_cache, Self::pipeline_config(ash::vk::CullModeFlags::NONE, depth_test.compare_op(), false), log_depth)?,
+            }))
+            .collect()
+    }
+
+    fn create_transparent_pipelines(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        log_depth: bool,
+    ) -> anyhow::Result<DepthTestPipelines, GentooRenderError> {
+        let compare_op = DepthTest::Default.compare_op();
+
+        Ok(DepthTestPipelines {
+            culled: Self::create_pipeline(device.clone(), render_pass, pipeline_layout, pipeline_cache, Self::pipeline_config(ash::vk::CullModeFlags::BACK, compare_op, true), log_depth)?,
+            double_sided: Self::create_pipeline(device, render_pass, pipeline_layout, pipeline_cache, Self::pipeline_config(ash::vk::CullModeFlags::NONE, compare_op, true), log_depth)?,
+        })
+    }
+
+    fn pipeline(&self, depth_test: DepthTest, double_sided: bool) -> &Pipeline {
+        self.pipelines[depth_test as usize].get(double_sided)
+    }
+
+    fn tessellated_pipeline(&self, depth_test: DepthTest, double_sided: bool) -> Option<&Pipeline> {
+        self.tessellated_pipelines
+            .as_ref()
+            .map(|pipelines| pipelines[depth_test as usize].get(double_sided))
+    }
+
     fn create_pipeline(
         device: Rc<Device>,
         render_pass: &ash::vk::RenderPass,
         pipeline_layout: &ash::vk::PipelineLayout,
         pipeline_cache: &Rc<PipelineCache>,
+        config: PipelineConfig,
+        log_depth: bool,
     ) -> anyhow::Result<Pipeline, GentooRenderError> {
-        assert!(
+        debug_assert!(
             pipeline_layout != &ash::vk::PipelineLayout::null(),
             "Cannot create pipeline before pipeline layout"
         );
 
-        Ok(Pipeline::new(
+        Pipeline::new(
             device,
-            "shaders/simple_shader.vert.spv",
-            "shaders/simple_shader.frag.spv",
+            ShaderSource::Path("shaders/simple_shader.vert.spv"),
+            Self::fragment_shader(log_depth),
             render_pass,
             pipeline_layout,
             pipeline_cache,
-            &Vertex::get_binding_descriptions(),
-            &Vertex::get_attribute_descriptions(),
-            ash::vk::CullModeFlags::BACK,
-        )?)
+            &Vertex::layout(),
+            config,
+        )
+    }
+
+    fn pipeline_config(cull_mode: ash::vk::CullModeFlags, depth_compare_op: ash::vk::CompareOp, transparent: bool) -> PipelineConfig {
+        PipelineConfig {
+            cull_mode,
+            depth_compare_op,
+            // Transparent objects keep the scene's alpha at 1
+            blend_mode: if transparent { BlendMode::PremultipliedAlpha } else { BlendMode::Opaque },
+            depth_write: !transparent,
+            ..Default::default()
+        }
+    }
+
+    fn create_tessellated_pipelines(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        log_depth: bool,
+    ) -> anyhow::Result<Vec<DepthTestPipelines>, GentooRenderError> {
+        DepthTest::ALL
+            .iter()
+            .map(|depth_test| Ok(DepthTestPipelines {
+                culled: Self::create_tessellated_pipeline(device.clone(), render_pass, pipeline_layout, pipeline_cache, Self::pipeline_config(ash::vk::CullModeFlags::BACK, depth_test.compare_op(), false), log_depth)?,
+                double_sided: Self::create_tessellated_pipeline(device.clone(), render_pass, pipeline_layout, pipeline_cache, Self::pipeline_config(ash::vk::CullModeFlags::NONE, depth_test.compare_op(), false), log_depth)?,
+            }))
+            .collect()
+    }
+
+    fn create_tessellated_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        config: PipelineConfig,
+        log_depth: bool,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Pipeline::new_tessellated(
+            device,
+            ShaderSource::Path("shaders/pn_triangles.vert.spv"),
+            TessellationShaders {
+                control: ShaderSource::Path("shaders/pn_triangles.tesc.spv"),
+                evaluation: ShaderSource::Path("shaders/pn_triangles.tese.spv"),
+                patch_control_points: 3,
+            },
+            Self::fragment_shader(log_depth),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            config,
+        )
     }
 
     fn create_pipeline_layout(
@@ -85,10 +312,12 @@ impl SimpleRenderSystem {
         })
     }
 
-    pub fn render(&self, frame_info: &FrameInfo) {
+    /// `backface_debug` draws everything unculled and tints back-facing
+    /// fragments red, showing inverted winding. Tessellation is skipped meanwhile.
+    /// `sorted_transparency` draws the transparent objects back to front after
+    /// the opaque ones, otherwise they are left to the `OitSystem`.
+    pub fn render(&self, frame_info: &FrameInfo, backface_debug: bool, sorted_transparency: bool) {
         unsafe {
-            self.pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
-
             self.device.logical_device.cmd_bind_descriptor_sets(
                 frame_info.command_buffer,
                 ash::vk::PipelineBindPoint::GRAPHICS,
@@ -99,32 +328,302 @@ impl SimpleRenderSystem {
             );
         }
 
-        for kv in frame_info.game_objects.iter() {
-            let obj = kv.1;
-
-            match &obj.model {
-                Some(model) => {
-                    let push = SimplePushConstantData {
-                        model_matrix: obj.transform.mat4(),
-                        normal_matrix: obj.transform.normal_matrix(),
-                    };
-
-                    unsafe {
-                        let push_ptr = push.as_bytes();
-
-                        self.device.logical_device.cmd_push_constants(
-                            frame_info.command_buffer,
-                            self.pipeline_layout,
-                            ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
-                            0,
-                            push_ptr,
-                        );
-
-                        model.bind(frame_info.command_buffer);
-                        model.draw(&self.device.logical_device, frame_info.command_buffer);
-                    }
-                },
-                None => { },
+        // Group objects by pipeline so each one is only bound once per frame
+        for depth_test in DepthTest::ALL {
+            if backface_debug {
+                self.render_objects(frame_info, self.pipeline(depth_test, true), true, |obj| Self::is_opaque_with(obj, depth_test));
+                continue;
+            }
+
+            for double_sided in [false, true] {
+                self.render_objects(frame_info, self.pipeline(depth_test, double_sided), false, |obj| self.uses_pipeline(obj, depth_test, double_sided));
+
+                if let Some(pipeline) = self.tessellated_pipeline(depth_test, double_sided) {
+                    self.render_objects(frame_info, pipeline, false, |obj| Self::uses_tessellated_pipeline(obj, depth_test, double_sided));
+                }
+            }
+        }
+
+        if sorted_transparency {
+            self.render_transparent(frame_info, backface_debug);
+        }
+    }
+
+    fn is_tessellated(&self, obj: &GameObject) -> bool {
+        obj.material.tessellated && self.tessellated_pipelines.is_some()
+    }
+
+    fn is_opaque_with(obj: &GameObject, depth_test: DepthTest) -> bool {
+        !obj.material.is_transparent() && obj.material.depth_test == depth_test
+    }
+
+    fn uses_pipeline(&self, obj: &GameObject, depth_test: DepthTest, double_sided: bool) -> bool {
+        !self.is_tessellated(obj) && obj.material.double_sided == double_sided && Self::is_opaque_with(obj, depth_test)
+    }
+
+    fn uses_tessellated_pipeline(obj: &GameObject, depth_test: DepthTest, double_sided: bool) -> bool {
+        obj.material.tessellated && obj.material.double_sided == double_sided && Self::is_opaque_with(obj, depth_test)
+    }
+
+    fn transparent_pipeline(&self, obj: &GameObject, backface_debug: bool) -> &Pipeline {
+        if backface_debug || obj.material.double_sided {
+            &self.transparent_pipelines.double_sided
+        } else {
+            &self.transparent_pipelines.culled
+        }
+    }
+
+    /// The transparent objects, farthest from the camera first.
+    fn sorted_transparent_objects<'a>(frame_info: &FrameInfo<'a>) -> Vec<&'a GameObject> {
+        let mut objects = frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some() && obj.animation.is_none() && obj.material.is_transparent())
+            .map(|obj| (frame_info.camera.view_matrix.transform_point3(obj.transform.translation()).z, obj))
+            .collect::<Vec<_>>();
+
+        // The camera looks down -z, so the farthest objects have the lowest z
+        objects.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        objects.into_iter().map(|(_, obj)| obj).collect()
+    }
+
+    /// Records the draws into secondary command buffers spread over the
+    /// recording threads and executes them in `frame_info.command_buffer`.
+    /// The render pass has to be begun with `SECONDARY_COMMAND_BUFFERS`.
+    pub fn render_parallel(
+        &self,
+        frame_info: &FrameInfo,
+        recording_info: SecondaryRecordingInfo,
+        backface_debug: bool,
+        sorted_transparency: bool,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let recording_threads = self.recording_workers.threads();
+
+        if recording_threads == 0 {
+            return Err(GentooRenderError::InvalidUsage("Cannot render in parallel without recording threads"));
+        }
+
+        let mut draws = Vec::with_capacity(frame_info.game_objects.len());
+        for depth_test in DepthTest::ALL {
+            if backface_debug {
+                self.collect_draws(frame_info, self.pipeline(depth_test, true), true, |obj| Self::is_opaque_with(obj, depth_test), &mut draws);
+                continue;
+            }
+
+            for double_sided in [false, true] {
+                self.collect_draws(frame_info, self.pipeline(depth_test, double_sided), false, |obj| self.uses_pipeline(obj, depth_test, double_sided), &mut draws);
+
+                if let Some(pipeline) = self.tessellated_pipeline(depth_test, double_sided) {
+                    self.collect_draws(frame_info, pipeline, false, |obj| Self::uses_tessellated_pipeline(obj, depth_test, double_sided), &mut draws);
+                }
+            }
+        }
+
+        // The chunks are executed in order, so the sorting survives the split
+        if sorted_transparency {
+            draws.extend(Self::sorted_transparent_objects(frame_info).into_iter().map(|obj| DrawCommand {
+                pipeline: self.transparent_pipeline(obj, backface_debug).graphics_pipeline,
+                push: SimplePushConstantData::for_object(obj, backface_debug),
+                depth_range: obj.material.depth_range,
+                buffers: obj.model.as_ref().unwrap().buffers(),
+            }));
+        }
+
+        if draws.is_empty() {
+            return Ok(());
+        }
+
+        let chunk_size = draws.len().div_ceil(recording_threads);
+
+        let pipeline_layout = self.pipeline_layout;
+        let global_descriptor_set = frame_info.global_descriptor_set;
+
+        let mut jobs = Vec::with_capacity(recording_threads);
+        let mut draws = draws.into_iter().peekable();
+
+        while draws.peek().is_some() {
+            let chunk = draws.by_ref().take(chunk_size).collect::<Vec<_>>();
+
+            jobs.push(Box::new(move |logical_device: &ash::Device, command_buffer| unsafe {
+                Self::record_draws(
+                    logical_device,
+                    command_buffer,
+                    recording_info,
+                    pipeline_layout,
+                    global_descriptor_set,
+                    &chunk,
+                )
+            }) as RecordingJob);
+        }
+
+        let command_buffers = self.recording_workers.record(frame_info.frame_index, jobs)?;
+
+        unsafe {
+            self.device.logical_device.cmd_execute_commands(frame_info.command_buffer, &command_buffers);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn record_draws(
+        logical_device: &ash::Device,
+        command_buffer: ash::vk::CommandBuffer,
+        recording_info: SecondaryRecordingInfo,
+        pipeline_layout: ash::vk::PipelineLayout,
+        global_descriptor_set: ash::vk::DescriptorSet,
+        draws: &[DrawCommand],
+    ) -> anyhow::Result<(), GentooRenderError> {
+        recording_info.begin(logical_device, command_buffer)?;
+
+        logical_device.cmd_bind_descriptor_sets(
+            command_buffer,
+            ash::vk::PipelineBindPoint::GRAPHICS,
+            pipeline_layout,
+            0,
+            &[global_descriptor_set],
+            &[],
+        );
+
+        let mut bound_pipeline = ash::vk::Pipeline::null();
+        let mut depth_range = FULL_DEPTH_RANGE;
+
+        for draw in draws {
+            if draw.pipeline != bound_pipeline {
+                logical_device.cmd_bind_pipeline(command_buffer, ash::vk::PipelineBindPoint::GRAPHICS, draw.pipeline);
+                bound_pipeline = draw.pipeline;
+            }
+
+            if draw.depth_range != depth_range {
+                Renderer::set_viewport(logical_device, command_buffer, recording_info.extent, draw.depth_range);
+                depth_range = draw.depth_range;
+            }
+
+            logical_device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout,
+                ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                0,
+                draw.push.as_bytes(),
+            );
+
+            draw.buffers.bind_and_draw(logical_device, command_buffer);
+        }
+
+        logical_device.end_command_buffer(command_buffer)?;
+
+        Ok(())
+    }
+
+    fn collect_draws<F>(&self, frame_info: &FrameInfo, pipeline: &Pipeline, backface_debug: bool, filter: F, draws: &mut Vec<DrawCommand>)
+    where
+        F: Fn(&GameObject) -> bool,
+    {
+        let objects = frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some() && obj.animation.is_none() && filter(obj));
+
+        draws.extend(objects.map(|obj| DrawCommand {
+            pipeline: pipeline.graphics_pipeline,
+            push: SimplePushConstantData::for_object(obj, backface_debug),
+            depth_range: obj.material.depth_range,
+            buffers: obj.model.as_ref().unwrap().buffers(),
+        }));
+    }
+
+    fn render_objects<F>(&self, frame_info: &FrameInfo, pipeline: &Pipeline, backface_debug: bool, filter: F)
+    where
+        F: Fn(&GameObject) -> bool,
+    {
+        let mut objects = frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some() && obj.animation.is_none() && filter(obj))
+            .peekable();
+
+        if objects.peek().is_none() {
+            return;
+        }
+
+        unsafe {
+            pipeline.bind(&self.device.logical_device, frame_info.command_buffer);
+        }
+
+        let mut depth_range = FULL_DEPTH_RANGE;
+
+        for obj in objects {
+            let model = obj.model.as_ref().unwrap();
+
+            if obj.material.depth_range != depth_range {
+                depth_range = obj.material.depth_range;
+
+                unsafe {
+                    Renderer::set_viewport(&self.device.logical_device, frame_info.command_buffer, frame_info.extent, depth_range);
+                }
+            }
+
+            let push = SimplePushConstantData::for_object(obj, backface_debug);
+
+            unsafe {
+                let push_ptr = push.as_bytes();
+
+                self.device.logical_device.cmd_push_constants(
+                    frame_info.command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push_ptr,
+                );
+
+                model.bind(frame_info.command_buffer);
+                model.draw(&self.device.logical_device, frame_info.command_buffer);
+            }
+        }
+
+        // Later systems expect the full range
+        if depth_range != FULL_DEPTH_RANGE {
+            unsafe {
+                Renderer::set_viewport(&self.device.logical_device, frame_info.command_buffer, frame_info.extent, FULL_DEPTH_RANGE);
+            }
+        }
+    }
+
+    /// Binds the pipeline per object, the sorted order mixes culled and double sided ones.
+    fn render_transparent(&self, frame_info: &FrameInfo, backface_debug: bool) {
+        let logical_device = &self.device.logical_device;
+
+        let mut bound_pipeline = ash::vk::Pipeline::null();
+        let mut depth_range = FULL_DEPTH_RANGE;
+
+        for obj in Self::sorted_transparent_objects(frame_info) {
+            let model = obj.model.as_ref().unwrap();
+            let pipeline = self.transparent_pipeline(obj, backface_debug);
+
+            unsafe {
+                if pipeline.graphics_pipeline != bound_pipeline {
+                    pipeline.bind(logical_device, frame_info.command_buffer);
+                    bound_pipeline = pipeline.graphics_pipeline;
+                }
+
+                if obj.material.depth_range != depth_range {
+                    depth_range = obj.material.depth_range;
+                    Renderer::set_viewport(logical_device, frame_info.command_buffer, frame_info.extent, depth_range);
+                }
+
+                logical_device.cmd_push_constants(
+                    frame_info.command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    SimplePushConstantData::for_object(obj, backface_debug).as_bytes(),
+                );
+
+                model.bind(frame_info.command_buffer);
+                model.draw(logical_device, frame_info.command_buffer);
+            }
+        }
+
+        if depth_range != FULL_DEPTH_RANGE {
+            unsafe {
+                Renderer::set_viewport(logical_device, frame_info.command_buffer, frame_info.extent, FULL_DEPTH_RANGE);
             }
         }
     }
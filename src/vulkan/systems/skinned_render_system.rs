@@ -0,0 +1,270 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, Buffer, SkinnedVertex, MAX_JOINTS, MAX_FRAMES_IN_FLIGHT, Renderer, FULL_DEPTH_RANGE, pipeline::{Pipeline, PipelineCache, PipelineConfig}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo, RENDER_LAYER_MAIN};
+
+use super::{SimplePushConstantData, SimpleRenderSystem};
+
+/// Animated objects drawn per frame, further ones are skipped with a warning.
+pub const MAX_SKINNED_OBJECTS: usize = 8;
+
+/// Bytes of one object's joint matrices, a multiple of any storage buffer
+/// offset alignment.
+const JOINT_SLOT_SIZE: usize = MAX_JOINTS * std::mem::size_of::<glam::Mat4>();
+
+/// Draws objects with an animation component, skinning their vertices on the
+/// GPU. The joint matrices of every object live in one storage buffer per
+/// frame, each draw selects its slot with a dynamic offset.
+pub struct SkinnedRenderSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    joint_buffers: Vec<Buffer<glam::Mat4>>,
+    joint_descriptor_sets: Vec<ash::vk::DescriptorSet>,
+    _joint_set_layout: Rc<DescriptorSetLayout>,
+    _descriptor_pool: Rc<DescriptorPool>,
+    /// See `SimpleRenderSystem::fragment_shader`
+    log_depth: bool,
+}
+
+impl SkinnedRenderSystem {
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let descriptor_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(MAX_FRAMES_IN_FLIGHT as u32)
+            .add_pool_size(ash::vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, MAX_FRAMES_IN_FLIGHT as u32)
+            .build()?;
+
+        let joint_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, ash::vk::ShaderStageFlags::VERTEX, 1)
+            .build()?;
+
+        let mut joint_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        let mut joint_descriptor_sets = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let mut buffer = Buffer::new(
+                device.clone(),
+                MAX_SKINNED_OBJECTS * MAX_JOINTS,
+                ash::vk::BufferUsageFlags::STORAGE_BUFFER,
+                ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+            )?;
+
+            buffer.map_persistent()?;
+
+            // The dynamic offset moves a single slot over the buffer
+            let buffer_info = ash::vk::DescriptorBufferInfo {
+                buffer: buffer.buffer,
+                offset: 0,
+                range: JOINT_SLOT_SIZE as u64,
+            };
+
+            let set = DescriptorSetWriter::new(joint_set_layout.clone(), descriptor_pool.clone())
+                .write_to_buffer(0, &[buffer_info])
+                .build()
+                .unwrap();
+
+            joint_buffers.push(buffer);
+            joint_descriptor_sets.push(set);
+        }
+
+        let mut set_layouts = global_set_layout.to_vec();
+        set_layouts.push(joint_set_layout.layout);
+
+        let pipeline_layout = Self::create_pipeline_layout(&device.logical_device, &set_layouts)?;
+
+        let pipeline = Self::create_pipeline(
+            device.clone(),
+            render_pass,
+            &pipeline_layout,
+            pipeline_cache,
+            false,
+        )?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+            joint_buffers,
+            joint_descriptor_sets,
+            _joint_set_layout: joint_set_layout,
+            _descriptor_pool: descriptor_pool,
+            log_depth: false,
+        })
+    }
+
+    /// Like `SimpleRenderSystem::set_log_depth`
+    pub fn set_log_depth(
+        &mut self,
+        log_depth: bool,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        let previous = std::mem::replace(&mut self.log_depth, log_depth);
+
+        let result = self.reload_pipelines(render_pass, pipeline_cache);
+        if result.is_err() {
+            self.log_depth = previous;
+        }
+
+        result
+    }
+
+    /// Rebuilds the pipeline from the shader files on disk, keeping the
+    /// previous one on failure.
+    pub fn reload_pipelines(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(
+            self.device.clone(),
+            render_pass,
+            &self.pipeline_layout,
+            pipeline_cache,
+            self.log_depth,
+        )?;
+
+        Ok(())
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+        log_depth: bool,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/skinned_shader.vert.spv"),
+            SimpleRenderSystem::fragment_shader(log_depth),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &SkinnedVertex::layout(),
+            PipelineConfig::default(),
+        )
+    }
+
+    fn create_pipeline_layout(
+        logical_device: &ash::Device,
+        set_layouts: &[ash::vk::DescriptorSetLayout],
+    ) -> anyhow::Result<ash::vk::PipelineLayout, GentooRenderError> {
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<SimplePushConstantData>() as u32,
+        }];
+
+        let pipeline_layout_info = ash::vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(&push_constant_range);
+
+        Ok(unsafe {
+            logical_device.create_pipeline_layout(&pipeline_layout_info, None)?
+        })
+    }
+
+    /// Uploads the joint matrices of this frame's animated objects and draws them.
+    pub fn render(&mut self, frame_info: &FrameInfo) -> anyhow::Result<(), GentooRenderError> {
+        let mut objects = frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some() && obj.animation.is_some())
+            .collect::<Vec<_>>();
+
+        if objects.is_empty() {
+            return Ok(());
+        }
+
+        if objects.len() > MAX_SKINNED_OBJECTS {
+            log::warn!("Only drawing {} of {} animated objects", MAX_SKINNED_OBJECTS, objects.len());
+            objects.truncate(MAX_SKINNED_OBJECTS);
+        }
+
+        let mut joint_matrices = vec![glam::Mat4::IDENTITY; objects.len() * MAX_JOINTS];
+        for (slot, obj) in joint_matrices.chunks_mut(MAX_JOINTS).zip(&objects) {
+            let matrices = obj.animation.as_ref().unwrap().joint_matrices();
+            slot[..matrices.len()].copy_from_slice(&matrices);
+        }
+
+        self.joint_buffers[frame_info.frame_index].map(0)?.write_slice(&joint_matrices);
+
+        let logical_device = &self.device.logical_device;
+
+        unsafe {
+            self.pipeline.bind(logical_device, frame_info.command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+        }
+
+        let mut depth_range = FULL_DEPTH_RANGE;
+
+        for (slot, obj) in objects.iter().enumerate() {
+            let model = obj.model.as_ref().unwrap();
+
+            if obj.material.depth_range != depth_range {
+                depth_range = obj.material.depth_range;
+
+                unsafe {
+                    Renderer::set_viewport(logical_device, frame_info.command_buffer, frame_info.extent, depth_range);
+                }
+            }
+
+            let push = SimplePushConstantData {
+                model_matrix: obj.transform.mat4(),
+                normal_matrix: obj.transform.normal_matrix(),
+            };
+
+            unsafe {
+                logical_device.cmd_bind_descriptor_sets(
+                    frame_info.command_buffer,
+                    ash::vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    1,
+                    &[self.joint_descriptor_sets[frame_info.frame_index]],
+                    &[(slot * JOINT_SLOT_SIZE) as u32],
+                );
+
+                logical_device.cmd_push_constants(
+                    frame_info.command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                model.bind(frame_info.command_buffer);
+                model.draw(logical_device, frame_info.command_buffer);
+            }
+        }
+
+        if depth_range != FULL_DEPTH_RANGE {
+            unsafe {
+                Renderer::set_viewport(logical_device, frame_info.command_buffer, frame_info.extent, FULL_DEPTH_RANGE);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SkinnedRenderSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping skinned render system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -0,0 +1,635 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, OffscreenTarget, Swapchain, Texture, Buffer, Vertex, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter, FrameUniforms}}, settings::SsaoSettings, FrameInfo, RENDER_LAYER_PREPASS};
+
+use super::SimplePushConstantData;
+
+const KERNEL_SIZE: usize = 16;
+const NOISE_SIZE: u32 = 4;
+const NORMAL_DEPTH_FORMAT: ash::vk::Format = ash::vk::Format::R16G16B16A16_SFLOAT;
+const OCCLUSION_FORMAT: ash::vk::Format = ash::vk::Format::R8_UNORM;
+
+/// Hemisphere sample offsets around +z, denser towards the center.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(C)]
+struct SsaoKernel {
+    samples: [glam::Vec4; KERNEL_SIZE],
+}
+
+/// Set 1 of the occlusion pass, written every frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct SsaoParams {
+    projection: glam::Mat4,
+    radius: f32,
+    bias: f32,
+}
+
+struct Attachment {
+    device: Rc<Device>,
+    image: ash::vk::Image,
+    memory: ash::vk::DeviceMemory,
+    view: ash::vk::ImageView,
+}
+
+impl Attachment {
+    fn new(
+        device: &Rc<Device>,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        usage: ash::vk::ImageUsageFlags,
+        aspect_mask: ash::vk::ImageAspectFlags,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let (image, memory, view) = OffscreenTarget::create_attachment(device, format, extent, usage, aspect_mask)?;
+
+        Ok(Self {
+            device: device.clone(),
+            image,
+            memory,
+            view,
+        })
+    }
+}
+
+impl Drop for Attachment {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view);
+            self.device.destroy_image(self.image, self.memory);
+        }
+    }
+}
+
+/// Everything sized to the scene extent.
+struct SsaoTargets {
+    device: Rc<Device>,
+    extent: ash::vk::Extent2D,
+    normal_depth: Attachment,
+    _depth: Attachment,
+    occlusion: Attachment,
+    blurred: Attachment,
+    geometry_framebuffer: ash::vk::Framebuffer,
+    occlusion_framebuffer: ash::vk::Framebuffer,
+    blur_framebuffer: ash::vk::Framebuffer,
+}
+
+impl Drop for SsaoTargets {
+    fn drop(&mut self) {
+        log::debug!("Dropping SSAO targets");
+
+        unsafe {
+            self.device.logical_device.destroy_framebuffer(self.geometry_framebuffer, None);
+            self.device.logical_device.destroy_framebuffer(self.occlusion_framebuffer, None);
+            self.device.logical_device.destroy_framebuffer(self.blur_framebuffer, None);
+        }
+    }
+}
+
+/// Screen space ambient occlusion. Renders view space normals and depth of
+/// the scene, estimates the occlusion from them and blurs the result, which
+/// the scene shader then samples to darken the ambient light.
+pub struct SsaoSystem {
+    device: Rc<Device>,
+    geometry_render_pass: ash::vk::RenderPass,
+    occlusion_render_pass: ash::vk::RenderPass,
+    targets: Option<SsaoTargets>,
+    sampler: ash::vk::Sampler,
+    _noise: Rc<Texture>,
+    _kernel: Buffer<SsaoKernel>,
+    geometry_pipeline: Pipeline,
+    geometry_pipeline_layout: ash::vk::PipelineLayout,
+    occlusion_pipeline: Pipeline,
+    occlusion_pipeline_layout: ash::vk::PipelineLayout,
+    occlusion_set_layout: Rc<DescriptorSetLayout>,
+    occlusion_descriptor_set: ash::vk::DescriptorSet,
+    params: FrameUniforms<SsaoParams>,
+    blur_pipeline: Pipeline,
+    blur_pipeline_layout: ash::vk::PipelineLayout,
+    blur_set_layout: Rc<DescriptorSetLayout>,
+    blur_descriptor_set: ash::vk::DescriptorSet,
+    descriptor_pool: Rc<DescriptorPool>,
+}
+
+impl SsaoSystem {
+    /// The per frame parameters are allocated from `global_pool`.
+    pub fn new(
+        device: Rc<Device>,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        global_pool: &Rc<DescriptorPool>,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let geometry_render_pass = Self::create_render_pass(&device, NORMAL_DEPTH_FORMAT, Some(Swapchain::find_depth_format(&device)))?;
+        let occlusion_render_pass = Self::create_render_pass(&device, OCCLUSION_FORMAT, None)?;
+
+        let (random, noise_pixels) = {
+            let mut random = Self::random();
+            let pixels = (0..NOISE_SIZE * NOISE_SIZE)
+                .flat_map(|_| [(random() * 255.0) as u8, (random() * 255.0) as u8, 0, 255])
+                .collect::<Vec<_>>();
+
+            (random, pixels)
+        };
+
+        let noise = Texture::new_linear(device.clone(), &noise_pixels, NOISE_SIZE, NOISE_SIZE)?;
+
+        let mut kernel = Buffer::new(
+            device.clone(),
+            1,
+            ash::vk::BufferUsageFlags::UNIFORM_BUFFER,
+            ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        kernel.map(0)?.write_slice(&[Self::generate_kernel(random)]);
+
+        let sampler = unsafe {
+            device.logical_device.create_sampler(
+                &ash::vk::SamplerCreateInfo::builder()
+                    .mag_filter(ash::vk::Filter::NEAREST)
+                    .min_filter(ash::vk::Filter::NEAREST)
+                    .address_mode_u(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_v(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_w(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                None,
+            )?
+        };
+
+        let descriptor_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(2)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 3)
+            .add_pool_size(ash::vk::DescriptorType::UNIFORM_BUFFER, 1)
+            .build()?;
+
+        let occlusion_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(1, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(2, ash::vk::DescriptorType::UNIFORM_BUFFER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let blur_set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        // The noise and kernel never change, the targets are written when created
+        let occlusion_descriptor_set = DescriptorSetWriter::new(occlusion_set_layout.clone(), descriptor_pool.clone())
+            .write_image(1, &[noise.descriptor_info()])
+            .write_to_buffer(2, &[kernel.descriptor_info()])
+            .build()
+            .unwrap();
+
+        let blur_descriptor_set = descriptor_pool.allocate_descriptor(&[blur_set_layout.layout])?;
+
+        let geometry_pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: std::mem::size_of::<SimplePushConstantData>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(global_set_layout)
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        // Without culling, double sided objects occlude from both sides
+        let geometry_pipeline = Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/simple_shader.vert.spv"),
+            ShaderSource::Path("shaders/ssao_geometry.frag.spv"),
+            &geometry_render_pass,
+            &geometry_pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::Opaque,
+                ..Default::default()
+            },
+        )?;
+
+        let params = FrameUniforms::new(device.clone(), global_pool, ash::vk::ShaderStageFlags::FRAGMENT)?;
+
+        let occlusion_pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[occlusion_set_layout.layout, params.set_layout.layout]),
+                None,
+            )?
+        };
+
+        let occlusion_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            ShaderSource::Path("shaders/ssao.frag.spv"),
+            &occlusion_render_pass,
+            &occlusion_pipeline_layout,
+            pipeline_cache,
+        )?;
+
+        let blur_pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&[blur_set_layout.layout]),
+                None,
+            )?
+        };
+
+        let blur_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            ShaderSource::Path("shaders/ssao_blur.frag.spv"),
+            &occlusion_render_pass,
+            &blur_pipeline_layout,
+            pipeline_cache,
+        )?;
+
+        Ok(Self {
+            device,
+            geometry_render_pass,
+            occlusion_render_pass,
+            targets: None,
+            sampler,
+            _noise: noise,
+            _kernel: kernel,
+            geometry_pipeline,
+            geometry_pipeline_layout,
+            occlusion_pipeline,
+            occlusion_pipeline_layout,
+            occlusion_set_layout,
+            occlusion_descriptor_set,
+            params,
+            blur_pipeline,
+            blur_pipeline_layout,
+            blur_set_layout,
+            blur_descriptor_set,
+            descriptor_pool,
+        })
+    }
+
+    /// Recreates the targets if `extent` changed, returns whether it did.
+    /// The `occlusion_map` descriptor has to be rewritten in that case.
+    pub fn resize(&mut self, extent: ash::vk::Extent2D) -> anyhow::Result<bool, GentooRenderError> {
+        if self.targets.as_ref().map(|targets| targets.extent) == Some(extent) {
+            return Ok(false);
+        }
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        self.targets = None;
+        self.targets = Some(self.create_targets(extent)?);
+
+        let targets = self.targets.as_ref().unwrap();
+
+        DescriptorSetWriter::new(self.occlusion_set_layout.clone(), self.descriptor_pool.clone())
+            .write_image(0, &[self.image_info(&targets.normal_depth)])
+            .overwrite(self.occlusion_descriptor_set);
+
+        DescriptorSetWriter::new(self.blur_set_layout.clone(), self.descriptor_pool.clone())
+            .write_image(0, &[self.image_info(&targets.occlusion)])
+            .overwrite(self.blur_descriptor_set);
+
+        Ok(true)
+    }
+
+    /// The blurred occlusion, in the layout the scene shader reads it.
+    /// None before the first `resize`.
+    pub fn occlusion_map(&self) -> Option<ash::vk::DescriptorImageInfo> {
+        self.targets.as_ref().map(|targets| self.image_info(&targets.blurred))
+    }
+
+    /// Records the geometry, occlusion and blur passes, outside of any render pass.
+    pub fn render(&mut self, frame_info: &FrameInfo, settings: &SsaoSettings) -> anyhow::Result<(), GentooRenderError> {
+        self.params.write(frame_info.frame_index, SsaoParams {
+            projection: frame_info.camera.projection_matrix,
+            radius: settings.radius,
+            bias: settings.bias,
+        })?;
+
+        let targets = match &self.targets {
+            Some(targets) => targets,
+            None => return Ok(()),
+        };
+
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+        let extent = targets.extent;
+
+        let clear_values = [
+            ash::vk::ClearValue {
+                color: ash::vk::ClearColorValue {
+                    float32: [0.0; 4],
+                },
+            },
+            ash::vk::ClearValue {
+                depth_stencil: ash::vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+
+        unsafe {
+            self.begin_render_pass(command_buffer, self.geometry_render_pass, targets.geometry_framebuffer, extent, &clear_values);
+
+            self.geometry_pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.geometry_pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+
+            for obj in frame_info.game_objects.values().filter(|obj| obj.is_rendered_in(RENDER_LAYER_PREPASS) && obj.model.is_some() && obj.animation.is_none()) {
+                let model = obj.model.as_ref().unwrap();
+
+                let push = SimplePushConstantData {
+                    model_matrix: obj.transform.mat4(),
+                    normal_matrix: obj.transform.normal_matrix(),
+                };
+
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.geometry_pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                model.bind(command_buffer);
+                model.draw(logical_device, command_buffer);
+            }
+
+            logical_device.cmd_end_render_pass(command_buffer);
+
+            self.begin_render_pass(command_buffer, self.occlusion_render_pass, targets.occlusion_framebuffer, extent, &[]);
+
+            self.occlusion_pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.occlusion_pipeline_layout,
+                0,
+                &[self.occlusion_descriptor_set, self.params.descriptor_set(frame_info.frame_index)],
+                &[],
+            );
+
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            logical_device.cmd_end_render_pass(command_buffer);
+
+            self.begin_render_pass(command_buffer, self.occlusion_render_pass, targets.blur_framebuffer, extent, &[]);
+
+            self.blur_pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.blur_pipeline_layout,
+                0,
+                &[self.blur_descriptor_set],
+                &[],
+            );
+
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn begin_render_pass(
+        &self,
+        command_buffer: ash::vk::CommandBuffer,
+        render_pass: ash::vk::RenderPass,
+        framebuffer: ash::vk::Framebuffer,
+        extent: ash::vk::Extent2D,
+        clear_values: &[ash::vk::ClearValue],
+    ) {
+        self.device.logical_device.cmd_begin_render_pass(
+            command_buffer,
+            &ash::vk::RenderPassBeginInfo::builder()
+                .render_pass(render_pass)
+                .framebuffer(framebuffer)
+                .render_area(ash::vk::Rect2D {
+                    offset: ash::vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                })
+                .clear_values(clear_values),
+            ash::vk::SubpassContents::INLINE,
+        );
+
+        Renderer::set_viewport_and_scissor(&self.device.logical_device, command_buffer, extent);
+    }
+
+    fn image_info(&self, attachment: &Attachment) -> ash::vk::DescriptorImageInfo {
+        ash::vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler)
+            .image_view(attachment.view)
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build()
+    }
+
+    fn create_targets(&self, extent: ash::vk::Extent2D) -> anyhow::Result<SsaoTargets, GentooRenderError> {
+        let sampled_color = ash::vk::ImageUsageFlags::COLOR_ATTACHMENT | ash::vk::ImageUsageFlags::SAMPLED;
+
+        let normal_depth = Attachment::new(&self.device, NORMAL_DEPTH_FORMAT, extent, sampled_color, ash::vk::ImageAspectFlags::COLOR)?;
+        let depth = Attachment::new(
+            &self.device,
+            Swapchain::find_depth_format(&self.device),
+            extent,
+            ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ash::vk::ImageAspectFlags::DEPTH,
+        )?;
+        let occlusion = Attachment::new(&self.device, OCCLUSION_FORMAT, extent, sampled_color, ash::vk::ImageAspectFlags::COLOR)?;
+        let blurred = Attachment::new(&self.device, OCCLUSION_FORMAT, extent, sampled_color, ash::vk::ImageAspectFlags::COLOR)?;
+
+        let create_framebuffer = |render_pass: ash::vk::RenderPass, attachments: &[ash::vk::ImageView]| unsafe {
+            self.device.logical_device.create_framebuffer(
+                &ash::vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(attachments)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1),
+                None,
+            )
+        };
+
+        let geometry_framebuffer = create_framebuffer(self.geometry_render_pass, &[normal_depth.view, depth.view])?;
+        let occlusion_framebuffer = create_framebuffer(self.occlusion_render_pass, &[occlusion.view])?;
+        let blur_framebuffer = create_framebuffer(self.occlusion_render_pass, &[blurred.view])?;
+
+        Ok(SsaoTargets {
+            device: self.device.clone(),
+            extent,
+            normal_depth,
+            _depth: depth,
+            occlusion,
+            blurred,
+            geometry_framebuffer,
+            occlusion_framebuffer,
+            blur_framebuffer,
+        })
+    }
+
+    fn create_fullscreen_pipeline(
+        device: &Rc<Device>,
+        frag_shader: ShaderSource,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            frag_shader,
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::Opaque,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A color target that ends up ready for sampling, with an optional depth
+    /// attachment that is only used during the pass.
+    fn create_render_pass(
+        device: &Rc<Device>,
+        color_format: ash::vk::Format,
+        depth_format: Option<ash::vk::Format>,
+    ) -> anyhow::Result<ash::vk::RenderPass, GentooRenderError> {
+        let mut attachments = vec![ash::vk::AttachmentDescription {
+            format: color_format,
+            samples: ash::vk::SampleCountFlags::TYPE_1,
+            // Fullscreen passes overwrite every pixel
+            load_op: if depth_format.is_some() { ash::vk::AttachmentLoadOp::CLEAR } else { ash::vk::AttachmentLoadOp::DONT_CARE },
+            store_op: ash::vk::AttachmentStoreOp::STORE,
+            stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: ash::vk::ImageLayout::UNDEFINED,
+            final_layout: ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ..Default::default()
+        }];
+
+        let color_attachment_refs = [ash::vk::AttachmentReference {
+            attachment: 0,
+            layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+
+        let depth_attachment_ref = ash::vk::AttachmentReference {
+            attachment: 1,
+            layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+
+        let mut subpass = ash::vk::SubpassDescription::builder()
+            .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs);
+
+        if let Some(depth_format) = depth_format {
+            attachments.push(ash::vk::AttachmentDescription {
+                format: depth_format,
+                samples: ash::vk::SampleCountFlags::TYPE_1,
+                load_op: ash::vk::AttachmentLoadOp::CLEAR,
+                store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: ash::vk::ImageLayout::UNDEFINED,
+                final_layout: ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            });
+
+            subpass = subpass.depth_stencil_attachment(&depth_attachment_ref);
+        }
+
+        let dependencies = [
+            // The previous frame may still be sampling the target
+            ash::vk::SubpassDependency {
+                src_subpass: ash::vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | ash::vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                dst_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE | ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            ash::vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: ash::vk::SUBPASS_EXTERNAL,
+                src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: ash::vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            },
+        ];
+
+        Ok(unsafe {
+            device.logical_device.create_render_pass(
+                &ash::vk::RenderPassCreateInfo::builder()
+                    .attachments(&attachments)
+                    .subpasses(&[subpass.build()])
+                    .dependencies(&dependencies),
+                None,
+            )?
+        })
+    }
+
+    /// Deterministic xorshift, the kernel and noise only have to look random.
+    fn random() -> impl FnMut() -> f32 {
+        let mut state = 0x9E37_79B9_u32;
+
+        move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            state as f32 / u32::MAX as f32
+        }
+    }
+
+    fn generate_kernel(mut random: impl FnMut() -> f32) -> SsaoKernel {
+        let mut samples = [glam::Vec4::ZERO; KERNEL_SIZE];
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let direction = glam::vec3(random() * 2.0 - 1.0, random() * 2.0 - 1.0, random()).normalize_or_zero();
+
+            // Most samples close to the surface point
+            let t = i as f32 / KERNEL_SIZE as f32;
+            let scale = 0.1 + 0.9 * t * t;
+
+            *sample = (direction * random() * scale).extend(0.0);
+        }
+
+        SsaoKernel { samples }
+    }
+}
+
+impl Drop for SsaoSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping SSAO system");
+
+        self.targets = None;
+
+        unsafe {
+            self.device.logical_device.destroy_sampler(self.sampler, None);
+            self.device.logical_device.destroy_pipeline_layout(self.geometry_pipeline_layout, None);
+            self.device.logical_device.destroy_pipeline_layout(self.occlusion_pipeline_layout, None);
+            self.device.logical_device.destroy_pipeline_layout(self.blur_pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.geometry_render_pass, None);
+            self.device.logical_device.destroy_render_pass(self.occlusion_render_pass, None);
+        }
+    }
+}
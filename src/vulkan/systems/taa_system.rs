@@ -0,0 +1,463 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, OffscreenTarget, BlitTarget, VertexLayout, Renderer, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode}, descriptor_set::{DescriptorPool, DescriptorSetLayout, DescriptorSetWriter}}, FrameInfo};
+
+/// Float, so the blended history doesn't drift from rounding every frame
+const HISTORY_FORMAT: ash::vk::Format = ash::vk::Format::R16G16B16A16_SFLOAT;
+/// Length of the jitter sequence
+const JITTER_SAMPLES: u32 = 8;
+
+#[derive(Debug)]
+#[repr(C)]
+struct TaaPushConstants {
+    reprojection: glam::Mat4,
+    history_valid: u32,
+}
+
+impl TaaPushConstants {
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        let size_in_bytes = std::mem::size_of::<Self>();
+        let size_in_u8 = size_in_bytes / std::mem::size_of::<u8>();
+        let start_ptr = self as *const Self as *const u8;
+        std::slice::from_raw_parts(start_ptr, size_in_u8)
+    }
+}
+
+/// Two history images, each frame reads one and writes the other.
+struct TaaTargets {
+    device: Rc<Device>,
+    extent: ash::vk::Extent2D,
+    /// The scene target views the descriptor sets were written with
+    scene_views: (ash::vk::ImageView, ash::vk::ImageView),
+    history: [(ash::vk::Image, ash::vk::DeviceMemory, ash::vk::ImageView); 2],
+    framebuffers: [ash::vk::Framebuffer; 2],
+    /// Set `i` writes `history[i]` and reads the other one
+    descriptor_sets: [ash::vk::DescriptorSet; 2],
+}
+
+impl Drop for TaaTargets {
+    fn drop(&mut self) {
+        log::debug!("Dropping TAA targets");
+
+        unsafe {
+            for framebuffer in self.framebuffers {
+                self.device.logical_device.destroy_framebuffer(framebuffer, None);
+            }
+
+            for (image, memory, view) in self.history {
+                self.device.destroy_image_view(view);
+                self.device.destroy_image(image, memory);
+            }
+        }
+    }
+}
+
+/// Temporal anti-aliasing. The camera is jittered by a sub-pixel offset
+/// every frame and the scene is blended into a history that is reprojected
+/// with the camera motion, reconstructed from the depth buffer. Moving
+/// objects and the logarithmic depth buffer aren't reprojected correctly,
+/// clamping the history to the current neighborhood hides most of it.
+pub struct TaaSystem {
+    device: Rc<Device>,
+    render_pass: ash::vk::RenderPass,
+    targets: Option<TaaTargets>,
+    sampler: ash::vk::Sampler,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+    set_layout: Rc<DescriptorSetLayout>,
+    descriptor_pool: Rc<DescriptorPool>,
+    frame: u32,
+    /// History written by the last resolve
+    current: usize,
+    history_valid: bool,
+    previous_view_projection: glam::Mat4,
+}
+
+impl TaaSystem {
+    /// The history weight comes from the `PostProcessUbo` in set 1.
+    pub fn new(
+        device: Rc<Device>,
+        post_process_set_layout: ash::vk::DescriptorSetLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let render_pass = Self::create_render_pass(&device)?;
+
+        let sampler = unsafe {
+            device.logical_device.create_sampler(
+                &ash::vk::SamplerCreateInfo::builder()
+                    .mag_filter(ash::vk::Filter::LINEAR)
+                    .min_filter(ash::vk::Filter::LINEAR)
+                    .address_mode_u(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_v(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .address_mode_w(ash::vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                None,
+            )?
+        };
+
+        let descriptor_pool = DescriptorPool::new(device.clone())
+            .set_max_sets(2)
+            .add_pool_size(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 6)
+            .build()?;
+
+        let set_layout = DescriptorSetLayout::new(device.clone())
+            .add_binding(0, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(1, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .add_binding(2, ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER, ash::vk::ShaderStageFlags::FRAGMENT, 1)
+            .build()?;
+
+        let pipeline_layout = {
+            let push_constant_range = [ash::vk::PushConstantRange {
+                stage_flags: ash::vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: std::mem::size_of::<TaaPushConstants>() as u32,
+            }];
+
+            unsafe {
+                device.logical_device.create_pipeline_layout(
+                    &ash::vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&[set_layout.layout, post_process_set_layout])
+                        .push_constant_ranges(&push_constant_range),
+                    None,
+                )?
+            }
+        };
+
+        let pipeline = Pipeline::new(
+            device.clone(),
+            ShaderSource::Path("shaders/fullscreen.vert.spv"),
+            ShaderSource::Path("shaders/taa_resolve.frag.spv"),
+            &render_pass,
+            &pipeline_layout,
+            pipeline_cache,
+            &VertexLayout::default(),
+            PipelineConfig {
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                blend_mode: BlendMode::Opaque,
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            device,
+            render_pass,
+            targets: None,
+            sampler,
+            pipeline,
+            pipeline_layout,
+            set_layout,
+            descriptor_pool,
+            frame: 0,
+            current: 0,
+            history_valid: false,
+            previous_view_projection: glam::Mat4::IDENTITY,
+        })
+    }
+
+    /// This frame's projection offset for `CameraBuilder::set_jitter`, a
+    /// Halton (2, 3) point within the pixel.
+    pub fn jitter(&self, extent: ash::vk::Extent2D) -> glam::Vec2 {
+        let index = self.frame % JITTER_SAMPLES + 1;
+        let offset = glam::vec2(Self::halton(index, 2), Self::halton(index, 3)) - 0.5;
+
+        offset * 2.0 / glam::vec2(extent.width as f32, extent.height as f32)
+    }
+
+    /// Starts accumulating from scratch, e.g. after the camera jumped.
+    pub fn reset(&mut self) {
+        self.history_valid = false;
+    }
+
+    /// Blends the scene into the history, outside of any render pass. The
+    /// scene target has to come from a renderer that resolves the scene, see
+    /// `Renderer::set_resolve_scene`. The returned blit source is the
+    /// anti-aliased scene.
+    pub fn resolve(
+        &mut self,
+        frame_info: &FrameInfo,
+        scene: &OffscreenTarget,
+    ) -> anyhow::Result<BlitTarget, GentooRenderError> {
+        self.prepare_targets(scene)?;
+
+        let view_projection = frame_info.camera.unjittered_projection() * frame_info.camera.view_matrix;
+
+        let write = 1 - self.current;
+        let targets = self.targets.as_ref().unwrap();
+        let logical_device = &self.device.logical_device;
+        let command_buffer = frame_info.command_buffer;
+
+        let barrier = |image, aspect_mask, old_layout, new_layout, src_access_mask| {
+            ash::vk::ImageMemoryBarrier::builder()
+                .image(image)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(ash::vk::AccessFlags::SHADER_READ)
+                .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(ash::vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()
+        };
+
+        let push = TaaPushConstants {
+            reprojection: self.previous_view_projection * view_projection.inverse(),
+            history_valid: self.history_valid as u32,
+        };
+
+        unsafe {
+            // The scene pass leaves the color ready to be blitted and depth
+            // as an attachment
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | ash::vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(
+                        scene.color_image,
+                        ash::vk::ImageAspectFlags::COLOR,
+                        ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    ),
+                    barrier(
+                        scene.depth_image,
+                        ash::vk::ImageAspectFlags::DEPTH,
+                        ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                        ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                        ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    ),
+                ],
+            );
+
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &ash::vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.render_pass)
+                    .framebuffer(targets.framebuffers[write])
+                    .render_area(ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent: targets.extent,
+                    }),
+                ash::vk::SubpassContents::INLINE,
+            );
+
+            Renderer::set_viewport_and_scissor(logical_device, command_buffer, targets.extent);
+
+            self.pipeline.bind(logical_device, command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[targets.descriptor_sets[write], frame_info.post_process_descriptor_set],
+                &[],
+            );
+
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                ash::vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push.as_bytes(),
+            );
+
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            logical_device.cmd_end_render_pass(command_buffer);
+        }
+
+        self.current = write;
+        self.history_valid = true;
+        self.previous_view_projection = view_projection;
+        self.frame = self.frame.wrapping_add(1);
+
+        // Sampled as the history by the next resolve
+        Ok(BlitTarget {
+            image: targets.history[write].0,
+            format: HISTORY_FORMAT,
+            layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            final_layout: ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            mip_level: 0,
+            region: Renderer::blit_region(targets.extent),
+        })
+    }
+
+    /// Recreates the history when the scene target changed, which throws
+    /// the accumulated frames away.
+    fn prepare_targets(&mut self, scene: &OffscreenTarget) -> anyhow::Result<(), GentooRenderError> {
+        let scene_views = (scene.color_image_view, scene.depth_image_view);
+
+        if let Some(targets) = &self.targets {
+            if targets.extent == scene.extent && targets.scene_views == scene_views {
+                return Ok(());
+            }
+        }
+
+        unsafe {
+            self.device.logical_device.device_wait_idle()?;
+        }
+
+        // Sets are only allocated once, the pool holds exactly two
+        let descriptor_sets = match self.targets.take() {
+            Some(targets) => targets.descriptor_sets,
+            None => [
+                self.descriptor_pool.allocate_descriptor(&[self.set_layout.layout])?,
+                self.descriptor_pool.allocate_descriptor(&[self.set_layout.layout])?,
+            ],
+        };
+
+        self.history_valid = false;
+
+        let create_history = || -> anyhow::Result<_, GentooRenderError> {
+            let (image, memory, view) = OffscreenTarget::create_attachment(
+                &self.device,
+                HISTORY_FORMAT,
+                scene.extent,
+                ash::vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | ash::vk::ImageUsageFlags::SAMPLED
+                    | ash::vk::ImageUsageFlags::TRANSFER_SRC
+                    | ash::vk::ImageUsageFlags::TRANSFER_DST,
+                ash::vk::ImageAspectFlags::COLOR,
+            )?;
+
+            // The first resolve reads a history it didn't write
+            OffscreenTarget::clear_attachment(
+                &self.device,
+                image,
+                ash::vk::ImageAspectFlags::COLOR,
+                ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )?;
+
+            Ok((image, memory, view))
+        };
+
+        let history = [create_history()?, create_history()?];
+
+        let create_framebuffer = |view: ash::vk::ImageView| unsafe {
+            self.device.logical_device.create_framebuffer(
+                &ash::vk::FramebufferCreateInfo::builder()
+                    .render_pass(self.render_pass)
+                    .attachments(&[view])
+                    .width(scene.extent.width)
+                    .height(scene.extent.height)
+                    .layers(1),
+                None,
+            )
+        };
+
+        let framebuffers = [create_framebuffer(history[0].2)?, create_framebuffer(history[1].2)?];
+
+        let image_info = |view, layout| ash::vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler)
+            .image_view(view)
+            .image_layout(layout)
+            .build();
+
+        for (write, descriptor_set) in descriptor_sets.iter().enumerate() {
+            DescriptorSetWriter::new(self.set_layout.clone(), self.descriptor_pool.clone())
+                .write_image(0, &[image_info(scene.color_image_view, ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)])
+                .write_image(1, &[image_info(scene.depth_image_view, ash::vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)])
+                .write_image(2, &[image_info(history[1 - write].2, ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)])
+                .overwrite(*descriptor_set);
+        }
+
+        self.targets = Some(TaaTargets {
+            device: self.device.clone(),
+            extent: scene.extent,
+            scene_views,
+            history,
+            framebuffers,
+            descriptor_sets,
+        });
+
+        Ok(())
+    }
+
+    /// Radical inverse of `index` in `base`, evenly spread points in [0, 1).
+    fn halton(mut index: u32, base: u32) -> f32 {
+        let mut fraction = 1.0;
+        let mut result = 0.0;
+
+        while index > 0 {
+            fraction /= base as f32;
+            result += fraction * (index % base) as f32;
+            index /= base;
+        }
+
+        result
+    }
+
+    /// Overwrites the whole history image, which is blitted afterwards.
+    fn create_render_pass(device: &Rc<Device>) -> anyhow::Result<ash::vk::RenderPass, GentooRenderError> {
+        let dependencies = [
+            // The previous frame may still be reading the history
+            ash::vk::SubpassDependency {
+                src_subpass: ash::vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: ash::vk::PipelineStageFlags::FRAGMENT_SHADER | ash::vk::PipelineStageFlags::TRANSFER,
+                dst_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            ash::vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: ash::vk::SUBPASS_EXTERNAL,
+                src_stage_mask: ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: ash::vk::PipelineStageFlags::TRANSFER,
+                src_access_mask: ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: ash::vk::AccessFlags::TRANSFER_READ,
+                ..Default::default()
+            },
+        ];
+
+        Ok(unsafe {
+            device.logical_device.create_render_pass(
+                &ash::vk::RenderPassCreateInfo::builder()
+                    .attachments(&[ash::vk::AttachmentDescription {
+                        format: HISTORY_FORMAT,
+                        samples: ash::vk::SampleCountFlags::TYPE_1,
+                        load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+                        store_op: ash::vk::AttachmentStoreOp::STORE,
+                        stencil_load_op: ash::vk::AttachmentLoadOp::DONT_CARE,
+                        stencil_store_op: ash::vk::AttachmentStoreOp::DONT_CARE,
+                        initial_layout: ash::vk::ImageLayout::UNDEFINED,
+                        final_layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        ..Default::default()
+                    }])
+                    .subpasses(&[ash::vk::SubpassDescription::builder()
+                        .pipeline_bind_point(ash::vk::PipelineBindPoint::GRAPHICS)
+                        .color_attachments(&[ash::vk::AttachmentReference {
+                            attachment: 0,
+                            layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        }])
+                        .build()])
+                    .dependencies(&dependencies),
+                None,
+            )?
+        })
+    }
+}
+
+impl Drop for TaaSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping TAA system");
+
+        self.targets = None;
+
+        unsafe {
+            self.device.logical_device.destroy_sampler(self.sampler, None);
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::rc::Rc;
+
+use crate::{vulkan::{GentooRenderError, Device, ShaderSource, Vertex, pipeline::{Pipeline, PipelineCache, PipelineConfig, BlendMode, DepthBias}}, settings::WireframeSettings, FrameInfo, GameObjectId, RENDER_LAYER_MAIN};
+
+#[derive(Debug)]
+#[repr(C)]
+struct WireframePushConstantData {
+    model_matrix: glam::Mat4,
+    edge_color: glam::Vec4,
+}
+
+impl WireframePushConstantData {
+    unsafe fn as_bytes(&self) -> &[u8] {
+        std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
+    }
+}
+
+/// Draws the edges of the shaded scene on top of it, for inspecting topology.
+/// Has to be recorded after the objects themselves.
+pub struct WireframeSystem {
+    device: Rc<Device>,
+    pipeline: Pipeline,
+    pipeline_layout: ash::vk::PipelineLayout,
+}
+
+impl WireframeSystem {
+    pub fn new(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        global_set_layout: &[ash::vk::DescriptorSetLayout],
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Self, GentooRenderError> {
+        let push_constant_range = [ash::vk::PushConstantRange {
+            stage_flags: ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<WireframePushConstantData>() as u32,
+        }];
+
+        let pipeline_layout = unsafe {
+            device.logical_device.create_pipeline_layout(
+                &ash::vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(global_set_layout)
+                    .push_constant_ranges(&push_constant_range),
+                None,
+            )?
+        };
+
+        let pipeline = Self::create_pipeline(device.clone(), render_pass, &pipeline_layout, pipeline_cache)?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            pipeline_layout,
+        })
+    }
+
+    /// Rebuilds the pipeline for a scene render pass with other attachment
+    /// formats, the old one is incompatible with it.
+    pub fn recreate_pipeline_for_format(
+        &mut self,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<(), GentooRenderError> {
+        self.pipeline = Self::create_pipeline(self.device.clone(), render_pass, &self.pipeline_layout, pipeline_cache)?;
+
+        Ok(())
+    }
+
+    /// `selected` is the object drawn when `settings.selected_only` is set.
+    /// Objects with `DebugRenderComponent::wireframe` are drawn even while the
+    /// overlay is disabled.
+    pub fn render(&self, frame_info: &FrameInfo, settings: &WireframeSettings, selected: Option<GameObjectId>) {
+        let objects = frame_info.game_objects
+            .values()
+            .filter(|obj| obj.is_rendered_in(RENDER_LAYER_MAIN) && obj.model.is_some() && obj.animation.is_none())
+            .filter(|obj| obj.debug.wireframe || (settings.enabled && (!settings.selected_only || selected == Some(obj.id))))
+            .collect::<Vec<_>>();
+
+        if objects.is_empty() {
+            return;
+        }
+
+        let logical_device = &self.device.logical_device;
+
+        unsafe {
+            self.pipeline.bind(logical_device, frame_info.command_buffer);
+
+            logical_device.cmd_bind_descriptor_sets(
+                frame_info.command_buffer,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[frame_info.global_descriptor_set],
+                &[],
+            );
+        }
+
+        for obj in objects {
+            let model = obj.model.as_ref().unwrap();
+
+            let push = WireframePushConstantData {
+                model_matrix: obj.transform.mat4(),
+                edge_color: obj.debug.wireframe_color.unwrap_or(settings.color),
+            };
+
+            unsafe {
+                logical_device.cmd_push_constants(
+                    frame_info.command_buffer,
+                    self.pipeline_layout,
+                    ash::vk::ShaderStageFlags::VERTEX | ash::vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push.as_bytes(),
+                );
+
+                model.bind(frame_info.command_buffer);
+                model.draw(logical_device, frame_info.command_buffer);
+            }
+        }
+    }
+
+    fn create_pipeline(
+        device: Rc<Device>,
+        render_pass: &ash::vk::RenderPass,
+        pipeline_layout: &ash::vk::PipelineLayout,
+        pipeline_cache: &Rc<PipelineCache>,
+    ) -> anyhow::Result<Pipeline, GentooRenderError> {
+        Ok(Pipeline::new(
+            device,
+            ShaderSource::Path("shaders/wireframe.vert.spv"),
+            ShaderSource::Path("shaders/wireframe.frag.spv"),
+            render_pass,
+            pipeline_layout,
+            pipeline_cache,
+            &Vertex::layout(),
+            PipelineConfig {
+                polygon_mode: ash::vk::PolygonMode::LINE,
+                // Hidden edges fail the depth test against the shaded faces
+                cull_mode: ash::vk::CullModeFlags::NONE,
+                depth_write: false,
+                depth_bias: Some(DepthBias {
+                    constant_factor: -1.0,
+                    slope_factor: -1.0,
+                }),
+                blend_mode: BlendMode::PremultipliedAlpha,
+                ..Default::default()
+            },
+        )?)
+    }
+}
+
+impl Drop for WireframeSystem {
+    fn drop(&mut self) {
+        log::debug!("Dropping wireframe system");
+
+        unsafe {
+            self.device.logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
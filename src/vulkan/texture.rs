@@ -0,0 +1,367 @@
+use std::rc::Rc;
+
+use super::{Device, GentooRenderError, Buffer, Sampler};
+
+/// A sampled RGBA8 image, either 2D or a cubemap. Color textures are sRGB.
+pub struct Texture {
+    device: Rc<Device>,
+    image: ash::vk::Image,
+    memory: ash::vk::DeviceMemory,
+    pub image_view: ash::vk::ImageView,
+    pub sampler: Rc<Sampler>,
+}
+
+impl Texture {
+    pub fn new(device: Rc<Device>, pixels: &[u8], width: u32, height: u32) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        Self::create(device, pixels, width, height, false, ash::vk::Format::R8G8B8A8_SRGB)
+    }
+
+    /// For data that isn't a color, e.g. noise or normal maps, the values are
+    /// read back unchanged instead of being decoded from sRGB.
+    pub fn new_linear(device: Rc<Device>, pixels: &[u8], width: u32, height: u32) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        Self::create(device, pixels, width, height, false, ash::vk::Format::R8G8B8A8_UNORM)
+    }
+
+    /// `faces` are the +x, -x, +y, -y, +z and -z faces, each `size` by `size`
+    /// tightly packed RGBA8 pixels.
+    pub fn new_cubemap(device: Rc<Device>, faces: [&[u8]; 6], size: u32) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let pixels = faces.concat();
+
+        Self::create(device, &pixels, size, size, true, ash::vk::Format::R8G8B8A8_SRGB)
+    }
+
+    /// Loads the six faces of a cubemap in the order of `new_cubemap`.
+    pub fn cubemap_from_files<P: AsRef<std::path::Path>>(device: Rc<Device>, file_paths: [P; 6]) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let mut faces = Vec::with_capacity(6);
+
+        for file_path in &file_paths {
+            log::debug!("Loading cubemap face: {}", file_path.as_ref().display());
+
+            faces.push(Self::fit_to_device(&device, image::open(file_path)?, true).into_rgba8());
+        }
+
+        let size = faces[0].width();
+
+        if faces.iter().any(|face| face.dimensions() != (size, size)) {
+            log::error!("Cubemap faces must be square and of equal size");
+            return Err(GentooRenderError::CubemapFaceSizeError);
+        }
+
+        let pixels = faces.iter().map(|face| face.as_raw().as_slice()).collect::<Vec<_>>();
+
+        Self::new_cubemap(device, [pixels[0], pixels[1], pixels[2], pixels[3], pixels[4], pixels[5]], size)
+    }
+
+    fn create(device: Rc<Device>, pixels: &[u8], width: u32, height: u32, cubemap: bool, format: ash::vk::Format) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let layers = if cubemap { 6 } else { 1 };
+
+        Self::check_pixels(pixels, width, height, layers)?;
+
+        let extent = ash::vk::Extent2D { width, height };
+
+        let (image, memory) = Self::create_image(&device, pixels, extent, layers, format)?;
+
+        let (view_type, address_mode) = if cubemap {
+            (ash::vk::ImageViewType::CUBE, ash::vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        } else {
+            (ash::vk::ImageViewType::TYPE_2D, ash::vk::SamplerAddressMode::REPEAT)
+        };
+
+        let image_view = device.create_image_view(
+            &ash::vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(view_type)
+                .format(format)
+                .subresource_range(
+                    ash::vk::ImageSubresourceRange {
+                        aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: layers,
+                    }
+                ),
+        )?;
+
+        // There are no mip levels besides the base one
+        let sampler = Sampler::new(device.clone())
+            .address_mode(address_mode)
+            .lod_range(0.0, 0.0)
+            .build()?;
+
+        Ok(Rc::new(Self {
+            device,
+            image,
+            memory,
+            image_view,
+            sampler,
+        }))
+    }
+
+    /// The pixels have to be tightly packed RGBA8 for every layer
+    fn check_pixels(pixels: &[u8], width: u32, height: u32, layers: u32) -> anyhow::Result<(), GentooRenderError> {
+        let expected = [width, height, layers]
+            .into_iter()
+            .try_fold(4usize, |len, factor| len.checked_mul(factor as usize));
+
+        match expected {
+            Some(expected) if expected == pixels.len() => Ok(()),
+            _ => {
+                log::error!("Texture of {}x{}x{} has {} bytes of pixels", width, height, layers, pixels.len());
+                Err(GentooRenderError::TextureSizeError {
+                    width,
+                    height,
+                    layers,
+                    len: pixels.len(),
+                })
+            }
+        }
+    }
+
+    /// `premultiplied` multiplies the color by alpha at load time. Pair those
+    /// textures with `BlendMode::PremultipliedAlpha`, which blends with
+    /// `ONE`/`ONE_MINUS_SRC_ALPHA`. Straight alpha textures under that blend
+    /// mode, or premultiplied ones under `SRC_ALPHA`, get dark fringes along
+    /// soft edges.
+    pub fn from_file<P: AsRef<std::path::Path>>(device: Rc<Device>, file_path: P, premultiplied: bool) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        log::debug!("Loading texture file: {}", file_path.as_ref().display());
+
+        Self::from_image(device, image::open(file_path)?, premultiplied)
+    }
+
+    /// Decodes an encoded image, e.g. one embedded with `include_bytes!`. See
+    /// `from_file` for `premultiplied`.
+    pub fn from_memory(device: Rc<Device>, bytes: &[u8], premultiplied: bool) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        Self::from_image(device, image::load_from_memory(bytes)?, premultiplied)
+    }
+
+    pub fn descriptor_info(&self) -> ash::vk::DescriptorImageInfo {
+        ash::vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler.sampler)
+            .image_view(self.image_view)
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build()
+    }
+
+    /// Downscales images larger than `Device::max_texture_dimension`, keeping
+    /// the aspect ratio.
+    fn fit_to_device(device: &Rc<Device>, image: image::DynamicImage, cubemap: bool) -> image::DynamicImage {
+        let max_dimension = device.max_texture_dimension(cubemap);
+
+        if image.width() <= max_dimension && image.height() <= max_dimension {
+            return image;
+        }
+
+        let resized = image.resize(max_dimension, max_dimension, image::imageops::FilterType::Triangle);
+
+        log::info!(
+            "Downscaled texture from {}x{} to {}x{} to fit the maximum texture dimension {}",
+            image.width(),
+            image.height(),
+            resized.width(),
+            resized.height(),
+            max_dimension,
+        );
+
+        resized
+    }
+
+    fn from_image(device: Rc<Device>, image: image::DynamicImage, premultiplied: bool) -> anyhow::Result<Rc<Self>, GentooRenderError> {
+        let mut image = Self::fit_to_device(&device, image, false).into_rgba8();
+        let (width, height) = image.dimensions();
+
+        if premultiplied {
+            premultiply(&mut image);
+        }
+
+        Self::new(device, image.as_raw(), width, height)
+    }
+
+    fn create_image(
+        device: &Rc<Device>,
+        pixels: &[u8],
+        extent: ash::vk::Extent2D,
+        layers: u32,
+        format: ash::vk::Format,
+    ) -> anyhow::Result<(ash::vk::Image, ash::vk::DeviceMemory), GentooRenderError> {
+        let mut staging_buffer = Buffer::<u8>::new(
+            device.clone(),
+            pixels.len(),
+            ash::vk::BufferUsageFlags::TRANSFER_SRC,
+            ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        staging_buffer.map(0)?.write_slice(pixels);
+
+        let (image, memory) = device.create_image_with_info(
+            &ash::vk::ImageCreateInfo::builder()
+                .format(format)
+                .initial_layout(ash::vk::ImageLayout::UNDEFINED)
+                .samples(ash::vk::SampleCountFlags::TYPE_1)
+                .tiling(ash::vk::ImageTiling::OPTIMAL)
+                .usage(ash::vk::ImageUsageFlags::SAMPLED | ash::vk::ImageUsageFlags::TRANSFER_DST)
+                .sharing_mode(ash::vk::SharingMode::EXCLUSIVE)
+                .image_type(ash::vk::ImageType::TYPE_2D)
+                .mip_levels(1)
+                .array_layers(layers)
+                .flags(if layers == 6 { ash::vk::ImageCreateFlags::CUBE_COMPATIBLE } else { ash::vk::ImageCreateFlags::empty() })
+                .extent(ash::vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                }),
+            ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        let subresource_range = ash::vk::ImageSubresourceRange {
+            aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: layers,
+        };
+
+        let command_buffer = device.begin_single_time_commands()?;
+
+        unsafe {
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::HOST,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .image(image)
+                    .subresource_range(subresource_range)
+                    .src_access_mask(ash::vk::AccessFlags::default())
+                    .dst_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .old_layout(ash::vk::ImageLayout::UNDEFINED)
+                    .new_layout(ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .build()],
+            );
+
+            device.logical_device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer.buffer,
+                image,
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[ash::vk::BufferImageCopy::builder()
+                    .image_subresource(
+                        ash::vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(ash::vk::ImageAspectFlags::COLOR)
+                            .base_array_layer(0)
+                            .layer_count(layers)
+                            .mip_level(0)
+                            .build(),
+                    )
+                    .image_extent(
+                        ash::vk::Extent3D {
+                            width: extent.width,
+                            height: extent.height,
+                            depth: 1,
+                        },
+                    )
+                    .build()],
+            );
+
+            device.logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier::builder()
+                    .image(image)
+                    .subresource_range(subresource_range)
+                    .src_access_mask(ash::vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(ash::vk::AccessFlags::SHADER_READ)
+                    .old_layout(ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .build()],
+            );
+        }
+
+        device.end_single_time_commands(command_buffer)?;
+
+        Ok((image, memory))
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        log::debug!("Dropping texture");
+
+        unsafe {
+            self.device.destroy_image_view(self.image_view);
+            self.device.destroy_image(self.image, self.memory);
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn premultiply(image: &mut image::RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as f32 / 255.0;
+
+        // The texture is sampled as sRGB, so the multiplication has to
+        // happen on linear values for the decoded result to come out right
+        for channel in &mut pixel.0[..3] {
+            let linear = srgb_to_linear(*channel as f32 / 255.0) * alpha;
+            *channel = (linear_to_srgb(linear) * 255.0).round() as u8;
+        }
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_length_is_checked() {
+        assert!(Texture::check_pixels(&[0; 2 * 3 * 4], 2, 3, 1).is_ok());
+        assert!(Texture::check_pixels(&[0; 6 * 4], 1, 1, 6).is_ok());
+
+        assert!(matches!(
+            Texture::check_pixels(&[0; 2 * 3 * 3], 2, 3, 1),
+            Err(GentooRenderError::TextureSizeError { width: 2, height: 3, layers: 1, len: 18 }),
+        ));
+        // Doesn't overflow for huge dimensions
+        assert!(Texture::check_pixels(&[0; 4], u32::MAX, u32::MAX, 6).is_err());
+    }
+
+    #[test]
+    fn premultiplied_soft_edge() {
+        let straight = image::open("textures/soft_edge.png").unwrap().into_rgba8();
+        let mut premultiplied = straight.clone();
+        premultiply(&mut premultiplied);
+
+        assert!(straight.pixels().any(|pixel| pixel[3] > 0 && pixel[3] < 255), "the edge should be soft");
+
+        for (before, after) in straight.pixels().zip(premultiplied.pixels()) {
+            assert_eq!(before[3], after[3]);
+
+            match before[3] {
+                0 => assert_eq!(&after.0[..3], &[0, 0, 0]),
+                255 => assert_eq!(before, after),
+                _ => assert!(before.0[..3].iter().zip(&after.0[..3]).all(|(b, a)| a <= b)),
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use super::{GentooRenderError, ShaderModule};
+
+/// What a vertex attribute means, documents the layout and keeps it in the
+/// same order as the shader inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexSemantic {
+    Position,
+    Color,
+    Normal,
+    Uv,
+    /// Second texture coordinate set, e.g. for lightmaps
+    Uv1,
+    Tangent,
+    JointIndices,
+    JointWeights,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub semantic: VertexSemantic,
+    pub format: ash::vk::Format,
+    /// Byte offset from the start of the vertex
+    pub offset: u32,
+}
+
+/// Interleaved vertex data in a single binding. The attribute at index `n` of
+/// `attributes` is read by the shader input at `location = n`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VertexLayout {
+    pub stride: u32,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(stride: u32) -> Self {
+        Self {
+            stride,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn add_attribute(mut self, semantic: VertexSemantic, format: ash::vk::Format, offset: u32) -> Self {
+        self.attributes.push(VertexAttribute {
+            semantic,
+            format,
+            offset,
+        });
+
+        self
+    }
+
+    /// Where an attribute with `semantic` is read from, if the layout has one.
+    pub fn location(&self, semantic: VertexSemantic) -> Option<u32> {
+        self.attributes
+            .iter()
+            .position(|attribute| attribute.semantic == semantic)
+            .map(|location| location as u32)
+    }
+
+    pub fn binding_descriptions(&self) -> Vec<ash::vk::VertexInputBindingDescription> {
+        if self.attributes.is_empty() {
+            return Vec::new();
+        }
+
+        vec![
+            ash::vk::VertexInputBindingDescription {
+                binding: 0,
+                stride: self.stride,
+                input_rate: ash::vk::VertexInputRate::VERTEX,
+            },
+        ]
+    }
+
+    pub fn attribute_descriptions(&self) -> Vec<ash::vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .enumerate()
+            .map(|(location, attribute)| ash::vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: location as u32,
+                format: attribute.format,
+                offset: attribute.offset,
+            })
+            .collect()
+    }
+
+    /// Checks that every input of `vert_shader` is provided by this layout.
+    /// Attributes the shader doesn't read are fine.
+    pub fn validate(&self, vert_shader: &ShaderModule, shader_name: &str) -> anyhow::Result<(), GentooRenderError> {
+        match vert_shader.input_locations.iter().find(|location| **location as usize >= self.attributes.len()) {
+            Some(location) => {
+                log::error!("Vertex shader {} reads location {}, the vertex layout has {} attributes", shader_name, location, self.attributes.len());
+
+                Err(GentooRenderError::VertexLayoutError {
+                    shader: shader_name.to_string(),
+                    location: *location,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
@@ -8,6 +8,9 @@ pub struct Dimensions {
 pub struct WindowSettings {
     pub title: &'static str,
     pub dimensions: Dimensions,
+    /// Smallest logical inner size the window can be resized to, keeps the
+    /// swapchain and the UI from degenerating at a few pixels
+    pub min_dimensions: Option<Dimensions>,
     pub resizable: bool,
 }
 
@@ -15,44 +18,123 @@ pub struct WindowSettings {
 pub enum WindowMode {
     Windowed,
     Borderless,
-    // Exclusive,
+    Exclusive,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub handle: winit::monitor::MonitorHandle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoModeInfo {
+    pub resolution: Dimensions,
+    pub refresh_rate: u16,
+    pub bit_depth: u16,
+    pub mode: winit::monitor::VideoMode,
+}
+
+impl std::fmt::Display for VideoModeInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{} @ {} Hz ({} bit)", self.resolution.width, self.resolution.height, self.refresh_rate, self.bit_depth)
+    }
 }
 
 pub struct Window {
     pub raw_window: winit::window::Window,
 
     pub mode: WindowMode,
+    /// Monitor the fullscreen modes go to, the current one when unset
+    pub monitor: Option<MonitorInfo>,
+    /// Used by `WindowMode::Exclusive`, the best mode of `monitor` when unset
+    pub exclusive_video_mode: Option<VideoModeInfo>,
 }
 
 impl Window {
     pub fn new(event_loop: &winit::event_loop::EventLoop<()>, settings: WindowSettings) -> Self {
-        let raw_window = winit::window::WindowBuilder::new()
+        let mut builder = winit::window::WindowBuilder::new()
             .with_inner_size(winit::dpi::LogicalSize::new(settings.dimensions.width, settings.dimensions.height))
             .with_title(settings.title)
-            .with_resizable(settings.resizable)
-            .build(&event_loop).unwrap();
+            .with_resizable(settings.resizable);
+
+        if let Some(min) = settings.min_dimensions {
+            builder = builder.with_min_inner_size(winit::dpi::LogicalSize::new(min.width, min.height));
+        }
+
+        let raw_window = builder.build(&event_loop).unwrap();
 
         Self {
             raw_window,
             mode: WindowMode::Windowed,
+            monitor: None,
+            exclusive_video_mode: None,
         }
     }
 
+    pub fn available_monitors(&self) -> Vec<MonitorInfo> {
+        self.raw_window
+            .available_monitors()
+            .enumerate()
+            .map(|(i, handle)| MonitorInfo {
+                name: handle.name().unwrap_or_else(|| format!("Monitor {}", i)),
+                handle,
+            })
+            .collect()
+    }
+
+    pub fn current_monitor(&self) -> Option<MonitorInfo> {
+        let handle = self.raw_window.current_monitor()?;
+
+        self.available_monitors()
+            .into_iter()
+            .find(|monitor| monitor.handle == handle)
+    }
+
+    /// Best mode first.
+    pub fn video_modes(monitor: &MonitorInfo) -> Vec<VideoModeInfo> {
+        let mut modes = monitor.handle.video_modes().collect::<Vec<_>>();
+        modes.sort_unstable_by(|a, b| b.cmp(a));
+
+        modes
+            .into_iter()
+            .map(|mode| VideoModeInfo {
+                resolution: Dimensions {
+                    width: mode.size().width,
+                    height: mode.size().height,
+                },
+                refresh_rate: mode.refresh_rate(),
+                bit_depth: mode.bit_depth(),
+                mode,
+            })
+            .collect()
+    }
+
     pub fn update_mode(&mut self) {
         match self.mode {
             WindowMode::Windowed => self.raw_window.set_fullscreen(None),
-            WindowMode::Borderless => self.raw_window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None))),
-            // WindowMode::Exclusive => {
-            //     let vm = self
-            //         .raw_window
-            //         .current_monitor()
-            //         .expect("No monitor detected")
-            //         .video_modes()
-            //         .min()
-            //         .expect("No video modes found");
-
-            //     self.raw_window.set_fullscreen(Some(winit::window::Fullscreen::Exclusive(vm)));
-            // }
+            WindowMode::Borderless => {
+                let handle = self.monitor.as_ref().map(|monitor| monitor.handle.clone());
+                self.raw_window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(handle)));
+            }
+            WindowMode::Exclusive => {
+                let monitor = self.monitor.clone().or_else(|| self.current_monitor());
+                let video_mode = self.exclusive_video_mode
+                    .clone()
+                    .or_else(|| Self::video_modes(monitor.as_ref()?).into_iter().next());
+
+                match video_mode {
+                    Some(video_mode) => {
+                        log::info!("Entering exclusive fullscreen: {}", video_mode);
+                        self.raw_window.set_fullscreen(Some(winit::window::Fullscreen::Exclusive(video_mode.mode)));
+                    }
+                    None => {
+                        log::warn!("No video modes found, staying windowed");
+                        self.mode = WindowMode::Windowed;
+                        self.raw_window.set_fullscreen(None);
+                    }
+                }
+            }
         }
     }
 }